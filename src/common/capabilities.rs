@@ -0,0 +1,117 @@
+use super::crypto::{
+    algorithms::{encryption::AsymmetricEncryption, encryption::BlockCiphers, hashes::Hash},
+    KeyUsage,
+};
+use serde::{Deserialize, Serialize};
+
+/// A cryptographic operation a security module can perform.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operation {
+    /// Signing data with a private key.
+    Sign,
+    /// Verifying a signature with a public key.
+    Verify,
+    /// Encrypting data, with a public key or a symmetric key.
+    Encrypt,
+    /// Decrypting data, with a private key or a symmetric key.
+    Decrypt,
+    /// Deriving a shared secret from a private key and a peer's public key.
+    KeyAgreement,
+    /// Computing and verifying message authentication codes.
+    Mac,
+    /// Generating random bytes, see `Provider::random_bytes`.
+    RandomBytes,
+}
+
+impl Operation {
+    /// Returns whether a key created with `usages` may perform the operation.
+    ///
+    /// Signing and MACs need `KeyUsage::SignEncrypt`; encryption, decryption and key
+    /// agreement need `KeyUsage::Decrypt`. Verifying signatures and generating random bytes
+    /// use no private key material and are always permitted.
+    pub fn permitted_by(self, usages: &[KeyUsage]) -> bool {
+        match self {
+            Operation::Sign | Operation::Mac => usages.contains(&KeyUsage::SignEncrypt),
+            Operation::Encrypt | Operation::Decrypt | Operation::KeyAgreement => {
+                usages.contains(&KeyUsage::Decrypt)
+            }
+            Operation::Verify | Operation::RandomBytes => true,
+        }
+    }
+}
+
+/// An asymmetric key algorithm a security module can create keys for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAlgorithm {
+    /// The algorithm, including the key size or curve.
+    pub algorithm: AsymmetricEncryption,
+    /// The hashes keys of this algorithm can sign with.
+    pub hashes: Vec<Hash>,
+    /// The operations keys of this algorithm support.
+    pub operations: Vec<Operation>,
+}
+
+impl KeyAlgorithm {
+    /// Creates a new `KeyAlgorithm`.
+    pub fn new(
+        algorithm: AsymmetricEncryption,
+        hashes: Vec<Hash>,
+        operations: Vec<Operation>,
+    ) -> Self {
+        Self {
+            algorithm,
+            hashes,
+            operations,
+        }
+    }
+}
+
+/// Describes what a security module supports, as reported by `Provider::capabilities`.
+///
+/// Callers can check an algorithm before calling `create_key` instead of interpreting its
+/// error, and user interfaces can offer only the algorithms the module supports.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// The asymmetric key algorithms keys can be created for.
+    pub key_algorithms: Vec<KeyAlgorithm>,
+    /// The block ciphers symmetric keys can be created for.
+    pub block_ciphers: Vec<BlockCiphers>,
+    /// The hashes the module can compute or use in signatures and MACs.
+    pub hashes: Vec<Hash>,
+    /// The key usages keys can be created with.
+    pub key_usages: Vec<KeyUsage>,
+    /// The number of keys the module can store, `None` if it is not limited or not known.
+    pub max_keys: Option<usize>,
+    /// The operations the module supports with any of its keys, and without a key.
+    pub operations: Vec<Operation>,
+}
+
+impl Capabilities {
+    /// Returns the description of `algorithm`, or `None` if keys cannot be created for it.
+    pub fn key_algorithm(&self, algorithm: AsymmetricEncryption) -> Option<&KeyAlgorithm> {
+        self.key_algorithms
+            .iter()
+            .find(|supported| supported.algorithm == algorithm)
+    }
+
+    /// Returns whether keys can be created for `algorithm`.
+    pub fn supports_key_algorithm(&self, algorithm: AsymmetricEncryption) -> bool {
+        self.key_algorithm(algorithm).is_some()
+    }
+
+    /// Returns whether symmetric keys can be created for `cipher`.
+    pub fn supports_block_cipher(&self, cipher: BlockCiphers) -> bool {
+        self.block_ciphers.contains(&cipher)
+    }
+
+    /// Returns whether the module supports `hash`.
+    pub fn supports_hash(&self, hash: Hash) -> bool {
+        self.hashes.contains(&hash)
+    }
+
+    /// Returns whether the module supports `operation`.
+    pub fn supports_operation(&self, operation: Operation) -> bool {
+        self.operations.contains(&operation)
+    }
+}
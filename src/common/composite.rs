@@ -0,0 +1,528 @@
+//! A provider spreading keys over several security modules.
+//!
+//! Machines differ in the security modules they have: some have a TPM, some a YubiKey, some
+//! nothing. A `CompositeProvider` is configured with the backends in order of preference and
+//! creates each key on the first one that supports it, so application code does not depend
+//! on the modules of the machine it runs on.
+
+use super::{
+    capabilities::Capabilities,
+    crypto::{
+        algorithms::{encryption::AsymmetricEncryption, hashes::Hash},
+        KeyUsage,
+    },
+    error::{FactoryError, SecurityModuleError},
+    factory::{SecModules, SecurityModule},
+    traits::{
+        interaction::InteractionHandler,
+        key_handle::KeyHandle,
+        module_provider::Provider,
+        operations::{Decryptor, Encryptor, KeyAgreement, Signer, Verifier},
+    },
+};
+#[cfg(feature = "hsm")]
+use crate::hsm::HsmProviderConfig;
+#[cfg(feature = "plugin")]
+use crate::plugin::PluginKeyConfig;
+#[cfg(feature = "tpm")]
+use crate::{
+    common::crypto::algorithms::{
+        encryption::{BlockCiphers, SymmetricMode},
+        hashes::Sha2Bits,
+        KeyBits,
+    },
+    tpm::TpmConfig,
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tracing::{info, instrument, warn};
+
+/// The configuration of keys created or loaded by a `CompositeProvider`.
+///
+/// It names no security module; each backend turns it into the configuration its provider
+/// expects.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompositeKeyConfig {
+    /// The algorithm of the key, including the key size or curve.
+    pub key_algorithm: AsymmetricEncryption,
+    /// The hash for signatures, `None` for the backend's default.
+    pub hash: Option<Hash>,
+    /// The usages the key is created with.
+    pub key_usages: Vec<KeyUsage>,
+}
+
+/// Turns a `CompositeKeyConfig` into the configuration of a backend's provider.
+pub type KeyConfigMapper = fn(&CompositeKeyConfig) -> Box<dyn Any>;
+
+/// Whether a `CompositeProvider` may use its fallback backend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FallbackPolicy {
+    /// Keys are only created on and loaded from the preferred backends.
+    #[default]
+    Deny,
+    /// Keys no preferred backend supports are created on the fallback backend.
+    Allow,
+}
+
+/// A security module used by a `CompositeProvider`.
+#[derive(Clone, Debug)]
+pub struct Backend {
+    name: String,
+    provider: Arc<Mutex<dyn Provider>>,
+    key_config: KeyConfigMapper,
+}
+
+impl Backend {
+    /// Creates a backend named `name`, passing keys to `provider` with the configuration
+    /// returned by `key_config`.
+    pub fn new(
+        name: impl Into<String>,
+        provider: Arc<Mutex<dyn Provider>>,
+        key_config: KeyConfigMapper,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            provider,
+            key_config,
+        }
+    }
+
+    /// Creates a backend for the instance `instance` of `module`, as returned by
+    /// `SecModules::get_instance`.
+    ///
+    /// The Android keystore needs the Java VM in its configuration, so it has to be added
+    /// with `Backend::new` instead.
+    #[cfg_attr(
+        not(any(feature = "hsm", feature = "tpm", feature = "plugin")),
+        allow(unreachable_code, unused_variables)
+    )]
+    pub fn from_module(
+        module: SecurityModule,
+        instance: impl Into<String>,
+    ) -> Result<Self, FactoryError> {
+        let key_config: KeyConfigMapper = match module {
+            #[cfg(feature = "hsm")]
+            SecurityModule::Hsm(_) => hsm_config,
+            #[cfg(all(feature = "tpm", feature = "android"))]
+            SecurityModule::Tpm(crate::tpm::core::instance::TpmType::Android(_)) => {
+                return Err(FactoryError::NotImplemented(
+                    "the Android keystore in a composite provider".to_owned(),
+                ))
+            }
+            #[cfg(feature = "tpm")]
+            SecurityModule::Tpm(_) => tpm_config,
+            #[cfg(feature = "plugin")]
+            SecurityModule::Plugin(_) => plugin_config,
+        };
+        let name = format!("{:?}", module);
+        let provider = SecModules::get_instance(instance.into(), module, None)?;
+        Ok(Self::new(name, provider, key_config))
+    }
+
+    /// The name of the backend.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn provider(&self) -> MutexGuard<'_, dyn Provider + 'static> {
+        self.provider.lock().unwrap()
+    }
+
+    /// Returns whether the backend reports support for keys configured as `config`.
+    fn supports(&self, config: &CompositeKeyConfig) -> bool {
+        match self.provider().capabilities() {
+            Ok(capabilities) => {
+                capabilities
+                    .key_algorithm(config.key_algorithm)
+                    .is_some_and(|algorithm| {
+                        config
+                            .hash
+                            .is_none_or(|hash| algorithm.hashes.contains(&hash))
+                    })
+                    && config
+                        .key_usages
+                        .iter()
+                        .all(|usage| capabilities.key_usages.contains(usage))
+            }
+            Err(err) => {
+                warn!("Capabilities of {} unavailable: {}", self.name, err);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hsm")]
+fn hsm_config(config: &CompositeKeyConfig) -> Box<dyn Any> {
+    Box::new(HsmProviderConfig {
+        key_algorithm: config.key_algorithm,
+        key_usages: config.key_usages.clone(),
+        hash: config.hash,
+        ..Default::default()
+    })
+}
+
+/// The configuration of a TPM key. The TPM only checks the block cipher of asymmetric keys,
+/// so one every TPM supports is passed, and signatures default to SHA-256.
+#[cfg(feature = "tpm")]
+pub(crate) fn tpm_config(config: &CompositeKeyConfig) -> Box<dyn Any> {
+    TpmConfig::new(
+        config.key_algorithm,
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+        config.hash.unwrap_or(Hash::Sha2(Sha2Bits::Sha256)),
+        config.key_usages.clone(),
+    )
+}
+
+#[cfg(feature = "plugin")]
+fn plugin_config(config: &CompositeKeyConfig) -> Box<dyn Any> {
+    Box::new(PluginKeyConfig {
+        key_algorithm: Some(config.key_algorithm),
+        hash: config.hash,
+        key_usages: config.key_usages.clone(),
+        ..Default::default()
+    })
+}
+
+/// A provider passing each key to one of several backends.
+///
+/// `create_key` uses the first backend, in order of preference, whose capabilities include
+/// the algorithm, hash and usages of the `CompositeKeyConfig`, and moves on to the next
+/// if creating the key fails. The provider remembers which backend holds each key, and
+/// `load_key` tries the backends in order for keys it does not know. The fallback backend,
+/// typically a software keystore, is only used if the `FallbackPolicy` allows it.
+///
+/// The operations of `KeyHandle` are those of the backend holding the last created or
+/// loaded key.
+#[derive(Debug)]
+pub struct CompositeProvider {
+    /// The preferred backends, followed by the fallback backend if there is one.
+    backends: Vec<Backend>,
+    has_fallback: bool,
+    policy: FallbackPolicy,
+    /// Whether each backend failed to initialize, which excludes it.
+    failed: Vec<bool>,
+    /// The index of the backend holding each key.
+    keys: HashMap<String, usize>,
+    /// The index of the backend holding the loaded key.
+    active: Option<usize>,
+}
+
+impl CompositeProvider {
+    /// Creates a provider using `backends`, in order of preference, without fallback.
+    pub fn new(backends: Vec<Backend>) -> Self {
+        let failed = vec![false; backends.len()];
+        Self {
+            backends,
+            has_fallback: false,
+            policy: FallbackPolicy::Deny,
+            failed,
+            keys: HashMap::new(),
+            active: None,
+        }
+    }
+
+    /// Adds `fallback` after the preferred backends, used as far as `policy` allows.
+    pub fn with_fallback(mut self, fallback: Backend, policy: FallbackPolicy) -> Self {
+        if self.has_fallback {
+            self.backends.pop();
+            self.failed.pop();
+        }
+        self.backends.push(fallback);
+        self.failed.push(false);
+        self.has_fallback = true;
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the name of the backend holding `key_id`, if the key was created or loaded.
+    pub fn backend_of(&self, key_id: &str) -> Option<&str> {
+        self.keys
+            .get(key_id)
+            .map(|&index| self.backends[index].name())
+    }
+
+    /// The indices of the backends that may be used, in order of preference.
+    fn candidates(&self) -> Vec<usize> {
+        let usable = match (self.has_fallback, self.policy) {
+            (true, FallbackPolicy::Deny) => self.backends.len() - 1,
+            _ => self.backends.len(),
+        };
+        (0..usable).filter(|&index| !self.failed[index]).collect()
+    }
+
+    fn active(&self) -> Result<MutexGuard<'_, dyn Provider + 'static>, SecurityModuleError> {
+        self.active
+            .map(|index| self.backends[index].provider())
+            .ok_or_else(|| SecurityModuleError::InitializationError("No key loaded".to_owned()))
+    }
+
+    /// Returns whether the backend holding the loaded key offers an operation.
+    fn active_offers(&self, offers: impl FnOnce(&dyn Provider) -> bool) -> bool {
+        self.active().is_ok_and(|provider| offers(&*provider))
+    }
+
+    fn config(config: Box<dyn Any>) -> Result<Box<CompositeKeyConfig>, SecurityModuleError> {
+        config.downcast::<CompositeKeyConfig>().map_err(|_| {
+            SecurityModuleError::InitializationError("Expected a CompositeKeyConfig".to_owned())
+        })
+    }
+
+    /// Loads `key_id` from the backend at `index` and makes it the active one.
+    fn load_from(
+        &mut self,
+        index: usize,
+        key_id: &str,
+        config: &CompositeKeyConfig,
+    ) -> Result<(), SecurityModuleError> {
+        let backend = &self.backends[index];
+        backend
+            .provider()
+            .load_key(key_id, (backend.key_config)(config))?;
+        self.keys.insert(key_id.to_owned(), index);
+        self.active = Some(index);
+        Ok(())
+    }
+}
+
+impl Provider for CompositeProvider {
+    /// Creates the key `key_id` with the `CompositeKeyConfig` in `config` on the first
+    /// backend supporting it.
+    #[instrument(skip(config))]
+    fn create_key(
+        &mut self,
+        key_id: &str,
+        config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        let config = Self::config(config)?;
+        let mut error = None;
+        for index in self.candidates() {
+            let backend = &self.backends[index];
+            if !backend.supports(&config) {
+                continue;
+            }
+            let result = backend
+                .provider()
+                .create_key(key_id, (backend.key_config)(&config));
+            match result {
+                Ok(()) => {
+                    info!("Created key {} on {}", key_id, backend.name);
+                    self.keys.insert(key_id.to_owned(), index);
+                    self.active = Some(index);
+                    return Ok(());
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to create key {} on {}: {}",
+                        key_id, backend.name, err
+                    );
+                    error = Some(err);
+                }
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            SecurityModuleError::InitializationError(format!(
+                "No backend supports {:?} keys with the usages {:?}",
+                config.key_algorithm, config.key_usages
+            ))
+        }))
+    }
+
+    /// Loads the key `key_id` with the `CompositeKeyConfig` in `config` from the backend
+    /// holding it.
+    #[instrument(skip(config))]
+    fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        let config = Self::config(config)?;
+        let candidates = self.candidates();
+        if let Some(&index) = self.keys.get(key_id) {
+            if !candidates.contains(&index) {
+                return Err(SecurityModuleError::InitializationError(format!(
+                    "Key {} is held by {}, which may not be used",
+                    key_id, self.backends[index].name
+                )));
+            }
+            return self.load_from(index, key_id, &config);
+        }
+        let mut error = None;
+        for index in candidates {
+            match self.load_from(index, key_id, &config) {
+                Ok(()) => return Ok(()),
+                Err(err) => error = Some(err),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            SecurityModuleError::InitializationError("No backend may be used".to_owned())
+        }))
+    }
+
+    /// Initializes the backends, excluding those that fail as long as one succeeds.
+    #[instrument]
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        self.failed.iter_mut().for_each(|failed| *failed = false);
+        let mut error = None;
+        for index in self.candidates() {
+            let backend = &self.backends[index];
+            if let Err(err) = backend.provider().initialize_module() {
+                warn!("Failed to initialize {}: {}", backend.name, err);
+                self.failed[index] = true;
+                error = Some(err);
+            }
+        }
+        if self.candidates().is_empty() {
+            return Err(error.unwrap_or_else(|| {
+                SecurityModuleError::InitializationError("No backend may be used".to_owned())
+            }));
+        }
+        Ok(())
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        self.active()
+            .map(|mut provider| provider.get_pub_key())
+            .unwrap_or_default()
+    }
+
+    fn set_interaction_handler(&mut self, handler: Arc<dyn InteractionHandler>) {
+        for backend in &self.backends {
+            backend.provider().set_interaction_handler(handler.clone());
+        }
+    }
+
+    /// Returns random bytes from the first backend providing them.
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        let mut error = None;
+        for index in self.candidates() {
+            match self.backends[index].provider().random_bytes(len) {
+                Ok(bytes) => return Ok(bytes),
+                Err(err) => error = Some(err),
+            }
+        }
+        Err(error.unwrap_or_else(|| {
+            SecurityModuleError::InitializationError("No backend may be used".to_owned())
+        }))
+    }
+
+    /// Returns the union of the capabilities of the backends that may be used.
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        let mut merged: Option<Capabilities> = None;
+        for index in self.candidates() {
+            let backend = &self.backends[index];
+            match backend.provider().capabilities() {
+                Ok(capabilities) => match merged.as_mut() {
+                    Some(merged) => merge(merged, capabilities),
+                    None => merged = Some(capabilities),
+                },
+                Err(err) => warn!("Capabilities of {} unavailable: {}", backend.name, err),
+            }
+        }
+        Ok(merged.unwrap_or_default())
+    }
+}
+
+/// Adds the algorithms, usages and operations of `other` missing in `capabilities`.
+fn merge(capabilities: &mut Capabilities, other: Capabilities) {
+    fn extend<T: PartialEq>(items: &mut Vec<T>, other: Vec<T>) {
+        for item in other {
+            if !items.contains(&item) {
+                items.push(item);
+            }
+        }
+    }
+
+    for algorithm in other.key_algorithms {
+        match capabilities
+            .key_algorithms
+            .iter_mut()
+            .find(|known| known.algorithm == algorithm.algorithm)
+        {
+            Some(known) => {
+                extend(&mut known.hashes, algorithm.hashes);
+                extend(&mut known.operations, algorithm.operations);
+            }
+            None => capabilities.key_algorithms.push(algorithm),
+        }
+    }
+    extend(&mut capabilities.block_ciphers, other.block_ciphers);
+    extend(&mut capabilities.hashes, other.hashes);
+    extend(&mut capabilities.key_usages, other.key_usages);
+    extend(&mut capabilities.operations, other.operations);
+    // The keys of the backends add up, unless one of them is not limited.
+    capabilities.max_keys = capabilities
+        .max_keys
+        .zip(other.max_keys)
+        .map(|(keys, other)| keys + other);
+}
+
+impl KeyHandle for CompositeProvider {
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        self.active_offers(|provider| provider.as_signer().is_some())
+            .then_some(self)
+    }
+
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        self.active_offers(|provider| provider.as_verifier().is_some())
+            .then_some(self)
+    }
+
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        self.active_offers(|provider| provider.as_encryptor().is_some())
+            .then_some(self)
+    }
+
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        self.active_offers(|provider| provider.as_decryptor().is_some())
+            .then_some(self)
+    }
+
+    fn as_key_agreement(&self) -> Option<&dyn KeyAgreement> {
+        self.active_offers(|provider| provider.as_key_agreement().is_some())
+            .then_some(self)
+    }
+
+    fn mac_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.active()?.mac_data(data)
+    }
+
+    fn verify_mac(&self, data: &[u8], mac: &[u8]) -> Result<bool, SecurityModuleError> {
+        self.active()?.verify_mac(data, mac)
+    }
+}
+
+impl Signer for CompositeProvider {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.active()?.sign_data(data)
+    }
+}
+
+impl Verifier for CompositeProvider {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        self.active()?.verify_signature(data, signature)
+    }
+}
+
+impl Encryptor for CompositeProvider {
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.active()?.encrypt_data(data)
+    }
+}
+
+impl Decryptor for CompositeProvider {
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.active()?.decrypt_data(encrypted_data)
+    }
+}
+
+impl KeyAgreement for CompositeProvider {
+    fn agree(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let provider = self.active()?;
+        let agreement = provider.as_key_agreement().ok_or_else(|| {
+            SecurityModuleError::UnsupportedOperation(
+                "The key does not support key agreement".to_owned(),
+            )
+        })?;
+        agreement.agree(peer_public_key)
+    }
+}
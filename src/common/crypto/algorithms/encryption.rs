@@ -32,7 +32,7 @@ use serde::{Deserialize, Serialize};
 /// facilitating interfacing with C code or when ABI compatibility is required.
 
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AsymmetricEncryption {
     /// RSA encryption with selectable key sizes.
     ///
@@ -227,7 +227,7 @@ pub enum EccCurves {
 /// Marked with `#[repr(C)]` to ensure it has the same memory layout as a C enum,
 /// facilitating ABI compatibility and interfacing with C code.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BlockCiphers {
     /// AES (Advanced Encryption Standard) block cipher with selectable key sizes and modes.
     Aes(SymmetricMode, KeyBits),
@@ -266,7 +266,7 @@ impl Default for BlockCiphers {
 ///
 /// `#[repr(C)]` attribute is used for C compatibility.
 #[repr(C)]
-#[derive(Clone, Debug, Default, Copy)]
+#[derive(Clone, Debug, Default, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SymmetricMode {
     /// AES in Galois/Counter Mode (GCM) with selectable key sizes.
     /// GCM is preferred for its performance and security, providing both encryption and authentication.
@@ -317,7 +317,7 @@ pub enum SymmetricMode {
 ///
 /// Uses `#[repr(C)]` for C language compatibility.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TripleDesNumKeys {
     /// Two-key Triple DES, using two different keys for encryption.
     Tdes2,
@@ -344,7 +344,7 @@ pub enum TripleDesNumKeys {
 ///
 /// Marked with `#[repr(C)]` to ensure compatibility with C-based environments.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Rc2KeyBits {
     /// RC2 with a 40-bit key.
     Rc2_40,
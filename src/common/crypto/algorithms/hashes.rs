@@ -1,3 +1,6 @@
+use openssl::{hash::MessageDigest, nid::Nid};
+use serde::{Deserialize, Serialize};
+
 /// Represents the available hashing algorithms.
 ///
 /// This enum provides a C-compatible representation of various hashing algorithms,
@@ -8,7 +11,7 @@
 /// purposes due to practical collision attacks and should be avoided for new applications.
 /// Prefer using more secure algorithms like SHA-2 or SHA-3 for cryptographic purposes.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Hash {
     /// SHA-1 hashing algorithm.
     ///
@@ -47,6 +50,26 @@ impl Default for Hash {
     }
 }
 
+impl Hash {
+    /// The OpenSSL digest for this hash, `None` for MD2, MD4, MD5, RIPEMD-160 and the
+    /// truncated SHA-512 variants.
+    pub fn message_digest(self) -> Option<MessageDigest> {
+        let nid = match self {
+            Hash::Sha1 => Nid::SHA1,
+            Hash::Sha2(Sha2Bits::Sha224) => Nid::SHA224,
+            Hash::Sha2(Sha2Bits::Sha256) => Nid::SHA256,
+            Hash::Sha2(Sha2Bits::Sha384) => Nid::SHA384,
+            Hash::Sha2(Sha2Bits::Sha512) => Nid::SHA512,
+            Hash::Sha3(Sha3Bits::Sha3_224) => Nid::SHA3_224,
+            Hash::Sha3(Sha3Bits::Sha3_256) => Nid::SHA3_256,
+            Hash::Sha3(Sha3Bits::Sha3_384) => Nid::SHA3_384,
+            Hash::Sha3(Sha3Bits::Sha3_512) => Nid::SHA3_512,
+            _ => return None,
+        };
+        MessageDigest::from_nid(nid)
+    }
+}
+
 /// Specifies the digest sizes for the SHA-2 family of hashing algorithms.
 ///
 /// This enum lists the supported digest sizes for SHA-2, providing a range of options
@@ -69,7 +92,7 @@ impl Default for Hash {
 ///
 /// `#[repr(C)]` attribute is used for C compatibility, facilitating interoperability with C-based systems.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Sha2Bits {
     /// 224-bit digest size.
     Sha224,
@@ -141,7 +164,7 @@ impl From<Sha2Bits> for u32 {
 ///
 /// Uses `#[repr(C)]` for C language compatibility, important for interoperability with C-based systems.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Sha3Bits {
     /// 224-bit digest size for SHA-3.
     Sha3_224,
@@ -1,6 +1,8 @@
 pub mod encryption;
 pub mod hashes;
 
+use serde::{Deserialize, Serialize};
+
 /// Represents the bit length of a cryptographic key.
 ///
 /// This enum defines various key bit lengths commonly used in cryptography.
@@ -20,7 +22,7 @@ pub mod hashes;
 ///
 /// This enum can be converted to and from `u32` values using the `From` trait implementations.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyBits {
     Bits128,
     Bits192,
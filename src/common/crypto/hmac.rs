@@ -0,0 +1,64 @@
+use crate::common::{
+    crypto::algorithms::hashes::{Hash, Sha2Bits},
+    error::SecurityModuleError,
+    traits::key_handle::KeyHandle,
+};
+use ring::{hmac, rand::SystemRandom};
+use tracing::instrument;
+
+/// An HMAC key held in memory.
+///
+/// The software counterpart of the keyed-hash keys of the security modules: it offers the
+/// same `mac_data` and `verify_mac` operations, so code written against `KeyHandle` runs
+/// unchanged on platforms without a TPM or HSM.
+#[derive(Debug)]
+pub struct SoftwareHmacKey {
+    key: hmac::Key,
+}
+
+impl SoftwareHmacKey {
+    /// Creates an HMAC key from existing key material.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash algorithm, one of SHA-1, SHA-256, SHA-384 or SHA-512.
+    /// * `key` - The secret key.
+    pub fn new(hash: Hash, key: &[u8]) -> Result<Self, SecurityModuleError> {
+        Ok(Self {
+            key: hmac::Key::new(algorithm(hash)?, key),
+        })
+    }
+
+    /// Generates a random HMAC key as long as the output of `hash`.
+    pub fn generate(hash: Hash) -> Result<Self, SecurityModuleError> {
+        let key = hmac::Key::generate(algorithm(hash)?, &SystemRandom::new()).map_err(|_| {
+            SecurityModuleError::InitializationError("Failed to generate HMAC key".to_owned())
+        })?;
+        Ok(Self { key })
+    }
+}
+
+impl KeyHandle for SoftwareHmacKey {
+    #[instrument(skip(data))]
+    fn mac_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        Ok(hmac::sign(&self.key, data).as_ref().to_vec())
+    }
+
+    #[instrument(skip(data, mac))]
+    fn verify_mac(&self, data: &[u8], mac: &[u8]) -> Result<bool, SecurityModuleError> {
+        Ok(hmac::verify(&self.key, data, mac).is_ok())
+    }
+}
+
+fn algorithm(hash: Hash) -> Result<hmac::Algorithm, SecurityModuleError> {
+    match hash {
+        Hash::Sha1 => Ok(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY),
+        Hash::Sha2(Sha2Bits::Sha256) => Ok(hmac::HMAC_SHA256),
+        Hash::Sha2(Sha2Bits::Sha384) => Ok(hmac::HMAC_SHA384),
+        Hash::Sha2(Sha2Bits::Sha512) => Ok(hmac::HMAC_SHA512),
+        _ => Err(SecurityModuleError::InitializationError(format!(
+            "{:?} is not supported for HMAC keys",
+            hash
+        ))),
+    }
+}
@@ -1,8 +1,13 @@
 pub mod algorithms;
+pub mod hmac;
 pub mod pkcs;
+pub mod public_key;
+pub mod rng;
+
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
-#[derive(Eq, Hash, PartialEq, Clone, Debug, Copy)]
+#[derive(Eq, Hash, PartialEq, Clone, Debug, Copy, Serialize, Deserialize)]
 pub enum KeyUsage {
     ClientAuth,
     Decrypt,
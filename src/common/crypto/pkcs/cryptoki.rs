@@ -0,0 +1,618 @@
+//! The subset of the PKCS #11 v2.40 C interface used by `Pkcs11Provider` and by the
+//! PKCS #11 module this crate exports.
+//!
+//! Names follow the specification, so they can be looked up there. Structures are packed
+//! on Windows, as required by the specification for that platform.
+#![allow(non_camel_case_types, non_snake_case, dead_code)]
+
+use crate::common::crypto::algorithms::hashes::{Hash, Sha2Bits, Sha3Bits};
+use std::os::raw::{c_uchar, c_ulong, c_void};
+
+pub(crate) type CK_BYTE = c_uchar;
+pub(crate) type CK_BBOOL = c_uchar;
+pub(crate) type CK_ULONG = c_ulong;
+pub(crate) type CK_RV = CK_ULONG;
+pub(crate) type CK_FLAGS = CK_ULONG;
+pub(crate) type CK_SLOT_ID = CK_ULONG;
+pub(crate) type CK_SESSION_HANDLE = CK_ULONG;
+pub(crate) type CK_OBJECT_HANDLE = CK_ULONG;
+pub(crate) type CK_USER_TYPE = CK_ULONG;
+pub(crate) type CK_OBJECT_CLASS = CK_ULONG;
+pub(crate) type CK_KEY_TYPE = CK_ULONG;
+pub(crate) type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub(crate) type CK_MECHANISM_TYPE = CK_ULONG;
+pub(crate) type CK_RSA_PKCS_MGF_TYPE = CK_ULONG;
+pub(crate) type CK_STATE = CK_ULONG;
+pub(crate) type CK_RSA_PKCS_OAEP_SOURCE_TYPE = CK_ULONG;
+
+pub(crate) const CK_TRUE: CK_BBOOL = 1;
+pub(crate) const CK_FALSE: CK_BBOOL = 0;
+
+pub(crate) const CKR_OK: CK_RV = 0x000;
+pub(crate) const CKR_GENERAL_ERROR: CK_RV = 0x005;
+pub(crate) const CKR_SLOT_ID_INVALID: CK_RV = 0x003;
+pub(crate) const CKR_ARGUMENTS_BAD: CK_RV = 0x007;
+pub(crate) const CKR_ATTRIBUTE_SENSITIVE: CK_RV = 0x011;
+pub(crate) const CKR_ATTRIBUTE_TYPE_INVALID: CK_RV = 0x012;
+pub(crate) const CKR_DATA_LEN_RANGE: CK_RV = 0x021;
+pub(crate) const CKR_DEVICE_ERROR: CK_RV = 0x030;
+pub(crate) const CKR_DEVICE_REMOVED: CK_RV = 0x032;
+pub(crate) const CKR_ENCRYPTED_DATA_INVALID: CK_RV = 0x040;
+pub(crate) const CKR_FUNCTION_NOT_SUPPORTED: CK_RV = 0x054;
+pub(crate) const CKR_KEY_HANDLE_INVALID: CK_RV = 0x060;
+pub(crate) const CKR_KEY_TYPE_INCONSISTENT: CK_RV = 0x063;
+pub(crate) const CKR_KEY_FUNCTION_NOT_PERMITTED: CK_RV = 0x068;
+pub(crate) const CKR_MECHANISM_INVALID: CK_RV = 0x070;
+pub(crate) const CKR_MECHANISM_PARAM_INVALID: CK_RV = 0x071;
+pub(crate) const CKR_OBJECT_HANDLE_INVALID: CK_RV = 0x082;
+pub(crate) const CKR_OPERATION_ACTIVE: CK_RV = 0x090;
+pub(crate) const CKR_OPERATION_NOT_INITIALIZED: CK_RV = 0x091;
+pub(crate) const CKR_PIN_INCORRECT: CK_RV = 0x0a0;
+pub(crate) const CKR_PIN_LOCKED: CK_RV = 0x0a4;
+pub(crate) const CKR_SESSION_HANDLE_INVALID: CK_RV = 0x0b3;
+pub(crate) const CKR_SESSION_PARALLEL_NOT_SUPPORTED: CK_RV = 0x0b4;
+pub(crate) const CKR_SIGNATURE_INVALID: CK_RV = 0x0c0;
+pub(crate) const CKR_TOKEN_NOT_PRESENT: CK_RV = 0x0e0;
+pub(crate) const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x100;
+pub(crate) const CKR_USER_NOT_LOGGED_IN: CK_RV = 0x101;
+pub(crate) const CKR_USER_TYPE_INVALID: CK_RV = 0x103;
+pub(crate) const CKR_BUFFER_TOO_SMALL: CK_RV = 0x150;
+pub(crate) const CKR_CRYPTOKI_NOT_INITIALIZED: CK_RV = 0x190;
+pub(crate) const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x191;
+
+pub(crate) const CKF_TOKEN_PRESENT: CK_FLAGS = 0x0001;
+pub(crate) const CKF_HW_SLOT: CK_FLAGS = 0x0004;
+pub(crate) const CKF_RW_SESSION: CK_FLAGS = 0x0002;
+pub(crate) const CKF_SERIAL_SESSION: CK_FLAGS = 0x0004;
+pub(crate) const CKF_OS_LOCKING_OK: CK_FLAGS = 0x0002;
+pub(crate) const CKF_LOGIN_REQUIRED: CK_FLAGS = 0x0004;
+pub(crate) const CKF_USER_PIN_INITIALIZED: CK_FLAGS = 0x0008;
+pub(crate) const CKF_PROTECTED_AUTHENTICATION_PATH: CK_FLAGS = 0x0100;
+pub(crate) const CKF_TOKEN_INITIALIZED: CK_FLAGS = 0x0400;
+pub(crate) const CKF_USER_PIN_FINAL_TRY: CK_FLAGS = 0x0002_0000;
+pub(crate) const CKF_USER_PIN_LOCKED: CK_FLAGS = 0x0004_0000;
+pub(crate) const CKF_RNG: CK_FLAGS = 0x0001;
+pub(crate) const CKF_HW: CK_FLAGS = 0x0001;
+pub(crate) const CKF_ENCRYPT: CK_FLAGS = 0x0100;
+pub(crate) const CKF_DECRYPT: CK_FLAGS = 0x0200;
+pub(crate) const CKF_SIGN: CK_FLAGS = 0x0800;
+pub(crate) const CKF_VERIFY: CK_FLAGS = 0x2000;
+pub(crate) const CKF_GENERATE_KEY_PAIR: CK_FLAGS = 0x0001_0000;
+
+pub(crate) const CKS_RO_PUBLIC_SESSION: CK_STATE = 0;
+pub(crate) const CKS_RO_USER_FUNCTIONS: CK_STATE = 1;
+pub(crate) const CKS_RW_PUBLIC_SESSION: CK_STATE = 2;
+pub(crate) const CKS_RW_USER_FUNCTIONS: CK_STATE = 3;
+
+pub(crate) const CKU_USER: CK_USER_TYPE = 1;
+
+pub(crate) const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 2;
+pub(crate) const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 3;
+
+pub(crate) const CKK_RSA: CK_KEY_TYPE = 0x000;
+pub(crate) const CKK_EC: CK_KEY_TYPE = 0x003;
+
+pub(crate) const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x000;
+pub(crate) const CKA_TOKEN: CK_ATTRIBUTE_TYPE = 0x001;
+pub(crate) const CKA_PRIVATE: CK_ATTRIBUTE_TYPE = 0x002;
+pub(crate) const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x003;
+pub(crate) const CKA_VALUE: CK_ATTRIBUTE_TYPE = 0x011;
+pub(crate) const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x100;
+pub(crate) const CKA_ID: CK_ATTRIBUTE_TYPE = 0x102;
+pub(crate) const CKA_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x103;
+pub(crate) const CKA_ENCRYPT: CK_ATTRIBUTE_TYPE = 0x104;
+pub(crate) const CKA_DECRYPT: CK_ATTRIBUTE_TYPE = 0x105;
+pub(crate) const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x108;
+pub(crate) const CKA_VERIFY: CK_ATTRIBUTE_TYPE = 0x10a;
+pub(crate) const CKA_DERIVE: CK_ATTRIBUTE_TYPE = 0x10c;
+pub(crate) const CKA_MODULUS: CK_ATTRIBUTE_TYPE = 0x120;
+pub(crate) const CKA_MODULUS_BITS: CK_ATTRIBUTE_TYPE = 0x121;
+pub(crate) const CKA_PUBLIC_EXPONENT: CK_ATTRIBUTE_TYPE = 0x122;
+pub(crate) const CKA_PRIVATE_EXPONENT: CK_ATTRIBUTE_TYPE = 0x123;
+pub(crate) const CKA_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x162;
+pub(crate) const CKA_NEVER_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x164;
+pub(crate) const CKA_ALWAYS_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x165;
+pub(crate) const CKA_MODIFIABLE: CK_ATTRIBUTE_TYPE = 0x170;
+pub(crate) const CKA_EC_PARAMS: CK_ATTRIBUTE_TYPE = 0x180;
+pub(crate) const CKA_EC_POINT: CK_ATTRIBUTE_TYPE = 0x181;
+pub(crate) const CKA_ALWAYS_AUTHENTICATE: CK_ATTRIBUTE_TYPE = 0x202;
+
+/// Marks an attribute the token cannot reveal in `C_GetAttributeValue`.
+pub(crate) const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
+pub(crate) const CKM_RSA_PKCS_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x0000;
+pub(crate) const CKM_RSA_PKCS: CK_MECHANISM_TYPE = 0x0001;
+pub(crate) const CKM_SHA1_RSA_PKCS: CK_MECHANISM_TYPE = 0x0006;
+pub(crate) const CKM_RSA_PKCS_OAEP: CK_MECHANISM_TYPE = 0x0009;
+pub(crate) const CKM_SHA1_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x000e;
+pub(crate) const CKM_SHA256_RSA_PKCS: CK_MECHANISM_TYPE = 0x0040;
+pub(crate) const CKM_SHA384_RSA_PKCS: CK_MECHANISM_TYPE = 0x0041;
+pub(crate) const CKM_SHA512_RSA_PKCS: CK_MECHANISM_TYPE = 0x0042;
+pub(crate) const CKM_SHA256_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0043;
+pub(crate) const CKM_SHA384_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0044;
+pub(crate) const CKM_SHA512_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0045;
+pub(crate) const CKM_SHA224_RSA_PKCS: CK_MECHANISM_TYPE = 0x0046;
+pub(crate) const CKM_SHA224_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0047;
+pub(crate) const CKM_SHA3_256_RSA_PKCS: CK_MECHANISM_TYPE = 0x0060;
+pub(crate) const CKM_SHA3_384_RSA_PKCS: CK_MECHANISM_TYPE = 0x0061;
+pub(crate) const CKM_SHA3_512_RSA_PKCS: CK_MECHANISM_TYPE = 0x0062;
+pub(crate) const CKM_SHA3_256_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0063;
+pub(crate) const CKM_SHA3_384_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0064;
+pub(crate) const CKM_SHA3_512_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0065;
+pub(crate) const CKM_SHA3_224_RSA_PKCS: CK_MECHANISM_TYPE = 0x0066;
+pub(crate) const CKM_SHA3_224_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0067;
+pub(crate) const CKM_SHA_1: CK_MECHANISM_TYPE = 0x0220;
+pub(crate) const CKM_SHA256: CK_MECHANISM_TYPE = 0x0250;
+pub(crate) const CKM_SHA224: CK_MECHANISM_TYPE = 0x0255;
+pub(crate) const CKM_SHA384: CK_MECHANISM_TYPE = 0x0260;
+pub(crate) const CKM_SHA512: CK_MECHANISM_TYPE = 0x0270;
+pub(crate) const CKM_SHA3_256: CK_MECHANISM_TYPE = 0x02b0;
+pub(crate) const CKM_SHA3_224: CK_MECHANISM_TYPE = 0x02b5;
+pub(crate) const CKM_SHA3_384: CK_MECHANISM_TYPE = 0x02c0;
+pub(crate) const CKM_SHA3_512: CK_MECHANISM_TYPE = 0x02d0;
+pub(crate) const CKM_EC_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x1040;
+pub(crate) const CKM_ECDSA: CK_MECHANISM_TYPE = 0x1041;
+pub(crate) const CKM_ECDSA_SHA1: CK_MECHANISM_TYPE = 0x1042;
+pub(crate) const CKM_ECDSA_SHA224: CK_MECHANISM_TYPE = 0x1043;
+pub(crate) const CKM_ECDSA_SHA256: CK_MECHANISM_TYPE = 0x1044;
+pub(crate) const CKM_ECDSA_SHA384: CK_MECHANISM_TYPE = 0x1045;
+pub(crate) const CKM_ECDSA_SHA512: CK_MECHANISM_TYPE = 0x1046;
+pub(crate) const CKM_ECDSA_SHA3_224: CK_MECHANISM_TYPE = 0x1047;
+pub(crate) const CKM_ECDSA_SHA3_256: CK_MECHANISM_TYPE = 0x1048;
+pub(crate) const CKM_ECDSA_SHA3_384: CK_MECHANISM_TYPE = 0x1049;
+pub(crate) const CKM_ECDSA_SHA3_512: CK_MECHANISM_TYPE = 0x104a;
+
+pub(crate) const CKZ_DATA_SPECIFIED: CK_RSA_PKCS_OAEP_SOURCE_TYPE = 0x1;
+
+pub(crate) const CKG_MGF1_SHA1: CK_RSA_PKCS_MGF_TYPE = 0x1;
+pub(crate) const CKG_MGF1_SHA256: CK_RSA_PKCS_MGF_TYPE = 0x2;
+pub(crate) const CKG_MGF1_SHA384: CK_RSA_PKCS_MGF_TYPE = 0x3;
+pub(crate) const CKG_MGF1_SHA512: CK_RSA_PKCS_MGF_TYPE = 0x4;
+pub(crate) const CKG_MGF1_SHA224: CK_RSA_PKCS_MGF_TYPE = 0x5;
+pub(crate) const CKG_MGF1_SHA3_224: CK_RSA_PKCS_MGF_TYPE = 0x6;
+pub(crate) const CKG_MGF1_SHA3_256: CK_RSA_PKCS_MGF_TYPE = 0x7;
+pub(crate) const CKG_MGF1_SHA3_384: CK_RSA_PKCS_MGF_TYPE = 0x8;
+pub(crate) const CKG_MGF1_SHA3_512: CK_RSA_PKCS_MGF_TYPE = 0x9;
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct CK_VERSION {
+    pub(crate) major: CK_BYTE,
+    pub(crate) minor: CK_BYTE,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_INFO {
+    pub(crate) cryptokiVersion: CK_VERSION,
+    pub(crate) manufacturerID: [CK_BYTE; 32],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) libraryDescription: [CK_BYTE; 32],
+    pub(crate) libraryVersion: CK_VERSION,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_SLOT_INFO {
+    pub(crate) slotDescription: [CK_BYTE; 64],
+    pub(crate) manufacturerID: [CK_BYTE; 32],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) hardwareVersion: CK_VERSION,
+    pub(crate) firmwareVersion: CK_VERSION,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_TOKEN_INFO {
+    pub(crate) label: [CK_BYTE; 32],
+    pub(crate) manufacturerID: [CK_BYTE; 32],
+    pub(crate) model: [CK_BYTE; 16],
+    pub(crate) serialNumber: [CK_BYTE; 16],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) ulMaxSessionCount: CK_ULONG,
+    pub(crate) ulSessionCount: CK_ULONG,
+    pub(crate) ulMaxRwSessionCount: CK_ULONG,
+    pub(crate) ulRwSessionCount: CK_ULONG,
+    pub(crate) ulMaxPinLen: CK_ULONG,
+    pub(crate) ulMinPinLen: CK_ULONG,
+    pub(crate) ulTotalPublicMemory: CK_ULONG,
+    pub(crate) ulFreePublicMemory: CK_ULONG,
+    pub(crate) ulTotalPrivateMemory: CK_ULONG,
+    pub(crate) ulFreePrivateMemory: CK_ULONG,
+    pub(crate) hardwareVersion: CK_VERSION,
+    pub(crate) firmwareVersion: CK_VERSION,
+    pub(crate) utcTime: [CK_BYTE; 16],
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_SESSION_INFO {
+    pub(crate) slotID: CK_SLOT_ID,
+    pub(crate) state: CK_STATE,
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) ulDeviceError: CK_ULONG,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_MECHANISM_INFO {
+    pub(crate) ulMinKeySize: CK_ULONG,
+    pub(crate) ulMaxKeySize: CK_ULONG,
+    pub(crate) flags: CK_FLAGS,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_ATTRIBUTE {
+    pub(crate) type_: CK_ATTRIBUTE_TYPE,
+    pub(crate) pValue: *mut c_void,
+    pub(crate) ulValueLen: CK_ULONG,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_MECHANISM {
+    pub(crate) mechanism: CK_MECHANISM_TYPE,
+    pub(crate) pParameter: *mut c_void,
+    pub(crate) ulParameterLen: CK_ULONG,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_RSA_PKCS_PSS_PARAMS {
+    pub(crate) hashAlg: CK_MECHANISM_TYPE,
+    pub(crate) mgf: CK_RSA_PKCS_MGF_TYPE,
+    pub(crate) sLen: CK_ULONG,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct CK_RSA_PKCS_OAEP_PARAMS {
+    pub(crate) hashAlg: CK_MECHANISM_TYPE,
+    pub(crate) mgf: CK_RSA_PKCS_MGF_TYPE,
+    pub(crate) source: CK_RSA_PKCS_OAEP_SOURCE_TYPE,
+    pub(crate) pSourceData: *mut c_void,
+    pub(crate) ulSourceDataLen: CK_ULONG,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+pub(crate) struct CK_C_INITIALIZE_ARGS {
+    pub(crate) CreateMutex: *mut c_void,
+    pub(crate) DestroyMutex: *mut c_void,
+    pub(crate) LockMutex: *mut c_void,
+    pub(crate) UnlockMutex: *mut c_void,
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) pReserved: *mut c_void,
+}
+
+/// A function of the list this crate neither calls nor implements; only its size matters.
+pub(crate) type Unused = Option<unsafe extern "C" fn() -> CK_RV>;
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+pub(crate) struct CK_FUNCTION_LIST {
+    pub(crate) version: CK_VERSION,
+    pub(crate) C_Initialize: Option<unsafe extern "C" fn(pInitArgs: *mut c_void) -> CK_RV>,
+    pub(crate) C_Finalize: Option<unsafe extern "C" fn(pReserved: *mut c_void) -> CK_RV>,
+    pub(crate) C_GetInfo: Option<unsafe extern "C" fn(pInfo: *mut CK_INFO) -> CK_RV>,
+    pub(crate) C_GetFunctionList: Option<CK_C_GetFunctionList>,
+    pub(crate) C_GetSlotList: Option<
+        unsafe extern "C" fn(
+            tokenPresent: CK_BBOOL,
+            pSlotList: *mut CK_SLOT_ID,
+            pulCount: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_GetSlotInfo:
+        Option<unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: *mut CK_SLOT_INFO) -> CK_RV>,
+    pub(crate) C_GetTokenInfo:
+        Option<unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: *mut CK_TOKEN_INFO) -> CK_RV>,
+    pub(crate) C_GetMechanismList: Option<
+        unsafe extern "C" fn(
+            slotID: CK_SLOT_ID,
+            pMechanismList: *mut CK_MECHANISM_TYPE,
+            pulCount: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_GetMechanismInfo: Option<
+        unsafe extern "C" fn(
+            slotID: CK_SLOT_ID,
+            type_: CK_MECHANISM_TYPE,
+            pInfo: *mut CK_MECHANISM_INFO,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_InitToken: Unused,
+    pub(crate) C_InitPIN: Unused,
+    pub(crate) C_SetPIN: Unused,
+    pub(crate) C_OpenSession: Option<
+        unsafe extern "C" fn(
+            slotID: CK_SLOT_ID,
+            flags: CK_FLAGS,
+            pApplication: *mut c_void,
+            Notify: *mut c_void,
+            phSession: *mut CK_SESSION_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_CloseSession: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_CloseAllSessions: Option<unsafe extern "C" fn(slotID: CK_SLOT_ID) -> CK_RV>,
+    pub(crate) C_GetSessionInfo: Option<
+        unsafe extern "C" fn(hSession: CK_SESSION_HANDLE, pInfo: *mut CK_SESSION_INFO) -> CK_RV,
+    >,
+    pub(crate) C_GetOperationState: Unused,
+    pub(crate) C_SetOperationState: Unused,
+    pub(crate) C_Login: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            userType: CK_USER_TYPE,
+            pPin: *const CK_BYTE,
+            ulPinLen: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_Logout: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_CreateObject: Unused,
+    pub(crate) C_CopyObject: Unused,
+    pub(crate) C_DestroyObject: Option<
+        unsafe extern "C" fn(hSession: CK_SESSION_HANDLE, hObject: CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_GetObjectSize: Unused,
+    pub(crate) C_GetAttributeValue: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            hObject: CK_OBJECT_HANDLE,
+            pTemplate: *mut CK_ATTRIBUTE,
+            ulCount: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SetAttributeValue: Unused,
+    pub(crate) C_FindObjectsInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pTemplate: *mut CK_ATTRIBUTE,
+            ulCount: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_FindObjects: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            phObject: *mut CK_OBJECT_HANDLE,
+            ulMaxObjectCount: CK_ULONG,
+            pulObjectCount: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_FindObjectsFinal:
+        Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_EncryptInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pMechanism: *mut CK_MECHANISM,
+            hKey: CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_Encrypt: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pData: *const CK_BYTE,
+            ulDataLen: CK_ULONG,
+            pEncryptedData: *mut CK_BYTE,
+            pulEncryptedDataLen: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_EncryptUpdate: Unused,
+    pub(crate) C_EncryptFinal: Unused,
+    pub(crate) C_DecryptInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pMechanism: *mut CK_MECHANISM,
+            hKey: CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_Decrypt: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pEncryptedData: *const CK_BYTE,
+            ulEncryptedDataLen: CK_ULONG,
+            pData: *mut CK_BYTE,
+            pulDataLen: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_DecryptUpdate: Unused,
+    pub(crate) C_DecryptFinal: Unused,
+    pub(crate) C_DigestInit: Unused,
+    pub(crate) C_Digest: Unused,
+    pub(crate) C_DigestUpdate: Unused,
+    pub(crate) C_DigestKey: Unused,
+    pub(crate) C_DigestFinal: Unused,
+    pub(crate) C_SignInit: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pMechanism: *mut CK_MECHANISM,
+            hKey: CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_Sign: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pData: *const CK_BYTE,
+            ulDataLen: CK_ULONG,
+            pSignature: *mut CK_BYTE,
+            pulSignatureLen: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SignUpdate: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pPart: *const CK_BYTE,
+            ulPartLen: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SignFinal: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pSignature: *mut CK_BYTE,
+            pulSignatureLen: *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SignRecoverInit: Unused,
+    pub(crate) C_SignRecover: Unused,
+    pub(crate) C_VerifyInit: Unused,
+    pub(crate) C_Verify: Unused,
+    pub(crate) C_VerifyUpdate: Unused,
+    pub(crate) C_VerifyFinal: Unused,
+    pub(crate) C_VerifyRecoverInit: Unused,
+    pub(crate) C_VerifyRecover: Unused,
+    pub(crate) C_DigestEncryptUpdate: Unused,
+    pub(crate) C_DecryptDigestUpdate: Unused,
+    pub(crate) C_SignEncryptUpdate: Unused,
+    pub(crate) C_DecryptVerifyUpdate: Unused,
+    pub(crate) C_GenerateKey: Unused,
+    pub(crate) C_GenerateKeyPair: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            pMechanism: *mut CK_MECHANISM,
+            pPublicKeyTemplate: *mut CK_ATTRIBUTE,
+            ulPublicKeyAttributeCount: CK_ULONG,
+            pPrivateKeyTemplate: *mut CK_ATTRIBUTE,
+            ulPrivateKeyAttributeCount: CK_ULONG,
+            phPublicKey: *mut CK_OBJECT_HANDLE,
+            phPrivateKey: *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_WrapKey: Unused,
+    pub(crate) C_UnwrapKey: Unused,
+    pub(crate) C_DeriveKey: Unused,
+    pub(crate) C_SeedRandom: Unused,
+    pub(crate) C_GenerateRandom: Option<
+        unsafe extern "C" fn(
+            hSession: CK_SESSION_HANDLE,
+            RandomData: *mut CK_BYTE,
+            ulRandomLen: CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_GetFunctionStatus: Unused,
+    pub(crate) C_CancelFunction: Unused,
+    pub(crate) C_WaitForSlotEvent: Unused,
+}
+
+/// The mechanisms that involve a hash function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HashMechanisms {
+    /// RSA PKCS #1 v1.5 signatures over the hash.
+    pub(crate) rsa_pkcs: CK_MECHANISM_TYPE,
+    /// RSA PSS signatures over the hash.
+    pub(crate) rsa_pss: CK_MECHANISM_TYPE,
+    /// ECDSA signatures over the hash.
+    pub(crate) ecdsa: CK_MECHANISM_TYPE,
+    /// The hash itself, e.g. for `CK_RSA_PKCS_PSS_PARAMS::hashAlg`.
+    pub(crate) digest: CK_MECHANISM_TYPE,
+    /// MGF1 with the hash.
+    pub(crate) mgf: CK_RSA_PKCS_MGF_TYPE,
+}
+
+/// The mechanisms for `hash`, `None` for hashes PKCS #11 has no mechanisms for.
+pub(crate) fn hash_mechanisms(hash: Hash) -> Option<HashMechanisms> {
+    Some(match hash {
+        Hash::Sha1 => HashMechanisms {
+            rsa_pkcs: CKM_SHA1_RSA_PKCS,
+            rsa_pss: CKM_SHA1_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA1,
+            digest: CKM_SHA_1,
+            mgf: CKG_MGF1_SHA1,
+        },
+        Hash::Sha2(Sha2Bits::Sha224) => HashMechanisms {
+            rsa_pkcs: CKM_SHA224_RSA_PKCS,
+            rsa_pss: CKM_SHA224_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA224,
+            digest: CKM_SHA224,
+            mgf: CKG_MGF1_SHA224,
+        },
+        Hash::Sha2(Sha2Bits::Sha256) => HashMechanisms {
+            rsa_pkcs: CKM_SHA256_RSA_PKCS,
+            rsa_pss: CKM_SHA256_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA256,
+            digest: CKM_SHA256,
+            mgf: CKG_MGF1_SHA256,
+        },
+        Hash::Sha2(Sha2Bits::Sha384) => HashMechanisms {
+            rsa_pkcs: CKM_SHA384_RSA_PKCS,
+            rsa_pss: CKM_SHA384_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA384,
+            digest: CKM_SHA384,
+            mgf: CKG_MGF1_SHA384,
+        },
+        Hash::Sha2(Sha2Bits::Sha512) => HashMechanisms {
+            rsa_pkcs: CKM_SHA512_RSA_PKCS,
+            rsa_pss: CKM_SHA512_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA512,
+            digest: CKM_SHA512,
+            mgf: CKG_MGF1_SHA512,
+        },
+        Hash::Sha3(Sha3Bits::Sha3_224) => HashMechanisms {
+            rsa_pkcs: CKM_SHA3_224_RSA_PKCS,
+            rsa_pss: CKM_SHA3_224_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA3_224,
+            digest: CKM_SHA3_224,
+            mgf: CKG_MGF1_SHA3_224,
+        },
+        Hash::Sha3(Sha3Bits::Sha3_256) => HashMechanisms {
+            rsa_pkcs: CKM_SHA3_256_RSA_PKCS,
+            rsa_pss: CKM_SHA3_256_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA3_256,
+            digest: CKM_SHA3_256,
+            mgf: CKG_MGF1_SHA3_256,
+        },
+        Hash::Sha3(Sha3Bits::Sha3_384) => HashMechanisms {
+            rsa_pkcs: CKM_SHA3_384_RSA_PKCS,
+            rsa_pss: CKM_SHA3_384_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA3_384,
+            digest: CKM_SHA3_384,
+            mgf: CKG_MGF1_SHA3_384,
+        },
+        Hash::Sha3(Sha3Bits::Sha3_512) => HashMechanisms {
+            rsa_pkcs: CKM_SHA3_512_RSA_PKCS,
+            rsa_pss: CKM_SHA3_512_RSA_PKCS_PSS,
+            ecdsa: CKM_ECDSA_SHA3_512,
+            digest: CKM_SHA3_512,
+            mgf: CKG_MGF1_SHA3_512,
+        },
+        _ => return None,
+    })
+}
+
+pub(crate) type CK_C_GetFunctionList =
+    unsafe extern "C" fn(ppFunctionList: *mut *const CK_FUNCTION_LIST) -> CK_RV;
+
+/// The name of a return value, for error messages.
+pub(crate) fn rv_name(rv: CK_RV) -> String {
+    match rv {
+        CKR_GENERAL_ERROR => "CKR_GENERAL_ERROR".to_owned(),
+        CKR_ARGUMENTS_BAD => "CKR_ARGUMENTS_BAD".to_owned(),
+        CKR_ATTRIBUTE_SENSITIVE => "CKR_ATTRIBUTE_SENSITIVE".to_owned(),
+        CKR_ATTRIBUTE_TYPE_INVALID => "CKR_ATTRIBUTE_TYPE_INVALID".to_owned(),
+        CKR_DEVICE_ERROR => "CKR_DEVICE_ERROR".to_owned(),
+        CKR_DEVICE_REMOVED => "CKR_DEVICE_REMOVED".to_owned(),
+        CKR_FUNCTION_NOT_SUPPORTED => "CKR_FUNCTION_NOT_SUPPORTED".to_owned(),
+        CKR_KEY_TYPE_INCONSISTENT => "CKR_KEY_TYPE_INCONSISTENT".to_owned(),
+        CKR_MECHANISM_INVALID => "CKR_MECHANISM_INVALID".to_owned(),
+        CKR_OPERATION_NOT_INITIALIZED => "CKR_OPERATION_NOT_INITIALIZED".to_owned(),
+        CKR_PIN_INCORRECT => "CKR_PIN_INCORRECT".to_owned(),
+        CKR_PIN_LOCKED => "CKR_PIN_LOCKED".to_owned(),
+        CKR_SESSION_HANDLE_INVALID => "CKR_SESSION_HANDLE_INVALID".to_owned(),
+        CKR_SIGNATURE_INVALID => "CKR_SIGNATURE_INVALID".to_owned(),
+        CKR_TOKEN_NOT_PRESENT => "CKR_TOKEN_NOT_PRESENT".to_owned(),
+        CKR_USER_NOT_LOGGED_IN => "CKR_USER_NOT_LOGGED_IN".to_owned(),
+        CKR_BUFFER_TOO_SMALL => "CKR_BUFFER_TOO_SMALL".to_owned(),
+        CKR_CRYPTOKI_NOT_INITIALIZED => "CKR_CRYPTOKI_NOT_INITIALIZED".to_owned(),
+        rv => format!("CKR 0x{:08x}", rv),
+    }
+}
@@ -1 +1,5 @@
+#[cfg(any(feature = "pkcs11", feature = "pkcs11-module"))]
+pub(crate) mod cryptoki;
+#[cfg(feature = "pkcs11-module")]
+pub mod module;
 pub mod standards;
@@ -0,0 +1,145 @@
+use crate::common::{
+    crypto::algorithms::{
+        encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+        hashes::{Hash, Sha2Bits},
+    },
+    error::FactoryError,
+    factory::SecurityModule,
+};
+use crate::hsm::RsaPadding;
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::Path};
+
+/// The environment variable naming the configuration file of the module.
+pub const CONFIG_VARIABLE: &str = "CRYPTO_LAYER_PKCS11_CONFIG";
+
+/// The configuration of the PKCS #11 module, read from the JSON file named by
+/// `CRYPTO_LAYER_PKCS11_CONFIG` in `C_Initialize`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ModuleConfig {
+    /// The slots of the module, each with one token.
+    pub slots: Vec<SlotConfig>,
+}
+
+/// A slot of the module, exposing keys of one security module as a token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotConfig {
+    /// The label of the token.
+    pub label: String,
+    /// The security module: `"TPM"`, `"NitroKey"`, `"YubiKey"` or `"YubiKey:<serial>"`.
+    pub module: String,
+    /// The keys of the token.
+    pub keys: Vec<KeyConfig>,
+    /// The management key returned when the security module asks for one, hex-encoded.
+    #[serde(default)]
+    pub management_key: Option<String>,
+}
+
+/// A key of a token, loaded from the security module when the user logs in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyConfig {
+    /// The `key_id` of the key in the security module, also its `CKA_ID` and `CKA_LABEL`.
+    pub id: String,
+    pub algorithm: AsymmetricEncryption,
+    /// The hash of signatures; `None` for SHA-256.
+    #[serde(default)]
+    pub hash: Option<Hash>,
+    /// The padding of signatures made with RSA keys.
+    #[serde(default)]
+    pub rsa_padding: RsaPadding,
+}
+
+impl ModuleConfig {
+    /// Reads the configuration from the file named by `CRYPTO_LAYER_PKCS11_CONFIG`.
+    pub fn from_env() -> Result<Self, String> {
+        let path = env::var_os(CONFIG_VARIABLE)
+            .ok_or_else(|| format!("{} is not set", CONFIG_VARIABLE))?;
+        Self::from_file(Path::new(&path))
+    }
+
+    /// Reads the configuration from the JSON file at `path`.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    /// Parses and validates the configuration in `json`.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let config: Self = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for slot in &config.slots {
+            slot.security_module()?;
+            slot.management_key()?;
+            for key in &slot.keys {
+                key.validate()?;
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl SlotConfig {
+    /// The security module named by `module`.
+    pub fn security_module(&self) -> Result<SecurityModule, String> {
+        self.module.parse().map_err(|e: FactoryError| e.to_string())
+    }
+
+    /// The decoded management key.
+    pub fn management_key(&self) -> Result<Option<Vec<u8>>, String> {
+        self.management_key
+            .as_deref()
+            .map(|key| hex::decode(key).map_err(|e| format!("Invalid management key: {}", e)))
+            .transpose()
+    }
+
+    /// Whether keys of the module decrypt with OAEP instead of PKCS #1 v1.5 padding.
+    pub(super) fn decrypts_with_oaep(&self) -> bool {
+        self.module == "TPM"
+    }
+}
+
+impl KeyConfig {
+    /// The hash of signatures.
+    pub fn signature_hash(&self) -> Hash {
+        self.hash.unwrap_or(Hash::Sha2(Sha2Bits::Sha256))
+    }
+
+    /// The size of the key in bits, as reported in `CK_MECHANISM_INFO`.
+    pub(super) fn key_bits(&self) -> u32 {
+        match self.algorithm {
+            AsymmetricEncryption::Rsa(bits) => bits.into(),
+            AsymmetricEncryption::Ecc(scheme) => match curve(scheme) {
+                Some(EccCurves::P256 | EccCurves::Secp256k1 | EccCurves::BrainpoolP256r1) => 256,
+                Some(EccCurves::Frp256v1) => 256,
+                Some(EccCurves::P384 | EccCurves::BrainpoolP384r1) => 384,
+                Some(EccCurves::BrainpoolP512r1) => 512,
+                Some(EccCurves::P521) => 521,
+                Some(EccCurves::BrainpoolP638) => 638,
+                Some(EccCurves::Curve25519) => 255,
+                Some(EccCurves::Curve448) => 448,
+                None => 0,
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let AsymmetricEncryption::Ecc(scheme) = self.algorithm {
+            if !matches!(scheme, EccSchemeAlgorithm::EcDsa(_)) {
+                return Err(format!("Key {}: only ECDSA keys are supported", self.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn curve(scheme: EccSchemeAlgorithm) -> Option<EccCurves> {
+    match scheme {
+        EccSchemeAlgorithm::EcDsa(curve)
+        | EccSchemeAlgorithm::EcDh(curve)
+        | EccSchemeAlgorithm::EcDaa(curve)
+        | EccSchemeAlgorithm::Sm2(curve)
+        | EccSchemeAlgorithm::EcSchnorr(curve)
+        | EccSchemeAlgorithm::EcMqv(curve) => Some(curve),
+        EccSchemeAlgorithm::Null => None,
+    }
+}
@@ -0,0 +1,821 @@
+//! A PKCS #11 module exposing keys of the configured security modules, with the
+//! `pkcs11-module` feature.
+//!
+//! The crate's shared library exports `C_GetFunctionList`, so it can be loaded by any
+//! PKCS #11 consumer, e.g. OpenSSH, Firefox, OpenSSL's pkcs11 provider or p11-kit:
+//!
+//! ```text
+//! CRYPTO_LAYER_PKCS11_CONFIG=tokens.json pkcs11-tool --module libcrypto_layer.so --login -O
+//! ```
+//!
+//! Each slot of the configuration is a token with keys of one security module:
+//!
+//! ```json
+//! { "slots": [{
+//!     "label": "YubiKey",
+//!     "module": "YubiKey:12345678",
+//!     "keys": [
+//!         { "id": "ssh", "algorithm": { "Ecc": { "EcDsa": "P256" } } },
+//!         { "id": "mail", "algorithm": { "Rsa": "Bits2048" }, "hash": { "Sha2": "Sha384" } }
+//!     ]
+//! }] }
+//! ```
+//!
+//! The keys are loaded in `C_Login` with the PIN, which is handed to the providers for PIN
+//! and password requests. Keys can sign with the combined hash-and-sign mechanism matching
+//! their configured hash and padding, e.g. `CKM_SHA256_RSA_PKCS` or `CKM_ECDSA_SHA256`, and
+//! RSA keys decrypt with `CKM_RSA_PKCS`, or `CKM_RSA_PKCS_OAEP` on a TPM. Signing
+//! pre-computed digests with `CKM_RSA_PKCS` or `CKM_ECDSA` is not supported, since providers
+//! always hash the data they sign.
+#![allow(non_snake_case)]
+
+use crate::common::{crypto::pkcs::cryptoki::*, factory::SecModule};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    os::raw::c_void,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::{Mutex, PoisonError},
+};
+use token::{Mechanism, ProviderFactory, Token};
+use tracing::error;
+
+pub mod config;
+pub(crate) mod token;
+
+pub use config::{KeyConfig, ModuleConfig, SlotConfig, CONFIG_VARIABLE};
+
+/// The state between `C_Initialize` and `C_Finalize`.
+static STATE: Lazy<Mutex<Option<State>>> = Lazy::new(|| Mutex::new(None));
+
+/// Creates the providers of tokens initialized afterwards.
+static PROVIDER_FACTORY: Mutex<ProviderFactory> =
+    Mutex::new(SecModule::create_instance as ProviderFactory);
+
+const MANUFACTURER: &str = "crypto-layer";
+
+struct State {
+    tokens: Vec<Token>,
+    sessions: HashMap<CK_SESSION_HANDLE, Session>,
+    next_session: CK_SESSION_HANDLE,
+    factory: ProviderFactory,
+}
+
+struct Session {
+    slot: usize,
+    flags: CK_FLAGS,
+    /// The objects left to return from `C_FindObjects`.
+    search: Option<Vec<CK_OBJECT_HANDLE>>,
+    operation: Option<Operation>,
+}
+
+enum Operation {
+    /// Signing with the key of the given index, with the data passed to `C_SignUpdate`.
+    Sign { key: usize, data: Vec<u8> },
+    /// Decrypting with the key of the given index.
+    Decrypt { key: usize },
+}
+
+impl State {
+    fn session(&mut self, handle: CK_SESSION_HANDLE) -> Result<(&mut Session, &mut Token), CK_RV> {
+        let session = self
+            .sessions
+            .get_mut(&handle)
+            .ok_or(CKR_SESSION_HANDLE_INVALID)?;
+        let token = &mut self.tokens[session.slot];
+        Ok((session, token))
+    }
+
+    fn token(&mut self, slot: CK_SLOT_ID) -> Result<&mut Token, CK_RV> {
+        self.tokens
+            .get_mut(slot as usize)
+            .ok_or(CKR_SLOT_ID_INVALID)
+    }
+
+    /// Logs out of the token in `slot` and ends the operations of its sessions.
+    fn logout(&mut self, slot: usize) {
+        self.tokens[slot].logout();
+        for session in self.sessions.values_mut().filter(|s| s.slot == slot) {
+            session.search = None;
+            session.operation = None;
+        }
+    }
+}
+
+/// Replaces the providers of tokens initialized afterwards, so tests can run without devices.
+#[cfg(all(test, feature = "hsm"))]
+pub(crate) fn set_provider_factory(factory: ProviderFactory) {
+    *PROVIDER_FACTORY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = factory;
+}
+
+/// Runs `f`, turning panics into `CKR_GENERAL_ERROR` so they do not unwind into C.
+fn guard(f: impl FnOnce() -> Result<(), CK_RV>) -> CK_RV {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => CKR_OK,
+        Ok(Err(rv)) => rv,
+        Err(_) => CKR_GENERAL_ERROR,
+    }
+}
+
+/// Runs `f` with the state of the initialized module.
+fn with_state(f: impl FnOnce(&mut State) -> Result<(), CK_RV>) -> CK_RV {
+    guard(|| {
+        let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        f(state.as_mut().ok_or(CKR_CRYPTOKI_NOT_INITIALIZED)?)
+    })
+}
+
+/// Writes `items` to `list` with the usual two-call convention: only the count is
+/// returned when `list` is null.
+unsafe fn write_list<T: Copy>(
+    items: &[T],
+    list: *mut T,
+    count: *mut CK_ULONG,
+) -> Result<(), CK_RV> {
+    let count = count.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+    if !list.is_null() {
+        if (*count as usize) < items.len() {
+            *count = items.len() as CK_ULONG;
+            return Err(CKR_BUFFER_TOO_SMALL);
+        }
+        ptr::copy_nonoverlapping(items.as_ptr(), list, items.len());
+    }
+    *count = items.len() as CK_ULONG;
+    Ok(())
+}
+
+unsafe fn input<'a, T>(data: *const T, len: CK_ULONG) -> Result<&'a [T], CK_RV> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(CKR_ARGUMENTS_BAD),
+        (false, len) => Ok(slice::from_raw_parts(data, len as usize)),
+    }
+}
+
+/// `value` padded with blanks, as in the string fields of the info structures.
+fn padded<const N: usize>(value: &str) -> [CK_BYTE; N] {
+    let mut field = [b' '; N];
+    let mut len = value.len().min(N);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+    field
+}
+
+fn library_version() -> CK_VERSION {
+    CK_VERSION {
+        major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+        minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+    }
+}
+
+/// Returns the functions of the module.
+///
+/// # Safety
+///
+/// `ppFunctionList` must be null or valid for writes.
+#[no_mangle]
+pub(crate) unsafe extern "C" fn C_GetFunctionList(
+    ppFunctionList: *mut *const CK_FUNCTION_LIST,
+) -> CK_RV {
+    match ppFunctionList.as_mut() {
+        Some(list) => {
+            *list = &FUNCTION_LIST;
+            CKR_OK
+        }
+        None => CKR_ARGUMENTS_BAD,
+    }
+}
+
+unsafe extern "C" fn C_Initialize(pInitArgs: *mut c_void) -> CK_RV {
+    guard(|| {
+        if let Some(args) = (pInitArgs as *const CK_C_INITIALIZE_ARGS).as_ref() {
+            let mutex_functions = [
+                args.CreateMutex,
+                args.DestroyMutex,
+                args.LockMutex,
+                args.UnlockMutex,
+            ];
+            let given = mutex_functions.iter().filter(|f| !f.is_null()).count();
+            let reserved = args.pReserved;
+            if !reserved.is_null() || (given != 0 && given != mutex_functions.len()) {
+                return Err(CKR_ARGUMENTS_BAD);
+            }
+        }
+        let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.is_some() {
+            return Err(CKR_CRYPTOKI_ALREADY_INITIALIZED);
+        }
+        let tokens = ModuleConfig::from_env()
+            .and_then(|config| config.slots.into_iter().map(Token::new).collect())
+            .map_err(|e| {
+                error!("C_Initialize: {}", e);
+                CKR_GENERAL_ERROR
+            })?;
+        *state = Some(State {
+            tokens,
+            sessions: HashMap::new(),
+            next_session: 1,
+            factory: *PROVIDER_FACTORY
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        });
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_Finalize(pReserved: *mut c_void) -> CK_RV {
+    guard(|| {
+        if !pReserved.is_null() {
+            return Err(CKR_ARGUMENTS_BAD);
+        }
+        let mut state = STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        state.take().ok_or(CKR_CRYPTOKI_NOT_INITIALIZED)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetInfo(pInfo: *mut CK_INFO) -> CK_RV {
+    with_state(|_| {
+        *pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)? = CK_INFO {
+            cryptokiVersion: CK_VERSION {
+                major: 2,
+                minor: 40,
+            },
+            manufacturerID: padded(MANUFACTURER),
+            flags: 0,
+            libraryDescription: padded("crypto-layer PKCS #11 module"),
+            libraryVersion: library_version(),
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetSlotList(
+    _tokenPresent: CK_BBOOL,
+    pSlotList: *mut CK_SLOT_ID,
+    pulCount: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let slots: Vec<CK_SLOT_ID> = (0..state.tokens.len() as CK_SLOT_ID).collect();
+        write_list(&slots, pSlotList, pulCount)
+    })
+}
+
+unsafe extern "C" fn C_GetSlotInfo(slotID: CK_SLOT_ID, pInfo: *mut CK_SLOT_INFO) -> CK_RV {
+    with_state(|state| {
+        let token = state.token(slotID)?;
+        *pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)? = CK_SLOT_INFO {
+            slotDescription: padded(&token.config.label),
+            manufacturerID: padded(MANUFACTURER),
+            flags: CKF_TOKEN_PRESENT | CKF_HW_SLOT,
+            hardwareVersion: CK_VERSION::default(),
+            firmwareVersion: CK_VERSION::default(),
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetTokenInfo(slotID: CK_SLOT_ID, pInfo: *mut CK_TOKEN_INFO) -> CK_RV {
+    with_state(|state| {
+        let token = state.token(slotID)?;
+        *pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)? = CK_TOKEN_INFO {
+            label: padded(&token.config.label),
+            manufacturerID: padded(MANUFACTURER),
+            model: padded(&token.config.module),
+            serialNumber: padded(&slotID.to_string()),
+            flags: CKF_TOKEN_INITIALIZED | CKF_USER_PIN_INITIALIZED | CKF_LOGIN_REQUIRED,
+            ulMaxSessionCount: CK_UNAVAILABLE_INFORMATION,
+            ulSessionCount: CK_UNAVAILABLE_INFORMATION,
+            ulMaxRwSessionCount: CK_UNAVAILABLE_INFORMATION,
+            ulRwSessionCount: CK_UNAVAILABLE_INFORMATION,
+            ulMaxPinLen: 255,
+            ulMinPinLen: 0,
+            ulTotalPublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulTotalPrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            hardwareVersion: CK_VERSION::default(),
+            firmwareVersion: CK_VERSION::default(),
+            utcTime: padded(""),
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetMechanismList(
+    slotID: CK_SLOT_ID,
+    pMechanismList: *mut CK_MECHANISM_TYPE,
+    pulCount: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| write_list(&state.token(slotID)?.mechanisms(), pMechanismList, pulCount))
+}
+
+unsafe extern "C" fn C_GetMechanismInfo(
+    slotID: CK_SLOT_ID,
+    type_: CK_MECHANISM_TYPE,
+    pInfo: *mut CK_MECHANISM_INFO,
+) -> CK_RV {
+    with_state(|state| {
+        let info = state
+            .token(slotID)?
+            .mechanism_info(type_)
+            .ok_or(CKR_MECHANISM_INVALID)?;
+        *pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)? = info;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_OpenSession(
+    slotID: CK_SLOT_ID,
+    flags: CK_FLAGS,
+    _pApplication: *mut c_void,
+    _Notify: *mut c_void,
+    phSession: *mut CK_SESSION_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        state.token(slotID)?;
+        if flags & CKF_SERIAL_SESSION == 0 {
+            return Err(CKR_SESSION_PARALLEL_NOT_SUPPORTED);
+        }
+        let handle_out = phSession.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+        let handle = state.next_session;
+        state.next_session += 1;
+        state.sessions.insert(
+            handle,
+            Session {
+                slot: slotID as usize,
+                flags,
+                search: None,
+                operation: None,
+            },
+        );
+        *handle_out = handle;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_CloseSession(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        let session = state
+            .sessions
+            .remove(&hSession)
+            .ok_or(CKR_SESSION_HANDLE_INVALID)?;
+        if !state.sessions.values().any(|s| s.slot == session.slot) {
+            state.logout(session.slot);
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_CloseAllSessions(slotID: CK_SLOT_ID) -> CK_RV {
+    with_state(|state| {
+        state.token(slotID)?;
+        let slot = slotID as usize;
+        state.sessions.retain(|_, session| session.slot != slot);
+        state.logout(slot);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetSessionInfo(
+    hSession: CK_SESSION_HANDLE,
+    pInfo: *mut CK_SESSION_INFO,
+) -> CK_RV {
+    with_state(|state| {
+        let (session, token) = state.session(hSession)?;
+        let rw = session.flags & CKF_RW_SESSION != 0;
+        *pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)? = CK_SESSION_INFO {
+            slotID: session.slot as CK_SLOT_ID,
+            state: match (rw, token.logged_in()) {
+                (false, false) => CKS_RO_PUBLIC_SESSION,
+                (false, true) => CKS_RO_USER_FUNCTIONS,
+                (true, false) => CKS_RW_PUBLIC_SESSION,
+                (true, true) => CKS_RW_USER_FUNCTIONS,
+            },
+            flags: session.flags,
+            ulDeviceError: 0,
+        };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_Login(
+    hSession: CK_SESSION_HANDLE,
+    userType: CK_USER_TYPE,
+    pPin: *const CK_BYTE,
+    ulPinLen: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let factory = state.factory;
+        let (_, token) = state.session(hSession)?;
+        if userType != CKU_USER {
+            return Err(CKR_USER_TYPE_INVALID);
+        }
+        if token.logged_in() {
+            return Err(CKR_USER_ALREADY_LOGGED_IN);
+        }
+        token.login(input(pPin, ulPinLen)?, factory)
+    })
+}
+
+unsafe extern "C" fn C_Logout(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        let (session, token) = state.session(hSession)?;
+        if !token.logged_in() {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+        let slot = session.slot;
+        state.logout(slot);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_GetAttributeValue(
+    hSession: CK_SESSION_HANDLE,
+    hObject: CK_OBJECT_HANDLE,
+    pTemplate: *mut CK_ATTRIBUTE,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let (_, token) = state.session(hSession)?;
+        if !token.objects().contains(&hObject) {
+            return Err(CKR_OBJECT_HANDLE_INVALID);
+        }
+        if pTemplate.is_null() && ulCount != 0 {
+            return Err(CKR_ARGUMENTS_BAD);
+        }
+        let template = match ulCount {
+            0 => &mut [],
+            count => slice::from_raw_parts_mut(pTemplate, count as usize),
+        };
+        // Every attribute is processed; the last error is returned.
+        let mut result = Ok(());
+        for attribute in template.iter_mut() {
+            // Fields are copied first, since the structure is packed on Windows.
+            let (type_, buffer, buffer_len) = (
+                attribute.type_,
+                attribute.pValue as *mut u8,
+                attribute.ulValueLen,
+            );
+            match token.attribute(hObject, type_) {
+                Ok(value) if buffer.is_null() => {
+                    attribute.ulValueLen = value.len() as CK_ULONG;
+                }
+                Ok(value) if (buffer_len as usize) < value.len() => {
+                    attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                    result = Err(CKR_BUFFER_TOO_SMALL);
+                }
+                Ok(value) => {
+                    ptr::copy_nonoverlapping(value.as_ptr(), buffer, value.len());
+                    attribute.ulValueLen = value.len() as CK_ULONG;
+                }
+                Err(rv) => {
+                    attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                    result = Err(rv);
+                }
+            }
+        }
+        result
+    })
+}
+
+unsafe extern "C" fn C_FindObjectsInit(
+    hSession: CK_SESSION_HANDLE,
+    pTemplate: *mut CK_ATTRIBUTE,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let (session, token) = state.session(hSession)?;
+        if session.search.is_some() {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        let template = input(pTemplate, ulCount)?
+            .iter()
+            .map(|attribute| {
+                let value = input(attribute.pValue as *const u8, attribute.ulValueLen)?;
+                Ok((attribute.type_, value))
+            })
+            .collect::<Result<Vec<_>, CK_RV>>()?;
+        let objects = token
+            .objects()
+            .into_iter()
+            .filter(|object| {
+                template.iter().all(|(type_, value)| {
+                    matches!(token.attribute(*object, *type_), Ok(v) if v == *value)
+                })
+            })
+            .collect();
+        session.search = Some(objects);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_FindObjects(
+    hSession: CK_SESSION_HANDLE,
+    phObject: *mut CK_OBJECT_HANDLE,
+    ulMaxObjectCount: CK_ULONG,
+    pulObjectCount: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let (session, _) = state.session(hSession)?;
+        let search = session
+            .search
+            .as_mut()
+            .ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        let count = pulObjectCount.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+        if phObject.is_null() && ulMaxObjectCount != 0 {
+            return Err(CKR_ARGUMENTS_BAD);
+        }
+        let found: Vec<CK_OBJECT_HANDLE> = search
+            .drain(..search.len().min(ulMaxObjectCount as usize))
+            .collect();
+        if !found.is_empty() {
+            ptr::copy_nonoverlapping(found.as_ptr(), phObject, found.len());
+        }
+        *count = found.len() as CK_ULONG;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_FindObjectsFinal(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        let (session, _) = state.session(hSession)?;
+        session.search.take().ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_SignInit(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: *mut CK_MECHANISM,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        let (session, token) = state.session(hSession)?;
+        if session.operation.is_some() {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        if !token.logged_in() {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+        let key = token.sign_init(hKey, &Mechanism::from_raw(pMechanism)?)?;
+        session.operation = Some(Operation::Sign {
+            key,
+            data: Vec::new(),
+        });
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_Sign(
+    hSession: CK_SESSION_HANDLE,
+    pData: *const CK_BYTE,
+    ulDataLen: CK_ULONG,
+    pSignature: *mut CK_BYTE,
+    pulSignatureLen: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let data = input(pData, ulDataLen)?;
+        sign(state, hSession, Some(data), pSignature, pulSignatureLen)
+    })
+}
+
+unsafe extern "C" fn C_SignUpdate(
+    hSession: CK_SESSION_HANDLE,
+    pPart: *const CK_BYTE,
+    ulPartLen: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let part = input(pPart, ulPartLen)?;
+        let (session, _) = state.session(hSession)?;
+        match session.operation.as_mut() {
+            Some(Operation::Sign { data, .. }) => {
+                data.extend_from_slice(part);
+                Ok(())
+            }
+            _ => Err(CKR_OPERATION_NOT_INITIALIZED),
+        }
+    })
+}
+
+unsafe extern "C" fn C_SignFinal(
+    hSession: CK_SESSION_HANDLE,
+    pSignature: *mut CK_BYTE,
+    pulSignatureLen: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| sign(state, hSession, None, pSignature, pulSignatureLen))
+}
+
+/// Finishes the signing operation of `session`, over `data` or the data passed to
+/// `C_SignUpdate`.
+///
+/// The operation stays active if only the length of the signature is requested.
+unsafe fn sign(
+    state: &mut State,
+    session: CK_SESSION_HANDLE,
+    data: Option<&[u8]>,
+    signature: *mut CK_BYTE,
+    signature_len: *mut CK_ULONG,
+) -> Result<(), CK_RV> {
+    let (session, token) = state.session(session)?;
+    let (key, buffered) = match session.operation.as_ref() {
+        Some(Operation::Sign { key, data }) => (*key, data),
+        _ => return Err(CKR_OPERATION_NOT_INITIALIZED),
+    };
+    let len = signature_len.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+    let expected = token.signature_len(key)?;
+    if signature.is_null() {
+        *len = expected as CK_ULONG;
+        return Ok(());
+    }
+    if (*len as usize) < expected {
+        *len = expected as CK_ULONG;
+        return Err(CKR_BUFFER_TOO_SMALL);
+    }
+    let result = token.sign(key, data.unwrap_or(buffered.as_slice()));
+    session.operation = None;
+    write_list(&result?, signature, len)
+}
+
+unsafe extern "C" fn C_DecryptInit(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: *mut CK_MECHANISM,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        let (session, token) = state.session(hSession)?;
+        if session.operation.is_some() {
+            return Err(CKR_OPERATION_ACTIVE);
+        }
+        if !token.logged_in() {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+        let key = token.decrypt_init(hKey, &Mechanism::from_raw(pMechanism)?)?;
+        session.operation = Some(Operation::Decrypt { key });
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn C_Decrypt(
+    hSession: CK_SESSION_HANDLE,
+    pEncryptedData: *const CK_BYTE,
+    ulEncryptedDataLen: CK_ULONG,
+    pData: *mut CK_BYTE,
+    pulDataLen: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let encrypted = input(pEncryptedData, ulEncryptedDataLen)?;
+        let (session, token) = state.session(hSession)?;
+        let key = match session.operation {
+            Some(Operation::Decrypt { key }) => key,
+            _ => return Err(CKR_OPERATION_NOT_INITIALIZED),
+        };
+        let len = pulDataLen.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+        if pData.is_null() {
+            *len = token.decrypted_len(key)? as CK_ULONG;
+            return Ok(());
+        }
+        let result = token.decrypt(key, encrypted);
+        match write_list(&result?, pData, len) {
+            // The caller retries with a larger buffer.
+            Err(CKR_BUFFER_TOO_SMALL) => Err(CKR_BUFFER_TOO_SMALL),
+            result => {
+                session.operation = None;
+                result
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn C_DestroyObject(
+    _hSession: CK_SESSION_HANDLE,
+    _hObject: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+unsafe extern "C" fn C_EncryptInit(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: *mut CK_MECHANISM,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+unsafe extern "C" fn C_Encrypt(
+    _hSession: CK_SESSION_HANDLE,
+    _pData: *const CK_BYTE,
+    _ulDataLen: CK_ULONG,
+    _pEncryptedData: *mut CK_BYTE,
+    _pulEncryptedDataLen: *mut CK_ULONG,
+) -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn C_GenerateKeyPair(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: *mut CK_MECHANISM,
+    _pPublicKeyTemplate: *mut CK_ATTRIBUTE,
+    _ulPublicKeyAttributeCount: CK_ULONG,
+    _pPrivateKeyTemplate: *mut CK_ATTRIBUTE,
+    _ulPrivateKeyAttributeCount: CK_ULONG,
+    _phPublicKey: *mut CK_OBJECT_HANDLE,
+    _phPrivateKey: *mut CK_OBJECT_HANDLE,
+) -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+unsafe extern "C" fn C_GenerateRandom(
+    _hSession: CK_SESSION_HANDLE,
+    _RandomData: *mut CK_BYTE,
+    _ulRandomLen: CK_ULONG,
+) -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+/// The entry for the other functions of the list. Their arguments are ignored, which is
+/// harmless with the C calling convention, where the caller removes them.
+unsafe extern "C" fn not_supported() -> CK_RV {
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+static FUNCTION_LIST: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+    version: CK_VERSION {
+        major: 2,
+        minor: 40,
+    },
+    C_Initialize: Some(C_Initialize),
+    C_Finalize: Some(C_Finalize),
+    C_GetInfo: Some(C_GetInfo),
+    C_GetFunctionList: Some(C_GetFunctionList),
+    C_GetSlotList: Some(C_GetSlotList),
+    C_GetSlotInfo: Some(C_GetSlotInfo),
+    C_GetTokenInfo: Some(C_GetTokenInfo),
+    C_GetMechanismList: Some(C_GetMechanismList),
+    C_GetMechanismInfo: Some(C_GetMechanismInfo),
+    C_InitToken: Some(not_supported),
+    C_InitPIN: Some(not_supported),
+    C_SetPIN: Some(not_supported),
+    C_OpenSession: Some(C_OpenSession),
+    C_CloseSession: Some(C_CloseSession),
+    C_CloseAllSessions: Some(C_CloseAllSessions),
+    C_GetSessionInfo: Some(C_GetSessionInfo),
+    C_GetOperationState: Some(not_supported),
+    C_SetOperationState: Some(not_supported),
+    C_Login: Some(C_Login),
+    C_Logout: Some(C_Logout),
+    C_CreateObject: Some(not_supported),
+    C_CopyObject: Some(not_supported),
+    C_DestroyObject: Some(C_DestroyObject),
+    C_GetObjectSize: Some(not_supported),
+    C_GetAttributeValue: Some(C_GetAttributeValue),
+    C_SetAttributeValue: Some(not_supported),
+    C_FindObjectsInit: Some(C_FindObjectsInit),
+    C_FindObjects: Some(C_FindObjects),
+    C_FindObjectsFinal: Some(C_FindObjectsFinal),
+    C_EncryptInit: Some(C_EncryptInit),
+    C_Encrypt: Some(C_Encrypt),
+    C_EncryptUpdate: Some(not_supported),
+    C_EncryptFinal: Some(not_supported),
+    C_DecryptInit: Some(C_DecryptInit),
+    C_Decrypt: Some(C_Decrypt),
+    C_DecryptUpdate: Some(not_supported),
+    C_DecryptFinal: Some(not_supported),
+    C_DigestInit: Some(not_supported),
+    C_Digest: Some(not_supported),
+    C_DigestUpdate: Some(not_supported),
+    C_DigestKey: Some(not_supported),
+    C_DigestFinal: Some(not_supported),
+    C_SignInit: Some(C_SignInit),
+    C_Sign: Some(C_Sign),
+    C_SignUpdate: Some(C_SignUpdate),
+    C_SignFinal: Some(C_SignFinal),
+    C_SignRecoverInit: Some(not_supported),
+    C_SignRecover: Some(not_supported),
+    C_VerifyInit: Some(not_supported),
+    C_Verify: Some(not_supported),
+    C_VerifyUpdate: Some(not_supported),
+    C_VerifyFinal: Some(not_supported),
+    C_VerifyRecoverInit: Some(not_supported),
+    C_VerifyRecover: Some(not_supported),
+    C_DigestEncryptUpdate: Some(not_supported),
+    C_DecryptDigestUpdate: Some(not_supported),
+    C_SignEncryptUpdate: Some(not_supported),
+    C_DecryptVerifyUpdate: Some(not_supported),
+    C_GenerateKey: Some(not_supported),
+    C_GenerateKeyPair: Some(C_GenerateKeyPair),
+    C_WrapKey: Some(not_supported),
+    C_UnwrapKey: Some(not_supported),
+    C_DeriveKey: Some(not_supported),
+    C_SeedRandom: Some(not_supported),
+    C_GenerateRandom: Some(C_GenerateRandom),
+    C_GetFunctionStatus: Some(not_supported),
+    C_CancelFunction: Some(not_supported),
+    C_WaitForSlotEvent: Some(not_supported),
+};
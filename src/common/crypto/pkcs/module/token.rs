@@ -0,0 +1,565 @@
+use super::config::{KeyConfig, SlotConfig};
+#[cfg(feature = "hsm")]
+use crate::hsm::{core::error::HsmError, HsmProviderConfig};
+#[cfg(feature = "plugin")]
+use crate::plugin::PluginKeyConfig;
+#[cfg(feature = "tpm")]
+use crate::{
+    common::crypto::algorithms::{
+        encryption::{BlockCiphers, SymmetricMode},
+        KeyBits,
+    },
+    tpm::TpmConfig,
+};
+use crate::{
+    common::{
+        crypto::{algorithms::encryption::AsymmetricEncryption, pkcs::cryptoki::*},
+        error::{FactoryError, SecurityModuleError},
+        factory::SecurityModule,
+        traits::{
+            interaction::{CredentialKind, CredentialRequest, InteractionHandler},
+            module_provider::Provider,
+        },
+    },
+    hsm::RsaPadding,
+};
+use openssl::{
+    asn1::Asn1Object,
+    bn::BigNumContext,
+    ec::PointConversionForm,
+    ecdsa::EcdsaSig,
+    pkey::{Id, PKey, Public},
+    rsa::Rsa,
+};
+use std::{
+    any::Any,
+    fmt,
+    mem::size_of,
+    ptr,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tracing::warn;
+
+/// Creates the provider of a key, `SecModule::create_instance` unless replaced in tests.
+pub(crate) type ProviderFactory =
+    fn(key_id: String, module: &SecurityModule) -> Result<Arc<Mutex<dyn Provider>>, FactoryError>;
+
+/// A mechanism passed to `C_SignInit` or `C_DecryptInit`, with a copy of its parameter.
+pub(super) struct Mechanism {
+    pub(super) type_: CK_MECHANISM_TYPE,
+    parameter: Vec<u8>,
+}
+
+impl Mechanism {
+    /// Copies the mechanism at `mechanism`.
+    ///
+    /// # Safety
+    ///
+    /// `mechanism` must be null or point to a valid `CK_MECHANISM`.
+    pub(super) unsafe fn from_raw(mechanism: *const CK_MECHANISM) -> Result<Self, CK_RV> {
+        let mechanism = mechanism.as_ref().ok_or(CKR_ARGUMENTS_BAD)?;
+        let (type_, parameter, parameter_len) = (
+            mechanism.mechanism,
+            mechanism.pParameter as *const u8,
+            mechanism.ulParameterLen,
+        );
+        let parameter = if parameter.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(parameter, parameter_len as usize).to_vec()
+        };
+        Ok(Self { type_, parameter })
+    }
+
+    fn pss_parameter(&self) -> Option<CK_RSA_PKCS_PSS_PARAMS> {
+        // SAFETY: the length is checked, and the structure consists of integers only.
+        (self.parameter.len() == size_of::<CK_RSA_PKCS_PSS_PARAMS>())
+            .then(|| unsafe { ptr::read_unaligned(self.parameter.as_ptr().cast()) })
+    }
+
+    fn oaep_parameter(&self) -> Option<CK_RSA_PKCS_OAEP_PARAMS> {
+        // SAFETY: the length is checked, and the structure consists of integers and a
+        // pointer that is never dereferenced.
+        (self.parameter.len() == size_of::<CK_RSA_PKCS_OAEP_PARAMS>())
+            .then(|| unsafe { ptr::read_unaligned(self.parameter.as_ptr().cast()) })
+    }
+}
+
+/// The token of a slot, exposing the configured keys of a security module.
+///
+/// Keys are loaded when the user logs in, so the token has no objects before. Each key
+/// is a public key object with handle `2 * index + 1` and a private key object with
+/// handle `2 * index + 2`.
+pub(super) struct Token {
+    pub(super) config: SlotConfig,
+    module: SecurityModule,
+    keys: Vec<Key>,
+    logged_in: bool,
+}
+
+/// A key loaded from the security module.
+struct Key {
+    config: KeyConfig,
+    provider: Arc<Mutex<dyn Provider>>,
+    public: PKey<Public>,
+}
+
+impl Token {
+    pub(super) fn new(config: SlotConfig) -> Result<Self, String> {
+        Ok(Self {
+            module: config.security_module()?,
+            config,
+            keys: Vec::new(),
+            logged_in: false,
+        })
+    }
+
+    pub(super) fn logged_in(&self) -> bool {
+        self.logged_in
+    }
+
+    /// Loads the configured keys, answering requests for the PIN with `pin`.
+    ///
+    /// Keys that cannot be loaded are skipped, so one missing key does not hide the others.
+    pub(super) fn login(&mut self, pin: &[u8], factory: ProviderFactory) -> Result<(), CK_RV> {
+        let handler: Arc<dyn InteractionHandler> = Arc::new(LoginCredentials {
+            pin: pin.to_vec(),
+            management_key: self.config.management_key().ok().flatten(),
+        });
+        let mut keys = Vec::new();
+        for config in &self.config.keys {
+            let provider = factory(config.id.clone(), &self.module)
+                .map_err(|e| error_rv("C_Login", &e.into()))?;
+            let public = {
+                let mut instance = provider.lock().unwrap_or_else(PoisonError::into_inner);
+                instance.set_interaction_handler(handler.clone());
+                instance
+                    .initialize_module()
+                    .map_err(|e| error_rv("C_Login", &e))?;
+                if let Err(e) = instance.load_key(&config.id, provider_config(&self.module, config))
+                {
+                    match error_rv("C_Login", &e) {
+                        rv @ (CKR_PIN_INCORRECT | CKR_PIN_LOCKED) => return Err(rv),
+                        _ => continue,
+                    }
+                }
+                match public_key(&instance.get_pub_key()) {
+                    Some(public) => public,
+                    None => {
+                        warn!("No public key for {}, skipping it", config.id);
+                        continue;
+                    }
+                }
+            };
+            keys.push(Key {
+                config: config.clone(),
+                provider,
+                public,
+            });
+        }
+        self.keys = keys;
+        self.logged_in = true;
+        Ok(())
+    }
+
+    /// Drops the loaded keys.
+    pub(super) fn logout(&mut self) {
+        self.keys.clear();
+        self.logged_in = false;
+    }
+
+    /// The handles of all objects.
+    pub(super) fn objects(&self) -> Vec<CK_OBJECT_HANDLE> {
+        (1..=self.keys.len() as CK_OBJECT_HANDLE * 2).collect()
+    }
+
+    /// The value of attribute `type_` of `object`.
+    pub(super) fn attribute(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        type_: CK_ATTRIBUTE_TYPE,
+    ) -> Result<Vec<u8>, CK_RV> {
+        let (key, private) = self.object(object).ok_or(CKR_OBJECT_HANDLE_INVALID)?;
+        let rsa = key.public.rsa().ok();
+        let ec = key.public.ec_key().ok();
+        let value = match type_ {
+            CKA_CLASS if private => ulong(CKO_PRIVATE_KEY),
+            CKA_CLASS => ulong(CKO_PUBLIC_KEY),
+            CKA_KEY_TYPE if rsa.is_some() => ulong(CKK_RSA),
+            CKA_KEY_TYPE => ulong(CKK_EC),
+            CKA_LABEL | CKA_ID => key.config.id.as_bytes().to_vec(),
+            CKA_TOKEN => bool(true),
+            CKA_PRIVATE => bool(private),
+            CKA_MODIFIABLE | CKA_DERIVE => bool(false),
+            CKA_SIGN | CKA_SENSITIVE | CKA_ALWAYS_SENSITIVE | CKA_NEVER_EXTRACTABLE if private => {
+                bool(true)
+            }
+            CKA_EXTRACTABLE | CKA_ALWAYS_AUTHENTICATE if private => bool(false),
+            CKA_DECRYPT if private => bool(rsa.is_some()),
+            CKA_VERIFY if !private => bool(true),
+            CKA_ENCRYPT if !private => bool(rsa.is_some()),
+            CKA_VALUE | CKA_PRIVATE_EXPONENT if private => return Err(CKR_ATTRIBUTE_SENSITIVE),
+            CKA_MODULUS if rsa.is_some() => rsa.map(|rsa| rsa.n().to_vec()).unwrap_or_default(),
+            CKA_PUBLIC_EXPONENT if rsa.is_some() => {
+                rsa.map(|rsa| rsa.e().to_vec()).unwrap_or_default()
+            }
+            CKA_MODULUS_BITS if rsa.is_some() => ulong(key.public.bits() as CK_ULONG),
+            CKA_EC_PARAMS if ec.is_some() => {
+                let nid = ec.and_then(|ec| ec.group().curve_name());
+                let oid = nid
+                    .and_then(|nid| nid.short_name().ok())
+                    .and_then(|name| Asn1Object::from_str(name).ok())
+                    .ok_or(CKR_GENERAL_ERROR)?;
+                der(0x06, oid.as_slice())
+            }
+            CKA_EC_POINT if ec.is_some() && !private => {
+                let ec = ec.ok_or(CKR_GENERAL_ERROR)?;
+                let mut context = BigNumContext::new().map_err(|_| CKR_GENERAL_ERROR)?;
+                let point = ec
+                    .public_key()
+                    .to_bytes(ec.group(), PointConversionForm::UNCOMPRESSED, &mut context)
+                    .map_err(|_| CKR_GENERAL_ERROR)?;
+                der(0x04, &point)
+            }
+            _ => return Err(CKR_ATTRIBUTE_TYPE_INVALID),
+        };
+        Ok(value)
+    }
+
+    /// The mechanisms of the configured keys.
+    pub(super) fn mechanisms(&self) -> Vec<CK_MECHANISM_TYPE> {
+        let mut mechanisms: Vec<CK_MECHANISM_TYPE> = self
+            .config
+            .keys
+            .iter()
+            .flat_map(|key| [self.sign_mechanism(key), self.decrypt_mechanism(key)])
+            .flatten()
+            .collect();
+        mechanisms.sort_unstable();
+        mechanisms.dedup();
+        mechanisms
+    }
+
+    /// The key sizes and functions `mechanism` is used for with the configured keys.
+    pub(super) fn mechanism_info(&self, mechanism: CK_MECHANISM_TYPE) -> Option<CK_MECHANISM_INFO> {
+        let mut info: Option<CK_MECHANISM_INFO> = None;
+        for key in &self.config.keys {
+            let flags = if self.sign_mechanism(key) == Some(mechanism) {
+                CKF_SIGN
+            } else if self.decrypt_mechanism(key) == Some(mechanism) {
+                CKF_DECRYPT
+            } else {
+                continue;
+            };
+            let bits = CK_ULONG::from(key.key_bits());
+            let current = info.get_or_insert(CK_MECHANISM_INFO {
+                ulMinKeySize: bits,
+                ulMaxKeySize: bits,
+                flags: CKF_HW,
+            });
+            current.ulMinKeySize = current.ulMinKeySize.min(bits);
+            current.ulMaxKeySize = current.ulMaxKeySize.max(bits);
+            current.flags |= flags;
+        }
+        info
+    }
+
+    /// Checks that the private key `object` signs with `mechanism`.
+    ///
+    /// # Returns
+    ///
+    /// The index of the key on success.
+    pub(super) fn sign_init(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        mechanism: &Mechanism,
+    ) -> Result<usize, CK_RV> {
+        let index = self.private_key(object)?;
+        let config = &self.keys[index].config;
+        if self.sign_mechanism(config) != Some(mechanism.type_) {
+            return Err(CKR_MECHANISM_INVALID);
+        }
+        if let (AsymmetricEncryption::Rsa(_), RsaPadding::Pss) =
+            (config.algorithm, config.rsa_padding)
+        {
+            let hash = config.signature_hash();
+            let expected = hash_mechanisms(hash).ok_or(CKR_MECHANISM_INVALID)?;
+            let salt_len = hash.message_digest().map_or(0, |md| md.size());
+            let parameter = mechanism
+                .pss_parameter()
+                .ok_or(CKR_MECHANISM_PARAM_INVALID)?;
+            let (hash_alg, mgf, s_len) = (parameter.hashAlg, parameter.mgf, parameter.sLen);
+            if hash_alg != expected.digest || mgf != expected.mgf || s_len as usize != salt_len {
+                return Err(CKR_MECHANISM_PARAM_INVALID);
+            }
+        }
+        Ok(index)
+    }
+
+    /// The length of signatures made by key `index`.
+    pub(super) fn signature_len(&self, index: usize) -> Result<usize, CK_RV> {
+        let key = self.keys.get(index).ok_or(CKR_KEY_HANDLE_INVALID)?;
+        Ok(match key.public.ec_key() {
+            Ok(ec) => 2 * field_size(ec.group().degree()),
+            Err(_) => key.public.size(),
+        })
+    }
+
+    /// Signs `data` with key `index`.
+    ///
+    /// # Returns
+    ///
+    /// The signature in the PKCS #11 format: the plain RSA signature, or `r || s` for ECDSA.
+    pub(super) fn sign(&self, index: usize, data: &[u8]) -> Result<Vec<u8>, CK_RV> {
+        let key = self.keys.get(index).ok_or(CKR_KEY_HANDLE_INVALID)?;
+        let signature = key
+            .provider
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sign_data(data)
+            .map_err(|e| error_rv("C_Sign", &e))?;
+        let size = self.signature_len(index)?;
+        match key.public.id() {
+            Id::EC => ecdsa_signature(&signature, size / 2),
+            _ => rsa_signature(&signature, size),
+        }
+        .ok_or_else(|| {
+            warn!("C_Sign: unexpected signature format for {}", key.config.id);
+            CKR_GENERAL_ERROR
+        })
+    }
+
+    /// Checks that the private key `object` decrypts with `mechanism`.
+    ///
+    /// # Returns
+    ///
+    /// The index of the key on success.
+    pub(super) fn decrypt_init(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        mechanism: &Mechanism,
+    ) -> Result<usize, CK_RV> {
+        let index = self.private_key(object)?;
+        let config = &self.keys[index].config;
+        let expected = self
+            .decrypt_mechanism(config)
+            .ok_or(CKR_KEY_FUNCTION_NOT_PERMITTED)?;
+        if mechanism.type_ != expected {
+            return Err(CKR_MECHANISM_INVALID);
+        }
+        if expected == CKM_RSA_PKCS_OAEP {
+            let hash = hash_mechanisms(config.signature_hash()).ok_or(CKR_MECHANISM_INVALID)?;
+            let parameter = mechanism
+                .oaep_parameter()
+                .ok_or(CKR_MECHANISM_PARAM_INVALID)?;
+            let (hash_alg, mgf, source) = (parameter.hashAlg, parameter.mgf, parameter.source);
+            let source_len = parameter.ulSourceDataLen;
+            if hash_alg != hash.digest
+                || mgf != hash.mgf
+                || (source != 0 && source != CKZ_DATA_SPECIFIED)
+                || source_len != 0
+            {
+                return Err(CKR_MECHANISM_PARAM_INVALID);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Decrypts `data` with key `index`.
+    pub(super) fn decrypt(&self, index: usize, data: &[u8]) -> Result<Vec<u8>, CK_RV> {
+        let key = self.keys.get(index).ok_or(CKR_KEY_HANDLE_INVALID)?;
+        key.provider
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .decrypt_data(data)
+            .map_err(|e| error_rv("C_Decrypt", &e))
+    }
+
+    /// The length of data decrypted by key `index`, at most.
+    pub(super) fn decrypted_len(&self, index: usize) -> Result<usize, CK_RV> {
+        let key = self.keys.get(index).ok_or(CKR_KEY_HANDLE_INVALID)?;
+        Ok(key.public.size())
+    }
+
+    fn object(&self, object: CK_OBJECT_HANDLE) -> Option<(&Key, bool)> {
+        let index = object.checked_sub(1)?;
+        let key = self.keys.get((index / 2) as usize)?;
+        Some((key, index % 2 == 1))
+    }
+
+    fn private_key(&self, object: CK_OBJECT_HANDLE) -> Result<usize, CK_RV> {
+        match self.object(object) {
+            Some((_, true)) => Ok(((object - 1) / 2) as usize),
+            Some((_, false)) => Err(CKR_KEY_FUNCTION_NOT_PERMITTED),
+            None => Err(CKR_KEY_HANDLE_INVALID),
+        }
+    }
+
+    /// The combined hash-and-sign mechanism matching the hash and padding of `key`.
+    fn sign_mechanism(&self, key: &KeyConfig) -> Option<CK_MECHANISM_TYPE> {
+        let mechanisms = hash_mechanisms(key.signature_hash())?;
+        Some(match (key.algorithm, key.rsa_padding) {
+            (AsymmetricEncryption::Rsa(_), RsaPadding::Pkcs1v15) => mechanisms.rsa_pkcs,
+            (AsymmetricEncryption::Rsa(_), RsaPadding::Pss) => mechanisms.rsa_pss,
+            (AsymmetricEncryption::Ecc(_), _) => mechanisms.ecdsa,
+        })
+    }
+
+    fn decrypt_mechanism(&self, key: &KeyConfig) -> Option<CK_MECHANISM_TYPE> {
+        match key.algorithm {
+            AsymmetricEncryption::Rsa(_) if self.config.decrypts_with_oaep() => {
+                Some(CKM_RSA_PKCS_OAEP)
+            }
+            AsymmetricEncryption::Rsa(_) => Some(CKM_RSA_PKCS),
+            AsymmetricEncryption::Ecc(_) => None,
+        }
+    }
+}
+
+/// Answers the requests of providers during `C_Login`.
+struct LoginCredentials {
+    pin: Vec<u8>,
+    management_key: Option<Vec<u8>>,
+}
+
+impl fmt::Debug for LoginCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginCredentials").finish_non_exhaustive()
+    }
+}
+
+impl InteractionHandler for LoginCredentials {
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        match request.kind {
+            CredentialKind::Pin | CredentialKind::Password => Ok(self.pin.clone()),
+            CredentialKind::ManagementKey => self.management_key.clone().ok_or_else(|| {
+                SecurityModuleError::InitializationError("No management key configured".to_owned())
+            }),
+            CredentialKind::Puk => Err(SecurityModuleError::InitializationError(
+                "A PUK cannot be entered through PKCS #11".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The configuration `key` is loaded with by the providers of `module`.
+#[cfg_attr(
+    not(any(feature = "hsm", feature = "tpm", feature = "plugin")),
+    allow(unused_variables)
+)]
+fn provider_config(module: &SecurityModule, key: &KeyConfig) -> Box<dyn Any> {
+    match *module {
+        #[cfg(feature = "hsm")]
+        SecurityModule::Hsm(_) => Box::new(HsmProviderConfig {
+            key_algorithm: key.algorithm,
+            hash: key.hash,
+            rsa_padding: key.rsa_padding,
+            ..Default::default()
+        }),
+        #[cfg(feature = "tpm")]
+        SecurityModule::Tpm(_) => TpmConfig::new(
+            key.algorithm,
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            key.signature_hash(),
+            Vec::new(),
+        ),
+        #[cfg(feature = "plugin")]
+        SecurityModule::Plugin(_) => Box::new(PluginKeyConfig {
+            key_algorithm: Some(key.algorithm),
+            hash: key.hash,
+            ..Default::default()
+        }),
+    }
+}
+
+/// The return value for `error`, which is logged.
+fn error_rv(function: &str, error: &SecurityModuleError) -> CK_RV {
+    warn!("{}: {}", function, error);
+    match error {
+        #[cfg(feature = "hsm")]
+        SecurityModuleError::Hsm(HsmError::WrongCredential { .. }) => CKR_PIN_INCORRECT,
+        #[cfg(feature = "hsm")]
+        SecurityModuleError::Hsm(HsmError::Blocked(_)) => CKR_PIN_LOCKED,
+        SecurityModuleError::DecryptionError(_) => CKR_ENCRYPTED_DATA_INVALID,
+        SecurityModuleError::UnsupportedOperation(_) => CKR_KEY_FUNCTION_NOT_PERMITTED,
+        _ => CKR_DEVICE_ERROR,
+    }
+}
+
+/// Parses the PEM public key returned by `Provider::get_pub_key`.
+fn public_key(pem: &str) -> Option<PKey<Public>> {
+    PKey::public_key_from_pem(pem.as_bytes()).ok().or_else(|| {
+        Rsa::public_key_from_pem_pkcs1(pem.as_bytes())
+            .ok()
+            .and_then(|rsa| PKey::from_rsa(rsa).ok())
+    })
+}
+
+/// The RSA signature in `signature`, either plain or as a marshalled `TPMT_SIGNATURE`.
+fn rsa_signature(signature: &[u8], size: usize) -> Option<Vec<u8>> {
+    match signature.len() {
+        len if len == size => Some(signature.to_vec()),
+        // sigAlg, hash and the size of the signature precede it
+        len if len == size + 6 => Some(signature[6..].to_vec()),
+        _ => None,
+    }
+}
+
+/// Converts the ECDSA signature in `signature`, DER-encoded or a marshalled `TPMT_SIGNATURE`,
+/// to `r || s` with `field_size` bytes each.
+pub(crate) fn ecdsa_signature(signature: &[u8], field_size: usize) -> Option<Vec<u8>> {
+    let (r, s) = match EcdsaSig::from_der(signature) {
+        Ok(signature) => (signature.r().to_vec(), signature.s().to_vec()),
+        Err(_) => tpm_ecdsa_signature(signature)?,
+    };
+    if r.len() > field_size || s.len() > field_size {
+        return None;
+    }
+    let mut raw = vec![0; 2 * field_size];
+    raw[field_size - r.len()..field_size].copy_from_slice(&r);
+    raw[2 * field_size - s.len()..].copy_from_slice(&s);
+    Some(raw)
+}
+
+/// Splits a marshalled ECDSA `TPMT_SIGNATURE` into `r` and `s`.
+fn tpm_ecdsa_signature(signature: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    // sigAlg and hash precede the sized r and s
+    let rest = signature.get(4..)?;
+    let (r, rest) = tpm2b(rest)?;
+    let (s, rest) = tpm2b(rest)?;
+    rest.is_empty().then(|| (r.to_vec(), s.to_vec()))
+}
+
+fn tpm2b(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let value = data.get(2..2 + len)?;
+    Some((value, &data[2 + len..]))
+}
+
+/// The number of bytes of a field element of a curve of `degree` bits.
+fn field_size(degree: u32) -> usize {
+    (degree as usize).div_ceil(8)
+}
+
+/// The DER encoding of `value` with `tag`.
+pub(crate) fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match value.len() {
+        len @ 0..=0x7f => encoded.push(len as u8),
+        len @ 0x80..=0xff => encoded.extend([0x81, len as u8]),
+        len => encoded.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+fn ulong(value: CK_ULONG) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+fn bool(value: bool) -> Vec<u8> {
+    vec![if value { CK_TRUE } else { CK_FALSE }]
+}
@@ -0,0 +1,492 @@
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits},
+            KeyBits,
+        },
+        error::SecurityModuleError,
+        traits::{
+            key_handle::KeyHandle,
+            module_provider::Provider,
+            operations::{Encryptor, KeyAgreement, Verifier},
+        },
+    },
+    hsm::RsaPadding,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use openssl::{
+    bn::BigNum,
+    derive::Deriver,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    encrypt::Encrypter,
+    md::Md,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    pkey_ctx::PkeyCtx,
+    rand::rand_bytes,
+    rsa::{Padding, Rsa},
+    sign::{self, RsaPssSaltlen},
+    symm::{self, Cipher},
+};
+use serde_json::Value;
+use std::fmt;
+use tracing::instrument;
+
+/// The padding of data encrypted with RSA keys.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RsaEncryptionPadding {
+    /// PKCS #1 v1.5 padding, as used by the YubiKey and PKCS #11 providers.
+    #[default]
+    Pkcs1v15,
+    /// OAEP with MGF1, both with the hash of the handle, as used by the TPM providers.
+    Oaep,
+}
+
+/// The public half of a key, which verifies signatures and encrypts data in software.
+///
+/// Neither operation needs the private key, so they need no security module and no cargo
+/// feature: a server can verify signatures made by a YubiKey without opening one. Handles
+/// are created from an exported SubjectPublicKeyInfo or JWK, or from the key of any
+/// provider.
+///
+/// Signatures are checked like the providers make them: RSA with PKCS #1 v1.5 or PSS
+/// padding, ECDSA in DER or as `r || s`, and EdDSA for Ed25519 and Ed448 keys. RSA keys
+/// encrypt with PKCS #1 v1.5 or OAEP padding. EC and X25519/X448 keys encrypt with ECIES
+/// in a format of this crate, which no provider decrypts: the holder of the private key
+/// decrypts it with `ecies_decrypt`.
+pub struct PublicKeyHandle {
+    key: PKey<Public>,
+    algorithm: AsymmetricEncryption,
+    hash: Hash,
+    rsa_padding: RsaPadding,
+    rsa_encryption_padding: RsaEncryptionPadding,
+}
+
+impl fmt::Debug for PublicKeyHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublicKeyHandle")
+            .field("algorithm", &self.algorithm)
+            .field("hash", &self.hash)
+            .field("rsa_padding", &self.rsa_padding)
+            .field("rsa_encryption_padding", &self.rsa_encryption_padding)
+            .finish()
+    }
+}
+
+impl PublicKeyHandle {
+    /// Creates a handle for the DER encoded SubjectPublicKeyInfo `der`.
+    ///
+    /// The algorithm is taken from the key. Signatures are checked with SHA-256 and
+    /// PKCS #1 v1.5 padding unless changed with the `with_*` methods.
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, SecurityModuleError> {
+        let key = PKey::public_key_from_der(der).map_err(key_error)?;
+        Self::new(key)
+    }
+
+    /// Creates a handle for the PEM encoded SubjectPublicKeyInfo `pem`, as returned by
+    /// `Provider::get_pub_key`.
+    pub fn from_pem(pem: &str) -> Result<Self, SecurityModuleError> {
+        // Some providers put the whole key on one line, which OpenSSL does not read.
+        let base64: String = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .flat_map(|line| line.chars().filter(|c| !c.is_whitespace()))
+            .collect();
+        let der = STANDARD
+            .decode(base64)
+            .map_err(|e| invalid_key(e.to_string()))?;
+        Self::from_spki_der(&der)
+    }
+
+    /// Creates a handle for the JSON Web Key `jwk`.
+    ///
+    /// RSA keys, EC keys on P-256, P-384, P-521 and secp256k1, and OKP keys on Ed25519,
+    /// Ed448, X25519 and X448 are supported.
+    pub fn from_jwk(jwk: &str) -> Result<Self, SecurityModuleError> {
+        let jwk: Value = serde_json::from_str(jwk).map_err(|e| invalid_key(e.to_string()))?;
+        let field = |name: &str| -> Result<Vec<u8>, SecurityModuleError> {
+            let value = jwk[name]
+                .as_str()
+                .ok_or_else(|| invalid_key(format!("The JWK has no {}", name)))?;
+            URL_SAFE_NO_PAD
+                .decode(value.trim_end_matches('='))
+                .map_err(|e| invalid_key(format!("Invalid {} in the JWK: {}", name, e)))
+        };
+        let key = match (jwk["kty"].as_str(), jwk["crv"].as_str()) {
+            (Some("RSA"), _) => {
+                let n = BigNum::from_slice(&field("n")?).map_err(key_error)?;
+                let e = BigNum::from_slice(&field("e")?).map_err(key_error)?;
+                Rsa::from_public_components(n, e).and_then(PKey::from_rsa)
+            }
+            (Some("EC"), Some(crv)) => {
+                let nid = match crv {
+                    "P-256" => Nid::X9_62_PRIME256V1,
+                    "P-384" => Nid::SECP384R1,
+                    "P-521" => Nid::SECP521R1,
+                    "secp256k1" => Nid::SECP256K1,
+                    crv => return Err(invalid_key(format!("Unsupported curve {}", crv))),
+                };
+                let group = EcGroup::from_curve_name(nid).map_err(key_error)?;
+                let x = BigNum::from_slice(&field("x")?).map_err(key_error)?;
+                let y = BigNum::from_slice(&field("y")?).map_err(key_error)?;
+                EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                    .and_then(PKey::from_ec_key)
+            }
+            (Some("OKP"), Some(crv)) => {
+                let id = match crv {
+                    "Ed25519" => Id::ED25519,
+                    "Ed448" => Id::ED448,
+                    "X25519" => Id::X25519,
+                    "X448" => Id::X448,
+                    crv => return Err(invalid_key(format!("Unsupported curve {}", crv))),
+                };
+                PKey::public_key_from_raw_bytes(&field("x")?, id)
+            }
+            (kty, _) => {
+                return Err(invalid_key(format!(
+                    "Unsupported JWK key type {}",
+                    kty.unwrap_or("")
+                )))
+            }
+        };
+        Self::new(key.map_err(key_error)?)
+    }
+
+    /// Creates a handle for the key created or loaded by `provider`.
+    ///
+    /// Only the public key is read, so verifying and encrypting with the handle does not
+    /// involve the security module afterwards. The provider does not report how it signs
+    /// and decrypts, so `hash`, `rsa_padding` and `rsa_encryption_padding` have to match
+    /// the configuration the key was created or loaded with. The TPM providers decrypt
+    /// with OAEP padding, the others with PKCS #1 v1.5 padding.
+    pub fn from_provider(
+        provider: &mut dyn Provider,
+        hash: Hash,
+        rsa_padding: RsaPadding,
+        rsa_encryption_padding: RsaEncryptionPadding,
+    ) -> Result<Self, SecurityModuleError> {
+        let pem = provider.get_pub_key();
+        if pem.is_empty() {
+            return Err(invalid_key("The provider has no public key".to_owned()));
+        }
+        Ok(Self::from_pem(&pem)?
+            .with_hash(hash)
+            .with_rsa_padding(rsa_padding)
+            .with_rsa_encryption_padding(rsa_encryption_padding))
+    }
+
+    fn new(key: PKey<Public>) -> Result<Self, SecurityModuleError> {
+        let algorithm = algorithm(&key)?;
+        Ok(Self {
+            key,
+            algorithm,
+            hash: Hash::Sha2(Sha2Bits::Sha256),
+            rsa_padding: RsaPadding::default(),
+            rsa_encryption_padding: RsaEncryptionPadding::default(),
+        })
+    }
+
+    /// Sets the hash of signatures and of OAEP padding, SHA-256 by default.
+    pub fn with_hash(mut self, hash: Hash) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Sets the padding of RSA signatures.
+    pub fn with_rsa_padding(mut self, padding: RsaPadding) -> Self {
+        self.rsa_padding = padding;
+        self
+    }
+
+    /// Sets the padding of data encrypted with RSA keys.
+    pub fn with_rsa_encryption_padding(mut self, padding: RsaEncryptionPadding) -> Self {
+        self.rsa_encryption_padding = padding;
+        self
+    }
+
+    /// The algorithm of the key.
+    ///
+    /// EC keys are reported as ECDSA keys, and X25519 and X448 keys as ECDH keys.
+    pub fn algorithm(&self) -> AsymmetricEncryption {
+        self.algorithm
+    }
+
+    /// The DER encoded SubjectPublicKeyInfo of the key.
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, SecurityModuleError> {
+        self.key.public_key_to_der().map_err(key_error)
+    }
+
+    /// Whether the key is an Ed25519 or Ed448 key, which signs without a separate digest.
+    fn is_edwards(&self) -> bool {
+        matches!(self.key.id(), Id::ED25519 | Id::ED448)
+    }
+
+    /// Whether the key is an X25519 or X448 key, which only agrees on secrets.
+    fn is_montgomery(&self) -> bool {
+        matches!(self.key.id(), Id::X25519 | Id::X448)
+    }
+
+    fn message_digest(&self) -> Result<openssl::hash::MessageDigest, SecurityModuleError> {
+        self.hash.message_digest().ok_or_else(|| {
+            SecurityModuleError::UnsupportedOperation(format!("{:?} is not supported", self.hash))
+        })
+    }
+
+    /// Converts an ECDSA signature given as `r || s` to DER, leaving DER signatures as they
+    /// are.
+    fn ecdsa_signature(&self, signature: &[u8]) -> Option<Vec<u8>> {
+        if EcdsaSig::from_der(signature).is_ok() {
+            return Some(signature.to_vec());
+        }
+        let field_len = self.key.bits().div_ceil(8) as usize;
+        if signature.len() != 2 * field_len {
+            return None;
+        }
+        let (r, s) = signature.split_at(field_len);
+        let r = BigNum::from_slice(r).ok()?;
+        let s = BigNum::from_slice(s).ok()?;
+        EcdsaSig::from_private_components(r, s)
+            .and_then(|signature| signature.to_der())
+            .ok()
+    }
+
+    fn rsa_encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let mut encrypter = Encrypter::new(&self.key).map_err(encryption_error)?;
+        match self.rsa_encryption_padding {
+            RsaEncryptionPadding::Pkcs1v15 => encrypter.set_rsa_padding(Padding::PKCS1),
+            RsaEncryptionPadding::Oaep => {
+                let md = self.message_digest()?;
+                encrypter
+                    .set_rsa_padding(Padding::PKCS1_OAEP)
+                    .and_then(|()| encrypter.set_rsa_oaep_md(md))
+                    .and_then(|()| encrypter.set_rsa_mgf1_md(md))
+            }
+        }
+        .map_err(encryption_error)?;
+        let mut encrypted = vec![0; encrypter.encrypt_len(data).map_err(encryption_error)?];
+        let len = encrypter
+            .encrypt(data, &mut encrypted)
+            .map_err(encryption_error)?;
+        encrypted.truncate(len);
+        Ok(encrypted)
+    }
+
+    /// Encrypts `data` with ECIES, see `ecies_decrypt` for the format.
+    fn ecies_encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let ephemeral = match self.key.id() {
+            Id::X25519 => PKey::generate_x25519(),
+            Id::X448 => PKey::generate_x448(),
+            _ => self
+                .key
+                .ec_key()
+                .and_then(|key| EcKey::generate(key.group()))
+                .and_then(PKey::from_ec_key),
+        }
+        .map_err(encryption_error)?;
+        let shared_secret = Deriver::new(&ephemeral)
+            .and_then(|mut deriver| {
+                deriver.set_peer(&self.key)?;
+                deriver.derive_to_vec()
+            })
+            .map_err(encryption_error)?;
+        let ephemeral_public_key = ephemeral.public_key_to_der().map_err(encryption_error)?;
+        let key = ecies_key(&shared_secret, &ephemeral_public_key)?;
+
+        let mut nonce = [0; ECIES_NONCE_LEN];
+        rand_bytes(&mut nonce).map_err(encryption_error)?;
+        let mut tag = [0; ECIES_TAG_LEN];
+        let ciphertext = symm::encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&nonce),
+            &[],
+            data,
+            &mut tag,
+        )
+        .map_err(encryption_error)?;
+
+        let mut encrypted = Vec::with_capacity(
+            2 + ephemeral_public_key.len() + nonce.len() + ciphertext.len() + tag.len(),
+        );
+        encrypted.extend_from_slice(&(ephemeral_public_key.len() as u16).to_be_bytes());
+        encrypted.extend_from_slice(&ephemeral_public_key);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted.extend_from_slice(&tag);
+        Ok(encrypted)
+    }
+}
+
+impl KeyHandle for PublicKeyHandle {
+    /// Returns the handle as a `Verifier` unless the key is an X25519 or X448 key.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        (!self.is_montgomery()).then_some(self)
+    }
+
+    /// Returns the handle as an `Encryptor` unless the key is an Ed25519 or Ed448 key.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        (!self.is_edwards()).then_some(self)
+    }
+}
+
+impl Verifier for PublicKeyHandle {
+    /// Verifies `signature` over `data` with the hash and padding of the handle.
+    #[instrument(skip(data, signature))]
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        if self.is_edwards() {
+            let mut verifier =
+                sign::Verifier::new_without_digest(&self.key).map_err(verification_error)?;
+            return Ok(verifier.verify_oneshot(signature, data).unwrap_or(false));
+        }
+
+        let md = self.message_digest()?;
+        let mut verifier = sign::Verifier::new(md, &self.key).map_err(verification_error)?;
+        let signature = match self.algorithm {
+            AsymmetricEncryption::Rsa(_) => {
+                if self.rsa_padding == RsaPadding::Pss {
+                    verifier
+                        .set_rsa_padding(Padding::PKCS1_PSS)
+                        .and_then(|()| verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH))
+                        .and_then(|()| verifier.set_rsa_mgf1_md(md))
+                        .map_err(verification_error)?;
+                }
+                signature.to_vec()
+            }
+            AsymmetricEncryption::Ecc(_) => match self.ecdsa_signature(signature) {
+                Some(signature) => signature,
+                None => return Ok(false),
+            },
+        };
+        verifier.update(data).map_err(verification_error)?;
+        // OpenSSL reports malformed signatures as errors; they are just invalid here.
+        Ok(verifier.verify(&signature).unwrap_or(false))
+    }
+}
+
+impl Encryptor for PublicKeyHandle {
+    /// Encrypts `data` for the holder of the private key.
+    ///
+    /// RSA keys encrypt with the padding of the handle. Other keys encrypt with ECIES in the
+    /// format described at `ecies_decrypt`, which is specific to this crate: neither the
+    /// providers' `decrypt_data` nor other ECIES implementations read it.
+    #[instrument(skip(data))]
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        match self.algorithm {
+            AsymmetricEncryption::Rsa(_) => self.rsa_encrypt(data),
+            AsymmetricEncryption::Ecc(_) if self.is_edwards() => {
+                Err(SecurityModuleError::UnsupportedOperation(
+                    "Ed25519 and Ed448 keys cannot encrypt data".to_owned(),
+                ))
+            }
+            AsymmetricEncryption::Ecc(_) => self.ecies_encrypt(data),
+        }
+    }
+}
+
+const ECIES_KEY_LEN: usize = 32;
+const ECIES_NONCE_LEN: usize = 12;
+const ECIES_TAG_LEN: usize = 16;
+
+/// Decrypts data encrypted with ECIES by a `PublicKeyHandle`, using the private key behind
+/// `key`.
+///
+/// The encrypted data consists of the length of the ephemeral public key as a big-endian
+/// `u16`, the ephemeral public key as a DER encoded SubjectPublicKeyInfo, a 12 byte nonce,
+/// and the data encrypted with AES-256-GCM followed by its 16 byte tag. The AES key is
+/// derived from the shared secret with HKDF-SHA256, with the ephemeral public key as info.
+pub fn ecies_decrypt(
+    key: &dyn KeyAgreement,
+    encrypted_data: &[u8],
+) -> Result<Vec<u8>, SecurityModuleError> {
+    let invalid = || SecurityModuleError::DecryptionError("Invalid ECIES data".to_owned());
+    let (len, rest) = encrypted_data.split_at_checked(2).ok_or_else(invalid)?;
+    let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+    let (ephemeral_public_key, rest) = rest.split_at_checked(len).ok_or_else(invalid)?;
+    let (nonce, rest) = rest.split_at_checked(ECIES_NONCE_LEN).ok_or_else(invalid)?;
+    let tag_offset = rest.len().checked_sub(ECIES_TAG_LEN).ok_or_else(invalid)?;
+    let (ciphertext, tag) = rest.split_at(tag_offset);
+
+    let shared_secret = key.agree(ephemeral_public_key)?;
+    let key = ecies_key(&shared_secret, ephemeral_public_key)?;
+    symm::decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &key,
+        Some(nonce),
+        &[],
+        ciphertext,
+        tag,
+    )
+    .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))
+}
+
+/// Derives the AES key of ECIES from the shared secret.
+fn ecies_key(
+    shared_secret: &[u8],
+    ephemeral_public_key: &[u8],
+) -> Result<[u8; ECIES_KEY_LEN], SecurityModuleError> {
+    let mut key = [0; ECIES_KEY_LEN];
+    PkeyCtx::new_id(Id::HKDF)
+        .and_then(|mut ctx| {
+            ctx.derive_init()?;
+            ctx.set_hkdf_md(Md::sha256())?;
+            ctx.set_hkdf_key(shared_secret)?;
+            ctx.add_hkdf_info(ephemeral_public_key)?;
+            ctx.derive(Some(&mut key))
+        })
+        .map_err(|e| SecurityModuleError::InitializationError(e.to_string()))?;
+    Ok(key)
+}
+
+/// Determines the algorithm of `key`.
+fn algorithm(key: &PKey<Public>) -> Result<AsymmetricEncryption, SecurityModuleError> {
+    let algorithm = match key.id() {
+        Id::RSA => match key.bits() {
+            bits @ (512 | 1024 | 2048 | 3072 | 4096 | 8192) => {
+                AsymmetricEncryption::Rsa(KeyBits::from(bits))
+            }
+            bits => return Err(invalid_key(format!("Unsupported RSA key size {}", bits))),
+        },
+        Id::EC => {
+            let key = key.ec_key().map_err(key_error)?;
+            let curve = match key.group().curve_name() {
+                Some(Nid::X9_62_PRIME256V1) => EccCurves::P256,
+                Some(Nid::SECP384R1) => EccCurves::P384,
+                Some(Nid::SECP521R1) => EccCurves::P521,
+                Some(Nid::SECP256K1) => EccCurves::Secp256k1,
+                Some(Nid::BRAINPOOL_P256R1) => EccCurves::BrainpoolP256r1,
+                Some(Nid::BRAINPOOL_P384R1) => EccCurves::BrainpoolP384r1,
+                Some(Nid::BRAINPOOL_P512R1) => EccCurves::BrainpoolP512r1,
+                nid => return Err(invalid_key(format!("Unsupported curve {:?}", nid))),
+            };
+            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(curve))
+        }
+        Id::ED25519 => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519)),
+        Id::ED448 => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve448)),
+        Id::X25519 => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::Curve25519)),
+        Id::X448 => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::Curve448)),
+        id => return Err(invalid_key(format!("Unsupported key type {:?}", id))),
+    };
+    Ok(algorithm)
+}
+
+fn invalid_key(message: String) -> SecurityModuleError {
+    SecurityModuleError::InitializationError(format!("Invalid public key: {}", message))
+}
+
+fn key_error(err: openssl::error::ErrorStack) -> SecurityModuleError {
+    invalid_key(err.to_string())
+}
+
+fn verification_error(err: openssl::error::ErrorStack) -> SecurityModuleError {
+    SecurityModuleError::SignatureVerificationError(err.to_string())
+}
+
+fn encryption_error(err: openssl::error::ErrorStack) -> SecurityModuleError {
+    SecurityModuleError::EncryptionError(err.to_string())
+}
@@ -0,0 +1,82 @@
+use crate::common::traits::module_provider::Provider;
+use rand_core::{impls, CryptoRng, Error, OsRng, RngCore};
+use std::sync::{Arc, Mutex};
+
+/// A random number generator backed by a security module.
+///
+/// Every request draws the same number of bytes from the security module and from the
+/// operating system's CSPRNG and XORs them, so the output is at least as unpredictable as
+/// the stronger of the two sources: a weak or compromised hardware RNG cannot degrade the
+/// OS randomness, and a weak OS RNG is backed by the hardware.
+///
+/// The generator implements `rand_core::RngCore` and `CryptoRng`, so it can be passed to
+/// any crate that accepts a cryptographically secure RNG.
+///
+/// # Examples
+///
+/// ```ignore
+/// let provider = SecModules::get_instance(key_id, module, None).unwrap();
+/// let mut rng = HardwareRng::new(provider);
+/// let key = rsa::RsaPrivateKey::new(&mut rng, 2048)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct HardwareRng {
+    provider: Arc<Mutex<dyn Provider>>,
+}
+
+impl HardwareRng {
+    /// Creates a generator drawing hardware randomness from `provider`.
+    ///
+    /// The provider must be initialized; whether it also needs a loaded key depends on the
+    /// security module.
+    pub fn new(provider: Arc<Mutex<dyn Provider>>) -> Self {
+        Self { provider }
+    }
+}
+
+impl RngCore for HardwareRng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    /// Fills `dest` with random bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either source of randomness fails, as required by `RngCore`. Use
+    /// `try_fill_bytes` to handle the error instead.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(err) = self.try_fill_bytes(dest) {
+            panic!("HardwareRng failed: {}", err);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        let hardware = self
+            .provider
+            .lock()
+            .map_err(|_| Error::new("Security module provider lock is poisoned"))?
+            .random_bytes(dest.len())
+            .map_err(|err| Error::new(err.to_string()))?;
+        if hardware.len() != dest.len() {
+            return Err(Error::new(format!(
+                "Security module returned {} random bytes instead of {}",
+                hardware.len(),
+                dest.len()
+            )));
+        }
+
+        OsRng.try_fill_bytes(dest)?;
+        dest.iter_mut()
+            .zip(hardware)
+            .for_each(|(byte, hardware)| *byte ^= hardware);
+
+        Ok(())
+    }
+}
+
+impl CryptoRng for HardwareRng {}
@@ -0,0 +1,85 @@
+use super::traits::device_events::{DeviceEvent, DeviceEventHandler};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long a watcher waits for a change before it lists the devices again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches for devices being connected and disconnected on a background thread.
+///
+/// Devices connected when the watcher starts are not reported. The thread stops when the
+/// watcher is dropped.
+#[derive(Debug)]
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    /// Starts a watcher for the devices of `module`.
+    ///
+    /// The thread calls `wait` with the poll interval, which returns once the interval has
+    /// passed or earlier if the platform announces a change, and then `list` for the ids of
+    /// the connected devices. Ids that appeared or disappeared since the last call are
+    /// reported to `handler`. A `None` from `list` skips the round.
+    pub fn spawn(
+        module: &str,
+        handler: Arc<dyn DeviceEventHandler>,
+        mut wait: impl FnMut(Duration) + Send + 'static,
+        mut list: impl FnMut() -> Option<Vec<String>> + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let module = module.to_owned();
+        let thread = thread::spawn({
+            let stop = Arc::clone(&stop);
+            move || {
+                let mut known: HashSet<String> = list().unwrap_or_default().into_iter().collect();
+                while !stop.load(Ordering::Relaxed) {
+                    wait(POLL_INTERVAL);
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Some(current) = list() else {
+                        continue;
+                    };
+                    let current: HashSet<String> = current.into_iter().collect();
+
+                    for device in known.difference(&current) {
+                        handler.device_event(&DeviceEvent::Removed {
+                            module: module.clone(),
+                            device: device.clone(),
+                        });
+                    }
+                    for device in current.difference(&known) {
+                        handler.device_event(&DeviceEvent::Inserted {
+                            module: module.clone(),
+                            device: device.clone(),
+                        });
+                    }
+                    known = current;
+                }
+            }
+        });
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
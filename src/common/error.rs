@@ -40,6 +40,10 @@ pub enum SecurityModuleError {
     ///
     /// This variant contains a descriptive error message.
     InitializationError(String),
+    /// Error returned when a key cannot perform the requested operation.
+    ///
+    /// This variant contains a descriptive error message naming the operation.
+    UnsupportedOperation(String),
 }
 
 impl fmt::Display for SecurityModuleError {
@@ -72,6 +76,9 @@ impl fmt::Display for SecurityModuleError {
             SecurityModuleError::InitializationError(ref error_msg) => {
                 write!(f, "Initialization error: {}", error_msg)
             }
+            SecurityModuleError::UnsupportedOperation(ref error_msg) => {
+                write!(f, "Unsupported operation: {}", error_msg)
+            }
         }
     }
 }
@@ -96,6 +103,7 @@ impl std::error::Error for SecurityModuleError {
             SecurityModuleError::EncryptionError(_) => None,
             SecurityModuleError::SignatureVerificationError(_) => None,
             SecurityModuleError::InitializationError(_) => None,
+            SecurityModuleError::UnsupportedOperation(_) => None,
         }
     }
 }
@@ -123,3 +131,57 @@ impl From<TpmError> for SecurityModuleError {
         SecurityModuleError::Tpm(err)
     }
 }
+
+/// Represents errors that occur while selecting or creating a security module instance.
+///
+/// Returned by the `FromStr` implementations of `SecurityModule`, `HsmType` and `TpmType`
+/// and by `SecModules::get_instance`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FactoryError {
+    /// The name does not denote any known security module.
+    UnknownModule(String),
+    /// The name denotes a security module, but the part selecting the instance is invalid.
+    InvalidName { name: String, reason: String },
+    /// The security module is known, but this build lacks the feature providing it.
+    FeatureDisabled {
+        module: String,
+        feature: &'static str,
+    },
+    /// The security module is known, but no provider is implemented for it.
+    NotImplemented(String),
+    /// The plugin could not be loaded or registered.
+    InvalidPlugin { plugin: String, reason: String },
+}
+
+impl fmt::Display for FactoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FactoryError::UnknownModule(name) => {
+                write!(f, "Unknown security module: {}", name)
+            }
+            FactoryError::InvalidName { name, reason } => {
+                write!(f, "Invalid security module name {}: {}", name, reason)
+            }
+            FactoryError::FeatureDisabled { module, feature } => write!(
+                f,
+                "Security module {} requires the `{}` feature",
+                module, feature
+            ),
+            FactoryError::NotImplemented(module) => {
+                write!(f, "No provider is implemented for {}", module)
+            }
+            FactoryError::InvalidPlugin { plugin, reason } => {
+                write!(f, "Invalid plugin {}: {}", plugin, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FactoryError {}
+
+impl From<FactoryError> for SecurityModuleError {
+    /// Converts a `FactoryError` into an `InitializationError`.
+    fn from(err: FactoryError) -> SecurityModuleError {
+        SecurityModuleError::InitializationError(err.to_string())
+    }
+}
@@ -1,16 +1,24 @@
-use super::traits::{log_config::LogConfig, module_provider::Provider};
+use super::{
+    error::FactoryError,
+    traits::{log_config::LogConfig, module_provider::Provider},
+};
 #[cfg(feature = "hsm")]
 use crate::hsm::core::instance::{HsmInstance, HsmType};
+#[cfg(feature = "plugin")]
+use crate::plugin::{self, abi::PluginEntry, PluginInfo};
 #[cfg(feature = "tpm")]
 use crate::tpm::core::instance::{TpmInstance, TpmType};
 use once_cell::sync::Lazy;
+#[cfg(feature = "plugin")]
+use std::path::Path;
 use std::{
     collections::HashMap,
+    str::FromStr,
     sync::{Arc, Mutex},
 };
 
 type ProviderArc = Arc<Mutex<dyn Provider>>;
-type SecurityModuleMap = HashMap<SecurityModule, ProviderArc>;
+type SecurityModuleMap = HashMap<InstanceKey, ProviderArc>;
 type SecurityModuleInstances = Lazy<Mutex<SecurityModuleMap>>;
 
 /// Represents the available types of security modules in the system.
@@ -24,28 +32,100 @@ pub enum SecurityModule {
     Hsm(HsmType),
     #[cfg(feature = "tpm")]
     Tpm(TpmType),
+    /// A provider plugin, by the name it is registered under.
+    #[cfg(feature = "plugin")]
+    Plugin(String),
 }
 
 /// Provides conversion from a string slice to a `SecurityModule` variant.
 ///
 /// This implementation allows for easy instantiation of `SecurityModule` variants
 /// from string identifiers, facilitating user or configuration-based module selection.
-impl From<&str> for SecurityModule {
-    fn from(item: &str) -> Self {
-        match item {
-            #[cfg(feature = "tpm")]
-            "TPM" => SecurityModule::Tpm(TpmType::default()),
-            #[cfg(feature = "hsm")]
-            "HSM" => SecurityModule::Hsm(HsmType::default()),
-            _ => panic!("Unsupported Security Module type"),
+/// `"TPM"` and `"HSM"` select the default TPM and HSM type; the names accepted by
+/// `HsmType` and `TpmType`, such as `"YubiKey:12345678"` or `"Linux"`, select a type.
+/// Any other name selects the plugin registered under it.
+impl FromStr for SecurityModule {
+    type Err = FactoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(feature = "tpm")]
+        if s == "TPM" {
+            return Ok(SecurityModule::Tpm(TpmType::default()));
+        }
+        #[cfg(feature = "hsm")]
+        if s == "HSM" {
+            return Ok(SecurityModule::Hsm(HsmType::default()));
+        }
+        #[cfg(feature = "hsm")]
+        match s.parse() {
+            Err(FactoryError::UnknownModule(_)) => {}
+            result => return result.map(SecurityModule::Hsm),
+        }
+        #[cfg(feature = "tpm")]
+        match s.parse() {
+            Err(FactoryError::UnknownModule(_)) => {}
+            result => return result.map(SecurityModule::Tpm),
+        }
+        #[cfg(feature = "plugin")]
+        if plugin::get(s).is_some() {
+            return Ok(SecurityModule::Plugin(s.to_owned()));
+        }
+        let feature = match s {
+            "TPM" => "tpm",
+            "HSM" | "YubiKey" | "NitroKey" => "hsm",
+            _ if s.starts_with("YubiKey:") => "hsm",
+            _ => return Err(FactoryError::UnknownModule(s.to_owned())),
+        };
+        Err(FactoryError::FeatureDisabled {
+            module: s.to_owned(),
+            feature,
+        })
+    }
+}
+
+impl TryFrom<&str> for SecurityModule {
+    type Error = FactoryError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Identifies a cached security module instance.
+///
+/// Instances are kept per module and instance name, so one process can hold several
+/// independent providers of the same module, for example one per key.
+#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+pub struct InstanceKey {
+    /// The module the instance belongs to.
+    pub module: SecurityModule,
+    /// The name distinguishing the instance from others of the same module.
+    pub name: String,
+}
+
+impl InstanceKey {
+    /// Creates the key of the instance `name` of `module`.
+    pub fn new(module: SecurityModule, name: impl Into<String>) -> Self {
+        Self {
+            module,
+            name: name.into(),
         }
     }
 }
 
+/// A security module found by `SecModules::discover`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredModule {
+    /// The module, as passed to `SecModules::get_instance`.
+    pub module: SecurityModule,
+    /// A human-readable description, e.g. the model and firmware version of the device.
+    pub description: String,
+}
+
 /// A thread-safe, lazily-initialized global registry of security module instances.
 ///
-/// This static variable holds a `Mutex`-protected `HashMap` that maps `SecurityModule`
-/// variants to their corresponding provider instances. It ensures that module instances
+/// This static variable holds a `Mutex`-protected `HashMap` that maps `InstanceKey`s
+/// to their corresponding provider instances. It ensures that module instances
 /// are unique and accessible across the application.
 static INSTANCES: SecurityModuleInstances = Lazy::new(|| Mutex::new(HashMap::new()));
 static LOGGING_INITIALIZED: Mutex<bool> = Mutex::new(false);
@@ -62,7 +142,8 @@ impl SecModules {
     /// Retrieves or creates an instance of a security module based on the provided key and type.
     ///
     /// If an instance for the given module and key does not exist, it is created and stored.
-    /// Otherwise, the existing instance is returned.
+    /// Otherwise, the existing instance is returned. Each `key_id` names a separate instance,
+    /// so several providers of the same module can be used at once.
     ///
     /// # Arguments
     ///
@@ -71,13 +152,27 @@ impl SecModules {
     ///
     /// # Returns
     ///
-    /// An `Option` containing an `Arc<Mutex<dyn Provider>>` to the requested module instance,
-    /// or `None` if the module type is not supported or an error occurs during instance creation.
+    /// An `Arc<Mutex<dyn Provider>>` to the requested module instance, or a `FactoryError`
+    /// if no provider can be created for the module.
+    #[cfg_attr(
+        not(any(feature = "hsm", feature = "tpm", feature = "plugin")),
+        allow(unreachable_code, unused_variables)
+    )]
     pub fn get_instance(
         key_id: String,
         module: SecurityModule,
         log: Option<Box<dyn LogConfig>>,
-    ) -> Option<Arc<Mutex<dyn Provider>>> {
+    ) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
+        Self::get_keyed_instance(InstanceKey::new(module, key_id), log)
+    }
+
+    /// Retrieves or creates the instance identified by `key`.
+    ///
+    /// The instance name is passed to the provider as its key identifier when it is created.
+    pub fn get_keyed_instance(
+        key: InstanceKey,
+        log: Option<Box<dyn LogConfig>>,
+    ) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
         // Initialize logging once
         if !*LOGGING_INITIALIZED.lock().unwrap() {
             if let Some(log_inst) = log {
@@ -88,12 +183,64 @@ impl SecModules {
 
         // Check if requested instance is in cache. If not, create a new instance
         let mut instances = INSTANCES.lock().unwrap();
-        if !instances.contains_key(&module) {
-            let instance = SecModule::create_instance(key_id, &module);
-            instances.insert(module.clone(), instance?);
+        if let Some(instance) = instances.get(&key) {
+            return Ok(instance.clone());
         }
+        let instance = SecModule::create_instance(key.name.clone(), &key.module)?;
+        instances.insert(key, instance.clone());
+        Ok(instance)
+    }
 
-        instances.get(&module).cloned()
+    /// Probes which of the security modules supported by this build are usable right now.
+    ///
+    /// Unlike the cargo features, which decide the backends compiled in, this checks at
+    /// runtime whether a TPM is reachable through the configured TCTI or the platform API,
+    /// YubiKeys are connected, the PKCS #11 module of a NitroKey loads and has a token, and
+    /// the Android keystore is present. Each connected YubiKey is listed separately, and
+    /// each registered plugin is listed as it is.
+    ///
+    /// Backends that cannot be probed are logged and left out, so the result is empty rather
+    /// than an error if nothing is available.
+    pub fn discover() -> Vec<DiscoveredModule> {
+        #[allow(unused_mut)]
+        let mut modules = Vec::new();
+        #[cfg(feature = "tpm")]
+        modules.extend(TpmInstance::discover());
+        #[cfg(feature = "hsm")]
+        modules.extend(HsmInstance::discover());
+        #[cfg(feature = "plugin")]
+        modules.extend(plugin::discover());
+        modules
+    }
+
+    /// Loads the provider plugin at `path` and registers it under the name it reports.
+    ///
+    /// The library must export the entry function described in `plugin::abi` and support
+    /// one of the ABI versions of this build. Once registered, the plugin is selected by
+    /// `SecurityModule::Plugin` or by parsing its name, and stays loaded for the lifetime
+    /// of the process.
+    #[cfg(feature = "plugin")]
+    pub fn load_plugin(path: impl AsRef<Path>) -> Result<PluginInfo, FactoryError> {
+        plugin::load(path.as_ref())
+    }
+
+    /// Registers a provider plugin linked into the application, given its entry function.
+    #[cfg(feature = "plugin")]
+    pub fn register_plugin(entry: PluginEntry) -> Result<PluginInfo, FactoryError> {
+        plugin::register(entry, None)
+    }
+
+    /// Returns the registered provider plugins, sorted by name.
+    #[cfg(feature = "plugin")]
+    pub fn plugins() -> Vec<PluginInfo> {
+        plugin::registered()
+    }
+
+    /// Removes the instance identified by `key` from the cache, returning it if it existed.
+    ///
+    /// The next `get_keyed_instance` for `key` creates a new instance.
+    pub fn remove_instance(key: &InstanceKey) -> Option<Arc<Mutex<dyn Provider>>> {
+        INSTANCES.lock().unwrap().remove(key)
     }
 }
 
@@ -102,7 +249,7 @@ impl SecModules {
 /// This struct is used internally to manage individual instances of security modules,
 /// encapsulating the module's type and the provider instance that handles its functionality.
 #[repr(C)]
-struct SecModule {
+pub(crate) struct SecModule {
     name: String,
     instance: Arc<Mutex<dyn Provider>>,
 }
@@ -122,17 +269,22 @@ impl SecModule {
     /// # Returns
     ///
     /// An `Arc<Mutex<dyn Provider>>` representing the created module instance,
-    /// or `None` if the module type is not supported or an error occurs during instance creation.
-    fn create_instance(
+    /// or a `FactoryError` if the module type is not supported in this build.
+    #[cfg_attr(
+        not(any(feature = "hsm", feature = "tpm", feature = "plugin")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn create_instance(
         key_id: String,
         module: &SecurityModule,
-    ) -> Option<Arc<Mutex<dyn Provider>>> {
-        match module {
+    ) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
+        match *module {
             #[cfg(feature = "hsm")]
-            SecurityModule::Hsm(hsm_type) => Some(HsmInstance::create_instance(key_id, hsm_type)),
+            SecurityModule::Hsm(ref hsm_type) => HsmInstance::create_instance(key_id, hsm_type),
             #[cfg(feature = "tpm")]
-            SecurityModule::Tpm(tpm_type) => Some(TpmInstance::create_instance(key_id, tpm_type)),
-            _ => unimplemented!(),
+            SecurityModule::Tpm(ref tpm_type) => TpmInstance::create_instance(key_id, tpm_type),
+            #[cfg(feature = "plugin")]
+            SecurityModule::Plugin(ref name) => plugin::create_instance(key_id, name),
         }
     }
 }
@@ -0,0 +1,155 @@
+use super::{
+    error::SecurityModuleError,
+    traits::interaction::{
+        CredentialKind, CredentialRequest, InteractionEvent, InteractionHandler,
+    },
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+type CacheKey = (CredentialKind, String, Option<String>);
+
+/// An `InteractionHandler` that remembers the secrets returned by another handler.
+///
+/// A cached secret is reused for requests of the same kind, module and key until `timeout`
+/// has passed since it was entered, or until the security module rejects it. Events are
+/// passed through unchanged.
+#[derive(Debug)]
+pub struct CachingInteractionHandler {
+    inner: Arc<dyn InteractionHandler>,
+    timeout: Duration,
+    cache: Mutex<HashMap<CacheKey, (Vec<u8>, Instant)>>,
+}
+
+impl CachingInteractionHandler {
+    /// Creates a cache in front of `inner` that keeps secrets for `timeout`.
+    pub fn new(inner: Arc<dyn InteractionHandler>, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets all cached secrets.
+    pub fn clear(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.values_mut().for_each(|(secret, _)| wipe(secret));
+        cache.clear();
+    }
+}
+
+impl InteractionHandler for CachingInteractionHandler {
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let key = cache_key(request);
+        {
+            let mut cache = self.cache.lock().unwrap();
+            match cache.get_mut(&key) {
+                Some((secret, entered)) if entered.elapsed() < self.timeout => {
+                    return Ok(secret.clone())
+                }
+                Some((secret, _)) => {
+                    wipe(secret);
+                    cache.remove(&key);
+                }
+                None => {}
+            }
+        }
+
+        // The lock is not held while the user is prompted.
+        let secret = self.inner.request_credential(request)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, (secret.clone(), Instant::now()));
+        Ok(secret)
+    }
+
+    fn credential_rejected(&self, request: &CredentialRequest) {
+        if let Some((mut secret, _)) = self.cache.lock().unwrap().remove(&cache_key(request)) {
+            wipe(&mut secret);
+        }
+        self.inner.credential_rejected(request);
+    }
+
+    fn notify(&self, event: &InteractionEvent) {
+        self.inner.notify(event);
+    }
+}
+
+impl Drop for CachingInteractionHandler {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// An `InteractionHandler` answering from a fixed set of secrets.
+///
+/// Useful for unattended setups and tests. Secrets are looked up by kind and module; a
+/// secret registered for a specific key takes precedence over one registered for all keys.
+#[derive(Debug, Default)]
+pub struct StaticCredentials {
+    secrets: HashMap<CacheKey, Vec<u8>>,
+}
+
+impl StaticCredentials {
+    /// Creates an empty set of secrets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the secret returned for `kind` requests of `module`, optionally limited to `key_id`.
+    pub fn with_credential(
+        mut self,
+        kind: CredentialKind,
+        module: &str,
+        key_id: Option<&str>,
+        secret: &[u8],
+    ) -> Self {
+        self.secrets.insert(
+            (kind, module.to_owned(), key_id.map(str::to_owned)),
+            secret.to_vec(),
+        );
+        self
+    }
+}
+
+impl InteractionHandler for StaticCredentials {
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        self.secrets
+            .get(&cache_key(request))
+            .or_else(|| {
+                self.secrets
+                    .get(&(request.kind, request.module.clone(), None))
+            })
+            .cloned()
+            .ok_or_else(|| {
+                SecurityModuleError::InitializationError(format!(
+                    "No credential available for: {}",
+                    request.prompt
+                ))
+            })
+    }
+}
+
+fn cache_key(request: &CredentialRequest) -> CacheKey {
+    (request.kind, request.module.clone(), request.key_id.clone())
+}
+
+/// Overwrites a secret before its memory is released.
+fn wipe(secret: &mut [u8]) {
+    for byte in secret.iter_mut() {
+        // SAFETY: `byte` is a valid, aligned reference; the volatile write keeps the
+        // compiler from eliding the store to memory that is about to be freed.
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
@@ -1,4 +1,8 @@
+pub mod capabilities;
+pub mod composite;
 pub mod crypto;
+pub mod device_events;
 pub mod error;
 pub mod factory;
+pub mod interaction;
 pub mod traits;
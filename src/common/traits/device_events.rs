@@ -0,0 +1,25 @@
+use std::fmt::Debug;
+
+/// A change of the hardware tokens connected to the system.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceEvent {
+    /// A device was connected.
+    Inserted {
+        /// The security module the device belongs to, e.g. `"YubiKey"`.
+        module: String,
+        /// Identifies the device within the module, e.g. a serial number.
+        device: String,
+    },
+    /// A device was disconnected.
+    Removed { module: String, device: String },
+}
+
+/// Receives the `DeviceEvent`s of a `DeviceWatcher`.
+///
+/// Long-running applications subscribe with e.g. `YubiKeyProvider::watch_devices` to
+/// update their UI when a token is plugged in or out, instead of finding out through
+/// failing operations.
+pub trait DeviceEventHandler: Send + Sync + Debug {
+    /// Called from the watcher's thread for every change.
+    fn device_event(&self, event: &DeviceEvent);
+}
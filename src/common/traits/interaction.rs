@@ -0,0 +1,69 @@
+use crate::common::error::SecurityModuleError;
+use std::fmt::Debug;
+
+/// The kind of secret a security module asks for.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CredentialKind {
+    /// A user PIN, e.g. the PIV PIN of a YubiKey.
+    Pin,
+    /// A PIN unblocking key.
+    Puk,
+    /// A password or authorization value, e.g. a TPM hierarchy or key auth value.
+    Password,
+    /// A management key, returned as raw key bytes.
+    ManagementKey,
+}
+
+/// Describes a secret a provider needs to continue an operation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CredentialRequest {
+    /// The kind of secret.
+    pub kind: CredentialKind,
+    /// The security module asking, e.g. `"YubiKey"` or `"TPM"`.
+    pub module: String,
+    /// The key the secret belongs to, `None` for secrets of the module itself.
+    pub key_id: Option<String>,
+    /// A human-readable description to show to the user.
+    pub prompt: String,
+    /// The number of attempts left before the secret is blocked, if the module reports it.
+    pub retries_left: Option<u8>,
+}
+
+/// Events a provider announces while it waits for the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InteractionEvent {
+    /// The user has to touch the device for the current operation to proceed.
+    TouchRequired { module: String },
+    /// The user has to confirm the current operation on the device.
+    ConfirmOnDevice { module: String, message: String },
+    /// The operation that required the user's attention has finished.
+    Completed { module: String },
+}
+
+/// Provides secrets to security modules and informs the user about required interactions.
+///
+/// Providers call this trait whenever they need a PIN, password or management key, and
+/// announce events such as "touch required" through it. GUI, CLI and FFI consumers plug in
+/// their own implementation with `Provider::set_interaction_handler`; wrapping it in a
+/// `CachingInteractionHandler` avoids asking the user for the same secret repeatedly.
+pub trait InteractionHandler: Send + Sync + Debug {
+    /// Asks for the secret described by `request`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the secret as raw bytes on success, or a `SecurityModuleError`
+    /// if the secret cannot be provided, e.g. because the user cancelled the prompt.
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError>;
+
+    /// Reports that the secret returned for `request` was rejected by the security module.
+    ///
+    /// Implementations that cache secrets must forget the rejected one.
+    fn credential_rejected(&self, _request: &CredentialRequest) {}
+
+    /// Announces an event that requires the user's attention.
+    fn notify(&self, _event: &InteractionEvent) {}
+}
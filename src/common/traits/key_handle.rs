@@ -1,3 +1,4 @@
+use super::operations::{Decryptor, Encryptor, KeyAgreement, Signer, Verifier};
 use crate::common::error::SecurityModuleError;
 use std::fmt::Debug;
 #[cfg(feature = "linux")]
@@ -32,7 +33,33 @@ pub enum GenericKeyHandle {
 /// modules that manage cryptographic keys, ensuring a consistent interface for key
 /// operations across different types of security modules. Implementors of this trait
 /// must ensure thread safety.
+///
+/// The operations themselves are defined by the `Signer`, `Verifier`, `Encryptor`,
+/// `Decryptor` and `KeyAgreement` traits. A handle returns them from the `as_*` methods
+/// only if its key can perform them; the `*_data` methods remain as a facade over them and
+/// fail with `SecurityModuleError::UnsupportedOperation` otherwise.
 pub trait KeyHandle: Send + Sync + Debug {
+    /// Returns the key as a `Signer`, or `None` if it cannot sign.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        None
+    }
+    /// Returns the key as a `Verifier`, or `None` if it cannot verify signatures.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        None
+    }
+    /// Returns the key as an `Encryptor`, or `None` if it cannot encrypt.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        None
+    }
+    /// Returns the key as a `Decryptor`, or `None` if it cannot decrypt.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        None
+    }
+    /// Returns the key as a `KeyAgreement`, or `None` if it cannot derive shared secrets.
+    fn as_key_agreement(&self) -> Option<&dyn KeyAgreement> {
+        None
+    }
+
     /// Signs the given data using the cryptographic key.
     ///
     /// # Arguments
@@ -41,10 +68,10 @@ pub trait KeyHandle: Send + Sync + Debug {
     /// # Returns
     /// A `Result` containing the signature as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[tracing::instrument]
-    fn sign_data(&self, _data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        Err(SecurityModuleError::InitializationError(
-            "Method not implemented".to_owned(),
-        ))
+    fn sign_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.as_signer()
+            .ok_or_else(|| unsupported("signing"))?
+            .sign(data)
     }
     /// Decrypts the given encrypted data using the cryptographic key.
     ///
@@ -54,10 +81,10 @@ pub trait KeyHandle: Send + Sync + Debug {
     /// # Returns
     /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[tracing::instrument]
-    fn decrypt_data(&self, _encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        Err(SecurityModuleError::InitializationError(
-            "Method not implemented".to_owned(),
-        ))
+    fn decrypt_data(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.as_decryptor()
+            .ok_or_else(|| unsupported("decryption"))?
+            .decrypt(encrypted_data)
     }
     /// Encrypts the given data using the cryptographic key.
     ///
@@ -67,10 +94,10 @@ pub trait KeyHandle: Send + Sync + Debug {
     /// # Returns
     /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[tracing::instrument]
-    fn encrypt_data(&self, _data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        Err(SecurityModuleError::InitializationError(
-            "Method not implemented".to_owned(),
-        ))
+    fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.as_encryptor()
+            .ok_or_else(|| unsupported("encryption"))?
+            .encrypt(data)
     }
     /// Verifies the signature of the given data using the cryptographic key.
     ///
@@ -82,13 +109,38 @@ pub trait KeyHandle: Send + Sync + Debug {
     /// A `Result` containing a boolean indicating whether the signature is valid (`true`) or not (`false`),
     /// or a `SecurityModuleError` on failure.
     #[tracing::instrument]
-    fn verify_signature(
-        &self,
-        _data: &[u8],
-        _signature: &[u8],
-    ) -> Result<bool, SecurityModuleError> {
-        Err(SecurityModuleError::InitializationError(
-            "Method not implemented".to_owned(),
-        ))
+    fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        self.as_verifier()
+            .ok_or_else(|| unsupported("signature verification"))?
+            .verify(data, signature)
     }
+
+    /// Computes a message authentication code over the given data using a MAC key.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice representing the data to be authenticated.
+    ///
+    /// # Returns
+    /// A `Result` containing the MAC as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    #[tracing::instrument]
+    fn mac_data(&self, _data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        Err(unsupported("MAC"))
+    }
+    /// Verifies the message authentication code of the given data using a MAC key.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice representing the authenticated data.
+    /// * `mac` - A byte slice representing the MAC to be verified against the data.
+    ///
+    /// # Returns
+    /// A `Result` containing a boolean indicating whether the MAC is valid (`true`) or not (`false`),
+    /// or a `SecurityModuleError` on failure.
+    #[tracing::instrument]
+    fn verify_mac(&self, _data: &[u8], _mac: &[u8]) -> Result<bool, SecurityModuleError> {
+        Err(unsupported("MAC"))
+    }
+}
+
+fn unsupported(operation: &str) -> SecurityModuleError {
+    SecurityModuleError::UnsupportedOperation(format!("The key does not support {operation}"))
 }
@@ -1,4 +1,7 @@
+pub mod device_events;
+pub mod interaction;
 pub mod key_handle;
 pub mod module_provider;
 pub mod module_provider_config;
+pub mod operations;
 pub mod log_config;
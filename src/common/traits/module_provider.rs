@@ -1,6 +1,6 @@
-use super::key_handle::KeyHandle;
-use crate::common::error::SecurityModuleError;
-use std::{any::Any, fmt::Debug};
+use super::{interaction::InteractionHandler, key_handle::KeyHandle};
+use crate::common::{capabilities::Capabilities, error::SecurityModuleError};
+use std::{any::Any, fmt::Debug, sync::Arc};
 
 /// Defines the interface for a security module provider.
 ///
@@ -57,4 +57,37 @@ pub trait Provider: Send + Sync + KeyHandle + Debug {
     fn initialize_module(&mut self) -> Result<(), SecurityModuleError>;
 
     fn get_pub_key(&mut self) -> String;
+
+    /// Sets the handler the provider asks for PINs, passwords and management keys, and
+    /// informs about required user interaction such as touching the device.
+    ///
+    /// Providers that need no secrets ignore the handler.
+    fn set_interaction_handler(&mut self, _handler: Arc<dyn InteractionHandler>) {}
+
+    /// Returns `len` random bytes from the random number generator of the security module.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of random bytes to return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the random bytes as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    fn random_bytes(&self, _len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        Err(SecurityModuleError::InitializationError(
+            "Method not implemented".to_owned(),
+        ))
+    }
+
+    /// Describes the algorithms, key usages, key counts and operations the security module
+    /// supports.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Capabilities` on success, or a `SecurityModuleError` on failure.
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        Err(SecurityModuleError::InitializationError(
+            "Method not implemented".to_owned(),
+        ))
+    }
 }
@@ -0,0 +1,69 @@
+use crate::common::error::SecurityModuleError;
+use std::fmt::Debug;
+
+/// A key that can sign data.
+///
+/// Handles return this trait from `KeyHandle::as_signer` only if their algorithm and key
+/// usages permit signing, so callers can check for it before passing data to the key.
+pub trait Signer: Send + Sync + Debug {
+    /// Signs the given data using the private key.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice representing the data to be signed.
+    ///
+    /// # Returns
+    /// A `Result` containing the signature as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError>;
+}
+
+/// A key that can verify signatures.
+pub trait Verifier: Send + Sync + Debug {
+    /// Verifies the signature of the given data using the public key.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice representing the data whose signature is to be verified.
+    /// * `signature` - A byte slice representing the signature to be verified against the data.
+    ///
+    /// # Returns
+    /// A `Result` containing a boolean indicating whether the signature is valid (`true`) or not (`false`),
+    /// or a `SecurityModuleError` on failure.
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError>;
+}
+
+/// A key that can encrypt data, with a public key or a symmetric key.
+pub trait Encryptor: Send + Sync + Debug {
+    /// Encrypts the given data.
+    ///
+    /// # Arguments
+    /// * `data` - A byte slice representing the data to be encrypted.
+    ///
+    /// # Returns
+    /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError>;
+}
+
+/// A key that can decrypt data, with a private key or a symmetric key.
+pub trait Decryptor: Send + Sync + Debug {
+    /// Decrypts the given encrypted data.
+    ///
+    /// # Arguments
+    /// * `encrypted_data` - A byte slice representing the data to be decrypted.
+    ///
+    /// # Returns
+    /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError>;
+}
+
+/// A private key that can derive a shared secret with a peer's public key, such as with ECDH.
+pub trait KeyAgreement: Send + Sync + Debug {
+    /// Derives the shared secret with the holder of `peer_public_key`.
+    ///
+    /// # Arguments
+    /// * `peer_public_key` - The DER encoded SubjectPublicKeyInfo of the peer's key, on the
+    ///   same curve as this key.
+    ///
+    /// # Returns
+    /// A `Result` containing the raw shared secret on success, or a `SecurityModuleError` on failure.
+    /// The secret should be passed through a key derivation function before use.
+    fn agree(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, SecurityModuleError>;
+}
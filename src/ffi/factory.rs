@@ -1,6 +1,7 @@
 use super::provider::ProviderFFI;
 use crate::common::factory::{SecModules, SecurityModule};
 use std::{ffi::CStr, os::raw::c_char, ptr};
+use tracing::error;
 
 /// Exposes a C-compatible interface to manage security module instances.
 /// Retrieves a security module instance based on key identifier and module type.
@@ -17,7 +18,8 @@ use std::{ffi::CStr, os::raw::c_char, ptr};
 ///
 /// # Returns
 /// - A valid pointer to a `ProviderFFI` if successful.
-/// - `ptr::null_mut()` if the instance cannot be retrieved or input pointers are null.
+/// - `ptr::null_mut()` if the instance cannot be retrieved or input pointers are null. The
+///   reason is logged.
 #[no_mangle]
 pub unsafe extern "C" fn secmodules_get_instance(
     key_id: *const c_char,
@@ -40,11 +42,20 @@ pub unsafe extern "C" fn secmodules_get_instance(
         Err(_) => return ptr::null_mut(),
     };
 
-    let module = SecurityModule::from(module_type_str);
-  
+    let module = match module_type_str.parse::<SecurityModule>() {
+        Ok(module) => module,
+        Err(e) => {
+            error!("{}", e);
+            return ptr::null_mut();
+        }
+    };
+
     match SecModules::get_instance(key_id_str.to_string(), module, None) {
-        Some(provider) => ProviderFFI::new(provider),
-        None => ptr::null_mut(),
+        Ok(provider) => ProviderFFI::new(provider),
+        Err(e) => {
+            error!("{}", e);
+            ptr::null_mut()
+        }
     }
 }
 
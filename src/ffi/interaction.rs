@@ -0,0 +1,180 @@
+use super::provider::ProviderFFI;
+use crate::common::{
+    error::SecurityModuleError,
+    traits::interaction::{
+        CredentialKind, CredentialRequest, InteractionEvent, InteractionHandler,
+    },
+};
+use std::{
+    ffi::{c_void, CString},
+    os::raw::c_char,
+    ptr::null,
+    sync::Arc,
+};
+
+/// The largest secret a `request_credential` callback can return.
+const MAX_CREDENTIAL_LEN: usize = 1024;
+
+/// The kind of an `InteractionEvent` passed to the `notify` callback.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum InteractionEventKind {
+    TouchRequired,
+    ConfirmOnDevice,
+    Completed,
+}
+
+/// C callbacks implementing an `InteractionHandler`.
+///
+/// `request_credential` writes the secret into `output`, stores its length in
+/// `actual_output_len` and returns 0; any other return value cancels the request. `key_id`
+/// is null for secrets of the module itself and `retries_left` is -1 if unknown.
+///
+/// `credential_rejected` and `notify` are optional. For `notify`, `message` is null unless
+/// the event carries one. All strings are only valid for the duration of the call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InteractionCallbacksFFI {
+    pub user_data: *mut c_void,
+    pub request_credential: unsafe extern "C" fn(
+        user_data: *mut c_void,
+        kind: CredentialKind,
+        module: *const c_char,
+        key_id: *const c_char,
+        prompt: *const c_char,
+        retries_left: i32,
+        output: *mut u8,
+        output_capacity: usize,
+        actual_output_len: *mut usize,
+    ) -> i32,
+    pub credential_rejected: Option<
+        unsafe extern "C" fn(
+            user_data: *mut c_void,
+            kind: CredentialKind,
+            module: *const c_char,
+            key_id: *const c_char,
+        ),
+    >,
+    pub notify: Option<
+        unsafe extern "C" fn(
+            user_data: *mut c_void,
+            event: InteractionEventKind,
+            module: *const c_char,
+            message: *const c_char,
+        ),
+    >,
+}
+
+// SAFETY: `provider_set_interaction_callbacks` requires the callbacks and `user_data` to be
+// usable from any thread.
+unsafe impl Send for InteractionCallbacksFFI {}
+unsafe impl Sync for InteractionCallbacksFFI {}
+
+impl InteractionHandler for InteractionCallbacksFFI {
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let module = c_string(&request.module);
+        let key_id = request.key_id.as_deref().map(c_string);
+        let prompt = c_string(&request.prompt);
+        let mut output = vec![0u8; MAX_CREDENTIAL_LEN];
+        let mut actual_output_len = 0;
+
+        // SAFETY: all pointers are valid for the duration of the call and `output` holds
+        // `MAX_CREDENTIAL_LEN` bytes.
+        let result = unsafe {
+            (self.request_credential)(
+                self.user_data,
+                request.kind,
+                module.as_ptr(),
+                key_id.as_ref().map_or(null(), |key_id| key_id.as_ptr()),
+                prompt.as_ptr(),
+                request.retries_left.map_or(-1, i32::from),
+                output.as_mut_ptr(),
+                output.len(),
+                &mut actual_output_len,
+            )
+        };
+        if result != 0 || actual_output_len > output.len() {
+            return Err(SecurityModuleError::InitializationError(format!(
+                "No credential provided for: {}",
+                request.prompt
+            )));
+        }
+
+        output.truncate(actual_output_len);
+        Ok(output)
+    }
+
+    fn credential_rejected(&self, request: &CredentialRequest) {
+        let Some(credential_rejected) = self.credential_rejected else {
+            return;
+        };
+        let module = c_string(&request.module);
+        let key_id = request.key_id.as_deref().map(c_string);
+
+        // SAFETY: all pointers are valid for the duration of the call.
+        unsafe {
+            credential_rejected(
+                self.user_data,
+                request.kind,
+                module.as_ptr(),
+                key_id.as_ref().map_or(null(), |key_id| key_id.as_ptr()),
+            )
+        }
+    }
+
+    fn notify(&self, event: &InteractionEvent) {
+        let Some(notify) = self.notify else {
+            return;
+        };
+        let (kind, module, message) = match event {
+            InteractionEvent::TouchRequired { module } => {
+                (InteractionEventKind::TouchRequired, module, None)
+            }
+            InteractionEvent::ConfirmOnDevice { module, message } => (
+                InteractionEventKind::ConfirmOnDevice,
+                module,
+                Some(c_string(message)),
+            ),
+            InteractionEvent::Completed { module } => {
+                (InteractionEventKind::Completed, module, None)
+            }
+        };
+        let module = c_string(module);
+
+        // SAFETY: all pointers are valid for the duration of the call.
+        unsafe {
+            notify(
+                self.user_data,
+                kind,
+                module.as_ptr(),
+                message.as_ref().map_or(null(), |message| message.as_ptr()),
+            )
+        }
+    }
+}
+
+/// Sets the callbacks the provider asks for PINs, passwords and management keys.
+/// # Safety
+/// `provider_ffi` must be valid. The callbacks and `user_data` must stay valid as long as
+/// the provider exists and must be safe to call from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn provider_set_interaction_callbacks(
+    provider_ffi: *mut ProviderFFI,
+    callbacks: InteractionCallbacksFFI,
+) -> i32 {
+    if provider_ffi.is_null() {
+        return -1;
+    }
+
+    let provider = &mut *provider_ffi;
+    (*provider.provider).set_interaction_handler(Arc::new(callbacks));
+    0
+}
+
+/// Converts `value` into a C string, dropping interior NUL bytes.
+fn c_string(value: &str) -> CString {
+    CString::new(value.replace('\0', "")).unwrap_or_default()
+}
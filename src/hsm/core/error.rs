@@ -1,3 +1,4 @@
+use crate::common::traits::interaction::CredentialKind;
 use std::fmt;
 
 /// Represents errors that can occur within a Hardware Security Module (HSM).
@@ -13,6 +14,8 @@ use std::fmt;
 /// - `Authentication(String)`: Represents errors related to authentication failures, with a message describing the issue.
 /// - `DeviceSpecific(String)`: Encapsulates device-specific errors, with details provided in the message.
 /// - `UnsupportedFeature(String)`: Indicates attempts to use a feature not supported by the HSM, with a message explaining which feature.
+/// - `WrongCredential { kind, retries_left }`: A PIN, PUK or management key was rejected; `retries_left` is the number of attempts left, if known.
+/// - `Blocked(CredentialKind)`: The PIN or PUK is blocked after too many wrong attempts.
 #[derive(Debug)]
 #[repr(C)]
 pub enum HsmError {
@@ -20,6 +23,11 @@ pub enum HsmError {
     Authentication(String),
     DeviceSpecific(String),
     UnsupportedFeature(String),
+    WrongCredential {
+        kind: CredentialKind,
+        retries_left: Option<u8>,
+    },
+    Blocked(CredentialKind),
 }
 
 impl fmt::Display for HsmError {
@@ -33,6 +41,15 @@ impl fmt::Display for HsmError {
             HsmError::Authentication(ref msg) => write!(f, "Authentication error: {}", msg),
             HsmError::DeviceSpecific(ref msg) => write!(f, "Device-specific error: {}", msg),
             HsmError::UnsupportedFeature(ref msg) => write!(f, "Unsupported feature: {}", msg),
+            HsmError::WrongCredential {
+                kind,
+                retries_left: Some(retries_left),
+            } => write!(f, "Wrong {:?}, {} retries left", kind, retries_left),
+            HsmError::WrongCredential {
+                kind,
+                retries_left: None,
+            } => write!(f, "Wrong {:?}", kind),
+            HsmError::Blocked(kind) => write!(f, "{:?} is blocked", kind),
         }
     }
 }
@@ -1,5 +1,20 @@
-use crate::common::traits::module_provider::Provider;
-use std::sync::{Arc, Mutex};
+#[cfg(feature = "hsm")]
+use crate::common::factory::DiscoveredModule;
+#[cfg(any(feature = "yubi", feature = "pkcs11"))]
+use crate::common::factory::SecurityModule;
+use crate::common::{error::FactoryError, traits::module_provider::Provider};
+#[cfg(feature = "yubi")]
+use crate::hsm::yubikey::YubiKeyProvider;
+#[cfg(feature = "pkcs11")]
+use crate::hsm::{nitrokey, pkcs11::Pkcs11Provider};
+#[cfg(feature = "pkcs11")]
+use std::path::Path;
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+#[cfg(any(feature = "yubi", feature = "pkcs11"))]
+use tracing::warn;
 
 /// Represents the types of HSMs supported by the HSM system.
 ///
@@ -9,52 +24,69 @@ use std::sync::{Arc, Mutex};
 ///
 /// # Variants
 ///
-/// - `YubiKey`: Represents a YubiKey HSM.
-/// - `NitroKey`: Represents a NitroKey HSM.
+/// - `YubiKey`: Represents a YubiKey HSM, optionally selected by its serial number.
+/// - `NitroKey`: Represents a NitroKey HSM, used through the OpenSC PKCS #11 module.
 ///
 /// # Examples
 ///
 /// Converting from a string to a `HsmType`:
 ///
 /// ```
-/// let HSM_type: HsmType = "YubiKey".into();
-/// assert_eq!(HSM_type, HsmType::YubiKey);
+/// let HSM_type: HsmType = "YubiKey".parse().unwrap();
+/// assert_eq!(HSM_type, HsmType::YubiKey(None));
+///
+/// let HSM_type = HsmType::try_from("YubiKey:12345678").unwrap();
+/// assert_eq!(HSM_type, HsmType::YubiKey(Some(12345678)));
 /// ```
 #[repr(C)]
 #[derive(Eq, Hash, PartialEq, Default, Clone, Debug)]
 pub enum HsmType {
     #[default]
     NitroKey,
-    YubiKey,
+    /// The YubiKey with the given serial number, or the only connected one for `None`.
+    ///
+    /// Each serial is a separate instance, so several YubiKeys can be used at once.
+    YubiKey(Option<u32>),
 }
 
-// Implement From<&str> for HsmType to convert string arguments into enum variants.
-impl From<&str> for HsmType {
-    /// Converts a string slice into a `HsmType` enum variant.
-    ///
-    /// This implementation allows for creating `HsmType` variants from string literals,
-    /// facilitating easier parsing and handling of HSM types from text sources.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the string does not match any of the supported HSM types.
+impl FromStr for HsmType {
+    type Err = FactoryError;
+
+    /// Parses the name of an HSM type.
     ///
-    /// # Parameters
+    /// The names are `"YubiKey"` and `"NitroKey"`. A YubiKey is selected by its serial number
+    /// with `"YubiKey:<serial>"`.
     ///
-    /// - `s`: A string slice representing the HSM type.
+    /// # Errors
     ///
-    /// # Returns
-    ///
-    /// A `HsmType` variant corresponding to the input string.
-    fn from(s: &str) -> Self {
-        match s {
-            "YubiKey" => HsmType::YubiKey,
-            "NitroKey" => HsmType::NitroKey,
-            _ => panic!("Unsupported HsmType"),
+    /// Returns `FactoryError::UnknownModule` for other names and `FactoryError::InvalidName`
+    /// if the serial number is not a number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("YubiKey", serial)) => serial
+                .parse()
+                .map(|serial| HsmType::YubiKey(Some(serial)))
+                .map_err(|_| FactoryError::InvalidName {
+                    name: s.to_owned(),
+                    reason: format!("{:?} is not a YubiKey serial number", serial),
+                }),
+            _ => match s {
+                "YubiKey" => Ok(HsmType::YubiKey(None)),
+                "NitroKey" => Ok(HsmType::NitroKey),
+                _ => Err(FactoryError::UnknownModule(s.to_owned())),
+            },
         }
     }
 }
 
+impl TryFrom<&str> for HsmType {
+    type Error = FactoryError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 /// A representation of an HSM instance.
 ///
 /// This struct encapsulates the information and functionality related to an instance of a
@@ -80,22 +112,89 @@ impl HsmInstance {
     /// Creates a new instance of a provider based on the specified HSM type.
     ///
     /// This method initializes an HSM instance according to the HSM type provided.
-    /// It is currently stubbed with `todo!()`, indicating that the implementation is incomplete
-    /// and needs to be provided.
+    /// YubiKeys are supported with the `yubi` feature, NitroKeys with the `pkcs11` feature.
     ///
     /// # Parameters
     ///
-    /// - `_key_id`: A `String` specifying the key identifier for the HSM instance.
+    /// - `key_id`: A `String` specifying the key identifier for the HSM instance.
     /// - `hpm_type`: A reference to a `HsmType` specifying the type of HSM for the HSM instance.
     ///
     /// # Returns
     ///
     /// An `Arc<Mutex<dyn Provider>>`, wrapping the provider for the HSM instance in a thread-safe
-    /// reference-counting pointer.
-    pub fn create_instance(_key_id: String, hpm_type: &HsmType) -> Arc<Mutex<dyn Provider>> {
+    /// reference-counting pointer, or `FactoryError::FeatureDisabled` if the feature providing
+    /// the HSM type is not enabled.
+    #[cfg_attr(
+        not(any(feature = "yubi", feature = "pkcs11")),
+        allow(unused_variables)
+    )]
+    pub fn create_instance(
+        key_id: String,
+        hpm_type: &HsmType,
+    ) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
         match hpm_type {
-            HsmType::YubiKey => todo!(),
-            HsmType::NitroKey => todo!(),
+            #[cfg(feature = "yubi")]
+            HsmType::YubiKey(serial) => {
+                let instance = match serial {
+                    Some(serial) => YubiKeyProvider::with_serial(key_id, (*serial).into()),
+                    None => YubiKeyProvider::new(key_id),
+                };
+                Ok(Arc::new(Mutex::new(instance)))
+            }
+            #[cfg(not(feature = "yubi"))]
+            HsmType::YubiKey(_) => Err(FactoryError::FeatureDisabled {
+                module: "YubiKey".to_owned(),
+                feature: "yubi",
+            }),
+            #[cfg(feature = "pkcs11")]
+            HsmType::NitroKey => Ok(Arc::new(Mutex::new(Pkcs11Provider::new(
+                key_id,
+                nitrokey::config(),
+            )))),
+            #[cfg(not(feature = "pkcs11"))]
+            HsmType::NitroKey => Err(FactoryError::FeatureDisabled {
+                module: "NitroKey".to_owned(),
+                feature: "pkcs11",
+            }),
+        }
+    }
+
+    /// Lists the connected YubiKeys and, if the OpenSC module loads and has a token, the
+    /// NitroKey, as used by `SecModules::discover`.
+    #[cfg(feature = "hsm")]
+    pub fn discover() -> Vec<DiscoveredModule> {
+        #[allow(unused_mut)]
+        let mut modules = Vec::new();
+
+        #[cfg(feature = "yubi")]
+        match YubiKeyProvider::list_devices() {
+            Ok(devices) => modules.extend(devices.into_iter().map(|device| DiscoveredModule {
+                module: SecurityModule::Hsm(HsmType::YubiKey(Some(device.serial.0))),
+                description: format!(
+                    "YubiKey {} ({:?}), serial {}",
+                    device.version, device.form_factor, device.serial
+                ),
+            })),
+            Err(e) => warn!("Could not list YubiKeys: {}", e),
         }
+
+        #[cfg(feature = "pkcs11")]
+        match Pkcs11Provider::list_tokens(Path::new(nitrokey::OPENSC_MODULE)) {
+            Ok(tokens) => {
+                // `HsmType::NitroKey` uses the first token.
+                if let Some(token) = tokens.first() {
+                    modules.push(DiscoveredModule {
+                        module: SecurityModule::Hsm(HsmType::NitroKey),
+                        description: format!(
+                            "{} {} \"{}\", serial {}",
+                            token.manufacturer, token.model, token.label, token.serial
+                        ),
+                    });
+                }
+            }
+            Err(e) => warn!("Could not load {}: {}", nitrokey::OPENSC_MODULE, e),
+        }
+
+        modules
     }
 }
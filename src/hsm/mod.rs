@@ -10,6 +10,7 @@
 ///
 /// - `core`: Contains core functionality for HSM providers.
 /// - `nitrokey`: Provides support for Nitrokey HSM devices.
+/// - `pkcs11`: Offers support for any token with a PKCS #11 module (conditionally compiled with the `pkcs11` feature).
 /// - `yubikey`: Offers support for YubiKey HSM devices (conditionally compiled with the `yubi` feature).
 ///
 /// ## `HsmProviderConfig` Structure
@@ -17,7 +18,10 @@
 /// The `HsmProviderConfig` struct defines the configuration parameters for an HSM provider. It contains:
 ///
 /// - `key_algorithm`: Specifies the asymmetric encryption algorithm supported by the HSM.
-/// - `key_usage`: Specifies the key usages supported by the HSM.
+/// - `key_usages`: Specifies the key usages recorded for created keys.
+/// - `slot`: Selects the slot a key is stored in, see `KeySlot`.
+/// - `hash`, `rsa_padding`: Select how signatures are made.
+/// - `pin_policy`, `touch_policy`: Select when created keys require the PIN or a touch.
 ///
 /// ## Usage
 ///
@@ -45,7 +49,10 @@
 /// // Pass the configuration to the HSM provider for initialization
 /// let provider = initialize_hsm_provider(config);
 /// ```
-use crate::common::crypto::{algorithms::encryption::AsymmetricEncryption, KeyUsage};
+use crate::common::crypto::{
+    algorithms::{encryption::AsymmetricEncryption, hashes::Hash},
+    KeyUsage,
+};
 use crate::common::traits::module_provider_config::ProviderConfig;
 
 /// The core functionality for hardware security module (HSM) providers.
@@ -54,17 +61,105 @@ pub mod core;
 /// Provides support for Nitrokey HSM devices.
 pub mod nitrokey;
 
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 
+/// Provides support for tokens with a PKCS #11 module (conditionally compiled with the `pkcs11` feature).
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+
 /// Provides support for YubiKey HSM devices (conditionally compiled with the `yubi` feature).
 #[cfg(feature = "yubi")]
 pub mod yubikey;
 
+/// The standard PIV key slots and their intended use.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlotRole {
+    /// Slot 9A, used to authenticate the card holder, e.g. for system login.
+    Authentication,
+    /// Slot 9C, used for digital signatures on documents and email.
+    Signature,
+    /// Slot 9D, used to decrypt data encrypted for the card holder.
+    KeyManagement,
+    /// Slot 9E, used to authenticate the card itself, usable without the PIN.
+    CardAuthentication,
+}
+
+/// Selects the slot a key is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum KeySlot {
+    /// Any free retired slot; keys are looked up in all slots.
+    #[default]
+    Any,
+    /// One of the standard slots, so the key can be used by smart card middleware.
+    Role(SlotRole),
+    /// One of the 20 retired key management slots, numbered from 1 (82) to 20 (95).
+    Retired(u8),
+}
+
+/// When the PIN has to be verified before a key can be used.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum PinPolicy {
+    /// The default of the device for the slot.
+    #[default]
+    Default,
+    /// The key can be used without the PIN.
+    Never,
+    /// The PIN has to be verified once per session.
+    Once,
+    /// The PIN has to be verified before every use of the key.
+    Always,
+}
+
+/// When the device has to be touched before a key can be used.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum TouchPolicy {
+    /// The default of the device for the slot.
+    #[default]
+    Default,
+    /// The key can be used without touching the device.
+    Never,
+    /// The device has to be touched for every use of the key.
+    Always,
+    /// A touch is valid for 15 seconds.
+    Cached,
+}
+
+/// The padding scheme of RSA signatures.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum RsaPadding {
+    /// PKCS #1 v1.5 signatures.
+    #[default]
+    Pkcs1v15,
+    /// RSASSA-PSS signatures with MGF1 and a salt as long as the digest.
+    Pss,
+}
+
 /// Configuration parameters for an HSM provider.
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct HsmProviderConfig {
     /// The asymmetric encryption algorithm supported by the HSM.
-    pub(super) key_algorithm: AsymmetricEncryption,
+    pub key_algorithm: AsymmetricEncryption,
+    /// The usages recorded for keys created with this configuration.
+    pub key_usages: Vec<KeyUsage>,
+    /// The slot the key is created in or loaded from.
+    pub slot: KeySlot,
+    /// The hash used for signatures; `None` keeps SHA-256, except for P-384 keys on a
+    /// YubiKey, which sign with SHA-384.
+    pub hash: Option<Hash>,
+    /// The padding of signatures made with RSA keys.
+    pub rsa_padding: RsaPadding,
+    /// When the PIN has to be verified to use a created key.
+    pub pin_policy: PinPolicy,
+    /// When the device has to be touched to use a created key.
+    pub touch_policy: TouchPolicy,
+    /// Whether a key already stored in the requested `slot` may be replaced. Keys are never
+    /// replaced in slots chosen for `KeySlot::Any`.
+    pub overwrite: bool,
 }
 
 impl ProviderConfig for HsmProviderConfig {
@@ -80,15 +175,33 @@ impl HsmProviderConfig {
     /// # Arguments
     ///
     /// - `key_algorithm`: The asymmetric encryption algorithm supported by the HSM.
-    /// - `key_usage`: The key usages supported by the HSM.
     ///
     /// # Returns
     ///
     /// A boxed trait object representing the HSM provider configuration.
     #[allow(clippy::new_ret_no_self)]
     pub fn new(key_algorithm: AsymmetricEncryption) -> Box<dyn Any> {
+        Self::with_key_usages(key_algorithm, Vec::new())
+    }
+
+    /// Creates a new instance of `HsmProviderConfig` with the usages of the key.
+    ///
+    /// # Arguments
+    ///
+    /// - `key_algorithm`: The asymmetric encryption algorithm supported by the HSM.
+    /// - `key_usages`: The key usages stored with keys created from this configuration.
+    ///
+    /// # Returns
+    ///
+    /// A boxed trait object representing the HSM provider configuration.
+    pub fn with_key_usages(
+        key_algorithm: AsymmetricEncryption,
+        key_usages: Vec<KeyUsage>,
+    ) -> Box<dyn Any> {
         Box::new(Self {
             key_algorithm,
+            key_usages,
+            ..Default::default()
         })
     }
 }
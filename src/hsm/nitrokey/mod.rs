@@ -1 +1,19 @@
+//! Nitrokey HSMs are used through the PKCS #11 module of OpenSC, with the `pkcs11` feature.
+#[cfg(feature = "pkcs11")]
+use crate::hsm::pkcs11::Pkcs11Config;
 
+/// The OpenSC PKCS #11 module as installed by its Windows installer.
+#[cfg(target_os = "windows")]
+pub const OPENSC_MODULE: &str = r"C:\Program Files\OpenSC Project\OpenSC\pkcs11\opensc-pkcs11.dll";
+/// The OpenSC PKCS #11 module as installed by its macOS installer.
+#[cfg(target_os = "macos")]
+pub const OPENSC_MODULE: &str = "/Library/OpenSC/lib/opensc-pkcs11.so";
+/// The OpenSC PKCS #11 module, found in the library search path of the distribution.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const OPENSC_MODULE: &str = "opensc-pkcs11.so";
+
+/// The configuration `HsmType::NitroKey` uses: the first token of the OpenSC module.
+#[cfg(feature = "pkcs11")]
+pub fn config() -> Pkcs11Config {
+    Pkcs11Config::new(OPENSC_MODULE)
+}
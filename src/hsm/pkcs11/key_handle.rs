@@ -0,0 +1,260 @@
+use super::{module::Mechanism, sys::*, Pkcs11Provider};
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves},
+            hashes::{Hash, Sha2Bits},
+        },
+        error::SecurityModuleError,
+        traits::{
+            key_handle::KeyHandle,
+            operations::{Decryptor, Encryptor, Signer, Verifier},
+        },
+    },
+    hsm::{core::error::HsmError, RsaPadding},
+};
+use openssl::{
+    bn::BigNum,
+    ecdsa::EcdsaSig,
+    hash::hash,
+    nid::Nid,
+    pkey::PKey,
+    rsa::Padding,
+    sign::{self, RsaPssSaltlen},
+};
+use tracing::instrument;
+
+/// Provides cryptographic operations for asymmetric keys on a PKCS #11 token,
+/// such as signing, encryption, decryption, and signature verification.
+impl KeyHandle for Pkcs11Provider {
+    /// Returns the key as a `Signer` once a key is created or loaded.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        self.key_algorithm.is_some().then_some(self)
+    }
+
+    /// Returns the key as a `Verifier` once a key is created or loaded.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        self.key_algorithm.is_some().then_some(self)
+    }
+
+    /// Returns the key as an `Encryptor` if it is an RSA key.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        self.require_rsa().is_ok().then_some(self)
+    }
+
+    /// Returns the key as a `Decryptor` if it is an RSA key.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        self.require_rsa().is_ok().then_some(self)
+    }
+}
+
+impl Signer for Pkcs11Provider {
+    /// Signs `data` on the token with the configured hash, SHA-256 by default.
+    ///
+    /// RSA keys sign with the combined hash-and-sign mechanism for the configured padding.
+    /// For ECDSA the digest is computed in software, as many tokens only provide `CKM_ECDSA`,
+    /// and the signature is returned DER-encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to be signed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the signature as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    #[instrument]
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key = self.private_key()?;
+        let session = self.session()?.lock().unwrap();
+        let hash_algorithm = self.signature_hash();
+        match self.key_algorithm()? {
+            AsymmetricEncryption::Rsa(_) => {
+                let mechanism = rsa_mechanism(hash_algorithm, self.rsa_padding)?;
+                Ok(session.sign(mechanism, key, data)?)
+            }
+            AsymmetricEncryption::Ecc(_) => {
+                let md = hash_algorithm.message_digest().ok_or_else(|| {
+                    HsmError::UnsupportedFeature(format!("{:?} is not supported", hash_algorithm))
+                })?;
+                let digest = hash(md, data).map_err(openssl_error)?;
+                let signature = session.sign(Mechanism::Plain(CKM_ECDSA), key, &digest)?;
+                ecdsa_signature_to_der(&signature)
+            }
+        }
+    }
+}
+
+impl Decryptor for Pkcs11Provider {
+    /// Decrypts data encrypted with PKCS #1 v1.5 padding on the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_data` - The data to be decrypted.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    #[instrument]
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key = self.private_key()?;
+        self.require_rsa()?;
+        let session = self.session()?.lock().unwrap();
+        Ok(session.decrypt(Mechanism::Plain(CKM_RSA_PKCS), key, encrypted_data)?)
+    }
+}
+
+impl Encryptor for Pkcs11Provider {
+    /// Encrypts data with PKCS #1 v1.5 padding on the token.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to be encrypted.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    #[instrument]
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key = self.public_key()?;
+        self.require_rsa()?;
+        let session = self.session()?.lock().unwrap();
+        Ok(session.encrypt(Mechanism::Plain(CKM_RSA_PKCS), key, data)?)
+    }
+}
+
+impl Verifier for Pkcs11Provider {
+    /// Verifies a signature made by `sign` with the public key, in software.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The original data associated with the signature.
+    /// * `signature` - The signature to be verified.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the signature is valid (`true`) or not (`false`),
+    /// or a `SecurityModuleError` on failure.
+    #[instrument]
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        let key_algorithm = self.key_algorithm()?;
+        let pkey = PKey::public_key_from_pem(self.pkey.as_bytes()).map_err(openssl_error)?;
+        let hash_algorithm = self.signature_hash();
+        let md = hash_algorithm.message_digest().ok_or_else(|| {
+            HsmError::UnsupportedFeature(format!("{:?} is not supported", hash_algorithm))
+        })?;
+
+        let mut verifier = sign::Verifier::new(md, &pkey).map_err(openssl_error)?;
+        if matches!(key_algorithm, AsymmetricEncryption::Rsa(_))
+            && self.rsa_padding == RsaPadding::Pss
+        {
+            verifier
+                .set_rsa_padding(Padding::PKCS1_PSS)
+                .and_then(|()| verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH))
+                .and_then(|()| verifier.set_rsa_mgf1_md(md))
+                .map_err(openssl_error)?;
+        }
+        verifier.update(data).map_err(openssl_error)?;
+        // OpenSSL reports malformed signatures as errors; they are just invalid here.
+        Ok(verifier.verify(signature).unwrap_or(false))
+    }
+}
+
+impl Pkcs11Provider {
+    /// The hash signatures are made with.
+    fn signature_hash(&self) -> Hash {
+        self.hash.unwrap_or(Hash::Sha2(Sha2Bits::Sha256))
+    }
+
+    fn key_algorithm(&self) -> Result<AsymmetricEncryption, HsmError> {
+        self.key_algorithm
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))
+    }
+
+    fn require_rsa(&self) -> Result<(), HsmError> {
+        match self.key_algorithm()? {
+            AsymmetricEncryption::Rsa(_) => Ok(()),
+            AsymmetricEncryption::Ecc(_) => Err(HsmError::UnsupportedFeature(
+                "Encryption is only supported with RSA keys".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The combined hash-and-sign mechanism for RSA signatures with `hash` and `padding`.
+pub(super) fn rsa_mechanism(hash: Hash, padding: RsaPadding) -> Result<Mechanism, HsmError> {
+    let mechanisms = hash_mechanisms(hash)
+        .ok_or_else(|| HsmError::UnsupportedFeature(format!("{:?} is not supported", hash)))?;
+    Ok(match padding {
+        RsaPadding::Pkcs1v15 => Mechanism::Plain(mechanisms.rsa_pkcs),
+        RsaPadding::Pss => {
+            let salt_len = hash.message_digest().map_or(0, |md| md.size());
+            Mechanism::RsaPss(
+                mechanisms.rsa_pss,
+                CK_RSA_PKCS_PSS_PARAMS {
+                    hashAlg: mechanisms.digest,
+                    mgf: mechanisms.mgf,
+                    sLen: salt_len as CK_ULONG,
+                },
+            )
+        }
+    })
+}
+
+/// The DER-encoded OID of `curve`, the value of `CKA_EC_PARAMS`.
+pub(crate) fn ec_params(curve: EccCurves) -> Result<&'static [u8], HsmError> {
+    match curve {
+        EccCurves::P256 => Ok(&[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]),
+        EccCurves::P384 => Ok(&[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22]),
+        EccCurves::P521 => Ok(&[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x23]),
+        EccCurves::Secp256k1 => Ok(&[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a]),
+        EccCurves::BrainpoolP256r1 => Ok(&[
+            0x06, 0x09, 0x2b, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x07,
+        ]),
+        EccCurves::BrainpoolP384r1 => Ok(&[
+            0x06, 0x09, 0x2b, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0b,
+        ]),
+        EccCurves::BrainpoolP512r1 => Ok(&[
+            0x06, 0x09, 0x2b, 0x24, 0x03, 0x03, 0x02, 0x08, 0x01, 0x01, 0x0d,
+        ]),
+        curve => Err(HsmError::UnsupportedFeature(format!(
+            "{:?} is not supported",
+            curve
+        ))),
+    }
+}
+
+/// The OpenSSL name of `curve`, for the curves of `ec_params`.
+pub(crate) fn curve_nid(curve: EccCurves) -> Result<Nid, HsmError> {
+    match curve {
+        EccCurves::P256 => Ok(Nid::X9_62_PRIME256V1),
+        EccCurves::P384 => Ok(Nid::SECP384R1),
+        EccCurves::P521 => Ok(Nid::SECP521R1),
+        EccCurves::Secp256k1 => Ok(Nid::SECP256K1),
+        EccCurves::BrainpoolP256r1 => Ok(Nid::BRAINPOOL_P256R1),
+        EccCurves::BrainpoolP384r1 => Ok(Nid::BRAINPOOL_P384R1),
+        EccCurves::BrainpoolP512r1 => Ok(Nid::BRAINPOOL_P512R1),
+        curve => Err(HsmError::UnsupportedFeature(format!(
+            "{:?} is not supported",
+            curve
+        ))),
+    }
+}
+
+/// Converts an ECDSA signature from the `r || s` form of `CKM_ECDSA` to DER.
+pub(crate) fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    if signature.is_empty() || !signature.len().is_multiple_of(2) {
+        return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+            "Invalid ECDSA signature length".to_owned(),
+        )));
+    }
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let r = BigNum::from_slice(r).map_err(openssl_error)?;
+    let s = BigNum::from_slice(s).map_err(openssl_error)?;
+    EcdsaSig::from_private_components(r, s)
+        .and_then(|signature| signature.to_der())
+        .map_err(openssl_error)
+}
+
+pub(super) fn openssl_error(err: openssl::error::ErrorStack) -> SecurityModuleError {
+    SecurityModuleError::Hsm(HsmError::DeviceSpecific(err.to_string()))
+}
@@ -0,0 +1,186 @@
+use crate::common::{
+    crypto::{
+        algorithms::{encryption::AsymmetricEncryption, hashes::Hash},
+        pkcs::cryptoki as sys,
+    },
+    traits::interaction::InteractionHandler,
+};
+use crate::hsm::{core::error::HsmError, RsaPadding};
+use module::{Module, Session};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use sys::{CK_OBJECT_HANDLE, CK_SLOT_ID};
+use tracing::instrument;
+
+pub mod key_handle;
+mod module;
+pub mod provider;
+
+/// The module name used in interaction requests and events.
+const MODULE_NAME: &str = "PKCS#11";
+
+/// Selects the token a `Pkcs11Provider` uses among the tokens of a module.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum TokenSelector {
+    /// The token in the first slot, e.g. for modules with a single token.
+    #[default]
+    First,
+    /// The token with the given label.
+    Label(String),
+    /// The token with the given serial number.
+    Serial(String),
+    /// The token in the slot with the given ID.
+    Slot(u64),
+}
+
+/// The PKCS #11 module a `Pkcs11Provider` loads, and the token it uses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pkcs11Config {
+    /// The path of the module's shared library, e.g. `opensc-pkcs11.so`.
+    pub module: PathBuf,
+    pub token: TokenSelector,
+}
+
+impl Pkcs11Config {
+    /// Uses the first token of the module at `module`.
+    pub fn new(module: impl Into<PathBuf>) -> Self {
+        Self {
+            module: module.into(),
+            token: TokenSelector::First,
+        }
+    }
+
+    /// Uses the token selected by `token` instead of the first one.
+    pub fn with_token(self, token: TokenSelector) -> Self {
+        Self { token, ..self }
+    }
+}
+
+/// A token found by `Pkcs11Provider::list_tokens`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkcs11Token {
+    /// The ID of the slot the token is in.
+    pub slot: u64,
+    pub label: String,
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
+}
+
+/// A provider for keys on a token of any PKCS #11 module, such as a Nitrokey HSM,
+/// SmartCard-HSM, network HSM or SoftHSMv2.
+///
+/// The module is loaded at runtime in `initialize_module`. Keys are identified by their
+/// `CKA_LABEL`, which is set to the `key_id` when they are created.
+#[derive(Debug)]
+pub struct Pkcs11Provider {
+    config: Pkcs11Config,
+    session: Option<Mutex<Session>>,
+    /// The label of the token, for prompts.
+    token_label: String,
+    private_key: Option<CK_OBJECT_HANDLE>,
+    public_key: Option<CK_OBJECT_HANDLE>,
+    key_algorithm: Option<AsymmetricEncryption>,
+    /// The hash for signatures, `None` for SHA-256.
+    hash: Option<Hash>,
+    rsa_padding: RsaPadding,
+    /// The public key as PEM.
+    pkey: String,
+    interaction: Option<Arc<dyn InteractionHandler>>,
+}
+
+impl Pkcs11Provider {
+    /// Constructs a new `Pkcs11Provider` for the token selected by `config`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - A string identifier for the cryptographic key to be managed by this provider.
+    /// * `config` - The module to load and the token to use.
+    #[instrument]
+    pub fn new(key_id: String, config: Pkcs11Config) -> Self {
+        Self {
+            config,
+            session: None,
+            token_label: String::new(),
+            private_key: None,
+            public_key: None,
+            key_algorithm: None,
+            hash: None,
+            rsa_padding: RsaPadding::default(),
+            pkey: String::new(),
+            interaction: None,
+        }
+    }
+
+    /// The module and token this provider uses.
+    pub fn config(&self) -> &Pkcs11Config {
+        &self.config
+    }
+
+    /// Lists the tokens present in the slots of the module at `module`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the tokens on success, or an `HsmError` if the module cannot be
+    /// loaded.
+    // `CK_SLOT_ID` is only 32 bits on Windows.
+    #[allow(clippy::useless_conversion)]
+    #[instrument]
+    pub fn list_tokens(module: &Path) -> Result<Vec<Pkcs11Token>, HsmError> {
+        let module = Module::load(module)?;
+        module
+            .slots()?
+            .into_iter()
+            .map(|slot| {
+                let info = module.token_info(slot)?;
+                Ok(Pkcs11Token {
+                    slot: u64::from(slot),
+                    label: info.label,
+                    manufacturer: info.manufacturer,
+                    model: info.model,
+                    serial: info.serial,
+                })
+            })
+            .collect()
+    }
+
+    /// The session with the token, opened by `initialize_module`.
+    fn session(&self) -> Result<&Mutex<Session>, HsmError> {
+        self.session
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("Module is not initialized".to_owned()))
+    }
+
+    /// The handle of the loaded private key.
+    fn private_key(&self) -> Result<CK_OBJECT_HANDLE, HsmError> {
+        self.private_key
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))
+    }
+
+    /// The handle of the loaded public key.
+    fn public_key(&self) -> Result<CK_OBJECT_HANDLE, HsmError> {
+        self.public_key
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))
+    }
+}
+
+/// Finds the slot of the token selected by `selector`.
+#[allow(clippy::useless_conversion)]
+fn select_slot(module: &Module, selector: &TokenSelector) -> Result<CK_SLOT_ID, HsmError> {
+    let slots = module.slots()?;
+    let slot = match selector {
+        TokenSelector::First => slots.first().copied(),
+        TokenSelector::Slot(id) => slots.iter().copied().find(|slot| u64::from(*slot) == *id),
+        TokenSelector::Label(label) => slots
+            .iter()
+            .copied()
+            .find(|slot| matches!(module.token_info(*slot), Ok(info) if info.label == *label)),
+        TokenSelector::Serial(serial) => slots
+            .iter()
+            .copied()
+            .find(|slot| matches!(module.token_info(*slot), Ok(info) if info.serial == *serial)),
+    };
+    slot.ok_or_else(|| HsmError::DeviceSpecific(format!("No token found for {:?}", selector)))
+}
@@ -0,0 +1,576 @@
+use super::sys::*;
+use crate::hsm::core::error::HsmError;
+use libloading::Library;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    os::raw::c_void,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Mutex, Weak},
+};
+use tracing::warn;
+
+/// The loaded modules, so a module is initialized only once per process.
+///
+/// `C_Initialize` may be called only once until `C_Finalize`, and `C_Finalize` ends every
+/// session of the process, so all providers using the same module share one `Module`.
+static MODULES: Lazy<Mutex<HashMap<PathBuf, Weak<Module>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Calls `$function` of the function list of `$module`, returning
+/// `CKR_FUNCTION_NOT_SUPPORTED` if the module does not provide it.
+macro_rules! call {
+    ($module:expr, $function:ident($($arg:expr),* $(,)?)) => {
+        match $module.functions().$function {
+            // SAFETY: the function list stays valid while the library is loaded, and the
+            // callers pass pointers that are valid for the duration of the call.
+            Some(function) => unsafe { function($($arg),*) },
+            None => CKR_FUNCTION_NOT_SUPPORTED,
+        }
+    };
+}
+
+/// A PKCS #11 module loaded from a shared library.
+pub(super) struct Module {
+    functions: *const CK_FUNCTION_LIST,
+    /// Whether this process initialized the module, and has to finalize it.
+    finalize: bool,
+    path: PathBuf,
+    // Dropped last, as the function list points into the library.
+    _library: Library,
+}
+
+// SAFETY: the module is initialized with `CKF_OS_LOCKING_OK`, which makes it safe to call
+// from several threads; the function list itself is never written.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl std::fmt::Debug for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Module").field("path", &self.path).finish()
+    }
+}
+
+/// The information about a token needed to select and log in to it.
+#[derive(Debug, Clone)]
+pub(super) struct TokenInfo {
+    pub(super) label: String,
+    pub(super) manufacturer: String,
+    pub(super) model: String,
+    pub(super) serial: String,
+    pub(super) flags: CK_FLAGS,
+}
+
+impl Module {
+    /// Loads and initializes the module at `path`, or returns the already loaded one.
+    pub(super) fn load(path: &Path) -> Result<Arc<Module>, HsmError> {
+        let mut modules = MODULES.lock().unwrap();
+        if let Some(module) = modules.get(path).and_then(Weak::upgrade) {
+            return Ok(module);
+        }
+
+        // SAFETY: loading a library runs its initializers; PKCS #11 modules are expected
+        // to be safe to load.
+        let library = unsafe { Library::new(path) }.map_err(|err| {
+            HsmError::DeviceSpecific(format!(
+                "Failed to load the PKCS #11 module {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        let mut functions = ptr::null();
+        // SAFETY: `C_GetFunctionList` has this signature in every PKCS #11 module.
+        let rv = unsafe {
+            let get_function_list = library
+                .get::<CK_C_GetFunctionList>(b"C_GetFunctionList\0")
+                .map_err(|err| {
+                    HsmError::DeviceSpecific(format!(
+                        "{} is not a PKCS #11 module: {}",
+                        path.display(),
+                        err
+                    ))
+                })?;
+            get_function_list(&mut functions)
+        };
+        check(rv, "C_GetFunctionList")?;
+        if functions.is_null() {
+            return Err(HsmError::DeviceSpecific(
+                "C_GetFunctionList returned no function list".to_owned(),
+            ));
+        }
+
+        let mut module = Module {
+            functions,
+            finalize: true,
+            path: path.to_owned(),
+            _library: library,
+        };
+        let mut args = CK_C_INITIALIZE_ARGS {
+            CreateMutex: ptr::null_mut(),
+            DestroyMutex: ptr::null_mut(),
+            LockMutex: ptr::null_mut(),
+            UnlockMutex: ptr::null_mut(),
+            flags: CKF_OS_LOCKING_OK,
+            pReserved: ptr::null_mut(),
+        };
+        match call!(module, C_Initialize(&mut args as *mut _ as *mut c_void)) {
+            CKR_OK => {}
+            // Another part of the process uses the module as well, and finalizes it.
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => module.finalize = false,
+            rv => {
+                module.finalize = false;
+                check(rv, "C_Initialize")?;
+            }
+        }
+
+        let module = Arc::new(module);
+        modules.insert(path.to_owned(), Arc::downgrade(&module));
+        Ok(module)
+    }
+
+    fn functions(&self) -> &CK_FUNCTION_LIST {
+        // SAFETY: checked to be non-null in `load`, and valid while the library is loaded.
+        unsafe { &*self.functions }
+    }
+
+    /// The slots with a token present.
+    pub(super) fn slots(&self) -> Result<Vec<CK_SLOT_ID>, HsmError> {
+        let mut count = 0;
+        check(
+            call!(self, C_GetSlotList(CK_TRUE, ptr::null_mut(), &mut count)),
+            "C_GetSlotList",
+        )?;
+        let mut slots = vec![0; count as usize];
+        check(
+            call!(self, C_GetSlotList(CK_TRUE, slots.as_mut_ptr(), &mut count)),
+            "C_GetSlotList",
+        )?;
+        slots.truncate(count as usize);
+        Ok(slots)
+    }
+
+    pub(super) fn token_info(&self, slot: CK_SLOT_ID) -> Result<TokenInfo, HsmError> {
+        // SAFETY: `CK_TOKEN_INFO` consists of integers and byte arrays only.
+        let mut info: CK_TOKEN_INFO = unsafe { std::mem::zeroed() };
+        check(
+            call!(self, C_GetTokenInfo(slot, &mut info)),
+            "C_GetTokenInfo",
+        )?;
+        Ok(TokenInfo {
+            label: padded_string(&info.label),
+            manufacturer: padded_string(&info.manufacturerID),
+            model: padded_string(&info.model),
+            serial: padded_string(&info.serialNumber),
+            flags: info.flags,
+        })
+    }
+
+    /// Opens a read/write session with the token in `slot`.
+    pub(super) fn open_session(self: &Arc<Self>, slot: CK_SLOT_ID) -> Result<Session, HsmError> {
+        let mut handle = 0;
+        check(
+            call!(
+                self,
+                C_OpenSession(
+                    slot,
+                    CKF_SERIAL_SESSION | CKF_RW_SESSION,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &mut handle,
+                )
+            ),
+            "C_OpenSession",
+        )?;
+        Ok(Session {
+            module: Arc::clone(self),
+            slot,
+            handle,
+        })
+    }
+
+    /// The mechanisms of the token in `slot`, with their key sizes and flags.
+    pub(super) fn mechanisms(
+        &self,
+        slot: CK_SLOT_ID,
+    ) -> Result<Vec<(CK_MECHANISM_TYPE, CK_MECHANISM_INFO)>, HsmError> {
+        let mut count = 0;
+        check(
+            call!(self, C_GetMechanismList(slot, ptr::null_mut(), &mut count)),
+            "C_GetMechanismList",
+        )?;
+        let mut mechanisms = vec![0; count as usize];
+        check(
+            call!(
+                self,
+                C_GetMechanismList(slot, mechanisms.as_mut_ptr(), &mut count)
+            ),
+            "C_GetMechanismList",
+        )?;
+        mechanisms.truncate(count as usize);
+
+        mechanisms
+            .into_iter()
+            .map(|mechanism| {
+                let mut info = CK_MECHANISM_INFO {
+                    ulMinKeySize: 0,
+                    ulMaxKeySize: 0,
+                    flags: 0,
+                };
+                check(
+                    call!(self, C_GetMechanismInfo(slot, mechanism, &mut info)),
+                    "C_GetMechanismInfo",
+                )?;
+                Ok((mechanism, info))
+            })
+            .collect()
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        if self.finalize {
+            // Keeps `load` from initializing the module again before it is finalized.
+            let _modules = MODULES.lock().unwrap();
+            let rv = call!(self, C_Finalize(ptr::null_mut()));
+            if rv != CKR_OK {
+                warn!("C_Finalize failed: {}", rv_name(rv));
+            }
+        }
+    }
+}
+
+/// The attributes of an object to create or search for.
+#[derive(Debug, Default)]
+pub(super) struct Template {
+    attributes: Vec<(CK_ATTRIBUTE_TYPE, Vec<u8>)>,
+}
+
+impl Template {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn bool(self, attribute: CK_ATTRIBUTE_TYPE, value: bool) -> Self {
+        let value = if value { CK_TRUE } else { CK_FALSE };
+        self.bytes(attribute, &[value])
+    }
+
+    pub(super) fn ulong(self, attribute: CK_ATTRIBUTE_TYPE, value: CK_ULONG) -> Self {
+        self.bytes(attribute, &value.to_ne_bytes())
+    }
+
+    pub(super) fn bytes(mut self, attribute: CK_ATTRIBUTE_TYPE, value: &[u8]) -> Self {
+        self.attributes.push((attribute, value.to_vec()));
+        self
+    }
+
+    /// The attributes in the layout of the C interface, pointing into `self`.
+    fn raw(&mut self) -> Vec<CK_ATTRIBUTE> {
+        self.attributes
+            .iter_mut()
+            .map(|(attribute, value)| CK_ATTRIBUTE {
+                type_: *attribute,
+                pValue: value.as_mut_ptr() as *mut c_void,
+                ulValueLen: value.len() as CK_ULONG,
+            })
+            .collect()
+    }
+}
+
+/// A mechanism and its parameter.
+pub(super) enum Mechanism {
+    Plain(CK_MECHANISM_TYPE),
+    RsaPss(CK_MECHANISM_TYPE, CK_RSA_PKCS_PSS_PARAMS),
+}
+
+impl Mechanism {
+    fn raw(&mut self) -> CK_MECHANISM {
+        match self {
+            Mechanism::Plain(mechanism) => CK_MECHANISM {
+                mechanism: *mechanism,
+                pParameter: ptr::null_mut(),
+                ulParameterLen: 0,
+            },
+            Mechanism::RsaPss(mechanism, params) => CK_MECHANISM {
+                mechanism: *mechanism,
+                pParameter: params as *mut _ as *mut c_void,
+                ulParameterLen: std::mem::size_of::<CK_RSA_PKCS_PSS_PARAMS>() as CK_ULONG,
+            },
+        }
+    }
+}
+
+/// A session with a token, closed on drop.
+#[derive(Debug)]
+pub(super) struct Session {
+    module: Arc<Module>,
+    slot: CK_SLOT_ID,
+    handle: CK_SESSION_HANDLE,
+}
+
+impl Session {
+    /// The information about the token of the session.
+    pub(super) fn token_info(&self) -> Result<TokenInfo, HsmError> {
+        self.module.token_info(self.slot)
+    }
+
+    /// The mechanisms of the token of the session, see `Module::mechanisms`.
+    pub(super) fn mechanisms(
+        &self,
+    ) -> Result<Vec<(CK_MECHANISM_TYPE, CK_MECHANISM_INFO)>, HsmError> {
+        self.module.mechanisms(self.slot)
+    }
+
+    /// Logs in as user with `pin`, or through the protected authentication path of the
+    /// token for `None`.
+    ///
+    /// Returns the raw return value on failure, so the caller can tell wrong and blocked
+    /// PINs apart.
+    pub(super) fn login(&self, pin: Option<&[u8]>) -> Result<(), CK_RV> {
+        let (pin, len) = match pin {
+            Some(pin) => (pin.as_ptr(), pin.len() as CK_ULONG),
+            None => (ptr::null(), 0),
+        };
+        match call!(self.module, C_Login(self.handle, CKU_USER, pin, len)) {
+            CKR_OK | CKR_USER_ALREADY_LOGGED_IN => Ok(()),
+            rv => Err(rv),
+        }
+    }
+
+    /// Generates a key pair, returning the handles of the public and the private key.
+    pub(super) fn generate_key_pair(
+        &self,
+        mechanism: CK_MECHANISM_TYPE,
+        mut public_template: Template,
+        mut private_template: Template,
+    ) -> Result<(CK_OBJECT_HANDLE, CK_OBJECT_HANDLE), HsmError> {
+        let mut mechanism = Mechanism::Plain(mechanism).raw();
+        let mut public_attributes = public_template.raw();
+        let mut private_attributes = private_template.raw();
+        let (mut public_key, mut private_key) = (0, 0);
+        check(
+            call!(
+                self.module,
+                C_GenerateKeyPair(
+                    self.handle,
+                    &mut mechanism,
+                    public_attributes.as_mut_ptr(),
+                    public_attributes.len() as CK_ULONG,
+                    private_attributes.as_mut_ptr(),
+                    private_attributes.len() as CK_ULONG,
+                    &mut public_key,
+                    &mut private_key,
+                )
+            ),
+            "C_GenerateKeyPair",
+        )?;
+        Ok((public_key, private_key))
+    }
+
+    /// The objects matching `template`.
+    pub(super) fn find_objects(
+        &self,
+        mut template: Template,
+    ) -> Result<Vec<CK_OBJECT_HANDLE>, HsmError> {
+        let mut attributes = template.raw();
+        check(
+            call!(
+                self.module,
+                C_FindObjectsInit(
+                    self.handle,
+                    attributes.as_mut_ptr(),
+                    attributes.len() as CK_ULONG,
+                )
+            ),
+            "C_FindObjectsInit",
+        )?;
+
+        let mut objects = Vec::new();
+        let result = loop {
+            let mut batch = [0; 16];
+            let mut count = 0;
+            let rv = call!(
+                self.module,
+                C_FindObjects(
+                    self.handle,
+                    batch.as_mut_ptr(),
+                    batch.len() as CK_ULONG,
+                    &mut count,
+                )
+            );
+            if let Err(err) = check(rv, "C_FindObjects") {
+                break Err(err);
+            }
+            if count == 0 {
+                break Ok(());
+            }
+            objects.extend_from_slice(&batch[..count as usize]);
+        };
+        let rv = call!(self.module, C_FindObjectsFinal(self.handle));
+        result.and_then(|()| check(rv, "C_FindObjectsFinal"))?;
+        Ok(objects)
+    }
+
+    /// The value of `attribute` of `object`.
+    pub(super) fn attribute(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        attribute: CK_ATTRIBUTE_TYPE,
+    ) -> Result<Vec<u8>, HsmError> {
+        let mut raw = CK_ATTRIBUTE {
+            type_: attribute,
+            pValue: ptr::null_mut(),
+            ulValueLen: 0,
+        };
+        check(
+            call!(
+                self.module,
+                C_GetAttributeValue(self.handle, object, &mut raw, 1)
+            ),
+            "C_GetAttributeValue",
+        )?;
+        if raw.ulValueLen == CK_UNAVAILABLE_INFORMATION {
+            return Err(HsmError::DeviceSpecific(format!(
+                "Attribute 0x{:x} is not available",
+                attribute
+            )));
+        }
+        let mut value = vec![0u8; raw.ulValueLen as usize];
+        raw.pValue = value.as_mut_ptr() as *mut c_void;
+        check(
+            call!(
+                self.module,
+                C_GetAttributeValue(self.handle, object, &mut raw, 1)
+            ),
+            "C_GetAttributeValue",
+        )?;
+        value.truncate(raw.ulValueLen as usize);
+        Ok(value)
+    }
+
+    pub(super) fn destroy_object(&self, object: CK_OBJECT_HANDLE) -> Result<(), HsmError> {
+        check(
+            call!(self.module, C_DestroyObject(self.handle, object)),
+            "C_DestroyObject",
+        )
+    }
+
+    pub(super) fn sign(
+        &self,
+        mut mechanism: Mechanism,
+        key: CK_OBJECT_HANDLE,
+        data: &[u8],
+    ) -> Result<Vec<u8>, HsmError> {
+        let mut raw = mechanism.raw();
+        check(
+            call!(self.module, C_SignInit(self.handle, &mut raw, key)),
+            "C_SignInit",
+        )?;
+        self.single_part(data, "C_Sign", |data, len, out, out_len| {
+            call!(self.module, C_Sign(self.handle, data, len, out, out_len))
+        })
+    }
+
+    pub(super) fn encrypt(
+        &self,
+        mut mechanism: Mechanism,
+        key: CK_OBJECT_HANDLE,
+        data: &[u8],
+    ) -> Result<Vec<u8>, HsmError> {
+        let mut raw = mechanism.raw();
+        check(
+            call!(self.module, C_EncryptInit(self.handle, &mut raw, key)),
+            "C_EncryptInit",
+        )?;
+        self.single_part(data, "C_Encrypt", |data, len, out, out_len| {
+            call!(self.module, C_Encrypt(self.handle, data, len, out, out_len))
+        })
+    }
+
+    pub(super) fn decrypt(
+        &self,
+        mut mechanism: Mechanism,
+        key: CK_OBJECT_HANDLE,
+        data: &[u8],
+    ) -> Result<Vec<u8>, HsmError> {
+        let mut raw = mechanism.raw();
+        check(
+            call!(self.module, C_DecryptInit(self.handle, &mut raw, key)),
+            "C_DecryptInit",
+        )?;
+        self.single_part(data, "C_Decrypt", |data, len, out, out_len| {
+            call!(self.module, C_Decrypt(self.handle, data, len, out, out_len))
+        })
+    }
+
+    pub(super) fn generate_random(&self, len: usize) -> Result<Vec<u8>, HsmError> {
+        let mut random = vec![0; len];
+        check(
+            call!(
+                self.module,
+                C_GenerateRandom(self.handle, random.as_mut_ptr(), len as CK_ULONG)
+            ),
+            "C_GenerateRandom",
+        )?;
+        Ok(random)
+    }
+
+    /// Runs a single-part operation such as `C_Sign`, first asking for the length of the
+    /// output and then for the output itself.
+    fn single_part(
+        &self,
+        data: &[u8],
+        name: &str,
+        mut function: impl FnMut(*const CK_BYTE, CK_ULONG, *mut CK_BYTE, *mut CK_ULONG) -> CK_RV,
+    ) -> Result<Vec<u8>, HsmError> {
+        let mut len = 0;
+        check(
+            function(
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                ptr::null_mut(),
+                &mut len,
+            ),
+            name,
+        )?;
+        let mut output = vec![0; len as usize];
+        check(
+            function(
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                output.as_mut_ptr(),
+                &mut len,
+            ),
+            name,
+        )?;
+        output.truncate(len as usize);
+        Ok(output)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let rv = call!(self.module, C_CloseSession(self.handle));
+        if rv != CKR_OK {
+            warn!("C_CloseSession failed: {}", rv_name(rv));
+        }
+    }
+}
+
+/// Turns a return value other than `CKR_OK` of `function` into an `HsmError`.
+pub(super) fn check(rv: CK_RV, function: &str) -> Result<(), HsmError> {
+    match rv {
+        CKR_OK => Ok(()),
+        rv => Err(HsmError::DeviceSpecific(format!(
+            "{} failed: {}",
+            function,
+            rv_name(rv)
+        ))),
+    }
+}
+
+/// Decodes a blank-padded string of the token information.
+pub(super) fn padded_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end().to_owned()
+}
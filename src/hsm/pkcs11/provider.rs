@@ -0,0 +1,523 @@
+use super::{
+    key_handle::{curve_nid, ec_params, openssl_error},
+    module::{check, Module, Session, Template},
+    select_slot,
+    sys::*,
+    Pkcs11Provider, MODULE_NAME,
+};
+use crate::common::{
+    capabilities::{Capabilities, KeyAlgorithm, Operation},
+    crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits, Sha3Bits},
+            KeyBits,
+        },
+        pkcs::cryptoki::hash_mechanisms,
+        KeyUsage,
+    },
+    error::SecurityModuleError,
+    traits::{
+        interaction::{CredentialKind, CredentialRequest, InteractionEvent, InteractionHandler},
+        module_provider::Provider,
+    },
+};
+use crate::hsm::{core::error::HsmError, HsmProviderConfig};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint},
+    pkey::PKey,
+    rsa::Rsa,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::instrument;
+
+/// The public exponent of generated RSA keys, 65537.
+const RSA_PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+
+/// The RSA key sizes `capabilities` reports if the token generates keys of that size.
+const RSA_KEY_BITS: [KeyBits; 5] = [
+    KeyBits::Bits1024,
+    KeyBits::Bits2048,
+    KeyBits::Bits3072,
+    KeyBits::Bits4096,
+    KeyBits::Bits8192,
+];
+
+/// The curves `capabilities` reports if the token generates keys of their size, with the
+/// size in bits.
+const EC_CURVES: [(EccCurves, CK_ULONG); 7] = [
+    (EccCurves::P256, 256),
+    (EccCurves::P384, 384),
+    (EccCurves::P521, 521),
+    (EccCurves::Secp256k1, 256),
+    (EccCurves::BrainpoolP256r1, 256),
+    (EccCurves::BrainpoolP384r1, 384),
+    (EccCurves::BrainpoolP512r1, 512),
+];
+
+/// The hashes signatures can be made with, if the token supports them.
+const HASHES: [Hash; 9] = [
+    Hash::Sha1,
+    Hash::Sha2(Sha2Bits::Sha224),
+    Hash::Sha2(Sha2Bits::Sha256),
+    Hash::Sha2(Sha2Bits::Sha384),
+    Hash::Sha2(Sha2Bits::Sha512),
+    Hash::Sha3(Sha3Bits::Sha3_224),
+    Hash::Sha3(Sha3Bits::Sha3_256),
+    Hash::Sha3(Sha3Bits::Sha3_384),
+    Hash::Sha3(Sha3Bits::Sha3_512),
+];
+
+/// Implements the `Provider` trait, providing cryptographic operations utilizing a token
+/// of a PKCS #11 module.
+impl Provider for Pkcs11Provider {
+    /// Creates a new key pair on the token, labelled with `key_id`.
+    ///
+    /// Key pairs with the same label are destroyed once the new pair has been generated, so
+    /// a key is replaced the same way as on a YubiKey, and kept if generation fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - A string slice that uniquely identifies the key for later usage.
+    /// * `config` - A boxed `HsmProviderConfig` selecting the algorithm, hash and padding.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that, on success, contains `Ok(())`, indicating that the key was created successfully.
+    /// On failure, it returns a `SecurityModuleError`.
+    #[instrument]
+    fn create_key(
+        &mut self,
+        key_id: &str,
+        config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        let hsm_config = config.downcast_ref::<HsmProviderConfig>().ok_or_else(|| {
+            SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                "Failed to get the Configurations".to_string(),
+            ))
+        })?;
+        let session = self.session()?.lock().unwrap();
+
+        let label = key_id.as_bytes();
+        let public_template = Template::new()
+            .ulong(CKA_CLASS, CKO_PUBLIC_KEY)
+            .bool(CKA_TOKEN, true)
+            .bytes(CKA_LABEL, label)
+            .bytes(CKA_ID, label)
+            .bool(CKA_VERIFY, true);
+        let private_template = Template::new()
+            .ulong(CKA_CLASS, CKO_PRIVATE_KEY)
+            .bool(CKA_TOKEN, true)
+            .bool(CKA_PRIVATE, true)
+            .bool(CKA_SENSITIVE, true)
+            .bool(CKA_EXTRACTABLE, false)
+            .bytes(CKA_LABEL, label)
+            .bytes(CKA_ID, label)
+            .bool(CKA_SIGN, true);
+        let (mechanism, public_template, private_template) = match hsm_config.key_algorithm {
+            AsymmetricEncryption::Rsa(bits) => (
+                CKM_RSA_PKCS_KEY_PAIR_GEN,
+                public_template
+                    .bool(CKA_ENCRYPT, true)
+                    .ulong(CKA_MODULUS_BITS, u32::from(bits) as CK_ULONG)
+                    .bytes(CKA_PUBLIC_EXPONENT, &RSA_PUBLIC_EXPONENT),
+                private_template.bool(CKA_DECRYPT, true),
+            ),
+            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(curve)) => (
+                CKM_EC_KEY_PAIR_GEN,
+                public_template.bytes(CKA_EC_PARAMS, ec_params(curve)?),
+                private_template,
+            ),
+            _ => {
+                return Err(SecurityModuleError::Hsm(HsmError::UnsupportedFeature(
+                    "Key Algorithm not supported".to_string(),
+                )))
+            }
+        };
+
+        // The previous keys are only destroyed once their replacement exists, so a failed
+        // generation leaves the token unchanged.
+        let previous = find_keys(&session, key_id, None)?;
+        let (public_key, private_key) = session
+            .generate_key_pair(mechanism, public_template, private_template)
+            .map_err(SecurityModuleError::Hsm)?;
+        let pkey = match public_key_pem(&session, public_key, hsm_config.key_algorithm) {
+            Ok(pkey) => pkey,
+            Err(err) => {
+                let _ = session.destroy_object(public_key);
+                let _ = session.destroy_object(private_key);
+                return Err(err);
+            }
+        };
+        for object in previous {
+            session
+                .destroy_object(object)
+                .map_err(SecurityModuleError::Hsm)?;
+        }
+        drop(session);
+
+        self.private_key = Some(private_key);
+        self.public_key = Some(public_key);
+        self.key_algorithm = Some(hsm_config.key_algorithm);
+        self.hash = hsm_config.hash;
+        self.rsa_padding = hsm_config.rsa_padding;
+        self.pkey = pkey;
+        Ok(())
+    }
+
+    /// Loads the key pair labelled with `key_id` from the token.
+    ///
+    /// PKCS #11 keeps no hash or padding with a key, so they are taken from `config`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - A string slice that uniquely identifies the key to be loaded.
+    /// * `config` - A boxed `HsmProviderConfig` with the algorithm of the key.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that, on success, contains `Ok(())`, indicating that the key was loaded successfully.
+    /// On failure, it returns a `SecurityModuleError`.
+    #[instrument]
+    fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        let hsm_config = config.downcast_ref::<HsmProviderConfig>().ok_or_else(|| {
+            SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                "Failed to get the Configurations".to_string(),
+            ))
+        })?;
+        let key_type = match hsm_config.key_algorithm {
+            AsymmetricEncryption::Rsa(_) => CKK_RSA,
+            AsymmetricEncryption::Ecc(_) => CKK_EC,
+        };
+        let session = self.session()?.lock().unwrap();
+
+        let private_key = find_keys(&session, key_id, Some((CKO_PRIVATE_KEY, key_type)))?;
+        let public_key = find_keys(&session, key_id, Some((CKO_PUBLIC_KEY, key_type)))?;
+        let (Some(&private_key), Some(&public_key)) = (private_key.first(), public_key.first())
+        else {
+            return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                "Key not found".to_string(),
+            )));
+        };
+        let pkey = public_key_pem(&session, public_key, hsm_config.key_algorithm)?;
+        drop(session);
+
+        self.private_key = Some(private_key);
+        self.public_key = Some(public_key);
+        self.key_algorithm = Some(hsm_config.key_algorithm);
+        self.hash = hsm_config.hash;
+        self.rsa_padding = hsm_config.rsa_padding;
+        self.pkey = pkey;
+        Ok(())
+    }
+
+    /// Loads the PKCS #11 module, opens a session with the selected token and logs in.
+    ///
+    /// The PIN is requested from the interaction handler. Tokens with a PIN pad get the
+    /// PIN there instead; the handler is then notified with `ConfirmOnDevice`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that, on success, contains `Ok(())`, indicating that the module was initialized successfully.
+    /// On failure, it returns a `SecurityModuleError`.
+    #[instrument]
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        let module = Module::load(&self.config.module).map_err(SecurityModuleError::Hsm)?;
+        let slot = select_slot(&module, &self.config.token).map_err(SecurityModuleError::Hsm)?;
+        let info = module.token_info(slot).map_err(SecurityModuleError::Hsm)?;
+        let session = module
+            .open_session(slot)
+            .map_err(SecurityModuleError::Hsm)?;
+        self.token_label = info.label;
+
+        if info.flags & CKF_LOGIN_REQUIRED != 0 {
+            if info.flags & CKF_USER_PIN_LOCKED != 0 {
+                return Err(SecurityModuleError::Hsm(HsmError::Blocked(
+                    CredentialKind::Pin,
+                )));
+            }
+            if info.flags & CKF_PROTECTED_AUTHENTICATION_PATH != 0 {
+                self.login_on_device(&session)?;
+            } else {
+                let retries_left = (info.flags & CKF_USER_PIN_FINAL_TRY != 0).then_some(1);
+                self.login(&session, retries_left)?;
+            }
+        }
+
+        self.session = Some(Mutex::new(session));
+        Ok(())
+    }
+
+    /// Sets the handler asked for the PIN in `initialize_module`.
+    fn set_interaction_handler(&mut self, handler: Arc<dyn InteractionHandler>) {
+        self.interaction = Some(handler);
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        self.pkey.clone()
+    }
+
+    /// Returns `len` random bytes generated by the token.
+    #[instrument]
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        self.session()?
+            .lock()
+            .unwrap()
+            .generate_random(len)
+            .map_err(SecurityModuleError::Hsm)
+    }
+
+    /// Describes what the token supports, from its mechanism list.
+    ///
+    /// Key algorithms are reported if the token generates key pairs of their size. Tokens
+    /// report only the sizes of elliptic curve keys, not the curves, so a curve of a supported
+    /// size can still be rejected by `create_key`. RSA keys sign with the hashes the token has
+    /// a hash-and-sign mechanism for, with either padding; ECDSA keys sign with all hashes, as
+    /// the digest is computed in software. Signatures are verified in software.
+    #[instrument]
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        let session = self.session()?.lock().unwrap();
+        let token_info = session.token_info().map_err(SecurityModuleError::Hsm)?;
+        let mechanisms: HashMap<_, _> = session
+            .mechanisms()
+            .map_err(SecurityModuleError::Hsm)?
+            .into_iter()
+            .collect();
+        drop(session);
+        let supports = |mechanism, flag| {
+            mechanisms
+                .get(&mechanism)
+                .is_some_and(|info| info.flags & flag != 0)
+        };
+        let supports_size = |mechanism, bits| {
+            mechanisms.get(&mechanism).is_some_and(|info| {
+                info.flags & CKF_GENERATE_KEY_PAIR != 0
+                    && (info.ulMinKeySize..=info.ulMaxKeySize).contains(&bits)
+            })
+        };
+
+        let mut key_algorithms = Vec::new();
+
+        let rsa_hashes: Vec<Hash> = HASHES
+            .into_iter()
+            .filter(|&hash| {
+                hash_mechanisms(hash).is_some_and(|mechanisms| {
+                    supports(mechanisms.rsa_pkcs, CKF_SIGN)
+                        || supports(mechanisms.rsa_pss, CKF_SIGN)
+                })
+            })
+            .collect();
+        let mut rsa_operations = vec![Operation::Verify];
+        if !rsa_hashes.is_empty() {
+            rsa_operations.insert(0, Operation::Sign);
+        }
+        if supports(CKM_RSA_PKCS, CKF_ENCRYPT) {
+            rsa_operations.push(Operation::Encrypt);
+        }
+        if supports(CKM_RSA_PKCS, CKF_DECRYPT) {
+            rsa_operations.push(Operation::Decrypt);
+        }
+        for bits in RSA_KEY_BITS {
+            if supports_size(CKM_RSA_PKCS_KEY_PAIR_GEN, u32::from(bits) as CK_ULONG) {
+                key_algorithms.push(KeyAlgorithm::new(
+                    AsymmetricEncryption::Rsa(bits),
+                    rsa_hashes.clone(),
+                    rsa_operations.clone(),
+                ));
+            }
+        }
+
+        let mut ecdsa_operations = vec![Operation::Verify];
+        if supports(CKM_ECDSA, CKF_SIGN) {
+            ecdsa_operations.insert(0, Operation::Sign);
+        }
+        for (curve, bits) in EC_CURVES {
+            if supports_size(CKM_EC_KEY_PAIR_GEN, bits) {
+                key_algorithms.push(KeyAlgorithm::new(
+                    AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(curve)),
+                    HASHES.to_vec(),
+                    ecdsa_operations.clone(),
+                ));
+            }
+        }
+
+        let mut operations: Vec<Operation> = Vec::new();
+        for key_algorithm in &key_algorithms {
+            for &operation in &key_algorithm.operations {
+                if !operations.contains(&operation) {
+                    operations.push(operation);
+                }
+            }
+        }
+        if token_info.flags & CKF_RNG != 0 {
+            operations.push(Operation::RandomBytes);
+        }
+
+        let mut key_usages = Vec::new();
+        if operations.contains(&Operation::Sign) {
+            key_usages.extend([
+                KeyUsage::ClientAuth,
+                KeyUsage::SignEncrypt,
+                KeyUsage::CreateX509,
+            ]);
+        }
+        if operations.contains(&Operation::Decrypt) {
+            key_usages.push(KeyUsage::Decrypt);
+        }
+
+        let hashes = HASHES
+            .into_iter()
+            .filter(|&hash| {
+                rsa_hashes.contains(&hash)
+                    || key_algorithms
+                        .iter()
+                        .any(|key| matches!(key.algorithm, AsymmetricEncryption::Ecc(_)))
+            })
+            .collect();
+
+        Ok(Capabilities {
+            key_algorithms,
+            block_ciphers: Vec::new(),
+            hashes,
+            key_usages,
+            max_keys: None,
+            operations,
+        })
+    }
+}
+
+impl Pkcs11Provider {
+    /// Logs in with the PIN from the interaction handler.
+    fn login(
+        &self,
+        session: &Session,
+        retries_left: Option<u8>,
+    ) -> Result<(), SecurityModuleError> {
+        let request = CredentialRequest {
+            kind: CredentialKind::Pin,
+            module: MODULE_NAME.to_owned(),
+            key_id: None,
+            prompt: format!("PIN of token {}", self.token_label),
+            retries_left,
+        };
+        let interaction = self.interaction.as_ref().ok_or_else(|| {
+            SecurityModuleError::Hsm(HsmError::Authentication(format!(
+                "No interaction handler set to ask for the {}",
+                request.prompt
+            )))
+        })?;
+        let pin = interaction.request_credential(&request)?;
+        session.login(Some(&pin)).map_err(|rv| {
+            if rv == CKR_PIN_INCORRECT {
+                interaction.credential_rejected(&request);
+            }
+            SecurityModuleError::Hsm(login_error(rv))
+        })
+    }
+
+    /// Logs in through the PIN pad of the token.
+    fn login_on_device(&self, session: &Session) -> Result<(), SecurityModuleError> {
+        if let Some(interaction) = &self.interaction {
+            interaction.notify(&InteractionEvent::ConfirmOnDevice {
+                module: MODULE_NAME.to_owned(),
+                message: format!("Enter the PIN of token {} on its PIN pad", self.token_label),
+            });
+        }
+        let result = session.login(None);
+        if let Some(interaction) = &self.interaction {
+            interaction.notify(&InteractionEvent::Completed {
+                module: MODULE_NAME.to_owned(),
+            });
+        }
+        result.map_err(|rv| SecurityModuleError::Hsm(login_error(rv)))
+    }
+}
+
+/// Maps the return value of a failed `C_Login` to an `HsmError`.
+fn login_error(rv: CK_RV) -> HsmError {
+    match rv {
+        CKR_PIN_INCORRECT => HsmError::WrongCredential {
+            kind: CredentialKind::Pin,
+            retries_left: None,
+        },
+        CKR_PIN_LOCKED => HsmError::Blocked(CredentialKind::Pin),
+        rv => check(rv, "C_Login").unwrap_err(),
+    }
+}
+
+/// Finds the keys labelled with `key_id`, optionally only those of the given class and type.
+fn find_keys(
+    session: &Session,
+    key_id: &str,
+    class_and_type: Option<(CK_OBJECT_CLASS, CK_KEY_TYPE)>,
+) -> Result<Vec<CK_OBJECT_HANDLE>, SecurityModuleError> {
+    let mut template = Template::new().bytes(CKA_LABEL, key_id.as_bytes());
+    if let Some((class, key_type)) = class_and_type {
+        template = template
+            .ulong(CKA_CLASS, class)
+            .ulong(CKA_KEY_TYPE, key_type);
+    }
+    session
+        .find_objects(template)
+        .map_err(SecurityModuleError::Hsm)
+}
+
+/// Reads the public key `object` from the token and encodes it as PEM.
+fn public_key_pem(
+    session: &Session,
+    object: CK_OBJECT_HANDLE,
+    algorithm: AsymmetricEncryption,
+) -> Result<String, SecurityModuleError> {
+    let attribute = |attribute| {
+        session
+            .attribute(object, attribute)
+            .map_err(SecurityModuleError::Hsm)
+    };
+    let pkey = match algorithm {
+        AsymmetricEncryption::Rsa(_) => {
+            let modulus = BigNum::from_slice(&attribute(CKA_MODULUS)?).map_err(openssl_error)?;
+            let exponent =
+                BigNum::from_slice(&attribute(CKA_PUBLIC_EXPONENT)?).map_err(openssl_error)?;
+            Rsa::from_public_components(modulus, exponent).and_then(PKey::from_rsa)
+        }
+        AsymmetricEncryption::Ecc(scheme) => {
+            let curve = match scheme {
+                EccSchemeAlgorithm::EcDsa(curve) => curve,
+                _ => {
+                    return Err(SecurityModuleError::Hsm(HsmError::UnsupportedFeature(
+                        "Key Algorithm not supported".to_string(),
+                    )))
+                }
+            };
+            let group = EcGroup::from_curve_name(curve_nid(curve)?).map_err(openssl_error)?;
+            let point = ec_point(&group, &attribute(CKA_EC_POINT)?)?;
+            EcKey::from_public_key(&group, &point).and_then(PKey::from_ec_key)
+        }
+    }
+    .map_err(openssl_error)?;
+    let pem = pkey.public_key_to_pem().map_err(openssl_error)?;
+    String::from_utf8(pem)
+        .map_err(|err| SecurityModuleError::Hsm(HsmError::DeviceSpecific(err.to_string())))
+}
+
+/// Decodes the value of `CKA_EC_POINT`.
+///
+/// The specification requires a DER-encoded OCTET STRING, but some modules return the
+/// bare point; both are accepted.
+pub(crate) fn ec_point(group: &EcGroup, value: &[u8]) -> Result<EcPoint, SecurityModuleError> {
+    let mut ctx = BigNumContext::new().map_err(openssl_error)?;
+    let unwrapped = match value {
+        [0x04, len, point @ ..] if *len as usize == point.len() && *len < 0x80 => Some(point),
+        [0x04, 0x81, len, point @ ..] if *len as usize == point.len() => Some(point),
+        _ => None,
+    };
+    unwrapped
+        .and_then(|point| EcPoint::from_bytes(group, point, &mut ctx).ok())
+        .map_or_else(
+            || EcPoint::from_bytes(group, value, &mut ctx).map_err(openssl_error),
+            Ok,
+        )
+}
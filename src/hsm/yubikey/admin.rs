@@ -0,0 +1,555 @@
+use super::{
+    apdu::{
+        find_tlv, parse_tlvs, tlv, PivCard, INS_AUTHENTICATE, INS_GET_METADATA,
+        INS_SET_MANAGEMENT_KEY, SW_SECURITY_STATUS,
+    },
+    transport::{connected_yubikey, PivTransport},
+    YubiKeyProvider, DEFAULT_PIN,
+};
+use crate::{common::traits::interaction::CredentialKind, hsm::core::error::HsmError};
+use ::yubikey::{Error, MgmKey, YubiKey};
+use openssl::{
+    memcmp,
+    rand::rand_bytes,
+    symm::{Cipher, Crypter, Mode},
+};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tracing::instrument;
+use x509_cert::der::zeroize::Zeroizing;
+
+/// Key reference of the PIV card management key.
+const SLOT_CARD_MANAGEMENT: u8 = 0x9b;
+/// Key reference of the PUK, used with GET METADATA.
+const SLOT_PUK: u8 = 0x81;
+
+/// The admin data object, holding flags about the management key.
+const OBJ_ADMIN_DATA: u32 = 0x005f_ff00;
+/// The PIN-protected data object, which can hold the management key.
+const OBJ_PRINTED: u32 = 0x005f_c109;
+const TAG_ADMIN: u8 = 0x80;
+const TAG_ADMIN_FLAGS: u8 = 0x81;
+const TAG_PROTECTED: u8 = 0x88;
+const TAG_PROTECTED_MANAGEMENT_KEY: u8 = 0x89;
+/// Set in the admin flags if the management key is stored in the PIN-protected object.
+const FLAG_PROTECTED_MANAGEMENT_KEY: u8 = 0x02;
+
+/// The algorithm of a PIV management key.
+///
+/// AES management keys require firmware 5.4 or later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagementKeyAlgorithm {
+    TripleDes,
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl ManagementKeyAlgorithm {
+    /// The PIV algorithm identifier.
+    pub(super) fn id(self) -> u8 {
+        match self {
+            Self::TripleDes => 0x03,
+            Self::Aes128 => 0x08,
+            Self::Aes192 => 0x0a,
+            Self::Aes256 => 0x0c,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x03 => Some(Self::TripleDes),
+            0x08 => Some(Self::Aes128),
+            0x0a => Some(Self::Aes192),
+            0x0c => Some(Self::Aes256),
+            _ => None,
+        }
+    }
+
+    /// The key length in bytes.
+    pub fn key_len(self) -> usize {
+        match self {
+            Self::TripleDes | Self::Aes192 => 24,
+            Self::Aes128 => 16,
+            Self::Aes256 => 32,
+        }
+    }
+
+    fn cipher(self) -> Cipher {
+        match self {
+            Self::TripleDes => Cipher::des_ede3(),
+            Self::Aes128 => Cipher::aes_128_ecb(),
+            Self::Aes192 => Cipher::aes_192_ecb(),
+            Self::Aes256 => Cipher::aes_256_ecb(),
+        }
+    }
+}
+
+/// A PIV management key.
+#[derive(Clone)]
+pub struct ManagementKey {
+    algorithm: ManagementKeyAlgorithm,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl ManagementKey {
+    /// Creates a management key from raw key bytes.
+    ///
+    /// Fails if the length of `key` does not match `algorithm`, or if a 3DES key is weak.
+    pub fn new(algorithm: ManagementKeyAlgorithm, key: &[u8]) -> Result<Self, HsmError> {
+        if key.len() != algorithm.key_len() {
+            return Err(HsmError::DeviceSpecific(format!(
+                "A {:?} management key must be {} bytes long",
+                algorithm,
+                algorithm.key_len()
+            )));
+        }
+        if algorithm == ManagementKeyAlgorithm::TripleDes {
+            MgmKey::from_bytes(key).map_err(device_error)?;
+        }
+        Ok(Self {
+            algorithm,
+            key: Zeroizing::new(key.to_vec()),
+        })
+    }
+
+    /// Generates a random management key.
+    pub fn generate(algorithm: ManagementKeyAlgorithm) -> Result<Self, HsmError> {
+        loop {
+            let mut key = Zeroizing::new(vec![0u8; algorithm.key_len()]);
+            rand_bytes(&mut key).map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+            // Retry in the unlikely case of a weak 3DES key.
+            if let Ok(key) = Self::new(algorithm, &key) {
+                return Ok(key);
+            }
+        }
+    }
+
+    pub fn algorithm(&self) -> ManagementKeyAlgorithm {
+        self.algorithm
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Encrypts or decrypts a single block in ECB mode, as used by PIV mutual authentication.
+    fn crypt_block(&self, mode: Mode, block: &[u8]) -> Result<Vec<u8>, HsmError> {
+        let cipher = self.algorithm.cipher();
+        let mut crypter = Crypter::new(cipher, mode, &self.key, None)
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        crypter.pad(false);
+        let mut output = vec![0u8; block.len() + cipher.block_size()];
+        let mut len = crypter
+            .update(block, &mut output)
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        len += crypter
+            .finalize(&mut output[len..])
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        output.truncate(len);
+        Ok(output)
+    }
+}
+
+impl Default for ManagementKey {
+    /// The management key a YubiKey ships with.
+    fn default() -> Self {
+        Self {
+            algorithm: ManagementKeyAlgorithm::TripleDes,
+            key: Zeroizing::new(MgmKey::default().as_ref().to_vec()),
+        }
+    }
+}
+
+impl fmt::Debug for ManagementKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagementKey")
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where the management key is kept after it has been changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagementKeyProtection {
+    /// The key is only known to its owner, who has to provide it.
+    None,
+    /// The key is stored on the YubiKey in an object that can only be read after PIN
+    /// verification, so knowing the PIN is enough for key management.
+    PinProtected,
+}
+
+/// The remaining attempts before the PIN and PUK are blocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryCounters {
+    pub pin: u8,
+    /// `None` on firmware before 5.3, which does not report the PUK counter.
+    pub puk: Option<u8>,
+}
+
+impl YubiKeyProvider {
+    /// Changes the PIN from `current_pin` to `new_pin`.
+    ///
+    /// # Returns
+    ///
+    /// `HsmError::WrongCredential` with the remaining attempts if `current_pin` is wrong,
+    /// `HsmError::Blocked` if the PIN is blocked.
+    #[instrument(skip(current_pin, new_pin))]
+    pub fn change_pin(&mut self, current_pin: &[u8], new_pin: &[u8]) -> Result<(), HsmError> {
+        let pin = String::from_utf8(new_pin.to_vec())
+            .map_err(|_| HsmError::DeviceSpecific("The PIN must be valid UTF-8".to_owned()))?;
+        let device = self.device()?;
+        let mut transport = device.lock().unwrap();
+        connected_yubikey(&mut **transport)?
+            .change_pin(current_pin, new_pin)
+            .map_err(|e| credential_error(e, CredentialKind::Pin))?;
+        self.pin = pin;
+        Ok(())
+    }
+
+    /// Changes the PUK from `current_puk` to `new_puk`.
+    #[instrument(skip(current_puk, new_puk))]
+    pub fn change_puk(&mut self, current_puk: &[u8], new_puk: &[u8]) -> Result<(), HsmError> {
+        let device = self.device()?;
+        let mut transport = device.lock().unwrap();
+        connected_yubikey(&mut **transport)?
+            .change_puk(current_puk, new_puk)
+            .map_err(|e| credential_error(e, CredentialKind::Puk))
+    }
+
+    /// Unblocks the PIN with the PUK and sets it to `new_pin`.
+    #[instrument(skip(puk, new_pin))]
+    pub fn unblock_pin(&mut self, puk: &[u8], new_pin: &[u8]) -> Result<(), HsmError> {
+        let pin = String::from_utf8(new_pin.to_vec())
+            .map_err(|_| HsmError::DeviceSpecific("The PIN must be valid UTF-8".to_owned()))?;
+        let device = self.device()?;
+        let mut transport = device.lock().unwrap();
+        connected_yubikey(&mut **transport)?
+            .unblock_pin(puk, new_pin)
+            .map_err(|e| credential_error(e, CredentialKind::Puk))?;
+        self.pin = pin;
+        Ok(())
+    }
+
+    /// Reports the remaining PIN and PUK attempts.
+    #[instrument]
+    pub fn retry_counters(&self) -> Result<RetryCounters, HsmError> {
+        let device = self.device()?;
+        let mut yubikey = device.lock().unwrap();
+        let pin = yubikey
+            .get_pin_retries()
+            .map_err(|e| credential_error(e, CredentialKind::Pin))?;
+
+        // GET METADATA returns the total and remaining attempts in tag 0x06.
+        let puk = yubikey
+            .get_metadata(SLOT_PUK)?
+            .and_then(|metadata| find_tlv(&metadata, 0x06).ok().map(<[u8]>::to_vec))
+            .and_then(|retries| retries.get(1).copied());
+
+        Ok(RetryCounters { pin, puk })
+    }
+
+    /// Replaces the management key with `new_key`.
+    ///
+    /// The PIN is verified and the current management key is used to authorize the change.
+    /// With `ManagementKeyProtection::PinProtected` the new key is also stored in the
+    /// PIN-protected data object, where `initialize_module` picks it up. If `require_touch`
+    /// is set, every authentication with the new key requires touching the YubiKey.
+    #[instrument(skip(new_key))]
+    pub fn set_management_key(
+        &mut self,
+        new_key: ManagementKey,
+        protection: ManagementKeyProtection,
+        require_touch: bool,
+    ) -> Result<(), HsmError> {
+        let device = self.device()?;
+        let mut transport = device.lock().unwrap();
+        let yubikey = connected_yubikey(&mut **transport)?;
+        yubikey
+            .verify_pin(self.pin.as_ref())
+            .map_err(|e| credential_error(e, CredentialKind::Pin))?;
+        self.authenticate(yubikey)?;
+
+        let mut data = vec![new_key.algorithm.id(), SLOT_CARD_MANAGEMENT];
+        data.extend(&tlv(0, new_key.as_bytes())[1..]);
+        let touch = if require_touch { 0xfe } else { 0xff };
+        PivCard::connect(yubikey.name())?
+            .transaction(|card| card.transmit(INS_SET_MANAGEMENT_KEY, 0xff, touch, &data))?
+            .into_data("SET MANAGEMENT KEY")?;
+
+        // The metadata objects can only be written with the new key.
+        let previous = self.management_key.replace(new_key.clone());
+        if let Err(err) = self.authenticate(yubikey) {
+            // The YubiKey holds the new key, keep it even though authentication failed.
+            drop(previous);
+            return Err(err);
+        }
+
+        let protected_key = match protection {
+            ManagementKeyProtection::None => None,
+            ManagementKeyProtection::PinProtected => Some(new_key.as_bytes()),
+        };
+        update_object(
+            yubikey,
+            OBJ_PRINTED,
+            TAG_PROTECTED,
+            TAG_PROTECTED_MANAGEMENT_KEY,
+            protected_key,
+        )?;
+
+        let flags = read_object_item(yubikey, OBJ_ADMIN_DATA, TAG_ADMIN, TAG_ADMIN_FLAGS)?
+            .and_then(|flags| flags.first().copied())
+            .unwrap_or(0);
+        let flags = match protection {
+            ManagementKeyProtection::None => flags & !FLAG_PROTECTED_MANAGEMENT_KEY,
+            ManagementKeyProtection::PinProtected => flags | FLAG_PROTECTED_MANAGEMENT_KEY,
+        };
+        update_object(
+            yubikey,
+            OBJ_ADMIN_DATA,
+            TAG_ADMIN,
+            TAG_ADMIN_FLAGS,
+            Some(&[flags]),
+        )
+    }
+
+    /// Resets the PIV applet to its factory state.
+    ///
+    /// This destroys all PIV keys and certificates and restores the default PIN, PUK and
+    /// management key. The YubiKey only accepts a reset once PIN and PUK are blocked, so
+    /// both are blocked first with wrong attempts. The provider continues with the
+    /// default credentials.
+    #[instrument]
+    pub fn reset_piv(&mut self) -> Result<(), HsmError> {
+        let device = self.device()?;
+        let mut transport = device.lock().unwrap();
+        let yubikey = connected_yubikey(&mut **transport)?;
+
+        // Random values are practically guaranteed to be wrong.
+        let mut wrong = [0u8; 8];
+        rand_bytes(&mut wrong).map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        loop {
+            match yubikey.verify_pin(&wrong) {
+                Err(Error::WrongPin { tries }) if tries > 0 => continue,
+                Err(Error::WrongPin { .. } | Error::PinLocked) => break,
+                Ok(()) => {
+                    return Err(HsmError::DeviceSpecific(
+                        "Failed to block the PIN".to_owned(),
+                    ))
+                }
+                Err(err) => return Err(device_error(err)),
+            }
+        }
+        yubikey.block_puk().map_err(device_error)?;
+        yubikey.reset_device().map_err(device_error)?;
+
+        // The default key has the same bytes on all firmware, but is an AES-192 key
+        // since firmware 5.7.
+        let algorithm = Self::management_key_algorithm(yubikey)?;
+        let management_key = ManagementKey::new(algorithm, ManagementKey::default().as_bytes())?;
+
+        self.pin = String::from_utf8_lossy(DEFAULT_PIN).into_owned();
+        self.management_key = Some(management_key);
+        self.slot_id = None;
+        self.pkey = String::new();
+        Ok(())
+    }
+
+    /// Authenticates with the management key of this provider.
+    pub(super) fn authenticate(&self, yubikey: &mut dyn PivTransport) -> Result<(), HsmError> {
+        let key = self
+            .management_key
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("No management key available".to_owned()))?;
+        yubikey.authenticate(key)
+    }
+
+    /// Returns the management key stored in the PIN-protected data object, if the
+    /// YubiKey is configured that way. The PIN must have been verified.
+    pub(super) fn protected_management_key(
+        yubikey: &mut dyn PivTransport,
+    ) -> Result<Option<ManagementKey>, HsmError> {
+        let flags = read_object_item(yubikey, OBJ_ADMIN_DATA, TAG_ADMIN, TAG_ADMIN_FLAGS)?
+            .and_then(|flags| flags.first().copied())
+            .unwrap_or(0);
+        if flags & FLAG_PROTECTED_MANAGEMENT_KEY == 0 {
+            return Ok(None);
+        }
+
+        let key = read_object_item(
+            yubikey,
+            OBJ_PRINTED,
+            TAG_PROTECTED,
+            TAG_PROTECTED_MANAGEMENT_KEY,
+        )?
+        .ok_or_else(|| {
+            HsmError::DeviceSpecific("The PIN-protected management key is missing".to_owned())
+        })?;
+        let algorithm = Self::management_key_algorithm(yubikey)?;
+        ManagementKey::new(algorithm, &key).map(Some)
+    }
+
+    /// Returns the algorithm of the management key set on the YubiKey.
+    ///
+    /// Firmware before 5.3 does not report it, but also only supports 3DES.
+    pub(super) fn management_key_algorithm(
+        yubikey: &mut dyn PivTransport,
+    ) -> Result<ManagementKeyAlgorithm, HsmError> {
+        let Some(metadata) = yubikey.get_metadata(SLOT_CARD_MANAGEMENT)? else {
+            return Ok(ManagementKeyAlgorithm::TripleDes);
+        };
+        find_tlv(&metadata, 0x01)?
+            .first()
+            .copied()
+            .and_then(ManagementKeyAlgorithm::from_id)
+            .ok_or_else(|| HsmError::DeviceSpecific("Unknown management key algorithm".to_owned()))
+    }
+
+    fn device(&self) -> Result<Arc<Mutex<Box<dyn PivTransport>>>, HsmError> {
+        self.yubikey
+            .clone()
+            .ok_or_else(|| HsmError::DeviceSpecific("Module is not initialized".to_owned()))
+    }
+}
+
+/// Authenticates with `key` to the connected YubiKey `yubikey`.
+pub(super) fn authenticate_yubikey(
+    yubikey: &mut YubiKey,
+    key: &ManagementKey,
+) -> Result<(), HsmError> {
+    if key.algorithm == ManagementKeyAlgorithm::TripleDes {
+        let key = MgmKey::from_bytes(key.as_bytes()).map_err(device_error)?;
+        return yubikey
+            .authenticate(key)
+            .map_err(|e| credential_error(e, CredentialKind::ManagementKey));
+    }
+
+    // The `yubikey` crate only implements 3DES, AES keys use the same mutual
+    // authentication over a raw connection.
+    PivCard::connect(yubikey.name())?.transaction(|card| {
+        let algorithm = key.algorithm.id();
+        let witness = card
+            .transmit(
+                INS_AUTHENTICATE,
+                algorithm,
+                SLOT_CARD_MANAGEMENT,
+                &tlv(0x7c, &tlv(0x80, &[])),
+            )?
+            .into_data("GENERAL AUTHENTICATE")?;
+        let witness = find_tlv(find_tlv(&witness, 0x7c)?, 0x80)?.to_vec();
+
+        let mut challenge = vec![0u8; witness.len()];
+        rand_bytes(&mut challenge).map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        let mut data = tlv(0x80, &key.crypt_block(Mode::Decrypt, &witness)?);
+        data.extend(tlv(0x81, &challenge));
+
+        let response = card.transmit(
+            INS_AUTHENTICATE,
+            algorithm,
+            SLOT_CARD_MANAGEMENT,
+            &tlv(0x7c, &data),
+        )?;
+        if response.sw == SW_SECURITY_STATUS {
+            return Err(HsmError::WrongCredential {
+                kind: CredentialKind::ManagementKey,
+                retries_left: None,
+            });
+        }
+        let response = response.into_data("GENERAL AUTHENTICATE")?;
+        let proof = find_tlv(find_tlv(&response, 0x7c)?, 0x82)?;
+        let expected = key.crypt_block(Mode::Encrypt, &challenge)?;
+        if proof.len() != expected.len() || !memcmp::eq(proof, &expected) {
+            return Err(HsmError::Authentication(
+                "The YubiKey failed to prove knowledge of the management key".to_owned(),
+            ));
+        }
+        Ok(())
+    })
+}
+
+/// Sends GET METADATA for `slot`, returning `None` if the firmware does not support it.
+pub(super) fn get_metadata(yubikey: &YubiKey, slot: u8) -> Result<Option<Vec<u8>>, HsmError> {
+    let response = PivCard::connect(yubikey.name())?
+        .transaction(|card| card.transmit(INS_GET_METADATA, 0x00, slot, &[]))?;
+    match response.sw {
+        super::apdu::SW_SUCCESS => Ok(Some(response.data)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the item `item_tag` from the TLV `tag` stored in the data object `object_id`.
+fn read_object_item(
+    yubikey: &mut dyn PivTransport,
+    object_id: u32,
+    tag: u8,
+    item_tag: u8,
+) -> Result<Option<Vec<u8>>, HsmError> {
+    let object = match yubikey.fetch_object(object_id) {
+        Ok(object) => object,
+        Err(Error::NotFound) => return Ok(None),
+        Err(err) => return Err(device_error(err)),
+    };
+    if object.is_empty() {
+        return Ok(None);
+    }
+    Ok(parse_tlvs(find_tlv(&object, tag)?)?
+        .into_iter()
+        .find(|(tag, _)| *tag == item_tag)
+        .map(|(_, value)| value.to_vec()))
+}
+
+/// Sets or, for `None`, removes the item `item_tag` of the TLV `tag` stored in the data
+/// object `object_id`, keeping all other items.
+fn update_object(
+    yubikey: &mut dyn PivTransport,
+    object_id: u32,
+    tag: u8,
+    item_tag: u8,
+    value: Option<&[u8]>,
+) -> Result<(), HsmError> {
+    let object = match yubikey.fetch_object(object_id) {
+        Ok(object) => object,
+        Err(Error::NotFound) => Zeroizing::new(Vec::new()),
+        Err(err) => return Err(device_error(err)),
+    };
+    let items = if object.is_empty() {
+        Vec::new()
+    } else {
+        parse_tlvs(find_tlv(&object, tag)?)?
+    };
+
+    let mut content = Zeroizing::new(Vec::new());
+    for (tag, item) in items.into_iter().filter(|(tag, _)| *tag != item_tag) {
+        content.extend(tlv(tag, item));
+    }
+    if let Some(value) = value {
+        content.extend(tlv(item_tag, value));
+    }
+
+    let mut object = Zeroizing::new(tlv(tag, &content));
+    yubikey
+        .save_object(object_id, &mut object)
+        .map_err(device_error)
+}
+
+/// Maps errors of PIN, PUK and management key operations to structured `HsmError`s.
+pub(super) fn credential_error(err: Error, kind: CredentialKind) -> HsmError {
+    match err {
+        Error::WrongPin { tries: 0 } | Error::PinLocked => HsmError::Blocked(kind),
+        Error::WrongPin { tries } => HsmError::WrongCredential {
+            kind,
+            retries_left: Some(tries),
+        },
+        Error::AuthenticationError => HsmError::WrongCredential {
+            kind,
+            retries_left: None,
+        },
+        err => device_error(err),
+    }
+}
+
+fn device_error(err: Error) -> HsmError {
+    HsmError::DeviceSpecific(err.to_string())
+}
@@ -0,0 +1,206 @@
+use crate::hsm::core::error::HsmError;
+use std::ffi::CString;
+
+/// The application identifier of the PIV applet.
+const PIV_AID: [u8; 5] = [0xa0, 0x00, 0x00, 0x03, 0x08];
+/// The application identifier of the management applet.
+pub(super) const MANAGEMENT_AID: [u8; 8] = [0xa0, 0x00, 0x00, 0x05, 0x27, 0x47, 0x11, 0x17];
+
+pub(super) const INS_SELECT: u8 = 0xa4;
+pub(super) const INS_AUTHENTICATE: u8 = 0x87;
+pub(super) const INS_GET_METADATA: u8 = 0xf7;
+pub(super) const INS_SET_MANAGEMENT_KEY: u8 = 0xff;
+pub(super) const INS_READ_DEVICE_INFO: u8 = 0x1d;
+const INS_GET_RESPONSE: u8 = 0xc0;
+
+pub(super) const SW_SUCCESS: u16 = 0x9000;
+pub(super) const SW_SECURITY_STATUS: u16 = 0x6982;
+const SW_INS_NOT_SUPPORTED: u16 = 0x6d00;
+const SW_CLA_NOT_SUPPORTED: u16 = 0x6e00;
+
+/// A raw PC/SC connection to the PIV applet of a YubiKey.
+///
+/// The `yubikey` crate covers the common PIV commands; this connection sends the ones it
+/// lacks, such as AES management keys and metadata for the PIN, PUK and management key.
+/// It shares the card with the `YubiKey` handle, so the authentication state established
+/// on one connection is visible on the other.
+pub(super) struct PivCard {
+    card: pcsc::Card,
+}
+
+/// The response to an APDU.
+pub(super) struct Response {
+    pub(super) data: Vec<u8>,
+    pub(super) sw: u16,
+}
+
+impl Response {
+    /// Returns the response data, or an error naming `command` if it failed.
+    pub(super) fn into_data(self, command: &str) -> Result<Vec<u8>, HsmError> {
+        if self.sw == SW_SUCCESS {
+            Ok(self.data)
+        } else {
+            Err(HsmError::DeviceSpecific(format!(
+                "{} failed with status {:04x}",
+                command, self.sw
+            )))
+        }
+    }
+}
+
+impl PivCard {
+    /// Connects to the YubiKey in the PC/SC reader `reader`.
+    pub(super) fn connect(reader: &str) -> Result<Self, HsmError> {
+        let reader = CString::new(reader)
+            .map_err(|_| HsmError::DeviceSpecific("Invalid reader name".to_owned()))?;
+        let card = pcsc::Context::establish(pcsc::Scope::System)
+            .and_then(|context| {
+                context.connect(&reader, pcsc::ShareMode::Shared, pcsc::Protocols::T1)
+            })
+            .map_err(pcsc_error)?;
+        Ok(Self { card })
+    }
+
+    /// Sends the commands issued by `f` within a single PC/SC transaction, so no other
+    /// connection can interleave its own commands.
+    pub(super) fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&Transaction<'_>) -> Result<T, HsmError>,
+    ) -> Result<T, HsmError> {
+        let transaction = self.card.transaction().map_err(pcsc_error)?;
+        f(&Transaction { inner: transaction })
+    }
+}
+
+/// An open PC/SC transaction on a `PivCard`.
+pub(super) struct Transaction<'tx> {
+    inner: pcsc::Transaction<'tx>,
+}
+
+impl Transaction<'_> {
+    /// Sends a short APDU and collects the complete response.
+    ///
+    /// If another application is selected on the card, the PIV applet is selected and the
+    /// command is sent again.
+    pub(super) fn transmit(
+        &self,
+        ins: u8,
+        p1: u8,
+        p2: u8,
+        data: &[u8],
+    ) -> Result<Response, HsmError> {
+        let response = self.transmit_raw(ins, p1, p2, data)?;
+        if !matches!(response.sw, SW_INS_NOT_SUPPORTED | SW_CLA_NOT_SUPPORTED) {
+            return Ok(response);
+        }
+
+        self.transmit_raw(INS_SELECT, 0x04, 0x00, &PIV_AID)?
+            .into_data("Selecting the PIV applet")?;
+        self.transmit_raw(ins, p1, p2, data)
+    }
+
+    /// Sends a short APDU to the applet `aid` and selects the PIV applet again afterwards.
+    pub(super) fn transmit_to(
+        &self,
+        aid: &[u8],
+        ins: u8,
+        p1: u8,
+        p2: u8,
+        data: &[u8],
+    ) -> Result<Response, HsmError> {
+        self.transmit_raw(INS_SELECT, 0x04, 0x00, aid)?
+            .into_data("Selecting the applet")?;
+        let response = self.transmit_raw(ins, p1, p2, data);
+        self.transmit_raw(INS_SELECT, 0x04, 0x00, &PIV_AID)?
+            .into_data("Selecting the PIV applet")?;
+        response
+    }
+
+    fn transmit_raw(&self, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Response, HsmError> {
+        let lc = u8::try_from(data.len())
+            .map_err(|_| HsmError::DeviceSpecific("APDU data is too long".to_owned()))?;
+        let mut apdu = vec![0x00, ins, p1, p2];
+        if !data.is_empty() {
+            apdu.push(lc);
+            apdu.extend_from_slice(data);
+        }
+        apdu.push(0x00);
+
+        let mut buffer = [0u8; pcsc::MAX_BUFFER_SIZE];
+        let mut response = Vec::new();
+        loop {
+            let received = self
+                .inner
+                .transmit(&apdu, &mut buffer)
+                .map_err(pcsc_error)?;
+            let (body, sw) = received.split_at(received.len().saturating_sub(2));
+            if sw.len() != 2 {
+                return Err(HsmError::DeviceSpecific(
+                    "Truncated response from the YubiKey".to_owned(),
+                ));
+            }
+            response.extend_from_slice(body);
+
+            // 61xx: more response data is available.
+            if sw[0] != 0x61 {
+                return Ok(Response {
+                    data: response,
+                    sw: u16::from_be_bytes([sw[0], sw[1]]),
+                });
+            }
+            apdu = vec![0x00, INS_GET_RESPONSE, 0x00, 0x00, 0x00];
+        }
+    }
+}
+
+/// Encodes a BER-TLV with a single byte tag.
+pub(super) fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match value.len() {
+        len @ 0..=0x7f => encoded.push(len as u8),
+        len @ 0x80..=0xff => encoded.extend_from_slice(&[0x81, len as u8]),
+        len => encoded.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+/// Splits a sequence of BER-TLVs with single byte tags into `(tag, value)` pairs.
+pub(super) fn parse_tlvs(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>, HsmError> {
+    let malformed = || HsmError::DeviceSpecific("Malformed TLV data".to_owned());
+
+    let mut items = Vec::new();
+    while let [tag, first, rest @ ..] = data {
+        let (len, rest) = match first {
+            0x81 => (*rest.first().ok_or_else(malformed)? as usize, &rest[1..]),
+            0x82 if rest.len() >= 2 => (
+                usize::from(u16::from_be_bytes([rest[0], rest[1]])),
+                &rest[2..],
+            ),
+            0x00..=0x7f => (*first as usize, rest),
+            _ => return Err(malformed()),
+        };
+        if rest.len() < len {
+            return Err(malformed());
+        }
+        items.push((*tag, &rest[..len]));
+        data = &rest[len..];
+    }
+    if !data.is_empty() {
+        return Err(malformed());
+    }
+    Ok(items)
+}
+
+/// Returns the value of the first TLV tagged `tag`.
+pub(super) fn find_tlv(data: &[u8], tag: u8) -> Result<&[u8], HsmError> {
+    parse_tlvs(data)?
+        .into_iter()
+        .find(|(item_tag, _)| *item_tag == tag)
+        .map(|(_, value)| value)
+        .ok_or_else(|| HsmError::DeviceSpecific(format!("TLV {:02x} is missing", tag)))
+}
+
+fn pcsc_error(err: pcsc::Error) -> HsmError {
+    HsmError::DeviceSpecific(format!("PC/SC error: {}", err))
+}
@@ -0,0 +1,224 @@
+use super::{transport::connected_yubikey, YubiKeyProvider};
+use crate::hsm::core::error::HsmError;
+use ::yubikey::{
+    piv::{self, SlotId},
+    Certificate, PinPolicy, Serial, TouchPolicy, Version,
+};
+use openssl::x509::X509;
+use tracing::instrument;
+use x509_cert::der::{asn1::ObjectIdentifier, Decode, Encode};
+
+/// Where Yubico publishes the PIV attestation root CA that `AttestationVerifier` expects.
+pub const YUBICO_PIV_ROOT_CA_URL: &str =
+    "https://developers.yubico.com/PIV/Introduction/piv-attestation-ca.pem";
+
+/// Firmware version as three bytes: major, minor, patch.
+const OID_FIRMWARE_VERSION: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.41482.3.3");
+/// Serial number as a DER INTEGER.
+const OID_SERIAL_NUMBER: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.41482.3.7");
+/// PIN and touch policy as two bytes.
+const OID_POLICY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.41482.3.8");
+
+/// Proof that a key was generated on a YubiKey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAttestation {
+    /// The DER attestation certificate for the key, signed by the attestation key in slot F9.
+    pub certificate: Vec<u8>,
+    /// The DER certificate of the slot F9 attestation key, signed by the Yubico PIV root or
+    /// one of its intermediates.
+    pub intermediate: Vec<u8>,
+}
+
+/// The properties of an attested key, as vouched for by the YubiKey.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationInfo {
+    pub serial: Serial,
+    pub firmware_version: Version,
+    pub pin_policy: PinPolicy,
+    pub touch_policy: TouchPolicy,
+    /// The DER SubjectPublicKeyInfo of the attested key.
+    pub public_key: Vec<u8>,
+}
+
+impl YubiKeyProvider {
+    /// Creates an attestation for the loaded key.
+    ///
+    /// Only keys generated on the YubiKey can be attested; imported keys are rejected by
+    /// the device.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the attestation certificate and the device intermediate on
+    /// success, or an `HsmError` if no key is loaded or the YubiKey refuses the attestation.
+    #[instrument]
+    pub fn attest_key(&self) -> Result<KeyAttestation, HsmError> {
+        let slot = self
+            .slot_id
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))?;
+        let yubikey = self
+            .yubikey
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("Module is not initialized".to_owned()))?;
+        let mut transport = yubikey.lock().unwrap();
+        let yubikey = connected_yubikey(&mut **transport)?;
+
+        let certificate = piv::attest(yubikey, slot)
+            .map_err(|e| HsmError::DeviceSpecific(format!("Attestation failed: {}", e)))?
+            .to_vec();
+        let intermediate = Certificate::read(yubikey, SlotId::Attestation)
+            .and_then(|intermediate| {
+                intermediate
+                    .cert
+                    .to_der()
+                    .map_err(|_| ::yubikey::Error::InvalidObject)
+            })
+            .map_err(|e| {
+                HsmError::DeviceSpecific(format!(
+                    "Failed to read the attestation certificate: {}",
+                    e
+                ))
+            })?;
+
+        Ok(KeyAttestation {
+            certificate,
+            intermediate,
+        })
+    }
+}
+
+/// Verifies `KeyAttestation`s against a trusted root.
+///
+/// Use the Yubico PIV attestation root CA, published at `YUBICO_PIV_ROOT_CA_URL`, to accept
+/// attestations of genuine YubiKeys. YubiKeys with firmware 5.7.4 and later chain up to
+/// Yubico's attestation root through further intermediates, which have to be added with
+/// `with_intermediates`.
+#[derive(Debug, Clone)]
+pub struct AttestationVerifier {
+    root: X509,
+    intermediates: Vec<X509>,
+}
+
+impl AttestationVerifier {
+    /// Creates a verifier trusting the PEM encoded root certificate `root_pem`.
+    pub fn new(root_pem: &[u8]) -> Result<Self, HsmError> {
+        let root = X509::from_pem(root_pem)
+            .map_err(|e| HsmError::DeviceSpecific(format!("Invalid root certificate: {}", e)))?;
+        Ok(Self {
+            root,
+            intermediates: Vec::new(),
+        })
+    }
+
+    /// Adds the PEM encoded intermediate certificates in `intermediates_pem`, in any order,
+    /// through which device certificates may chain up to the root.
+    ///
+    /// The intermediates are not trusted on their own: an attestation is only accepted if
+    /// the chain ends at the root.
+    pub fn with_intermediates(mut self, intermediates_pem: &[u8]) -> Result<Self, HsmError> {
+        let intermediates = X509::stack_from_pem(intermediates_pem).map_err(|e| {
+            HsmError::DeviceSpecific(format!("Invalid intermediate certificate: {}", e))
+        })?;
+        self.intermediates.extend(intermediates);
+        Ok(self)
+    }
+
+    /// Checks that `attestation` chains up to the trusted root and extracts the attested
+    /// properties.
+    ///
+    /// Only issuer names and signatures are checked: Yubico's intermediates are not marked
+    /// as CAs and attestation certificates carry no meaningful validity period, so a regular
+    /// path validation would reject genuine attestations.
+    pub fn verify(&self, attestation: &KeyAttestation) -> Result<AttestationInfo, HsmError> {
+        let intermediate = X509::from_der(&attestation.intermediate)
+            .map_err(|e| invalid(format!("Invalid intermediate certificate: {}", e)))?;
+        let certificate = X509::from_der(&attestation.certificate)
+            .map_err(|e| invalid(format!("Invalid attestation certificate: {}", e)))?;
+
+        if !is_issued_by(&certificate, &intermediate)? {
+            return Err(untrusted());
+        }
+
+        // Every intermediate is used at most once, which also stops at issuer loops.
+        let mut current = &intermediate;
+        for _ in 0..=self.intermediates.len() {
+            if is_issued_by(current, &self.root)? {
+                return parse_attestation(&attestation.certificate);
+            }
+            current = self
+                .intermediates
+                .iter()
+                .find(|issuer| is_issued_by(current, issuer).unwrap_or(false))
+                .ok_or_else(untrusted)?;
+        }
+        Err(untrusted())
+    }
+}
+
+/// Checks whether `certificate` names `issuer` as its issuer and carries its signature.
+fn is_issued_by(certificate: &X509, issuer: &X509) -> Result<bool, HsmError> {
+    let names_match = certificate
+        .issuer_name()
+        .try_cmp(issuer.subject_name())
+        .map_err(|e| invalid(e.to_string()))?
+        .is_eq();
+    if !names_match {
+        return Ok(false);
+    }
+    let key = issuer.public_key().map_err(|e| invalid(e.to_string()))?;
+    certificate.verify(&key).map_err(|e| invalid(e.to_string()))
+}
+
+fn untrusted() -> HsmError {
+    invalid("The attestation does not chain up to the trusted root".to_owned())
+}
+
+/// Extracts the YubiKey specific extensions of an attestation certificate.
+fn parse_attestation(der: &[u8]) -> Result<AttestationInfo, HsmError> {
+    let certificate = x509_cert::Certificate::from_der(der)
+        .map_err(|e| invalid(format!("Invalid attestation certificate: {}", e)))?;
+    let tbs = &certificate.tbs_certificate;
+    let extension = |oid: ObjectIdentifier| {
+        tbs.extensions
+            .iter()
+            .flatten()
+            .find(|extension| extension.extn_id == oid)
+            .map(|extension| extension.extn_value.as_bytes())
+            .ok_or_else(|| invalid(format!("Attestation extension {} is missing", oid)))
+    };
+
+    let serial = u32::from_der(extension(OID_SERIAL_NUMBER)?)
+        .map_err(|e| invalid(format!("Invalid serial number: {}", e)))?;
+    let firmware_version = match extension(OID_FIRMWARE_VERSION)? {
+        [major, minor, patch] => Version {
+            major: *major,
+            minor: *minor,
+            patch: *patch,
+        },
+        _ => return Err(invalid("Invalid firmware version".to_owned())),
+    };
+    let (pin_policy, touch_policy) = match extension(OID_POLICY)? {
+        [pin, touch] => (
+            PinPolicy::try_from(*pin).map_err(|_| invalid("Invalid PIN policy".to_owned()))?,
+            TouchPolicy::try_from(*touch)
+                .map_err(|_| invalid("Invalid touch policy".to_owned()))?,
+        ),
+        _ => return Err(invalid("Invalid policy".to_owned())),
+    };
+    let public_key = tbs
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| invalid(e.to_string()))?;
+
+    Ok(AttestationInfo {
+        serial: Serial(serial),
+        firmware_version,
+        pin_policy,
+        touch_policy,
+        public_key,
+    })
+}
+
+fn invalid(message: String) -> HsmError {
+    HsmError::DeviceSpecific(message)
+}
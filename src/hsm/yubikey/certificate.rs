@@ -0,0 +1,96 @@
+use super::{
+    provider::{certificate_object, read_key_object, save_key_object},
+    transport::connected_yubikey,
+    YubiKeyProvider,
+};
+use crate::hsm::core::error::HsmError;
+use ::yubikey::{
+    certificate::{CertInfo, Certificate},
+    Error,
+};
+use openssl::{pkey::PKey, x509::X509};
+use tracing::instrument;
+use x509_cert::der::Encode;
+
+impl YubiKeyProvider {
+    /// Stores a DER encoded X.509 certificate for the loaded key.
+    ///
+    /// The certificate is written to the certificate object of the key's slot, where smart
+    /// card middleware such as OpenSC or the Windows minidriver looks for it, and the key
+    /// object is updated to reference it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that, on success, contains `Ok(())`. On failure, e.g. if the certificate
+    /// is not issued for the loaded key, it returns an `HsmError`.
+    #[instrument(skip(certificate))]
+    pub fn store_certificate(&mut self, certificate: &[u8]) -> Result<(), HsmError> {
+        let slot = self
+            .slot_id
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))?;
+
+        let certificate_key = X509::from_der(certificate)
+            .and_then(|certificate| certificate.public_key())
+            .and_then(|key| key.public_key_to_der())
+            .map_err(|e| HsmError::DeviceSpecific(format!("Invalid certificate: {}", e)))?;
+        let key = PKey::public_key_from_pem(self.pkey.as_bytes())
+            .and_then(|key| key.public_key_to_der())
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        if certificate_key != key {
+            return Err(HsmError::DeviceSpecific(
+                "The certificate does not belong to the loaded key".to_owned(),
+            ));
+        }
+        let certificate = Certificate::from_bytes(certificate.to_vec())
+            .map_err(|e| HsmError::DeviceSpecific(format!("Invalid certificate: {}", e)))?;
+
+        let yubikey = self
+            .yubikey
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("Module is not initialized".to_owned()))?;
+        let mut transport = yubikey.lock().unwrap();
+        let yubikey = connected_yubikey(&mut **transport)?;
+        yubikey
+            .verify_pin(self.pin.as_ref())
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+        self.authenticate(yubikey)?;
+        certificate
+            .write(yubikey, slot, CertInfo::Uncompressed)
+            .map_err(|e| HsmError::DeviceSpecific(e.to_string()))?;
+
+        if let Some(mut metadata) = read_key_object(yubikey, slot)? {
+            metadata.certificate = certificate_object(slot);
+            save_key_object(yubikey, slot, &metadata)?;
+        }
+        Ok(())
+    }
+
+    /// Fetches the DER encoded X.509 certificate stored for the loaded key.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` that, on success, contains the certificate, or `None` if the slot has none.
+    /// On failure, it returns an `HsmError`.
+    #[instrument]
+    pub fn certificate(&self) -> Result<Option<Vec<u8>>, HsmError> {
+        let slot = self
+            .slot_id
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()))?;
+        let yubikey = self
+            .yubikey
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("Module is not initialized".to_owned()))?;
+        let mut transport = yubikey.lock().unwrap();
+        let yubikey = connected_yubikey(&mut **transport)?;
+
+        match Certificate::read(yubikey, slot) {
+            Ok(certificate) => certificate
+                .cert
+                .to_der()
+                .map(Some)
+                .map_err(|e| HsmError::DeviceSpecific(e.to_string())),
+            Err(Error::InvalidObject | Error::NotFound) => Ok(None),
+            Err(err) => Err(HsmError::DeviceSpecific(err.to_string())),
+        }
+    }
+}
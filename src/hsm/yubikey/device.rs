@@ -0,0 +1,223 @@
+use super::{
+    apdu::{parse_tlvs, PivCard, INS_READ_DEVICE_INFO, MANAGEMENT_AID},
+    YubiKeyProvider, MODULE_NAME,
+};
+use crate::{
+    common::{device_events::DeviceWatcher, traits::device_events::DeviceEventHandler},
+    hsm::core::error::HsmError,
+};
+use ::yubikey::{reader::Context, Serial, Version, YubiKey};
+use std::{collections::HashMap, sync::Arc, thread};
+use tracing::instrument;
+
+const TAG_FORM_FACTOR: u8 = 0x04;
+
+/// The physical shape of a YubiKey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormFactor {
+    /// The YubiKey does not report its form factor, e.g. firmware before 4.1.
+    Unknown,
+    UsbAKeychain,
+    UsbANano,
+    UsbCKeychain,
+    UsbCNano,
+    UsbCLightning,
+    UsbABio,
+    UsbCBio,
+}
+
+impl From<u8> for FormFactor {
+    fn from(value: u8) -> Self {
+        // The upper bits flag FIPS and security key editions.
+        match value & 0x0f {
+            0x01 => Self::UsbAKeychain,
+            0x02 => Self::UsbANano,
+            0x03 => Self::UsbCKeychain,
+            0x04 => Self::UsbCNano,
+            0x05 => Self::UsbCLightning,
+            0x06 => Self::UsbABio,
+            0x07 => Self::UsbCBio,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// A connected YubiKey, as found by `YubiKeyProvider::list_devices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YubiKeyDevice {
+    pub serial: Serial,
+    pub version: Version,
+    pub form_factor: FormFactor,
+    /// The name of the PC/SC reader the YubiKey is connected through.
+    pub reader: String,
+}
+
+impl YubiKeyProvider {
+    /// Constructs a new `YubiKeyProvider` bound to the YubiKey with the serial number
+    /// `serial`.
+    ///
+    /// `initialize_module` only opens that YubiKey, so several providers can use different
+    /// YubiKeys connected at the same time.
+    #[instrument]
+    pub fn with_serial(key_id: String, serial: Serial) -> Self {
+        Self {
+            serial: Some(serial),
+            ..Self::new(key_id)
+        }
+    }
+
+    /// The serial number of the YubiKey this provider is bound to, if any.
+    pub fn serial(&self) -> Option<Serial> {
+        self.serial
+    }
+
+    /// Lists the connected YubiKeys.
+    ///
+    /// YubiKeys that are opened exclusively by another application are skipped.
+    ///
+    /// Reading the form factor opens a second connection to each YubiKey and selects the
+    /// management application, which resets the PIN verification and management key
+    /// authentication of providers using that YubiKey. They verify the PIN and
+    /// authenticate again on their next operation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the connected YubiKeys on success, or an `HsmError` if the
+    /// PC/SC service is not available.
+    #[instrument]
+    pub fn list_devices() -> Result<Vec<YubiKeyDevice>, HsmError> {
+        let mut context = match Context::open() {
+            Ok(context) => context,
+            Err(err) if no_readers(&err) => return Ok(Vec::new()),
+            Err(err) => return Err(device_error(err)),
+        };
+        let readers = match context.iter() {
+            Ok(readers) => readers,
+            Err(err) if no_readers(&err) => return Ok(Vec::new()),
+            Err(err) => return Err(device_error(err)),
+        };
+
+        let mut devices = Vec::new();
+        for reader in readers {
+            let Ok(yubikey) = reader.open() else {
+                continue;
+            };
+            devices.push(YubiKeyDevice {
+                serial: yubikey.serial(),
+                version: yubikey.version(),
+                form_factor: read_form_factor(yubikey.name()),
+                reader: yubikey.name().to_owned(),
+            });
+        }
+        Ok(devices)
+    }
+
+    /// Reports YubiKeys being plugged in and out to `handler`, identified by their serial
+    /// number, until the returned watcher is dropped.
+    ///
+    /// The watcher waits for PC/SC reader changes and only opens newly appeared readers,
+    /// so it does not interfere with YubiKeys in use.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the running watcher on success, or an `HsmError` if the PC/SC
+    /// service is not available.
+    #[instrument]
+    pub fn watch_devices(handler: Arc<dyn DeviceEventHandler>) -> Result<DeviceWatcher, HsmError> {
+        let context = pcsc::Context::establish(pcsc::Scope::System)
+            .map_err(|err| HsmError::DeviceSpecific(format!("PC/SC error: {}", err)))?;
+        let mut states = vec![pcsc::ReaderState::new(
+            pcsc::PNP_NOTIFICATION(),
+            pcsc::State::UNAWARE,
+        )];
+        let wait = move |timeout| match context.get_status_change(timeout, &mut states) {
+            Ok(()) => states
+                .iter_mut()
+                .for_each(pcsc::ReaderState::sync_current_state),
+            Err(pcsc::Error::Timeout) => {}
+            // Without PnP notifications, fall back to polling.
+            Err(_) => thread::sleep(timeout),
+        };
+
+        let mut serials: HashMap<String, Serial> = HashMap::new();
+        let list = move || {
+            let mut context = match Context::open() {
+                Ok(context) => context,
+                Err(err) if no_readers(&err) => {
+                    serials.clear();
+                    return Some(Vec::new());
+                }
+                Err(_) => return None,
+            };
+            let mut current = HashMap::new();
+            for reader in context.iter().ok()? {
+                let name = reader.name().into_owned();
+                let serial = match serials.get(&name) {
+                    Some(serial) => *serial,
+                    None => match reader.open() {
+                        Ok(yubikey) => yubikey.serial(),
+                        Err(_) => continue,
+                    },
+                };
+                current.insert(name, serial);
+            }
+            serials = current;
+            Some(serials.values().map(Serial::to_string).collect())
+        };
+
+        Ok(DeviceWatcher::spawn(MODULE_NAME, handler, wait, list))
+    }
+}
+
+/// Opens the YubiKey with the serial number `serial`, or the only connected YubiKey.
+pub(super) fn open_device(serial: Option<Serial>) -> Result<YubiKey, HsmError> {
+    match serial {
+        Some(serial) => YubiKey::open_by_serial(serial).map_err(|err| match err {
+            ::yubikey::Error::NotFound => {
+                HsmError::DeviceSpecific(format!("No YubiKey with serial {} found", serial))
+            }
+            err => device_error(err),
+        }),
+        None => YubiKey::open().map_err(|err| match err {
+            // `YubiKey::open` reports a PC/SC error without a cause if it finds several.
+            ::yubikey::Error::PcscError { inner: None } => HsmError::DeviceSpecific(
+                "Several YubiKeys are connected, select one by its serial number".to_owned(),
+            ),
+            err => device_error(err),
+        }),
+    }
+}
+
+/// Reads the form factor from the management applet of the YubiKey in `reader`.
+fn read_form_factor(reader: &str) -> FormFactor {
+    let info = PivCard::connect(reader).and_then(|mut card| {
+        card.transaction(|card| {
+            card.transmit_to(&MANAGEMENT_AID, INS_READ_DEVICE_INFO, 0x00, 0x00, &[])?
+                .into_data("Reading the device information")
+        })
+    });
+
+    // The TLVs are preceded by their total length.
+    let form_factor = info.ok().and_then(|info| {
+        let tlvs = info.get(1..)?;
+        parse_tlvs(tlvs)
+            .ok()?
+            .into_iter()
+            .find(|(tag, _)| *tag == TAG_FORM_FACTOR)
+            .and_then(|(_, value)| value.first().copied())
+    });
+    form_factor.map_or(FormFactor::Unknown, FormFactor::from)
+}
+
+fn no_readers(err: &::yubikey::Error) -> bool {
+    matches!(
+        err,
+        ::yubikey::Error::PcscError {
+            inner: Some(pcsc::Error::NoReadersAvailable)
+        }
+    )
+}
+
+fn device_error(err: ::yubikey::Error) -> HsmError {
+    HsmError::DeviceSpecific(err.to_string())
+}
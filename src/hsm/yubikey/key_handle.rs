@@ -1,31 +1,32 @@
 use super::YubiKeyProvider;
 use crate::{
     common::{
+        capabilities::Operation,
         crypto::algorithms::{
             encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits, Sha3Bits},
             KeyBits,
         },
         error::SecurityModuleError,
-        traits::key_handle::KeyHandle,
+        traits::{
+            key_handle::KeyHandle,
+            operations::{Decryptor, Encryptor, Signer, Verifier},
+        },
     },
-    hsm::core::error::HsmError,
+    hsm::{core::error::HsmError, RsaPadding},
 };
 
-use ::yubikey::piv;
-use ::yubikey::{
-    piv::{AlgorithmId, SlotId},
-    MgmKey,
-};
+use ::yubikey::piv::AlgorithmId;
 use base64::{engine::general_purpose, Engine};
 use openssl::{
     ec::EcKey,
-    hash::MessageDigest,
+    error::ErrorStack,
+    hash::{hash, MessageDigest},
     pkey::PKey,
+    rand::rand_bytes,
     rsa::{Padding, Rsa},
-    sign::Verifier,
+    sign::{self, RsaPssSaltlen},
 };
-use rsa::sha2::Digest;
-use sha2::Sha256;
 use tracing::instrument;
 use x509_cert::der::zeroize::Zeroizing;
 
@@ -34,7 +35,8 @@ use x509_cert::der::zeroize::Zeroizing;
 
 /// Signs data using the cryptographic key on a YubiKey.
 ///
-/// This method hashes the input data using SHA-256 and then signs the hash.
+/// This method hashes the input data with the configured hash, SHA-256 by default, and then
+/// signs the hash with the configured RSA padding.
 ///
 /// # Arguments
 ///
@@ -45,28 +47,26 @@ use x509_cert::der::zeroize::Zeroizing;
 /// A `Result` containing the signature as a `Vec<u8>` on success, or a `yubikey::Error` on failure.
 ///
 
-impl KeyHandle for YubiKeyProvider {
+impl Signer for YubiKeyProvider {
     #[instrument]
-    fn sign_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        let yubikey = self.yubikey.as_ref().unwrap();
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let yubikey = self.transport()?;
         let mut yubikey = yubikey.lock().unwrap();
-        let data = data.to_vec();
         let key_algo = self.key_algo.unwrap();
-
-        // Input gets hashed with SHA-256
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let data = hasher.finalize();
-        let mut data: &[u8] = &data;
+        let hash_algorithm = self.signature_hash();
+        let md = message_digest(hash_algorithm)?;
+        let digest = hash(md, data).map_err(openssl_error)?;
 
         //TODO After PIN input implementation in App, insert code for re-authentication
-        let verify = yubikey.verify_pin(self.pin.as_ref());
+        let verify = self.with_reconnect(&mut **yubikey, |yubikey| {
+            yubikey.verify_pin(self.pin.as_ref())
+        });
         if !verify.is_ok() {
             return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
                 "PIN verification failed".to_string(),
             )));
         }
-        let auth = yubikey.authenticate(MgmKey::new(self.management_key.unwrap()).unwrap());
+        let auth = self.authenticate(&mut **yubikey);
         if !auth.is_ok() {
             return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
                 "Authentication  failed".to_string(),
@@ -74,39 +74,40 @@ impl KeyHandle for YubiKeyProvider {
         }
 
         let signature: Result<Zeroizing<Vec<u8>>, yubikey::Error>;
-        let mut vec_data: Vec<u8> = create_digest_info(data).unwrap();
         let algorithm_id: AlgorithmId;
+        let data: Vec<u8>;
 
+        // ECDSA uses the leftmost bits of digests longer than the curve order, and the
+        // YubiKey rejects longer input.
         match key_algo {
             AsymmetricEncryption::Rsa(KeyBits::Bits1024) => {
                 algorithm_id = AlgorithmId::Rsa1024;
-                vec_data = apply_pkcs1v15_padding(&vec_data, 128);
-                data = &vec_data.as_slice();
+                data = self.rsa_encode(&digest, hash_algorithm, md, 128)?;
             }
             AsymmetricEncryption::Rsa(KeyBits::Bits2048) => {
                 algorithm_id = AlgorithmId::Rsa2048;
-                vec_data = apply_pkcs1v15_padding(&vec_data, 256);
-                data = vec_data.as_slice();
+                data = self.rsa_encode(&digest, hash_algorithm, md, 256)?;
             }
 
             AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)) => {
                 algorithm_id = AlgorithmId::EccP256;
+                data = digest[..digest.len().min(32)].to_vec();
             }
             AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)) => {
                 algorithm_id = AlgorithmId::EccP384;
+                data = digest[..digest.len().min(48)].to_vec();
             }
             _ => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                return Err(SecurityModuleError::UnsupportedOperation(
                     "Key Algorithm not supported".to_string(),
-                )));
+                ));
             }
         }
-        signature = piv::sign_data(
-            &mut yubikey,
-            data,
-            algorithm_id,
-            SlotId::Retired(self.slot_id.unwrap()),
-        );
+        let touch = self.announce_touch(&mut **yubikey, self.slot_id.unwrap());
+        signature = yubikey.sign_data(&data, algorithm_id, self.slot_id.unwrap());
+        if touch {
+            self.announce_completed();
+        }
         match signature {
             Ok(buffer) => {
                 let signature = general_purpose::STANDARD.encode(&buffer);
@@ -120,7 +121,9 @@ impl KeyHandle for YubiKeyProvider {
             ))),
         }
     }
+}
 
+impl Decryptor for YubiKeyProvider {
     /// Decrypts data encrypted with the corresponding public key on a YubiKey.
     /// Only works with PKCS#1 v1.5 padding.
     /// Utilizes the YubiKey API for decryption.
@@ -133,31 +136,40 @@ impl KeyHandle for YubiKeyProvider {
     ///
     /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `yubikey::Error` on failure.
     #[instrument]
-    fn decrypt_data(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        let yubikey = self.yubikey.as_ref().unwrap();
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let yubikey = self.transport()?;
         let mut yubikey = yubikey.lock().unwrap();
 
         let decrypted: Result<Zeroizing<Vec<u8>>, &str>;
         let key_algo = self.key_algo.unwrap();
+        // Only RSA keys can decrypt, see below.
+        let touch = matches!(
+            key_algo,
+            AsymmetricEncryption::Rsa(KeyBits::Bits1024 | KeyBits::Bits2048)
+        ) && self.announce_touch(&mut **yubikey, self.slot_id.unwrap());
 
         match key_algo {
             AsymmetricEncryption::Rsa(KeyBits::Bits1024) => {
-                decrypted = piv::decrypt_data(
-                    &mut yubikey,
-                    encrypted_data,
-                    piv::AlgorithmId::Rsa1024,
-                    piv::SlotId::Retired(self.slot_id.unwrap()),
-                )
-                .map_err(|_| "Failed to decrypt data");
+                decrypted = self
+                    .with_reconnect(&mut **yubikey, |yubikey| {
+                        yubikey.decrypt_data(
+                            encrypted_data,
+                            AlgorithmId::Rsa1024,
+                            self.slot_id.unwrap(),
+                        )
+                    })
+                    .map_err(|_| "Failed to decrypt data");
             }
             AsymmetricEncryption::Rsa(KeyBits::Bits2048) => {
-                decrypted = piv::decrypt_data(
-                    &mut yubikey,
-                    encrypted_data,
-                    piv::AlgorithmId::Rsa2048,
-                    piv::SlotId::Retired(self.slot_id.unwrap()),
-                )
-                .map_err(|_| "Failed to decrypt data");
+                decrypted = self
+                    .with_reconnect(&mut **yubikey, |yubikey| {
+                        yubikey.decrypt_data(
+                            encrypted_data,
+                            AlgorithmId::Rsa2048,
+                            self.slot_id.unwrap(),
+                        )
+                    })
+                    .map_err(|_| "Failed to decrypt data");
             }
             // The Yubikey do not support decryption with ECC, see:
             // https://docs.yubico.com/yesdk/users-manual/application-piv/apdu/auth-decrypt.html
@@ -166,11 +178,14 @@ impl KeyHandle for YubiKeyProvider {
             AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)) => {}
             */
             _ => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                return Err(SecurityModuleError::UnsupportedOperation(
                     "Key Algorithm not supported".to_string(),
-                )));
+                ));
             }
         }
+        if touch {
+            self.announce_completed();
+        }
         fn remove_pkcs1_padding(buffer: &[u8]) -> Result<Vec<u8>, &'static str> {
             let mut pos = 2; // Start nach dem ersten Padding-Byte `0x02`
             if buffer[0] != 0 {
@@ -204,7 +219,9 @@ impl KeyHandle for YubiKeyProvider {
             }
         }
     }
+}
 
+impl Encryptor for YubiKeyProvider {
     /// Encrypts data with the cryptographic key on a YubiKey.
     ///
     /// Uses the YubiKey API for encryption.
@@ -218,7 +235,7 @@ impl KeyHandle for YubiKeyProvider {
     /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `yubikey::Error` on failure.
     /// Möglicher Fehler: Müssen Daten vor dem returnen noch in Base64 umgewandelt werden?
     #[instrument]
-    fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         match self.key_algo.unwrap() {
             AsymmetricEncryption::Rsa(KeyBits::Bits1024)
             | AsymmetricEncryption::Rsa(KeyBits::Bits2048) => {
@@ -233,16 +250,19 @@ impl KeyHandle for YubiKeyProvider {
                 Ok(encrypted_data)
             }
             _ => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                return Err(SecurityModuleError::UnsupportedOperation(
                     "Key Algorithm not supported".to_string(),
-                )));
+                ));
             }
         }
     }
+}
 
+impl Verifier for YubiKeyProvider {
     /// Verifies a signature against the provided data using the YubiKey.
     ///
-    /// This method hashes the input data using SHA-256 and then verifies the signature.
+    /// This method hashes the input data with the configured hash, SHA-256 by default, and
+    /// then verifies the signature.
     ///
     /// # Arguments
     ///
@@ -254,7 +274,7 @@ impl KeyHandle for YubiKeyProvider {
     /// A `Result` indicating whether the signature is valid (`true`) or not (`false`),
     /// or a `SecurityModuleError` on failure.
     #[instrument]
-    fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
         match self.key_algo.unwrap() {
             AsymmetricEncryption::Rsa(KeyBits::Bits1024)
             | AsymmetricEncryption::Rsa(KeyBits::Bits2048) => {
@@ -262,8 +282,16 @@ impl KeyHandle for YubiKeyProvider {
                     .expect("failed to create RSA from public key PEM");
                 let key_pkey = PKey::from_rsa(rsa).unwrap();
 
-                let mut verifier = Verifier::new(MessageDigest::sha256(), &key_pkey)
-                    .expect("failed to create verifier");
+                let md = message_digest(self.signature_hash())?;
+                let mut verifier =
+                    sign::Verifier::new(md, &key_pkey).expect("failed to create verifier");
+                if self.rsa_padding == RsaPadding::Pss {
+                    verifier
+                        .set_rsa_padding(Padding::PKCS1_PSS)
+                        .and_then(|()| verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH))
+                        .and_then(|()| verifier.set_rsa_mgf1_md(md))
+                        .map_err(openssl_error)?;
+                }
                 verifier
                     .update(data)
                     .map_err(|_| "failed to update verifier")
@@ -286,8 +314,9 @@ impl KeyHandle for YubiKeyProvider {
                     .expect("failed to create ECC from public key PEM");
                 let ecc = PKey::from_ec_key(ecc).expect("failed to create PKey from ECC");
 
-                let mut verifier = Verifier::new(MessageDigest::sha256(), &ecc)
-                    .expect("failed to create verifier");
+                let md = message_digest(self.signature_hash())?;
+                let mut verifier =
+                    sign::Verifier::new(md, &ecc).expect("failed to create verifier");
                 verifier
                     .update(data)
                     .map_err(|_| "failed to update verifier")
@@ -304,38 +333,207 @@ impl KeyHandle for YubiKeyProvider {
                 }
             }
             _ => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                return Err(SecurityModuleError::UnsupportedOperation(
                     "Key Algorithm not supported".to_string(),
-                )));
+                ));
             }
         }
     }
 }
 
-#[instrument]
-fn create_digest_info(digest: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let oid_sha256: [u8; 9] = [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]; // OID für SHA-256
-    let mut digest_info = vec![];
-
-    // ASN.1 SEQUENCE Header
-    digest_info.extend_from_slice(&[
-        0x30, 0x31, // SEQUENCE, Länge 49
-        0x30, 0x0d, // SEQUENCE, Länge 13
-        0x06, 0x09, // OID Header
-    ]);
-    digest_info.extend_from_slice(&oid_sha256); // OID SHA-256
-    digest_info.extend_from_slice(&[
-        0x05, 0x00, // NULL
-        0x04, 0x20, // OCTET STRING, Länge 32
-    ]);
-    digest_info.extend_from_slice(digest); // SHA-256 Hash-Wert
+impl KeyHandle for YubiKeyProvider {
+    /// Returns the key as a `Signer` unless its usages exclude signing.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        (self.key_algo.is_some() && self.permits(Operation::Sign)).then_some(self)
+    }
+
+    /// Returns the key as a `Verifier` once a key is created or loaded.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        self.key_algo.is_some().then_some(self)
+    }
+
+    /// Returns the key as an `Encryptor` if it is an RSA key whose usages permit encryption.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        (self.is_rsa_key() && self.permits(Operation::Encrypt)).then_some(self)
+    }
 
+    /// Returns the key as a `Decryptor` if it is an RSA key whose usages permit decryption.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        (self.is_rsa_key() && self.permits(Operation::Decrypt)).then_some(self)
+    }
+}
+
+impl YubiKeyProvider {
+    /// Returns whether the key is an RSA key, the only keys the YubiKey can decrypt with.
+    fn is_rsa_key(&self) -> bool {
+        matches!(
+            self.key_algo,
+            Some(AsymmetricEncryption::Rsa(KeyBits::Bits1024))
+                | Some(AsymmetricEncryption::Rsa(KeyBits::Bits2048))
+        )
+    }
+
+    /// Returns whether the usages of the key permit `operation`. Keys without recorded
+    /// usages are not restricted.
+    fn permits(&self, operation: Operation) -> bool {
+        self.key_usages.is_empty() || operation.permitted_by(&self.key_usages)
+    }
+
+    /// The hash signatures are made with; without a configured hash the one matching the
+    /// curve of ECDSA keys, SHA-256 otherwise.
+    fn signature_hash(&self) -> Hash {
+        self.hash.unwrap_or(match self.key_algo {
+            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384))) => {
+                Hash::Sha2(Sha2Bits::Sha384)
+            }
+            _ => Hash::Sha2(Sha2Bits::Sha256),
+        })
+    }
+
+    /// Encodes `digest` for a raw RSA signature with a modulus of `key_len` bytes.
+    fn rsa_encode(
+        &self,
+        digest: &[u8],
+        hash: Hash,
+        md: MessageDigest,
+        key_len: usize,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        match self.rsa_padding {
+            RsaPadding::Pkcs1v15 => {
+                apply_pkcs1v15_padding(&create_digest_info(digest, hash)?, key_len)
+            }
+            RsaPadding::Pss => emsa_pss_encode(digest, md, key_len),
+        }
+    }
+}
+
+/// Maps `hash` to the OpenSSL digest, for the hashes the YubiKey can sign.
+pub(crate) fn message_digest(hash: Hash) -> Result<MessageDigest, SecurityModuleError> {
+    hash.message_digest().ok_or_else(|| {
+        SecurityModuleError::Hsm(HsmError::UnsupportedFeature(format!(
+            "Signatures with {:?}",
+            hash
+        )))
+    })
+}
+
+/// Wraps `digest` in the DER `DigestInfo` of PKCS #1 v1.5 signatures.
+pub(crate) fn create_digest_info(
+    digest: &[u8],
+    hash: Hash,
+) -> Result<Vec<u8>, SecurityModuleError> {
+    // id-sha1, and the NIST hash algorithms under 2.16.840.1.101.3.4.2
+    let oid = match hash {
+        Hash::Sha1 => vec![0x2b, 0x0e, 0x03, 0x02, 0x1a],
+        Hash::Sha2(bits) => nist_hash_oid(match bits {
+            Sha2Bits::Sha256 => 0x01,
+            Sha2Bits::Sha384 => 0x02,
+            Sha2Bits::Sha512 => 0x03,
+            Sha2Bits::Sha224 => 0x04,
+            Sha2Bits::Sha512_224 => 0x05,
+            Sha2Bits::Sha512_256 => 0x06,
+        }),
+        Hash::Sha3(bits) => nist_hash_oid(match bits {
+            Sha3Bits::Sha3_224 => 0x07,
+            Sha3Bits::Sha3_256 => 0x08,
+            Sha3Bits::Sha3_384 => 0x09,
+            Sha3Bits::Sha3_512 => 0x0a,
+        }),
+        _ => {
+            return Err(SecurityModuleError::Hsm(HsmError::UnsupportedFeature(
+                format!("Signatures with {:?}", hash),
+            )))
+        }
+    };
+
+    // SEQUENCE { SEQUENCE { OID, NULL }, OCTET STRING }
+    let algorithm_len = 2 + oid.len() + 2;
+    let mut digest_info = vec![
+        0x30,
+        (2 + algorithm_len + 2 + digest.len()) as u8,
+        0x30,
+        algorithm_len as u8,
+        0x06,
+        oid.len() as u8,
+    ];
+    digest_info.extend_from_slice(&oid);
+    digest_info.extend_from_slice(&[0x05, 0x00, 0x04, digest.len() as u8]);
+    digest_info.extend_from_slice(digest);
     Ok(digest_info)
 }
 
+fn nist_hash_oid(last: u8) -> Vec<u8> {
+    vec![0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, last]
+}
+
+/// Encodes `digest` with EMSA-PSS for a modulus of `key_len` bytes, using MGF1 with the
+/// same hash and a salt as long as the digest.
+pub(crate) fn emsa_pss_encode(
+    digest: &[u8],
+    md: MessageDigest,
+    key_len: usize,
+) -> Result<Vec<u8>, SecurityModuleError> {
+    let hash_len = digest.len();
+    if key_len < 2 * hash_len + 2 {
+        return Err(SecurityModuleError::Hsm(HsmError::UnsupportedFeature(
+            "The hash is too long for PSS signatures with this key size".to_owned(),
+        )));
+    }
+    let mut salt = vec![0; hash_len];
+    rand_bytes(&mut salt).map_err(openssl_error)?;
+
+    let mut message = vec![0; 8];
+    message.extend_from_slice(digest);
+    message.extend_from_slice(&salt);
+    let h = hash(md, &message).map_err(openssl_error)?;
+
+    // DB = PS || 0x01 || salt, masked with MGF1(H)
+    let db_len = key_len - hash_len - 1;
+    let mut db = vec![0; db_len - hash_len - 1];
+    db.push(0x01);
+    db.extend_from_slice(&salt);
+    let mask = mgf1(&h, db_len, md).map_err(openssl_error)?;
+    db.iter_mut()
+        .zip(mask)
+        .for_each(|(byte, mask)| *byte ^= mask);
+    // The encoded message has one bit less than the modulus.
+    db[0] &= 0x7f;
+
+    let mut encoded = db;
+    encoded.extend_from_slice(&h);
+    encoded.push(0xbc);
+    Ok(encoded)
+}
+
+fn mgf1(seed: &[u8], len: usize, md: MessageDigest) -> Result<Vec<u8>, ErrorStack> {
+    let mut mask = Vec::with_capacity(len);
+    let mut counter: u32 = 0;
+    while mask.len() < len {
+        mask.extend_from_slice(&hash(md, &[seed, &counter.to_be_bytes()].concat())?);
+        counter += 1;
+    }
+    mask.truncate(len);
+    Ok(mask)
+}
+
+fn openssl_error(err: ErrorStack) -> SecurityModuleError {
+    SecurityModuleError::Hsm(HsmError::DeviceSpecific(err.to_string()))
+}
+
 #[instrument]
-fn apply_pkcs1v15_padding(data: &[u8], block_size: usize) -> Vec<u8> {
-    let padding_length = block_size - data.len() - 3;
+pub(crate) fn apply_pkcs1v15_padding(
+    data: &[u8],
+    block_size: usize,
+) -> Result<Vec<u8>, SecurityModuleError> {
+    // At least eight bytes of padding, RFC 8017 section 9.2
+    let padding_length = block_size
+        .checked_sub(data.len() + 3)
+        .filter(|&length| length >= 8)
+        .ok_or_else(|| {
+            SecurityModuleError::Hsm(HsmError::UnsupportedFeature(
+                "The hash is too long for PKCS #1 v1.5 signatures with this key size".to_owned(),
+            ))
+        })?;
     let mut padded_data = Vec::with_capacity(block_size);
     padded_data.push(0x00);
     padded_data.push(0x01);
@@ -344,5 +542,5 @@ fn apply_pkcs1v15_padding(data: &[u8], block_size: usize) -> Vec<u8> {
     }
     padded_data.push(0x00);
     padded_data.extend_from_slice(data);
-    padded_data
+    Ok(padded_data)
 }
@@ -0,0 +1,211 @@
+use super::apdu::{parse_tlvs, tlv};
+use crate::{
+    common::crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::Hash,
+            KeyBits,
+        },
+        KeyUsage,
+    },
+    hsm::{core::error::HsmError, HsmProviderConfig, PinPolicy, RsaPadding, TouchPolicy},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Marks data objects holding a `KeyMetadata` record.
+const MAGIC: &[u8; 4] = b"CLKM";
+/// The record version written by this implementation.
+const VERSION: u8 = 1;
+
+const TAG_KEY_ID: u8 = 0x01;
+const TAG_SLOT: u8 = 0x02;
+const TAG_ALGORITHM: u8 = 0x03;
+const TAG_USAGES: u8 = 0x04;
+const TAG_CREATED: u8 = 0x05;
+const TAG_CERTIFICATE: u8 = 0x06;
+const TAG_PUBLIC_KEY: u8 = 0x07;
+const TAG_HASH: u8 = 0x08;
+const TAG_RSA_PADDING: u8 = 0x09;
+const TAG_PIN_POLICY: u8 = 0x0a;
+const TAG_TOUCH_POLICY: u8 = 0x0b;
+
+/// Information about a key generated on the YubiKey, stored in a PIV data object next to it.
+///
+/// The record starts with a magic header and a version byte, followed by single byte tag
+/// TLVs. Unknown tags are skipped, so later versions can add fields without breaking older
+/// readers, which accept records of any version. Algorithm, usages, hash and policies are
+/// stored as JSON to keep the full descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyMetadata {
+    pub(crate) key_id: String,
+    /// The PIV key reference of the slot holding the private key.
+    pub(crate) slot: u8,
+    pub(crate) algorithm: AsymmetricEncryption,
+    pub(crate) usages: Vec<KeyUsage>,
+    /// Seconds since the Unix epoch, `None` for keys migrated from the legacy format.
+    pub(crate) created: Option<u64>,
+    /// The data object holding a certificate for the key, if one was stored.
+    pub(crate) certificate: Option<u32>,
+    /// The PEM encoded public key.
+    pub(crate) public_key: String,
+    /// The hash signatures are made with, `None` for the algorithm's default.
+    pub(crate) hash: Option<Hash>,
+    pub(crate) rsa_padding: RsaPadding,
+    pub(crate) pin_policy: PinPolicy,
+    pub(crate) touch_policy: TouchPolicy,
+}
+
+impl KeyMetadata {
+    /// Creates the record for a key generated now with `config`.
+    pub(crate) fn new(
+        key_id: &str,
+        slot: u8,
+        config: &HsmProviderConfig,
+        public_key: String,
+    ) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|created| created.as_secs())
+            .ok();
+        Self {
+            key_id: key_id.to_owned(),
+            slot,
+            algorithm: config.key_algorithm,
+            usages: config.key_usages.clone(),
+            created,
+            certificate: None,
+            public_key,
+            hash: config.hash,
+            rsa_padding: config.rsa_padding,
+            pin_policy: config.pin_policy,
+            touch_policy: config.touch_policy,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, HsmError> {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        data.extend(tlv(TAG_KEY_ID, self.key_id.as_bytes()));
+        data.extend(tlv(TAG_SLOT, &[self.slot]));
+        data.extend(tlv(TAG_ALGORITHM, &to_json(&self.algorithm)?));
+        data.extend(tlv(TAG_USAGES, &to_json(&self.usages)?));
+        if let Some(created) = self.created {
+            data.extend(tlv(TAG_CREATED, &created.to_be_bytes()));
+        }
+        if let Some(certificate) = self.certificate {
+            data.extend(tlv(TAG_CERTIFICATE, &certificate.to_be_bytes()));
+        }
+        data.extend(tlv(TAG_PUBLIC_KEY, self.public_key.as_bytes()));
+        if let Some(hash) = self.hash {
+            data.extend(tlv(TAG_HASH, &to_json(&hash)?));
+        }
+        data.extend(tlv(TAG_RSA_PADDING, &to_json(&self.rsa_padding)?));
+        data.extend(tlv(TAG_PIN_POLICY, &to_json(&self.pin_policy)?));
+        data.extend(tlv(TAG_TOUCH_POLICY, &to_json(&self.touch_policy)?));
+        Ok(data)
+    }
+
+    /// Parses a record written by `encode`.
+    ///
+    /// Returns `Ok(None)` if `data` does not start with the magic header, e.g. because it
+    /// is empty or in the legacy format.
+    pub(crate) fn decode(data: &[u8]) -> Result<Option<Self>, HsmError> {
+        let Some(data) = data.strip_prefix(MAGIC) else {
+            return Ok(None);
+        };
+        // Later versions only add tags, so records of any version are read alike.
+        let (_version, data) = data.split_first().ok_or_else(|| malformed("version"))?;
+
+        let (mut key_id, mut slot, mut algorithm, mut usages) = (None, None, None, None);
+        let (mut created, mut certificate, mut public_key) = (None, None, None);
+        let (mut hash, mut rsa_padding, mut pin_policy, mut touch_policy) =
+            (None, None, None, None);
+        for (tag, value) in parse_tlvs(data)? {
+            match tag {
+                TAG_KEY_ID => key_id = Some(utf8(value, "key id")?),
+                TAG_SLOT => slot = Some(*value.first().ok_or_else(|| malformed("slot"))?),
+                TAG_ALGORITHM => algorithm = Some(from_json(value, "algorithm")?),
+                TAG_USAGES => usages = Some(from_json(value, "usages")?),
+                TAG_CREATED => {
+                    let value = value.try_into().map_err(|_| malformed("creation time"))?;
+                    created = Some(u64::from_be_bytes(value));
+                }
+                TAG_CERTIFICATE => {
+                    let value = value.try_into().map_err(|_| malformed("certificate"))?;
+                    certificate = Some(u32::from_be_bytes(value));
+                }
+                TAG_PUBLIC_KEY => public_key = Some(utf8(value, "public key")?),
+                TAG_HASH => hash = Some(from_json(value, "hash")?),
+                TAG_RSA_PADDING => rsa_padding = Some(from_json(value, "RSA padding")?),
+                TAG_PIN_POLICY => pin_policy = Some(from_json(value, "PIN policy")?),
+                TAG_TOUCH_POLICY => touch_policy = Some(from_json(value, "touch policy")?),
+                _ => {}
+            }
+        }
+
+        Ok(Some(Self {
+            key_id: key_id.ok_or_else(|| malformed("key id"))?,
+            slot: slot.ok_or_else(|| malformed("slot"))?,
+            algorithm: algorithm.ok_or_else(|| malformed("algorithm"))?,
+            usages: usages.unwrap_or_default(),
+            created,
+            certificate,
+            public_key: public_key.ok_or_else(|| malformed("public key"))?,
+            hash,
+            rsa_padding: rsa_padding.unwrap_or_default(),
+            pin_policy: pin_policy.unwrap_or_default(),
+            touch_policy: touch_policy.unwrap_or_default(),
+        }))
+    }
+
+    /// Parses a record in the NUL-separated legacy format of key name, object id, public key
+    /// and algorithm name, for the key in `slot`.
+    pub(crate) fn decode_legacy(data: &[u8], slot: u8) -> Result<Self, HsmError> {
+        let parts: Vec<&[u8]> = data.split(|&x| x == 0).collect();
+        let [key_id, _, public_key, algorithm] = parts[..] else {
+            return Err(malformed("legacy record"));
+        };
+        if key_id.is_empty() || public_key.is_empty() {
+            return Err(malformed("legacy record"));
+        }
+
+        let algorithm = match algorithm {
+            b"Rsa1024" => AsymmetricEncryption::Rsa(KeyBits::Bits1024),
+            b"Rsa2048" => AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            b"EccP256" => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+            b"EccP384" => AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)),
+            _ => return Err(malformed("legacy algorithm")),
+        };
+
+        Ok(Self {
+            key_id: utf8(key_id, "key id")?,
+            slot,
+            algorithm,
+            usages: Vec::new(),
+            created: None,
+            certificate: None,
+            public_key: utf8(public_key, "public key")?,
+            hash: None,
+            rsa_padding: RsaPadding::default(),
+            pin_policy: PinPolicy::default(),
+            touch_policy: TouchPolicy::default(),
+        })
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, HsmError> {
+    serde_json::to_vec(value).map_err(|e| HsmError::DeviceSpecific(e.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(value: &[u8], field: &str) -> Result<T, HsmError> {
+    serde_json::from_slice(value).map_err(|_| malformed(field))
+}
+
+fn utf8(value: &[u8], field: &str) -> Result<String, HsmError> {
+    String::from_utf8(value.to_vec()).map_err(|_| malformed(field))
+}
+
+fn malformed(field: &str) -> HsmError {
+    HsmError::DeviceSpecific(format!("Malformed key metadata: invalid {}", field))
+}
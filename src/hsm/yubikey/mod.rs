@@ -1,10 +1,39 @@
-use crate::common::crypto::algorithms::encryption::AsymmetricEncryption;
-use ::yubikey::{piv::RetiredSlotId, YubiKey};
-use std::sync::{Arc, Mutex};
-use tracing::instrument;
+use crate::common::{
+    crypto::{
+        algorithms::{encryption::AsymmetricEncryption, hashes::Hash},
+        KeyUsage,
+    },
+    error::SecurityModuleError,
+    traits::interaction::{
+        CredentialKind, CredentialRequest, InteractionEvent, InteractionHandler,
+    },
+};
+use crate::hsm::{core::error::HsmError, RsaPadding};
+use ::yubikey::{piv::SlotId, Serial, TouchPolicy};
+use admin::{credential_error, ManagementKey};
+use simulator::PivSimulator;
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use tracing::{instrument, warn};
+use transport::PivTransport;
 
+pub mod admin;
+mod apdu;
+pub mod attestation;
+pub mod certificate;
+pub mod device;
 pub mod key_handle;
+pub(crate) mod metadata;
 pub mod provider;
+pub mod simulator;
+pub(crate) mod transport;
+
+/// The module name used in interaction requests and events.
+const MODULE_NAME: &str = "YubiKey";
+/// The PIN a YubiKey ships with.
+const DEFAULT_PIN: &[u8] = b"123456";
 
 /// A YubiKey-based cryptographic provider for managing cryptographic keys and performing
 /// cryptographic operations.
@@ -18,11 +47,21 @@ pub mod provider;
 pub struct YubiKeyProvider {
     /// A unique identifier for the cryptographic key managed by this provider.
     pub(super) pkey: String,
-    pub(super) slot_id: Option<RetiredSlotId>,
+    pub(super) slot_id: Option<SlotId>,
     pub(super) key_algo: Option<AsymmetricEncryption>,
-    pub(super) yubikey: Option<Arc<Mutex<YubiKey>>>,
+    /// The hash for signatures, `None` for the default of `key_algo`.
+    pub(super) hash: Option<Hash>,
+    pub(super) rsa_padding: RsaPadding,
+    /// The usages the key was created with, empty if they are not known.
+    pub(super) key_usages: Vec<KeyUsage>,
+    pub(super) yubikey: Option<Arc<Mutex<Box<dyn PivTransport>>>>,
+    /// The serial number of the YubiKey to open, `None` for the only connected one.
+    pub(super) serial: Option<Serial>,
+    /// The simulated YubiKey `initialize_module` opens instead of a connected one.
+    pub(super) simulator: Option<PivSimulator>,
     pub(super) pin: String,
-    pub(super) management_key: Option<[u8; 24]>,
+    pub(super) management_key: Option<ManagementKey>,
+    pub(super) interaction: Option<Arc<dyn InteractionHandler>>,
 }
 
 impl YubiKeyProvider {
@@ -41,9 +80,201 @@ impl YubiKeyProvider {
             pkey: String::new(),
             slot_id: None,
             key_algo: None,
+            hash: None,
+            rsa_padding: RsaPadding::default(),
+            key_usages: Vec::new(),
             yubikey: None,
+            serial: None,
+            simulator: None,
             pin: String::new(),
             management_key: None,
+            interaction: None,
+        }
+    }
+
+    /// Asks the interaction handler for the secret described by `request`.
+    ///
+    /// Without a handler the YubiKey's factory default `default` is used, which keeps
+    /// unattended setups with unchanged credentials working.
+    pub(super) fn request_credential(
+        &self,
+        request: &CredentialRequest,
+        default: &[u8],
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        match &self.interaction {
+            Some(interaction) => interaction.request_credential(request),
+            None => {
+                warn!(
+                    "No interaction handler set, using the factory default for: {}",
+                    request.prompt
+                );
+                Ok(default.to_vec())
+            }
+        }
+    }
+
+    /// Tells the interaction handler that the secret returned for `request` was wrong.
+    pub(super) fn reject_credential(&self, request: &CredentialRequest) {
+        if let Some(interaction) = &self.interaction {
+            interaction.credential_rejected(request);
+        }
+    }
+
+    /// Announces that the key in `slot` waits for a touch, if its touch policy requires one.
+    ///
+    /// Returns whether a `TouchRequired` event was sent, in which case the caller sends
+    /// `Completed` once the operation has finished. Firmware before 5.3 does not report
+    /// the touch policy; no event is sent then.
+    pub(super) fn announce_touch(&self, yubikey: &mut dyn PivTransport, slot: SlotId) -> bool {
+        let Some(interaction) = &self.interaction else {
+            return false;
+        };
+        let touch_required = matches!(
+            yubikey.key_policy(slot),
+            Ok(Some((_, TouchPolicy::Always | TouchPolicy::Cached)))
+        );
+        if touch_required {
+            interaction.notify(&InteractionEvent::TouchRequired {
+                module: MODULE_NAME.to_owned(),
+            });
+        }
+        touch_required
+    }
+
+    /// Runs `operation` on `yubikey`, and once more after `reconnect` if it failed because
+    /// the YubiKey was removed or reset in between.
+    ///
+    /// The reconnected YubiKey replaces the one behind the provider's mutex, so providers
+    /// cached in `SecModules` keep working after the YubiKey is plugged in again. An
+    /// operation refused for a missing PIN verification or management key authentication is
+    /// repeated after `unlock`, as another connection to the YubiKey, e.g. from
+    /// `list_devices`, resets both.
+    pub(super) fn with_reconnect<T, E: PivError>(
+        &self,
+        yubikey: &mut dyn PivTransport,
+        mut operation: impl FnMut(&mut dyn PivTransport) -> Result<T, E>,
+    ) -> Result<T, HsmError> {
+        match operation(yubikey) {
+            Err(err) if err.is_disconnected() => {
+                warn!(
+                    "Lost the connection to YubiKey {}: {}",
+                    yubikey.serial(),
+                    err.into_hsm_error()
+                );
+                self.reconnect(yubikey)?;
+            }
+            Err(err) if err.is_unauthenticated() => self.unlock(yubikey)?,
+            result => return result.map_err(PivError::into_hsm_error),
+        }
+        operation(yubikey).map_err(PivError::into_hsm_error)
+    }
+
+    /// Opens the YubiKey with the serial number of `yubikey` again, and verifies the PIN
+    /// and authenticates with the management key as `initialize_module` did.
+    pub(super) fn reconnect(&self, yubikey: &mut dyn PivTransport) -> Result<(), HsmError> {
+        yubikey.reconnect()?;
+        self.unlock(yubikey)
+    }
+
+    /// Verifies the PIN and authenticates with the management key, which key generation
+    /// and writing key objects require.
+    pub(super) fn unlock(&self, yubikey: &mut dyn PivTransport) -> Result<(), HsmError> {
+        yubikey.verify_pin(self.pin.as_bytes()).map_err(piv_error)?;
+        self.authenticate(yubikey)
+    }
+
+    /// Returns the YubiKey opened by `initialize_module`.
+    pub(super) fn transport(
+        &self,
+    ) -> Result<Arc<Mutex<Box<dyn PivTransport>>>, SecurityModuleError> {
+        self.yubikey.clone().ok_or_else(|| {
+            SecurityModuleError::InitializationError(
+                "The YubiKey module is not initialized".to_owned(),
+            )
+        })
+    }
+
+    /// Announces the end of an operation for which `announce_touch` returned `true`.
+    pub(super) fn announce_completed(&self) {
+        if let Some(interaction) = &self.interaction {
+            interaction.notify(&InteractionEvent::Completed {
+                module: MODULE_NAME.to_owned(),
+            });
         }
     }
 }
+
+/// An error of the operations `with_reconnect` runs.
+pub(super) trait PivError {
+    /// Whether the YubiKey is gone or was reset, so reconnecting may help.
+    fn is_disconnected(&self) -> bool;
+
+    /// Whether the PIN verification or management key authentication is missing.
+    fn is_unauthenticated(&self) -> bool;
+
+    fn into_hsm_error(self) -> HsmError;
+}
+
+impl PivError for ::yubikey::Error {
+    fn is_disconnected(&self) -> bool {
+        is_disconnected(self)
+    }
+
+    fn is_unauthenticated(&self) -> bool {
+        matches!(self, ::yubikey::Error::AuthenticationError)
+    }
+
+    fn into_hsm_error(self) -> HsmError {
+        piv_error(self)
+    }
+}
+
+/// Errors mapped with `piv_error`.
+impl PivError for HsmError {
+    fn is_disconnected(&self) -> bool {
+        matches!(self, HsmError::Communication(_))
+    }
+
+    fn is_unauthenticated(&self) -> bool {
+        matches!(self, HsmError::Authentication(_))
+    }
+
+    fn into_hsm_error(self) -> HsmError {
+        self
+    }
+}
+
+/// Maps `err` to an `HsmError` from which `with_reconnect` can still tell whether the
+/// YubiKey was lost or the authentication is missing.
+pub(super) fn piv_error(err: ::yubikey::Error) -> HsmError {
+    match err {
+        err if is_disconnected(&err) => HsmError::Communication(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            err.to_string(),
+        )),
+        ::yubikey::Error::AuthenticationError => HsmError::Authentication(err.to_string()),
+        ::yubikey::Error::WrongPin { .. } | ::yubikey::Error::PinLocked => {
+            credential_error(err, CredentialKind::Pin)
+        }
+        err => HsmError::DeviceSpecific(err.to_string()),
+    }
+}
+
+/// Whether `err` means that the YubiKey is gone or was reset, so reconnecting may help.
+fn is_disconnected(err: &::yubikey::Error) -> bool {
+    matches!(
+        err,
+        ::yubikey::Error::PcscError {
+            inner: Some(
+                pcsc::Error::ResetCard
+                    | pcsc::Error::RemovedCard
+                    | pcsc::Error::NoSmartcard
+                    | pcsc::Error::ReaderUnavailable
+                    | pcsc::Error::UnknownReader
+                    | pcsc::Error::InvalidHandle
+                    | pcsc::Error::NoService
+                    | pcsc::Error::ServiceStopped
+            )
+        }
+    )
+}
@@ -1,23 +1,35 @@
-use super::YubiKeyProvider;
+use super::{
+    admin::{credential_error, ManagementKey},
+    device::open_device,
+    metadata::KeyMetadata,
+    piv_error,
+    transport::PivTransport,
+    PivError, YubiKeyProvider, DEFAULT_PIN, MODULE_NAME,
+};
 use crate::common::{
-    crypto::algorithms::{
-        encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
-        KeyBits,
+    capabilities::{Capabilities, KeyAlgorithm, Operation},
+    crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits, Sha3Bits},
+            KeyBits,
+        },
+        KeyUsage,
     },
     error::SecurityModuleError,
-    traits::module_provider::Provider,
+    traits::{
+        interaction::{CredentialKind, CredentialRequest, InteractionHandler},
+        module_provider::Provider,
+    },
 };
-use crate::hsm::{core::error::HsmError, HsmProviderConfig};
-use ::yubikey::{
-    piv::{self, AlgorithmId, RetiredSlotId, SlotId},
-    Error, YubiKey,
+use crate::hsm::{
+    core::error::HsmError, HsmProviderConfig, KeySlot, PinPolicy, SlotRole, TouchPolicy,
 };
+use ::yubikey::piv::{AlgorithmId, RetiredSlotId, SlotId};
 use base64::{engine::general_purpose, Engine};
 use std::any::Any;
 use std::sync::{Arc, Mutex};
-use tracing::instrument;
-use x509_cert::der::Encode;
-use yubikey::MgmKey;
+use tracing::{instrument, warn};
 
 const SLOTS: [RetiredSlotId; 20] = [
     RetiredSlotId::R1,
@@ -42,6 +54,19 @@ const SLOTS: [RetiredSlotId; 20] = [
     RetiredSlotId::R20,
 ];
 
+/// The hashes signatures can be made with: those OpenSSL computes the digests of.
+const SIGNATURE_HASHES: [Hash; 9] = [
+    Hash::Sha1,
+    Hash::Sha2(Sha2Bits::Sha224),
+    Hash::Sha2(Sha2Bits::Sha256),
+    Hash::Sha2(Sha2Bits::Sha384),
+    Hash::Sha2(Sha2Bits::Sha512),
+    Hash::Sha3(Sha3Bits::Sha3_224),
+    Hash::Sha3(Sha3Bits::Sha3_256),
+    Hash::Sha3(Sha3Bits::Sha3_384),
+    Hash::Sha3(Sha3Bits::Sha3_512),
+];
+
 /// IDs/addresses for read/write objects operations;
 /// see https://developers.yubico.com/yubico-piv-tool/Actions/read_write_objects.html
 const SLOTSU32: [u32; 20] = [
@@ -102,6 +127,8 @@ impl Provider for YubiKeyProvider {
     ) -> Result<(), SecurityModuleError> {
         if let Some(hsm_config) = config.downcast_ref::<HsmProviderConfig>() {
             self.key_algo = Some(hsm_config.key_algorithm);
+            let key_config = hsm_config.clone();
+            let requested_slot = requested_slot(hsm_config.slot)?;
             let key_algo = self.key_algo.clone();
             let key_algorithm;
             match key_algo {
@@ -113,15 +140,27 @@ impl Provider for YubiKeyProvider {
                 }
             }
 
-            let slot: u32;
             let slot_id;
             let algorithm: AlgorithmId;
 
-            if !(self.load_key(key_id, config).is_ok()) {
-                let mut yubikey = self.yubikey.as_ref().unwrap().lock().unwrap();
-                let _ = yubikey.verify_pin(self.pin.as_ref());
-                let _ = yubikey.authenticate(MgmKey::new(self.management_key.unwrap()).unwrap());
-                match get_free_slot(&mut yubikey) {
+            if let Some(requested_slot) = requested_slot {
+                let yubikey = self.transport()?;
+                let mut yubikey = yubikey.lock().unwrap();
+                self.with_reconnect(&mut **yubikey, |yubikey| self.unlock(yubikey))
+                    .map_err(SecurityModuleError::Hsm)?;
+                if !hsm_config.overwrite && holds_key(&mut **yubikey, requested_slot) {
+                    return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(format!(
+                        "Slot {:?} already holds a key, set `overwrite` to replace it",
+                        requested_slot
+                    ))));
+                }
+                slot_id = requested_slot;
+            } else if !(self.load_key(key_id, config).is_ok()) {
+                let yubikey = self.transport()?;
+                let mut yubikey = yubikey.lock().unwrap();
+                self.with_reconnect(&mut **yubikey, |yubikey| self.unlock(yubikey))
+                    .map_err(SecurityModuleError::Hsm)?;
+                match get_free_slot(&mut **yubikey) {
                     Ok(free) => {
                         slot_id = free;
                     }
@@ -134,35 +173,27 @@ impl Provider for YubiKeyProvider {
             }
 
             fn generate_key(
-                mut yubikey: &mut YubiKey,
+                provider: &YubiKeyProvider,
+                yubikey: &mut dyn PivTransport,
                 algorithm: AlgorithmId,
-                slot_id: RetiredSlotId,
-            ) -> Result<(RetiredSlotId, String), SecurityModuleError> {
-                let pkey: String;
-
-                let gen_key = piv::generate(
-                    &mut yubikey,
-                    SlotId::Retired(slot_id),
-                    algorithm,
-                    yubikey::PinPolicy::Default,
-                    yubikey::TouchPolicy::Default,
+                slot_id: SlotId,
+                config: &HsmProviderConfig,
+            ) -> Result<(SlotId, String), SecurityModuleError> {
+                let gen_key = provider
+                    .with_reconnect(yubikey, |yubikey| {
+                        yubikey.generate(
+                            slot_id,
+                            algorithm,
+                            config.pin_policy.into(),
+                            config.touch_policy.into(),
+                        )
+                    })
+                    .map_err(SecurityModuleError::Hsm)?;
+                let gen_key = general_purpose::STANDARD.encode(gen_key);
+                let pkey = format!(
+                    "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----",
+                    gen_key.trim()
                 );
-                match gen_key {
-                    Ok(_) => {
-                        let gen_key = gen_key.as_ref().unwrap().to_der().unwrap();
-                        let gen_key = general_purpose::STANDARD.encode(&gen_key);
-                        let gen_key = format!(
-                            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----",
-                            gen_key.trim()
-                        );
-                        pkey = gen_key;
-                    }
-                    Err(err) => {
-                        return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                            err.to_string(),
-                        )))
-                    }
-                }
                 Ok((slot_id, pkey))
             }
 
@@ -192,42 +223,23 @@ impl Provider for YubiKeyProvider {
                 }
             }
 
-            let algo: &str;
-            match key_algorithm {
-                AsymmetricEncryption::Rsa(KeyBits::Bits1024) => algo = "Rsa1024",
-                AsymmetricEncryption::Rsa(KeyBits::Bits2048) => algo = "Rsa2048",
-                AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)) => {
-                    algo = "EccP256"
-                }
-                AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)) => {
-                    algo = "EccP384"
-                }
-                _ => {
-                    return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                        "Key Algorithm not supported".to_string(),
-                    )));
-                }
-            }
-
-            let mut yubikey = self.yubikey.as_ref().unwrap().lock().unwrap();
-            let (slot_id, pkey) = generate_key(&mut yubikey, algorithm, slot_id).unwrap();
+            let yubikey = self.transport()?;
+            let mut yubikey = yubikey.lock().unwrap();
+            let (slot_id, pkey) =
+                generate_key(self, &mut **yubikey, algorithm, slot_id, &key_config)?;
             self.slot_id = Some(slot_id);
             self.pkey = pkey;
+            self.hash = key_config.hash;
+            self.rsa_padding = key_config.rsa_padding;
+            self.key_usages = key_config.key_usages.clone();
 
             let pkey = self.pkey.clone();
-            slot = get_reference_u32slot(self.slot_id.unwrap());
-
-            let _ = yubikey.verify_pin(self.pin.as_ref());
-            let _ = yubikey.authenticate(MgmKey::new(self.management_key.unwrap()).unwrap());
 
-            match save_key_object(&mut yubikey, key_id, slot, &pkey, algo) {
-                Ok(_) => Ok(()),
-                Err(err) => {
-                    return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                        err.to_string(),
-                    )))
-                }
-            }
+            let metadata = KeyMetadata::new(key_id, slot_id.into(), &key_config, pkey);
+            self.with_reconnect(&mut **yubikey, |yubikey| {
+                save_key_object(yubikey, slot_id, &metadata)
+            })
+            .map_err(SecurityModuleError::Hsm)
         } else {
             Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
                 "Failed to get the Configurations".to_string(),
@@ -254,52 +266,27 @@ impl Provider for YubiKeyProvider {
     fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
         if let Some(hsm_config) = config.downcast_ref::<HsmProviderConfig>() {
             self.key_algo = Some(hsm_config.key_algorithm);
-            let mut yubikey = self.yubikey.as_ref().unwrap().lock().unwrap();
+            let slots = match requested_slot(hsm_config.slot)? {
+                Some(slot) => vec![slot],
+                None => all_slots(),
+            };
+            let yubikey = self.transport()?;
+            let mut yubikey = yubikey.lock().unwrap();
+            self.with_reconnect(&mut **yubikey, |yubikey| self.unlock(yubikey))
+                .map_err(SecurityModuleError::Hsm)?;
             let mut found = false;
-            for i in 10..20 {
-                let _ = yubikey.verify_pin(self.pin.as_ref());
-                let _ = yubikey.authenticate(MgmKey::new(self.management_key.unwrap()).unwrap());
-                let data = yubikey.fetch_object(SLOTSU32[i]);
-                let mut output: Vec<u8> = Vec::new();
-                match data {
-                    Ok(data) => {
-                        output = data.to_vec();
-                    }
-                    Err(_) => {}
-                }
-
-                let data = output;
-                match parse_slot_data(&data) {
-                    Ok((key_name, _, public_key, algo)) => {
-                        let _keyalgo;
-                        match self.key_algo {
-                            Some(AsymmetricEncryption::Rsa(KeyBits::Bits1024)) => {
-                                _keyalgo = "Rsa1024"
-                            }
-                            Some(AsymmetricEncryption::Rsa(KeyBits::Bits2048)) => {
-                                _keyalgo = "Rsa2048"
-                            }
-                            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
-                                EccCurves::P256,
-                            ))) => _keyalgo = "EccP256",
-                            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
-                                EccCurves::P384,
-                            ))) => _keyalgo = "EccP384",
-                            _ => {
-                                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                                    "Algorithm not supported".to_string(),
-                                )))
-                            }
-                        }
-                        if key_name == key_id.to_string() && _keyalgo == algo {
-                            self.slot_id = Some(SLOTS[i - 10]);
-                            self.pkey = public_key;
-                            found = true;
-                            break;
-                        }
-                    }
-                    Err(_) => {
-                        continue;
+            for slot in slots {
+                let metadata =
+                    self.with_reconnect(&mut **yubikey, |yubikey| read_key_object(yubikey, slot));
+                if let Ok(Some(metadata)) = metadata {
+                    if metadata.key_id == key_id && Some(metadata.algorithm) == self.key_algo {
+                        self.slot_id = Some(slot);
+                        self.pkey = metadata.public_key;
+                        self.hash = hsm_config.hash.or(metadata.hash);
+                        self.rsa_padding = metadata.rsa_padding;
+                        self.key_usages = metadata.usages;
+                        found = true;
+                        break;
                     }
                 }
             }
@@ -323,6 +310,13 @@ impl Provider for YubiKeyProvider {
     /// This method initializes the YubiKey device and sets up the necessary environment
     /// for cryptographic operations.
     ///
+    /// Opens the YubiKey selected with `with_serial`; without a serial exactly one YubiKey
+    /// has to be connected.
+    ///
+    /// The PIN and the management key are requested from the interaction handler and
+    /// verified; a rejected secret is reported back to the handler. Without a handler the
+    /// factory defaults are used.
+    ///
     /// # Arguments
     ///
     /// * `key_algorithm` - The asymmetric encryption algorithm to be used for the key.
@@ -334,238 +328,363 @@ impl Provider for YubiKeyProvider {
     /// On failure, it returns a Yubikey based `Error`.
     #[instrument]
     fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
-        let yubi = YubiKey::open().map_err(|_| Error::NotFound);
-        let mut yubikey: YubiKey;
-        match yubi {
-            Ok(yubi) => {
-                yubikey = yubi;
-            }
-            Err(err) => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                    err.to_string(),
-                )));
-            }
+        let mut yubikey: Box<dyn PivTransport> = match &self.simulator {
+            Some(simulator) => Box::new(simulator.clone()),
+            None => Box::new(open_device(self.serial).map_err(SecurityModuleError::Hsm)?),
+        };
+
+        let request = CredentialRequest {
+            kind: CredentialKind::Pin,
+            module: MODULE_NAME.to_owned(),
+            key_id: None,
+            prompt: format!("PIN of YubiKey {}", yubikey.serial()),
+            retries_left: yubikey.get_pin_retries().ok(),
+        };
+        let pin = self.request_credential(&request, DEFAULT_PIN)?;
+        if let Err(err) = yubikey.verify_pin(&pin) {
+            self.reject_credential(&request);
+            return Err(SecurityModuleError::Hsm(credential_error(
+                err,
+                CredentialKind::Pin,
+            )));
         }
-        // Hier muesste die Pin Eingabe und die Managementkey Eingabe implementiert werden. Ist aktuell hardcoded.
-        self.pin = "123456".to_string();
-        self.management_key = Some(*MgmKey::default().as_ref());
-
-        let verify = yubikey.verify_pin(self.pin.as_ref());
-        match verify {
-            Ok(_) => {
-                self.yubikey = Some(Arc::new(Mutex::new(yubikey)));
 
-                Ok(())
-            }
-            Err(err) => {
-                return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-                    err.to_string(),
-                )));
+        // A management key stored PIN-protected on the YubiKey is used without asking.
+        let request = CredentialRequest {
+            kind: CredentialKind::ManagementKey,
+            module: MODULE_NAME.to_owned(),
+            key_id: None,
+            prompt: format!("Management key of YubiKey {}", yubikey.serial()),
+            retries_left: None,
+        };
+        let management_key = match YubiKeyProvider::protected_management_key(&mut *yubikey)
+            .map_err(SecurityModuleError::Hsm)?
+        {
+            Some(management_key) => management_key,
+            None => {
+                let default = ManagementKey::default();
+                let management_key = self.request_credential(&request, default.as_bytes())?;
+                let algorithm = YubiKeyProvider::management_key_algorithm(&mut *yubikey)
+                    .map_err(SecurityModuleError::Hsm)?;
+                ManagementKey::new(algorithm, &management_key).map_err(|err| {
+                    self.reject_credential(&request);
+                    SecurityModuleError::Hsm(err)
+                })?
             }
+        };
+        self.management_key = Some(management_key);
+        if let Err(err) = self.authenticate(&mut *yubikey) {
+            self.management_key = None;
+            self.reject_credential(&request);
+            return Err(SecurityModuleError::Hsm(err));
         }
+
+        self.pin = String::from_utf8(pin).map_err(|_| {
+            SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+                "The PIN must be valid UTF-8".to_owned(),
+            ))
+        })?;
+        self.yubikey = Some(Arc::new(Mutex::new(yubikey)));
+
+        Ok(())
+    }
+
+    /// Sets the handler asked for the PIN and management key in `initialize_module` and
+    /// notified when a key waits for a touch.
+    fn set_interaction_handler(&mut self, handler: Arc<dyn InteractionHandler>) {
+        self.interaction = Some(handler);
     }
 
     fn get_pub_key(&mut self) -> String {
         let output = self.pkey.clone();
         output
     }
+
+    /// Random bytes are not available from a YubiKey.
+    ///
+    /// PIV has no command for random data. The only random data the YubiKey returns are
+    /// the witnesses of a management key authentication, and starting one would reset the
+    /// authenticated state that key generation relies on.
+    #[instrument]
+    fn random_bytes(&self, _len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        Err(SecurityModuleError::UnsupportedOperation(
+            "The YubiKey PIV application does not provide random data".to_owned(),
+        ))
+    }
+
+    /// Describes what the YubiKey supports.
+    ///
+    /// PIV generates RSA-1024 and RSA-2048 keys, which sign and decrypt, and P-256 and
+    /// P-384 keys, which sign. Keys are stored in the four standard and the 20 retired slots.
+    #[instrument]
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        let rsa_operations = vec![
+            Operation::Sign,
+            Operation::Verify,
+            Operation::Encrypt,
+            Operation::Decrypt,
+        ];
+        let ecc_operations = vec![Operation::Sign, Operation::Verify];
+        let key_algorithms = vec![
+            KeyAlgorithm::new(
+                AsymmetricEncryption::Rsa(KeyBits::Bits1024),
+                SIGNATURE_HASHES.to_vec(),
+                rsa_operations.clone(),
+            ),
+            KeyAlgorithm::new(
+                AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+                SIGNATURE_HASHES.to_vec(),
+                rsa_operations.clone(),
+            ),
+            KeyAlgorithm::new(
+                AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+                SIGNATURE_HASHES.to_vec(),
+                ecc_operations.clone(),
+            ),
+            KeyAlgorithm::new(
+                AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)),
+                SIGNATURE_HASHES.to_vec(),
+                ecc_operations,
+            ),
+        ];
+
+        Ok(Capabilities {
+            key_algorithms,
+            block_ciphers: Vec::new(),
+            hashes: SIGNATURE_HASHES.to_vec(),
+            key_usages: vec![
+                KeyUsage::ClientAuth,
+                KeyUsage::Decrypt,
+                KeyUsage::SignEncrypt,
+                KeyUsage::CreateX509,
+            ],
+            max_keys: Some(all_slots().len()),
+            operations: rsa_operations,
+        })
+    }
 }
 
 /// Saves the key object to the YubiKey device.
 ///
-/// This method saves a object to the YubiKey device. The object is stored in a slot and represents
-/// information about the key, such as the key name, slot, key and public key. This information
-/// belongs to a private key which is stored in a other Slot.
+/// This method saves a object to the YubiKey device. The object represents information about
+/// the key, such as the key name, slot, algorithm and public key, and is stored in the data
+/// object `metadata_object(slot_id)` belonging to the slot of the private key.
 ///
 /// # Arguments
-/// * 'key_id' - A string slice that uniquely identifies the key for later usage.
-/// * 'slot_id' - An address where an object will be stored must be given.
-/// * 'pkey' - The public key which is intended to be stored.
+/// * 'slot_id' - The slot holding the private key.
+/// * 'metadata' - The information about the key which is intended to be stored.
 ///
 /// # Returns
 ///
-/// The saved Object will be stored in the Yubikey on a free Retired slot as Object with futher information
 /// A `Result` that, on success, contains `Ok()`.
-/// On failure, it returns a `yubikey::Error`.
-fn save_key_object(
-    yubikey: &mut YubiKey,
-    key_id: &str,
-    slot_id: u32,
-    pkey: &str,
-    algo: &str,
-) -> Result<(), yubikey::Error> {
-    let key_name = key_id;
-    let slot = slot_id.to_string();
-    let public_key = pkey;
-
-    let total_length = key_name.len() + 1 + slot.len() + 1 + public_key.len() + 1 + algo.len();
-    let mut data = vec![0u8; total_length];
-    let data_slice: &mut [u8] = &mut data;
-
-    let mut offset = 0;
-    data_slice[offset..offset + key_name.len()].copy_from_slice(key_name.as_bytes());
-    offset += key_name.len();
-    data_slice[offset] = 0;
-    offset += 1;
-
-    data_slice[offset..offset + slot.len()].copy_from_slice(slot.as_bytes());
-    offset += slot.len();
-    data_slice[offset] = 0;
-    offset += 1;
-
-    data_slice[offset..offset + public_key.len()].copy_from_slice(public_key.as_bytes());
-    offset += public_key.len();
-    data_slice[offset] = 0;
-    offset += 1;
-
-    data_slice[offset..offset + algo.len()].copy_from_slice(algo.as_bytes());
-
-    let saved = yubikey.save_object(slot_id, data_slice);
-    match saved {
-        Ok(()) => Ok(()),
-        Err(err) => Err(err),
-    }
+/// On failure, it returns an `HsmError`.
+pub(super) fn save_key_object(
+    yubikey: &mut dyn PivTransport,
+    slot_id: SlotId,
+    metadata: &KeyMetadata,
+) -> Result<(), HsmError> {
+    let mut data = metadata.encode()?;
+    yubikey
+        .save_object(metadata_object(slot_id), &mut data)
+        .map_err(piv_error)
 }
 
-/// parses the u8 Data to different Key-Information Strings
+/// Reads the key object of the key in `slot_id`.
 ///
-/// This method creates a persisted cryptographic key using the specified algorithm
-/// and identifier, making it retrievable for future operations. The key is created
-/// and stored in the YubiKey.
-///
-/// # Arguments
-///
-///* 'data' - This array reference contains important information, it provides: the key name, the slot where it is stored and the public key itself
+/// Earlier versions stored the key objects of the slots R1 to R10 in the certificate objects
+/// of R11 to R20. Such objects, and objects in the legacy NUL-separated format, are moved
+/// to the current location and format, which requires the PIN to be verified and the
+/// management key to be authenticated; if that is not the case the object stays as it is
+/// and is migrated on a later read.
 ///
 /// # Returns
 ///
-/// A `Result` that, on success, contains `Ok(key_name, slot, public_key)` where the individual information is given.
-/// On failure, it returns a `Utf8Error`.
-fn parse_slot_data(data: &[u8]) -> Result<(String, String, String, String), SecurityModuleError> {
-    let parts: Vec<&[u8]> = data.split(|&x| x == 0).collect();
-    if !(parts.len() < 4
-        || parts[0].is_empty()
-        || parts[1].is_empty()
-        || parts[2].is_empty()
-        || parts[3].is_empty())
-    {
-        let key_name = std::str::from_utf8(parts[0]).unwrap();
-        let slot = std::str::from_utf8(parts[1]).unwrap();
-        let public_key = std::str::from_utf8(parts[2]).unwrap();
-        let algo = std::str::from_utf8(parts[3]).unwrap();
-
-        Ok((
-            key_name.to_string(),
-            slot.to_string(),
-            public_key.to_string(),
-            algo.to_string(),
-        ))
-    } else {
-        return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-            "Failed to parse the slot data".to_string(),
-        )));
+/// A `Result` that, on success, contains the key information, or `None` if there is none.
+/// On failure, e.g. for a record written by a newer version, it returns an `HsmError`.
+pub(super) fn read_key_object(
+    yubikey: &mut dyn PivTransport,
+    slot_id: SlotId,
+) -> Result<Option<KeyMetadata>, HsmError> {
+    if let Some(metadata) = decode_key_object(yubikey, metadata_object(slot_id), slot_id)? {
+        return Ok(Some(metadata));
     }
+
+    let Some(legacy_object) = legacy_metadata_object(slot_id) else {
+        return Ok(None);
+    };
+    let Some(metadata) = decode_key_object(yubikey, legacy_object, slot_id)? else {
+        return Ok(None);
+    };
+    let migrated = save_key_object(yubikey, slot_id, &metadata).and_then(|()| {
+        // The certificate object becomes usable for R11 to R20 again.
+        yubikey
+            .save_object(legacy_object, &mut [])
+            .map_err(piv_error)
+    });
+    if let Err(err) = migrated {
+        warn!("Failed to migrate key object {}: {}", metadata.key_id, err);
+    }
+    Ok(Some(metadata))
+}
+
+/// Reads the data object `object_id` as a key object in the current or legacy format.
+fn decode_key_object(
+    yubikey: &mut dyn PivTransport,
+    object_id: u32,
+    slot_id: SlotId,
+) -> Result<Option<KeyMetadata>, HsmError> {
+    let data = match yubikey.fetch_object(object_id) {
+        Ok(data) => data,
+        Err(err) if err.is_disconnected() => return Err(piv_error(err)),
+        Err(_) => return Ok(None),
+    };
+    if let Some(metadata) = KeyMetadata::decode(&data)? {
+        return Ok(Some(metadata));
+    }
+    Ok(KeyMetadata::decode_legacy(&data, slot_id.into()).ok())
 }
 
 /// Gets a free slot for storing a key object.
 ///
-/// This method goes through the available slots on the YubiKey and returns the first free slot
+/// This method goes through the retired slots on the YubiKey and returns the first one that
+/// neither holds a key created by this crate nor, on firmware 5.3 and later, any other key.
 ///
 /// # Arguments
 /// The method takes a Yubikey device as an input
 ///
 /// # Returns
 ///
-/// A `Result` that, on failure, returns the first free slot.
-/// On Success, it returns that no more free slots are available.
-fn get_free_slot(yubikey: &mut YubiKey) -> Result<RetiredSlotId, SecurityModuleError> {
-    let mut end = false;
-    let mut slot_id: RetiredSlotId = SLOTS[0];
-    let mut counter = 0;
-    for i in 10..20 {
-        let data = yubikey.fetch_object(SLOTSU32[i]);
-        let mut output: Vec<u8> = Vec::new();
-        match data {
-            Ok(data) => {
-                output = data.to_vec();
-            }
-            Err(_) => {}
-        }
-        let data = output;
-        let parsed = parse_slot_data(&data);
-        if !parsed.is_ok() {
-            slot_id = SLOTS[i - 10];
-            end = true;
+/// A `Result` that, on success, returns the first free slot.
+/// On failure, it returns that no more free slots are available.
+fn get_free_slot(yubikey: &mut dyn PivTransport) -> Result<SlotId, SecurityModuleError> {
+    for retired in SLOTS {
+        let slot_id = SlotId::Retired(retired);
+        if holds_key(yubikey, slot_id) {
+            continue;
         }
-        // match parsed {
-        //     Ok(_) => {
-        //         continue;
-        //     }
-        //     Err(_) => {
-
-        // }
-        if end {
-            break;
+        // A certificate object still holding a key object of R1 to R10 that could not be
+        // migrated is not free either.
+        let certificate_object = certificate_object(slot_id).unwrap();
+        if SLOTS[..10].iter().any(|&key_slot| {
+            legacy_metadata_object(SlotId::Retired(key_slot)) == Some(certificate_object)
+        }) && !matches!(
+            decode_key_object(yubikey, certificate_object, slot_id),
+            Ok(None)
+        ) {
+            continue;
         }
-        counter += 1;
-    }
-    if counter <= 9 {
-        Ok(slot_id)
-    } else {
-        let _ = list_all_slots(yubikey);
-
-        return Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
-            "No more free slots available".to_string(),
-        )));
+        return Ok(slot_id);
     }
+
+    let _ = list_all_slots(yubikey);
+    Err(SecurityModuleError::Hsm(HsmError::DeviceSpecific(
+        "No more free slots available".to_string(),
+    )))
 }
 
-/// Converts a `RetiredSlotId` to its corresponding u32 value.
-///
-/// This method converts a `RetiredSlotId` to its corresponding u32 value,
-/// which is required for slot operations on the YubiKey device.
+/// Whether `slot_id` holds a key created by this crate or, on firmware 5.3 and later, any
+/// other key.
 ///
-/// # Arguments
-///
-/// * `slot` - The `RetiredSlotId` to be converted.
-///
-/// # Returns
+/// Key objects that cannot be read, e.g. from a newer version, count as a key, so they are
+/// not overwritten.
+fn holds_key(yubikey: &mut dyn PivTransport, slot_id: SlotId) -> bool {
+    !matches!(read_key_object(yubikey, slot_id), Ok(None)) || yubikey.key_policy(slot_id).is_ok()
+}
+
+/// Maps the slot selected in a `HsmProviderConfig` to a PIV slot, `None` for `KeySlot::Any`.
+fn requested_slot(slot: KeySlot) -> Result<Option<SlotId>, SecurityModuleError> {
+    match slot {
+        KeySlot::Any => Ok(None),
+        KeySlot::Role(SlotRole::Authentication) => Ok(Some(SlotId::Authentication)),
+        KeySlot::Role(SlotRole::Signature) => Ok(Some(SlotId::Signature)),
+        KeySlot::Role(SlotRole::KeyManagement) => Ok(Some(SlotId::KeyManagement)),
+        KeySlot::Role(SlotRole::CardAuthentication) => Ok(Some(SlotId::CardAuthentication)),
+        KeySlot::Retired(number @ 1..=20) => {
+            Ok(Some(SlotId::Retired(SLOTS[usize::from(number) - 1])))
+        }
+        KeySlot::Retired(number) => Err(SecurityModuleError::InitializationError(format!(
+            "There is no retired slot {}, use 1 to 20",
+            number
+        ))),
+    }
+}
+
+/// All slots keys can be stored in: the standard slots followed by the retired slots.
+fn all_slots() -> Vec<SlotId> {
+    let standard = [
+        SlotId::Authentication,
+        SlotId::Signature,
+        SlotId::KeyManagement,
+        SlotId::CardAuthentication,
+    ];
+    standard
+        .into_iter()
+        .chain(SLOTS.into_iter().map(SlotId::Retired))
+        .collect()
+}
+
+/// The data object holding the key object of the key in `slot_id`.
 ///
-/// The corresponding u32 value of the `RetiredSlotId`.
-fn get_reference_u32slot(slot: RetiredSlotId) -> u32 {
-    let mut output: u32 = SLOTSU32[0];
-    for i in 0..20 {
-        if SLOTS[i] == slot {
-            output = SLOTSU32[i + 10];
-            break;
-        } else {
-            continue;
+/// The objects are outside the range defined by the PIV standard, so they never collide
+/// with certificates or other objects middleware relies on.
+fn metadata_object(slot_id: SlotId) -> u32 {
+    0x005f_c200 | u32::from(u8::from(slot_id))
+}
+
+/// Where earlier versions stored the key object of the key in `slot_id`.
+fn legacy_metadata_object(slot_id: SlotId) -> Option<u32> {
+    let index = SLOTS[..10]
+        .iter()
+        .position(|&retired| slot_id == SlotId::Retired(retired))?;
+    Some(SLOTSU32[index + 10])
+}
+
+/// The data object holding the certificate of the key in `slot_id`.
+pub(super) fn certificate_object(slot_id: SlotId) -> Option<u32> {
+    match slot_id {
+        SlotId::Authentication => Some(0x005f_c105),
+        SlotId::Signature => Some(0x005f_c10a),
+        SlotId::KeyManagement => Some(0x005f_c10b),
+        SlotId::CardAuthentication => Some(0x005f_c101),
+        SlotId::Retired(retired) => SLOTS
+            .iter()
+            .position(|&slot| slot == retired)
+            .map(|index| SLOTSU32[index]),
+        _ => None,
+    }
+}
+
+impl From<PinPolicy> for ::yubikey::PinPolicy {
+    fn from(policy: PinPolicy) -> Self {
+        match policy {
+            PinPolicy::Default => Self::Default,
+            PinPolicy::Never => Self::Never,
+            PinPolicy::Once => Self::Once,
+            PinPolicy::Always => Self::Always,
         }
     }
-    output
 }
 
-fn list_all_slots(yubikey: &mut YubiKey) -> Result<Vec<String>, SecurityModuleError> {
-    let mut output: Vec<String> = Vec::new();
-    for i in 10..20 {
-        let data = yubikey.fetch_object(SLOTSU32[i]);
-        let mut temp_vec: Vec<u8> = Vec::new();
-        match data {
-            Ok(data) => {
-                temp_vec = data.to_vec();
-            }
-            Err(_) => {}
+impl From<TouchPolicy> for ::yubikey::TouchPolicy {
+    fn from(policy: TouchPolicy) -> Self {
+        match policy {
+            TouchPolicy::Default => Self::Default,
+            TouchPolicy::Never => Self::Never,
+            TouchPolicy::Always => Self::Always,
+            TouchPolicy::Cached => Self::Cached,
         }
-        let data = temp_vec;
-        match parse_slot_data(&data) {
-            Ok((key_name, slot, pkey, algo)) => {
-                let output_string = format!(
-                    "Key Name: {}, Slot: {}, Public-Key: {}, Key-Algorithm: {}\n",
-                    key_name, slot, pkey, algo
-                );
-                output.push(output_string);
-            }
-            Err(_) => {}
+    }
+}
+
+fn list_all_slots(yubikey: &mut dyn PivTransport) -> Result<Vec<String>, SecurityModuleError> {
+    let mut output: Vec<String> = Vec::new();
+    for slot in all_slots() {
+        if let Ok(Some(metadata)) = read_key_object(yubikey, slot) {
+            let output_string = format!(
+                "Key Name: {}, Slot: {:02x}, Public-Key: {}, Key-Algorithm: {:?}\n",
+                metadata.key_id, metadata.slot, metadata.public_key, metadata.algorithm
+            );
+            output.push(output_string);
         }
     }
     Ok(output)
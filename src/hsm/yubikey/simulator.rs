@@ -0,0 +1,470 @@
+use super::{
+    admin::ManagementKey, apdu::tlv, transport::PivTransport, YubiKeyProvider, DEFAULT_PIN,
+};
+use crate::{common::traits::interaction::CredentialKind, hsm::core::error::HsmError};
+use ::yubikey::{
+    piv::{AlgorithmId, SlotId},
+    Error, PinPolicy, Serial, TouchPolicy, YubiKey,
+};
+use openssl::{
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    memcmp,
+    nid::Nid,
+    pkey::{Id, PKey, Private},
+    rsa::{Padding, Rsa},
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tracing::instrument;
+use x509_cert::der::zeroize::Zeroizing;
+
+/// PIN and PUK attempts of a YubiKey in its factory state.
+const ATTEMPTS: u8 = 3;
+/// Key references used with GET METADATA.
+const SLOT_PIN: u8 = 0x80;
+const SLOT_PUK: u8 = 0x81;
+const SLOT_CARD_MANAGEMENT: u8 = 0x9b;
+/// The PIN-protected data object, which can only be read after PIN verification.
+const OBJ_PRINTED: u32 = 0x005f_c109;
+
+/// A YubiKey simulated in memory, for testing `YubiKeyProvider` without a device.
+///
+/// The simulator covers what the provider relies on: PIN verification with a retry counter,
+/// management key authentication, key generation in the standard and retired slots with
+/// their default PIN policies, the raw RSA and ECDSA operations and data objects. Touch is
+/// granted at once, and ECDH is not supported. Attestation, certificates and PIN management
+/// need a connected YubiKey and fail with `HsmError::UnsupportedFeature`.
+///
+/// Clones share the simulated YubiKey, so a test can inspect it while a provider uses it.
+#[derive(Clone)]
+pub struct PivSimulator {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    serial: Serial,
+    pin: Vec<u8>,
+    pin_retries: u8,
+    management_key: ManagementKey,
+    keys: HashMap<SlotId, SimulatedKey>,
+    objects: HashMap<u32, Vec<u8>>,
+    pin_verified: bool,
+    /// Whether the PIN was verified since the last private key operation, as keys with
+    /// `PinPolicy::Always` require.
+    pin_fresh: bool,
+    authenticated: bool,
+    /// Whether the next command fails because the YubiKey was reset.
+    reset: bool,
+}
+
+struct SimulatedKey {
+    algorithm: AlgorithmId,
+    key: PKey<Private>,
+    pin_policy: PinPolicy,
+    touch_policy: TouchPolicy,
+}
+
+impl YubiKeyProvider {
+    /// Constructs a new `YubiKeyProvider` using `simulator` in place of a connected YubiKey.
+    #[instrument]
+    pub fn with_simulator(key_id: String, simulator: PivSimulator) -> Self {
+        Self {
+            simulator: Some(simulator),
+            ..Self::new(key_id)
+        }
+    }
+}
+
+impl PivSimulator {
+    /// Creates a YubiKey in its factory state: default PIN and management key, no keys.
+    pub fn new(serial: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                serial: Serial::from(serial),
+                pin: DEFAULT_PIN.to_vec(),
+                pin_retries: ATTEMPTS,
+                management_key: ManagementKey::default(),
+                keys: HashMap::new(),
+                objects: HashMap::new(),
+                pin_verified: false,
+                pin_fresh: false,
+                authenticated: false,
+                reset: false,
+            })),
+        }
+    }
+
+    /// Sets the PIN of the simulated YubiKey.
+    pub fn with_pin(self, pin: &[u8]) -> Self {
+        self.lock().pin = pin.to_vec();
+        self
+    }
+
+    /// Sets the management key of the simulated YubiKey.
+    pub fn with_management_key(self, key: ManagementKey) -> Self {
+        self.lock().management_key = key;
+        self
+    }
+
+    /// The remaining PIN attempts.
+    pub fn pin_retries(&self) -> u8 {
+        self.lock().pin_retries
+    }
+
+    /// Whether `slot` holds a key.
+    pub fn has_key(&self, slot: SlotId) -> bool {
+        self.lock().keys.contains_key(&slot)
+    }
+
+    /// Puts `key` into `slot`, as an import by another application would.
+    ///
+    /// RSA keys of 1024 and 2048 bits and keys on P-256 and P-384 are supported.
+    pub fn import_key(
+        &self,
+        slot: SlotId,
+        key: PKey<Private>,
+        pin_policy: PinPolicy,
+        touch_policy: TouchPolicy,
+    ) -> Result<(), HsmError> {
+        let curve = key.ec_key().ok().and_then(|key| key.group().curve_name());
+        let algorithm = match (key.id(), key.bits(), curve) {
+            (Id::RSA, 1024, _) => AlgorithmId::Rsa1024,
+            (Id::RSA, 2048, _) => AlgorithmId::Rsa2048,
+            (Id::EC, _, Some(Nid::X9_62_PRIME256V1)) => AlgorithmId::EccP256,
+            (Id::EC, _, Some(Nid::SECP384R1)) => AlgorithmId::EccP384,
+            _ => {
+                return Err(HsmError::UnsupportedFeature(
+                    "The YubiKey does not support this key".to_owned(),
+                ))
+            }
+        };
+        if !is_key_slot(slot) {
+            return Err(HsmError::DeviceSpecific(format!("{:?} holds no key", slot)));
+        }
+        self.lock().keys.insert(
+            slot,
+            SimulatedKey {
+                algorithm,
+                key,
+                pin_policy: resolve_pin_policy(slot, pin_policy),
+                touch_policy: resolve_touch_policy(touch_policy),
+            },
+        );
+        Ok(())
+    }
+
+    /// The content of the data object `object_id`, regardless of access conditions.
+    pub fn object(&self, object_id: u32) -> Option<Vec<u8>> {
+        self.lock().objects.get(&object_id).cloned()
+    }
+
+    /// Stores `data` in the data object `object_id`, regardless of access conditions.
+    pub fn put_object(&self, object_id: u32, data: &[u8]) {
+        self.lock().objects.insert(object_id, data.to_vec());
+    }
+
+    /// Simulates removing and inserting the YubiKey: the PIN verification and management
+    /// key authentication are lost, and the next command fails with a reset card error.
+    pub fn reset_connection(&self) {
+        let mut state = self.lock();
+        state.end_session();
+        state.reset = true;
+    }
+
+    /// Simulates another connection selecting a different application, as
+    /// `YubiKeyProvider::list_devices` does: the PIN verification and management key
+    /// authentication are lost.
+    pub fn reset_security_status(&self) {
+        self.lock().end_session();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Locks the state for a command, failing once after `reset_connection`.
+    fn session(&self) -> Result<MutexGuard<'_, State>, Error> {
+        let mut state = self.lock();
+        if state.reset {
+            state.reset = false;
+            return Err(Error::PcscError {
+                inner: Some(pcsc::Error::ResetCard),
+            });
+        }
+        Ok(state)
+    }
+}
+
+impl State {
+    fn end_session(&mut self) {
+        self.pin_verified = false;
+        self.pin_fresh = false;
+        self.authenticated = false;
+    }
+
+    /// Returns the key in `slot` for a private key operation with `algorithm`, if its PIN
+    /// policy is satisfied.
+    fn private_key(
+        &mut self,
+        slot: SlotId,
+        algorithm: AlgorithmId,
+    ) -> Result<PKey<Private>, Error> {
+        let key = self.keys.get(&slot).ok_or(Error::GenericError)?;
+        if key.algorithm != algorithm {
+            return Err(Error::GenericError);
+        }
+        let authorized = match key.pin_policy {
+            PinPolicy::Never => true,
+            PinPolicy::Always => self.pin_fresh,
+            _ => self.pin_verified,
+        };
+        if !authorized {
+            return Err(Error::AuthenticationError);
+        }
+        let key = key.key.clone();
+        self.pin_fresh = false;
+        Ok(key)
+    }
+}
+
+impl PivTransport for PivSimulator {
+    fn serial(&self) -> Serial {
+        self.lock().serial
+    }
+
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<(), Error> {
+        let mut state = self.session()?;
+        if state.pin_retries == 0 {
+            return Err(Error::PinLocked);
+        }
+        if pin.len() != state.pin.len() || !memcmp::eq(pin, &state.pin) {
+            state.pin_retries -= 1;
+            state.pin_verified = false;
+            state.pin_fresh = false;
+            return Err(Error::WrongPin {
+                tries: state.pin_retries,
+            });
+        }
+        state.pin_retries = ATTEMPTS;
+        state.pin_verified = true;
+        state.pin_fresh = true;
+        Ok(())
+    }
+
+    fn get_pin_retries(&mut self) -> Result<u8, Error> {
+        Ok(self.session()?.pin_retries)
+    }
+
+    fn authenticate(&mut self, key: &ManagementKey) -> Result<(), HsmError> {
+        let mut state = self
+            .session()
+            .map_err(|err| HsmError::DeviceSpecific(err.to_string()))?;
+        let expected = &state.management_key;
+        // The algorithm determines the key length.
+        let authenticated = key.algorithm() == expected.algorithm()
+            && memcmp::eq(key.as_bytes(), expected.as_bytes());
+        state.authenticated = authenticated;
+        if !authenticated {
+            return Err(HsmError::WrongCredential {
+                kind: CredentialKind::ManagementKey,
+                retries_left: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn generate(
+        &mut self,
+        slot: SlotId,
+        algorithm: AlgorithmId,
+        pin_policy: PinPolicy,
+        touch_policy: TouchPolicy,
+    ) -> Result<Vec<u8>, Error> {
+        let mut state = self.session()?;
+        if !state.authenticated {
+            return Err(Error::AuthenticationError);
+        }
+        if !is_key_slot(slot) {
+            return Err(Error::GenericError);
+        }
+        let key = match algorithm {
+            AlgorithmId::Rsa1024 => Rsa::generate(1024).and_then(PKey::from_rsa),
+            AlgorithmId::Rsa2048 => Rsa::generate(2048).and_then(PKey::from_rsa),
+            AlgorithmId::EccP256 => EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+                .and_then(|group| EcKey::generate(&group))
+                .and_then(PKey::from_ec_key),
+            AlgorithmId::EccP384 => EcGroup::from_curve_name(Nid::SECP384R1)
+                .and_then(|group| EcKey::generate(&group))
+                .and_then(PKey::from_ec_key),
+        }
+        .map_err(|_| Error::GenericError)?;
+        let public_key = key.public_key_to_der().map_err(|_| Error::GenericError)?;
+        state.keys.insert(
+            slot,
+            SimulatedKey {
+                algorithm,
+                key,
+                pin_policy: resolve_pin_policy(slot, pin_policy),
+                touch_policy: resolve_touch_policy(touch_policy),
+            },
+        );
+        Ok(public_key)
+    }
+
+    fn sign_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        // The same length checks as the `yubikey` crate.
+        let valid_len = match algorithm {
+            AlgorithmId::Rsa1024 | AlgorithmId::Rsa2048 => data.len() == key_len(algorithm),
+            AlgorithmId::EccP256 | AlgorithmId::EccP384 => data.len() <= key_len(algorithm),
+        };
+        if !valid_len {
+            return Err(Error::SizeError);
+        }
+        let key = self.session()?.private_key(slot, algorithm)?;
+
+        let signature = match algorithm {
+            AlgorithmId::Rsa1024 | AlgorithmId::Rsa2048 => raw_rsa(&key, data),
+            AlgorithmId::EccP256 | AlgorithmId::EccP384 => key
+                .ec_key()
+                .and_then(|key| EcdsaSig::sign(data, &key))
+                .and_then(|signature| signature.to_der())
+                .map_err(|_| Error::GenericError),
+        }?;
+        Ok(Zeroizing::new(signature))
+    }
+
+    fn decrypt_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if !matches!(algorithm, AlgorithmId::Rsa1024 | AlgorithmId::Rsa2048) {
+            return Err(Error::NotSupported);
+        }
+        if data.len() != key_len(algorithm) {
+            return Err(Error::SizeError);
+        }
+        let key = self.session()?.private_key(slot, algorithm)?;
+        raw_rsa(&key, data).map(Zeroizing::new)
+    }
+
+    fn fetch_object(&mut self, object_id: u32) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let state = self.session()?;
+        if object_id == OBJ_PRINTED && !state.pin_verified {
+            return Err(Error::GenericError);
+        }
+        state
+            .objects
+            .get(&object_id)
+            .map(|object| Zeroizing::new(object.clone()))
+            .ok_or(Error::NotFound)
+    }
+
+    fn save_object(&mut self, object_id: u32, data: &mut [u8]) -> Result<(), Error> {
+        let mut state = self.session()?;
+        if !state.authenticated {
+            return Err(Error::AuthenticationError);
+        }
+        state.objects.insert(object_id, data.to_vec());
+        Ok(())
+    }
+
+    fn key_policy(&mut self, slot: SlotId) -> Result<Option<(PinPolicy, TouchPolicy)>, Error> {
+        let state = self.session()?;
+        let key = state.keys.get(&slot).ok_or(Error::GenericError)?;
+        Ok(Some((key.pin_policy, key.touch_policy)))
+    }
+
+    fn get_metadata(&mut self, key_reference: u8) -> Result<Option<Vec<u8>>, HsmError> {
+        let state = self
+            .session()
+            .map_err(|err| HsmError::DeviceSpecific(err.to_string()))?;
+        // The algorithm is in tag 0x01, the total and remaining attempts in tag 0x06.
+        let metadata = match key_reference {
+            SLOT_PIN => tlv(0x06, &[ATTEMPTS, state.pin_retries]),
+            SLOT_PUK => tlv(0x06, &[ATTEMPTS, ATTEMPTS]),
+            SLOT_CARD_MANAGEMENT => tlv(0x01, &[state.management_key.algorithm().id()]),
+            _ => return Ok(None),
+        };
+        Ok(Some(metadata))
+    }
+
+    fn reconnect(&mut self) -> Result<(), HsmError> {
+        let mut state = self.lock();
+        state.end_session();
+        state.reset = false;
+        Ok(())
+    }
+
+    fn as_yubikey(&mut self) -> Option<&mut YubiKey> {
+        None
+    }
+}
+
+impl fmt::Debug for PivSimulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PivSimulator")
+            .field("serial", &self.lock().serial)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether `slot` can hold a key, as opposed to the attestation and management slots.
+fn is_key_slot(slot: SlotId) -> bool {
+    matches!(
+        slot,
+        SlotId::Authentication
+            | SlotId::Signature
+            | SlotId::KeyManagement
+            | SlotId::CardAuthentication
+            | SlotId::Retired(_)
+    )
+}
+
+/// The PIN policy a YubiKey applies for `PinPolicy::Default` in `slot`.
+fn resolve_pin_policy(slot: SlotId, policy: PinPolicy) -> PinPolicy {
+    match (policy, slot) {
+        (PinPolicy::Default, SlotId::Signature) => PinPolicy::Always,
+        (PinPolicy::Default, SlotId::CardAuthentication) => PinPolicy::Never,
+        (PinPolicy::Default, _) => PinPolicy::Once,
+        (policy, _) => policy,
+    }
+}
+
+fn resolve_touch_policy(policy: TouchPolicy) -> TouchPolicy {
+    match policy {
+        TouchPolicy::Default => TouchPolicy::Never,
+        policy => policy,
+    }
+}
+
+/// The length of the input of a private key operation with `algorithm`.
+fn key_len(algorithm: AlgorithmId) -> usize {
+    match algorithm {
+        AlgorithmId::Rsa1024 => 128,
+        AlgorithmId::Rsa2048 => 256,
+        AlgorithmId::EccP256 => 32,
+        AlgorithmId::EccP384 => 48,
+    }
+}
+
+/// Applies the unpadded RSA private key operation, as PIV does for signing and decryption.
+fn raw_rsa(key: &PKey<Private>, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let rsa = key.rsa().map_err(|_| Error::GenericError)?;
+    let mut output = vec![0; rsa.size() as usize];
+    let len = rsa
+        .private_decrypt(data, &mut output, Padding::NONE)
+        .map_err(|_| Error::GenericError)?;
+    output.truncate(len);
+    Ok(output)
+}
@@ -0,0 +1,154 @@
+use super::{
+    admin::{authenticate_yubikey, get_metadata, ManagementKey},
+    device::open_device,
+};
+use crate::hsm::core::error::HsmError;
+use ::yubikey::{
+    piv::{self, AlgorithmId, SlotId},
+    Error, PinPolicy, Serial, TouchPolicy, YubiKey,
+};
+use std::fmt;
+use x509_cert::der::{zeroize::Zeroizing, Encode};
+
+/// The PIV operations `YubiKeyProvider` performs on a YubiKey.
+///
+/// Connected YubiKeys implement it through the `yubikey` crate, `PivSimulator` in memory,
+/// so the provider logic can be tested without a device. Errors are those of the `yubikey`
+/// crate, so the provider maps both alike.
+pub(crate) trait PivTransport: Send + fmt::Debug {
+    fn serial(&self) -> Serial;
+
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<(), Error>;
+
+    fn get_pin_retries(&mut self) -> Result<u8, Error>;
+
+    /// Authenticates with the management key `key`.
+    fn authenticate(&mut self, key: &ManagementKey) -> Result<(), HsmError>;
+
+    /// Generates a key in `slot`, returning its DER SubjectPublicKeyInfo.
+    fn generate(
+        &mut self,
+        slot: SlotId,
+        algorithm: AlgorithmId,
+        pin_policy: PinPolicy,
+        touch_policy: TouchPolicy,
+    ) -> Result<Vec<u8>, Error>;
+
+    /// Applies the raw private key operation of the key in `slot` to `data`.
+    fn sign_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error>;
+
+    /// Decrypts `data` with the key in `slot`, returning the still padded plaintext.
+    fn decrypt_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error>;
+
+    fn fetch_object(&mut self, object_id: u32) -> Result<Zeroizing<Vec<u8>>, Error>;
+
+    fn save_object(&mut self, object_id: u32, data: &mut [u8]) -> Result<(), Error>;
+
+    /// The PIN and touch policy of the key in `slot`; fails if the slot holds no key.
+    ///
+    /// Firmware before 5.3 does not report the policy and returns `None`.
+    fn key_policy(&mut self, slot: SlotId) -> Result<Option<(PinPolicy, TouchPolicy)>, Error>;
+
+    /// Sends GET METADATA for the key reference `key_reference`, returning `None` if the
+    /// firmware does not support it.
+    fn get_metadata(&mut self, key_reference: u8) -> Result<Option<Vec<u8>>, HsmError>;
+
+    /// Opens the YubiKey again after it was removed or reset.
+    fn reconnect(&mut self) -> Result<(), HsmError>;
+
+    /// The connected YubiKey, for the features that are only available on a device.
+    fn as_yubikey(&mut self) -> Option<&mut YubiKey>;
+}
+
+/// Returns the connected YubiKey behind `transport`, or an error for features that are only
+/// available on a device, such as attestation and PIN management.
+pub(super) fn connected_yubikey(
+    transport: &mut dyn PivTransport,
+) -> Result<&mut YubiKey, HsmError> {
+    transport.as_yubikey().ok_or_else(|| {
+        HsmError::UnsupportedFeature("This operation requires a connected YubiKey".to_owned())
+    })
+}
+
+impl PivTransport for YubiKey {
+    fn serial(&self) -> Serial {
+        YubiKey::serial(self)
+    }
+
+    fn verify_pin(&mut self, pin: &[u8]) -> Result<(), Error> {
+        YubiKey::verify_pin(self, pin)
+    }
+
+    fn get_pin_retries(&mut self) -> Result<u8, Error> {
+        YubiKey::get_pin_retries(self)
+    }
+
+    fn authenticate(&mut self, key: &ManagementKey) -> Result<(), HsmError> {
+        authenticate_yubikey(self, key)
+    }
+
+    fn generate(
+        &mut self,
+        slot: SlotId,
+        algorithm: AlgorithmId,
+        pin_policy: PinPolicy,
+        touch_policy: TouchPolicy,
+    ) -> Result<Vec<u8>, Error> {
+        piv::generate(self, slot, algorithm, pin_policy, touch_policy)?
+            .to_der()
+            .map_err(|_| Error::ParseError)
+    }
+
+    fn sign_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        piv::sign_data(self, data, algorithm, slot)
+    }
+
+    fn decrypt_data(
+        &mut self,
+        data: &[u8],
+        algorithm: AlgorithmId,
+        slot: SlotId,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        piv::decrypt_data(self, data, algorithm, slot)
+    }
+
+    fn fetch_object(&mut self, object_id: u32) -> Result<Zeroizing<Vec<u8>>, Error> {
+        YubiKey::fetch_object(self, object_id)
+    }
+
+    fn save_object(&mut self, object_id: u32, data: &mut [u8]) -> Result<(), Error> {
+        YubiKey::save_object(self, object_id, data)
+    }
+
+    fn key_policy(&mut self, slot: SlotId) -> Result<Option<(PinPolicy, TouchPolicy)>, Error> {
+        piv::metadata(self, slot).map(|metadata| metadata.policy)
+    }
+
+    fn get_metadata(&mut self, key_reference: u8) -> Result<Option<Vec<u8>>, HsmError> {
+        get_metadata(self, key_reference)
+    }
+
+    fn reconnect(&mut self) -> Result<(), HsmError> {
+        *self = open_device(Some(YubiKey::serial(self)))?;
+        Ok(())
+    }
+
+    fn as_yubikey(&mut self) -> Option<&mut YubiKey> {
+        Some(self)
+    }
+}
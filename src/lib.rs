@@ -3,6 +3,8 @@ pub mod common;
 pub mod ffi;
 //#[cfg(feature = "hsm")]
 pub mod hsm;
+#[cfg(feature = "plugin")]
+pub mod plugin;
 #[cfg(test)]
 mod tests;
 #[cfg(feature = "tpm")]
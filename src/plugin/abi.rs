@@ -0,0 +1,167 @@
+//! The C ABI between this crate and provider plugins.
+//!
+//! A plugin is a shared library exporting a function named `PLUGIN_ENTRY_SYMBOL` of type
+//! `PluginEntry`. The host calls it with the range of ABI versions it supports; the plugin
+//! returns its function table for the highest version in that range it implements, or a
+//! null pointer if there is none. Tables of later versions only append fields, so a table
+//! always starts with the fields of `PluginApi`.
+//!
+//! All strings are UTF-8 and passed as pointer and length, except the static `name` and
+//! `version` of the table, which are NUL-terminated. Output buffers are allocated by the
+//! plugin and handed back to it with `free_buffer`. Functions return `PLUGIN_OK` on success
+//! and one of the other status codes on failure; a description of the last failure of an
+//! instance is available through `last_error`.
+
+use std::os::raw::c_char;
+
+/// The ABI version described by this module.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// The oldest ABI version the host still accepts.
+pub const PLUGIN_ABI_MIN_VERSION: u32 = 1;
+
+/// The name of the entry function a plugin exports.
+pub const PLUGIN_ENTRY_SYMBOL: &str = "crypto_layer_plugin_entry";
+
+/// The entry function of a plugin.
+///
+/// Called with the lowest and highest ABI version the host supports. Returns the function
+/// table of the version chosen by the plugin, which stays valid while the library is
+/// loaded, or a null pointer if the plugin supports none of them.
+pub type PluginEntry = unsafe extern "C" fn(min_version: u32, max_version: u32) -> *const PluginApi;
+
+/// The status code of a successful call.
+pub const PLUGIN_OK: i32 = 0;
+/// The operation is not supported by the plugin or the loaded key.
+pub const PLUGIN_UNSUPPORTED: i32 = 1;
+/// An argument, such as the key configuration, is invalid.
+pub const PLUGIN_INVALID_ARGUMENT: i32 = 2;
+/// The key to load does not exist.
+pub const PLUGIN_KEY_NOT_FOUND: i32 = 3;
+/// No key has been created or loaded.
+pub const PLUGIN_NO_KEY: i32 = 4;
+/// Any other failure.
+pub const PLUGIN_ERROR: i32 = -1;
+
+/// An instance created by `PluginApi::new_instance`, opaque to the host.
+#[repr(C)]
+pub struct PluginInstance {
+    _private: [u8; 0],
+}
+
+/// A buffer allocated by the plugin and released with `PluginApi::free_buffer`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl PluginBuffer {
+    /// An empty buffer, passed to the plugin to be filled.
+    pub const fn empty() -> Self {
+        Self {
+            data: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+/// The function table of ABI version 1.
+///
+/// Calls for one instance are made from one thread at a time. The optional operations are
+/// `None` if the plugin does not implement them for any key; a plugin can still return
+/// `PLUGIN_UNSUPPORTED` for keys that cannot perform an operation it implements.
+#[repr(C)]
+pub struct PluginApi {
+    /// The ABI version of the table, within the range passed to the entry function.
+    pub abi_version: u32,
+    /// The name the plugin is registered under, as a NUL-terminated string.
+    pub name: *const c_char,
+    /// The version of the plugin, as a NUL-terminated string.
+    pub version: *const c_char,
+    /// Releases a buffer returned by the plugin.
+    pub free_buffer: unsafe extern "C" fn(buffer: PluginBuffer),
+    /// Writes the JSON encoded `Capabilities` of the plugin to `out`.
+    pub capabilities: unsafe extern "C" fn(out: *mut PluginBuffer) -> i32,
+    /// Creates an instance for the key `key_id`, returning null on failure.
+    pub new_instance:
+        unsafe extern "C" fn(key_id: *const u8, key_id_len: usize) -> *mut PluginInstance,
+    /// Releases an instance.
+    pub free_instance: unsafe extern "C" fn(instance: *mut PluginInstance),
+    /// Writes a description of the last failure of `instance` to `out`.
+    pub last_error:
+        unsafe extern "C" fn(instance: *mut PluginInstance, out: *mut PluginBuffer) -> i32,
+    /// Connects the instance to its security module.
+    pub initialize: unsafe extern "C" fn(instance: *mut PluginInstance) -> i32,
+    /// Creates the key `key_id` with the JSON encoded `PluginKeyConfig` `config`.
+    pub create_key: unsafe extern "C" fn(
+        instance: *mut PluginInstance,
+        key_id: *const u8,
+        key_id_len: usize,
+        config: *const u8,
+        config_len: usize,
+    ) -> i32,
+    /// Loads the key `key_id` with the JSON encoded `PluginKeyConfig` `config`.
+    pub load_key: unsafe extern "C" fn(
+        instance: *mut PluginInstance,
+        key_id: *const u8,
+        key_id_len: usize,
+        config: *const u8,
+        config_len: usize,
+    ) -> i32,
+    /// Writes the PEM encoded public key of the loaded key to `out`.
+    pub public_key:
+        unsafe extern "C" fn(instance: *mut PluginInstance, out: *mut PluginBuffer) -> i32,
+    /// Signs `data` with the loaded key.
+    pub sign: Option<
+        unsafe extern "C" fn(
+            instance: *mut PluginInstance,
+            data: *const u8,
+            data_len: usize,
+            out: *mut PluginBuffer,
+        ) -> i32,
+    >,
+    /// Verifies `signature` over `data`, writing the result to `valid`.
+    pub verify: Option<
+        unsafe extern "C" fn(
+            instance: *mut PluginInstance,
+            data: *const u8,
+            data_len: usize,
+            signature: *const u8,
+            signature_len: usize,
+            valid: *mut bool,
+        ) -> i32,
+    >,
+    /// Encrypts `data` with the loaded key.
+    pub encrypt: Option<
+        unsafe extern "C" fn(
+            instance: *mut PluginInstance,
+            data: *const u8,
+            data_len: usize,
+            out: *mut PluginBuffer,
+        ) -> i32,
+    >,
+    /// Decrypts `data` with the loaded key.
+    pub decrypt: Option<
+        unsafe extern "C" fn(
+            instance: *mut PluginInstance,
+            data: *const u8,
+            data_len: usize,
+            out: *mut PluginBuffer,
+        ) -> i32,
+    >,
+    /// Derives the shared secret with the DER encoded SubjectPublicKeyInfo `peer_public_key`.
+    pub agree: Option<
+        unsafe extern "C" fn(
+            instance: *mut PluginInstance,
+            peer_public_key: *const u8,
+            peer_public_key_len: usize,
+            out: *mut PluginBuffer,
+        ) -> i32,
+    >,
+    /// Fills `out` with `len` random bytes.
+    pub random_bytes: Option<
+        unsafe extern "C" fn(instance: *mut PluginInstance, out: *mut u8, len: usize) -> i32,
+    >,
+}
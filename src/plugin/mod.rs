@@ -0,0 +1,244 @@
+//! Providers loaded at runtime from shared libraries.
+//!
+//! A plugin implements the C ABI in `abi` and is registered under the name it reports,
+//! either by loading its library with `SecModules::load_plugin` or, for plugins linked into
+//! the application, with `SecModules::register_plugin`. Registered plugins are selected
+//! like the built-in modules, as `SecurityModule::Plugin(name)` or by parsing their name.
+
+pub mod abi;
+mod provider;
+
+use crate::common::{
+    crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, BlockCiphers},
+            hashes::Hash,
+        },
+        KeyUsage,
+    },
+    error::FactoryError,
+    factory::{DiscoveredModule, SecurityModule},
+    traits::module_provider::Provider,
+};
+use abi::{
+    PluginApi, PluginEntry, PLUGIN_ABI_MIN_VERSION, PLUGIN_ABI_VERSION, PLUGIN_ENTRY_SYMBOL,
+};
+use libloading::Library;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    os::raw::c_char,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+pub use provider::PluginProvider;
+
+/// The registered plugins, by name.
+static PLUGINS: Lazy<Mutex<HashMap<String, Arc<Plugin>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The configuration of keys created or loaded by a plugin provider.
+///
+/// It is passed to the plugin as JSON, so `options` can hold settings specific to a plugin.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginKeyConfig {
+    /// The algorithm of an asymmetric key, `None` for a symmetric key.
+    pub key_algorithm: Option<AsymmetricEncryption>,
+    /// The cipher of a symmetric key.
+    pub sym_algorithm: Option<BlockCiphers>,
+    /// The hash for signatures, `None` for the plugin's default.
+    pub hash: Option<Hash>,
+    /// The usages the key is created with.
+    pub key_usages: Vec<KeyUsage>,
+    /// Settings specific to the plugin.
+    pub options: serde_json::Value,
+}
+
+/// Describes a registered plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginInfo {
+    /// The name the plugin is registered under.
+    pub name: String,
+    /// The version the plugin reports.
+    pub version: String,
+    /// The ABI version negotiated with the plugin.
+    pub abi_version: u32,
+}
+
+/// A registered plugin.
+pub(crate) struct Plugin {
+    info: PluginInfo,
+    api: *const PluginApi,
+    // Kept loaded while providers of the plugin exist, as `api` points into it.
+    _library: Option<Library>,
+}
+
+// SAFETY: the function table is never written, and the ABI requires plugins to accept calls
+// from any thread.
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin").field("info", &self.info).finish()
+    }
+}
+
+impl Plugin {
+    pub(crate) fn api(&self) -> &PluginApi {
+        // SAFETY: `api` was checked to be non-null on registration and stays valid while
+        // the library is loaded.
+        unsafe { &*self.api }
+    }
+
+    pub(crate) fn info(&self) -> &PluginInfo {
+        &self.info
+    }
+}
+
+/// Loads the plugin library at `path` and registers it under the name it reports.
+pub(crate) fn load(path: &Path) -> Result<PluginInfo, FactoryError> {
+    let invalid = |reason: String| FactoryError::InvalidPlugin {
+        plugin: path.display().to_string(),
+        reason,
+    };
+
+    // SAFETY: loading a library runs its initializers; plugins are expected to be safe
+    // to load.
+    let library = unsafe { Library::new(path) }.map_err(|err| invalid(err.to_string()))?;
+    // SAFETY: the symbol is declared by the ABI with the type `PluginEntry`.
+    let entry = unsafe { library.get::<PluginEntry>(PLUGIN_ENTRY_SYMBOL.as_bytes()) }
+        .map(|entry| *entry)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    let info = register(entry, Some(library)).map_err(|err| match err {
+        FactoryError::InvalidPlugin { reason, .. } => invalid(reason),
+        err => err,
+    })?;
+    info!(
+        "Loaded plugin {} {} from {}",
+        info.name,
+        info.version,
+        path.display()
+    );
+    Ok(info)
+}
+
+/// Negotiates the ABI version with the plugin behind `entry` and registers it.
+pub(crate) fn register(
+    entry: PluginEntry,
+    library: Option<Library>,
+) -> Result<PluginInfo, FactoryError> {
+    let invalid = |plugin: &str, reason: &str| FactoryError::InvalidPlugin {
+        plugin: plugin.to_owned(),
+        reason: reason.to_owned(),
+    };
+
+    // SAFETY: the entry function is called as declared by the ABI.
+    let api = unsafe { entry(PLUGIN_ABI_MIN_VERSION, PLUGIN_ABI_VERSION) };
+    if api.is_null() {
+        return Err(FactoryError::InvalidPlugin {
+            plugin: "plugin".to_owned(),
+            reason: format!(
+                "The plugin supports none of the ABI versions {} to {}",
+                PLUGIN_ABI_MIN_VERSION, PLUGIN_ABI_VERSION
+            ),
+        });
+    }
+    // SAFETY: a non-null table returned by the entry function is valid while the library
+    // is loaded.
+    let table = unsafe { &*api };
+    // SAFETY: the ABI requires `name` and `version` to be NUL-terminated strings.
+    let name = unsafe { c_string(table.name) }.ok_or_else(|| invalid("plugin", "Invalid name"))?;
+    let version =
+        unsafe { c_string(table.version) }.ok_or_else(|| invalid(&name, "Invalid version"))?;
+    if !(PLUGIN_ABI_MIN_VERSION..=PLUGIN_ABI_VERSION).contains(&table.abi_version) {
+        return Err(invalid(
+            &name,
+            &format!("Unsupported ABI version {}", table.abi_version),
+        ));
+    }
+    // Built-in modules take precedence when parsing names, so their names are reserved.
+    if name.is_empty()
+        || !matches!(
+            name.parse::<SecurityModule>(),
+            Err(FactoryError::UnknownModule(_))
+        )
+    {
+        return Err(invalid(&name, "The name is reserved"));
+    }
+
+    let plugin = Plugin {
+        info: PluginInfo {
+            name: name.clone(),
+            version,
+            abi_version: table.abi_version,
+        },
+        api,
+        _library: library,
+    };
+    let info = plugin.info.clone();
+    let mut plugins = PLUGINS.lock().unwrap();
+    if plugins.contains_key(&name) {
+        return Err(invalid(
+            &name,
+            "A plugin with this name is already registered",
+        ));
+    }
+    plugins.insert(name, Arc::new(plugin));
+    Ok(info)
+}
+
+/// Returns the plugin registered as `name`.
+pub(crate) fn get(name: &str) -> Option<Arc<Plugin>> {
+    PLUGINS.lock().unwrap().get(name).cloned()
+}
+
+/// Returns the registered plugins.
+pub(crate) fn registered() -> Vec<PluginInfo> {
+    let mut plugins: Vec<PluginInfo> = PLUGINS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|plugin| plugin.info.clone())
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+/// Lists the registered plugins for `SecModules::discover`.
+pub(crate) fn discover() -> Vec<DiscoveredModule> {
+    registered()
+        .into_iter()
+        .map(|info| DiscoveredModule {
+            description: format!("{} {}", info.name, info.version),
+            module: SecurityModule::Plugin(info.name),
+        })
+        .collect()
+}
+
+/// Creates a provider of the plugin `name` for the key `key_id`.
+pub(crate) fn create_instance(
+    key_id: String,
+    name: &str,
+) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
+    let plugin = get(name).ok_or_else(|| FactoryError::UnknownModule(name.to_owned()))?;
+    let provider = PluginProvider::new(plugin, &key_id)?;
+    Ok(Arc::new(Mutex::new(provider)))
+}
+
+/// Copies the NUL-terminated string at `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn c_string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok().map(str::to_owned)
+}
@@ -0,0 +1,375 @@
+use super::{
+    abi::{
+        PluginBuffer, PluginInstance, PLUGIN_INVALID_ARGUMENT, PLUGIN_KEY_NOT_FOUND, PLUGIN_NO_KEY,
+        PLUGIN_OK, PLUGIN_UNSUPPORTED,
+    },
+    Plugin, PluginKeyConfig,
+};
+use crate::common::{
+    capabilities::{Capabilities, Operation},
+    error::{FactoryError, SecurityModuleError},
+    traits::{
+        key_handle::KeyHandle,
+        module_provider::Provider,
+        operations::{Decryptor, Encryptor, KeyAgreement, Signer, Verifier},
+    },
+};
+use std::{
+    any::Any,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tracing::instrument;
+
+/// A provider whose operations are implemented by a plugin.
+///
+/// Keys are created and loaded with a `PluginKeyConfig`. The operations the plugin does not
+/// implement, and those the usages of the key exclude, are not offered by the `as_*`
+/// methods of `KeyHandle`.
+pub struct PluginProvider {
+    plugin: Arc<Plugin>,
+    /// The instance of the plugin, locked for every call so the plugin sees one at a time.
+    instance: Mutex<InstancePtr>,
+    config: Option<PluginKeyConfig>,
+}
+
+struct InstancePtr(*mut PluginInstance);
+
+// SAFETY: the instance is only used while the mutex is held, and the ABI requires plugins
+// to accept calls for an instance from any thread.
+unsafe impl Send for InstancePtr {}
+
+impl fmt::Debug for PluginProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginProvider")
+            .field("plugin", &self.plugin.info().name)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Drop for PluginProvider {
+    fn drop(&mut self) {
+        let instance = self.instance.get_mut().unwrap().0;
+        // SAFETY: the instance was created by this plugin and is not used afterwards.
+        unsafe { (self.plugin.api().free_instance)(instance) };
+    }
+}
+
+impl PluginProvider {
+    /// Creates an instance of `plugin` for the key `key_id`.
+    pub(crate) fn new(plugin: Arc<Plugin>, key_id: &str) -> Result<Self, FactoryError> {
+        // SAFETY: the key ID is passed as pointer and length, as declared by the ABI.
+        let instance = unsafe { (plugin.api().new_instance)(key_id.as_ptr(), key_id.len()) };
+        if instance.is_null() {
+            return Err(FactoryError::InvalidPlugin {
+                plugin: plugin.info().name.clone(),
+                reason: "Failed to create an instance".to_owned(),
+            });
+        }
+        Ok(Self {
+            plugin,
+            instance: Mutex::new(InstancePtr(instance)),
+            config: None,
+        })
+    }
+
+    /// Calls `function` with the locked instance, turning its status into an error.
+    fn call(
+        &self,
+        operation: &str,
+        function: impl FnOnce(*mut PluginInstance) -> i32,
+    ) -> Result<(), SecurityModuleError> {
+        let instance = self.instance.lock().unwrap();
+        match function(instance.0) {
+            PLUGIN_OK => Ok(()),
+            status => Err(self.status_error(instance.0, operation, status)),
+        }
+    }
+
+    /// Calls `function`, which writes its result to a buffer, and copies the buffer.
+    fn call_with_buffer(
+        &self,
+        operation: &str,
+        function: impl FnOnce(*mut PluginInstance, *mut PluginBuffer) -> i32,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let mut buffer = PluginBuffer::empty();
+        self.call(operation, |instance| function(instance, &mut buffer))?;
+        Ok(self.take_buffer(buffer))
+    }
+
+    /// Copies `buffer` and hands it back to the plugin.
+    fn take_buffer(&self, buffer: PluginBuffer) -> Vec<u8> {
+        if buffer.data.is_null() {
+            return Vec::new();
+        }
+        // SAFETY: a buffer returned by the plugin holds `len` initialized bytes until it is
+        // freed.
+        let data = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) }.to_vec();
+        // SAFETY: the buffer was allocated by this plugin and is not used afterwards.
+        unsafe { (self.plugin.api().free_buffer)(buffer) };
+        data
+    }
+
+    fn status_error(
+        &self,
+        instance: *mut PluginInstance,
+        operation: &str,
+        status: i32,
+    ) -> SecurityModuleError {
+        let mut buffer = PluginBuffer::empty();
+        // SAFETY: the instance is locked by the caller.
+        let message = match unsafe { (self.plugin.api().last_error)(instance, &mut buffer) } {
+            PLUGIN_OK => String::from_utf8_lossy(&self.take_buffer(buffer)).into_owned(),
+            _ => String::new(),
+        };
+        let message = format!(
+            "{} {} failed with status {}{}{}",
+            self.plugin.info().name,
+            operation,
+            status,
+            if message.is_empty() { "" } else { ": " },
+            message
+        );
+        match status {
+            PLUGIN_UNSUPPORTED => SecurityModuleError::UnsupportedOperation(message),
+            PLUGIN_INVALID_ARGUMENT | PLUGIN_KEY_NOT_FOUND | PLUGIN_NO_KEY => {
+                SecurityModuleError::InitializationError(message)
+            }
+            _ => match operation {
+                "sign" => SecurityModuleError::SigningError(message),
+                "verify" => SecurityModuleError::SignatureVerificationError(message),
+                "encrypt" => SecurityModuleError::EncryptionError(message),
+                "decrypt" => SecurityModuleError::DecryptionError(message),
+                _ => SecurityModuleError::InitializationError(message),
+            },
+        }
+    }
+
+    /// Returns whether the loaded key may perform `operation`, judged by its usages.
+    ///
+    /// Keys configured without usages are not restricted.
+    fn permits(&self, operation: Operation) -> bool {
+        self.config.as_ref().is_some_and(|config| {
+            config.key_usages.is_empty() || operation.permitted_by(&config.key_usages)
+        })
+    }
+
+    fn key_call(
+        &mut self,
+        operation: &str,
+        key_id: &str,
+        config: Box<dyn Any>,
+        function: unsafe extern "C" fn(
+            *mut PluginInstance,
+            *const u8,
+            usize,
+            *const u8,
+            usize,
+        ) -> i32,
+    ) -> Result<(), SecurityModuleError> {
+        let config = config.downcast::<PluginKeyConfig>().map_err(|_| {
+            SecurityModuleError::InitializationError("Expected a PluginKeyConfig".to_owned())
+        })?;
+        let json = serde_json::to_vec(&config)
+            .map_err(|e| SecurityModuleError::InitializationError(e.to_string()))?;
+        self.call(operation, |instance| {
+            // SAFETY: the key ID and configuration are passed as pointer and length.
+            unsafe {
+                function(
+                    instance,
+                    key_id.as_ptr(),
+                    key_id.len(),
+                    json.as_ptr(),
+                    json.len(),
+                )
+            }
+        })?;
+        self.config = Some(*config);
+        Ok(())
+    }
+}
+
+impl Provider for PluginProvider {
+    /// Creates the key `key_id` with the `PluginKeyConfig` in `config`.
+    #[instrument(skip(config))]
+    fn create_key(
+        &mut self,
+        key_id: &str,
+        config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        let function = self.plugin.api().create_key;
+        self.key_call("create_key", key_id, config, function)
+    }
+
+    /// Loads the key `key_id` with the `PluginKeyConfig` in `config`.
+    #[instrument(skip(config))]
+    fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        let function = self.plugin.api().load_key;
+        self.key_call("load_key", key_id, config, function)
+    }
+
+    #[instrument]
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        let function = self.plugin.api().initialize;
+        // SAFETY: the instance is locked by `call`.
+        self.call("initialize", |instance| unsafe { function(instance) })
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        let function = self.plugin.api().public_key;
+        self.call_with_buffer("public_key", |instance, out| unsafe {
+            // SAFETY: the instance is locked by `call`.
+            function(instance, out)
+        })
+        .map(|pem| String::from_utf8_lossy(&pem).into_owned())
+        .unwrap_or_default()
+    }
+
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        let function = self.plugin.api().random_bytes.ok_or_else(|| {
+            SecurityModuleError::UnsupportedOperation(format!(
+                "{} provides no random bytes",
+                self.plugin.info().name
+            ))
+        })?;
+        let mut bytes = vec![0; len];
+        // SAFETY: `bytes` holds `len` bytes, and the instance is locked by `call`.
+        self.call("random_bytes", |instance| unsafe {
+            function(instance, bytes.as_mut_ptr(), len)
+        })?;
+        Ok(bytes)
+    }
+
+    /// Returns the capabilities the plugin reports.
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        let mut buffer = PluginBuffer::empty();
+        // SAFETY: the buffer is filled by the plugin and released by `take_buffer`.
+        let status = unsafe { (self.plugin.api().capabilities)(&mut buffer) };
+        if status != PLUGIN_OK {
+            return Err(SecurityModuleError::InitializationError(format!(
+                "{} capabilities failed with status {}",
+                self.plugin.info().name,
+                status
+            )));
+        }
+        serde_json::from_slice(&self.take_buffer(buffer))
+            .map_err(|e| SecurityModuleError::InitializationError(e.to_string()))
+    }
+}
+
+impl KeyHandle for PluginProvider {
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        (self.plugin.api().sign.is_some() && self.permits(Operation::Sign)).then_some(self)
+    }
+
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        (self.plugin.api().verify.is_some() && self.permits(Operation::Verify)).then_some(self)
+    }
+
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        (self.plugin.api().encrypt.is_some() && self.permits(Operation::Encrypt)).then_some(self)
+    }
+
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        (self.plugin.api().decrypt.is_some() && self.permits(Operation::Decrypt)).then_some(self)
+    }
+
+    fn as_key_agreement(&self) -> Option<&dyn KeyAgreement> {
+        (self.plugin.api().agree.is_some() && self.permits(Operation::KeyAgreement)).then_some(self)
+    }
+}
+
+/// The error for operations a plugin does not implement, when called directly.
+fn not_implemented(operation: &str) -> SecurityModuleError {
+    SecurityModuleError::UnsupportedOperation(format!("The plugin does not implement {operation}"))
+}
+
+impl Signer for PluginProvider {
+    #[instrument(skip(data))]
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let function = self
+            .plugin
+            .api()
+            .sign
+            .ok_or_else(|| not_implemented("sign"))?;
+        // SAFETY: the data is passed as pointer and length, and the instance is locked.
+        self.call_with_buffer("sign", |instance, out| unsafe {
+            function(instance, data.as_ptr(), data.len(), out)
+        })
+    }
+}
+
+impl Verifier for PluginProvider {
+    #[instrument(skip(data, signature))]
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        let function = self
+            .plugin
+            .api()
+            .verify
+            .ok_or_else(|| not_implemented("verify"))?;
+        let mut valid = false;
+        // SAFETY: the inputs are passed as pointer and length, and the instance is locked.
+        self.call("verify", |instance| unsafe {
+            function(
+                instance,
+                data.as_ptr(),
+                data.len(),
+                signature.as_ptr(),
+                signature.len(),
+                &mut valid,
+            )
+        })?;
+        Ok(valid)
+    }
+}
+
+impl Encryptor for PluginProvider {
+    #[instrument(skip(data))]
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let function = self
+            .plugin
+            .api()
+            .encrypt
+            .ok_or_else(|| not_implemented("encrypt"))?;
+        // SAFETY: the data is passed as pointer and length, and the instance is locked.
+        self.call_with_buffer("encrypt", |instance, out| unsafe {
+            function(instance, data.as_ptr(), data.len(), out)
+        })
+    }
+}
+
+impl Decryptor for PluginProvider {
+    #[instrument(skip(encrypted_data))]
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let function = self
+            .plugin
+            .api()
+            .decrypt
+            .ok_or_else(|| not_implemented("decrypt"))?;
+        // SAFETY: the data is passed as pointer and length, and the instance is locked.
+        self.call_with_buffer("decrypt", |instance, out| unsafe {
+            function(instance, encrypted_data.as_ptr(), encrypted_data.len(), out)
+        })
+    }
+}
+
+impl KeyAgreement for PluginProvider {
+    #[instrument(skip(peer_public_key))]
+    fn agree(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let function = self
+            .plugin
+            .api()
+            .agree
+            .ok_or_else(|| not_implemented("key agreement"))?;
+        // SAFETY: the key is passed as pointer and length, and the instance is locked.
+        self.call_with_buffer("agree", |instance, out| unsafe {
+            function(
+                instance,
+                peer_public_key.as_ptr(),
+                peer_public_key.len(),
+                out,
+            )
+        })
+    }
+}
@@ -0,0 +1,235 @@
+use crate::common::{
+    capabilities::{Capabilities, KeyAlgorithm, Operation},
+    composite::{Backend, CompositeKeyConfig, CompositeProvider, FallbackPolicy},
+    crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            KeyBits,
+        },
+        KeyUsage,
+    },
+    error::SecurityModuleError,
+    traits::{key_handle::KeyHandle, module_provider::Provider, operations::Signer},
+};
+use std::{
+    any::Any,
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+const RSA: AsymmetricEncryption = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+const ECC: AsymmetricEncryption =
+    AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256));
+
+/// A provider supporting `algorithms`, whose signatures name it and the key.
+#[derive(Debug)]
+struct MockProvider {
+    name: &'static str,
+    algorithms: Vec<AsymmetricEncryption>,
+    available: bool,
+    max_keys: Option<usize>,
+    keys: HashSet<String>,
+    key: Option<String>,
+}
+
+impl Provider for MockProvider {
+    fn create_key(
+        &mut self,
+        key_id: &str,
+        config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        let config = config.downcast::<CompositeKeyConfig>().unwrap();
+        assert!(self.algorithms.contains(&config.key_algorithm));
+        self.keys.insert(key_id.to_owned());
+        self.key = Some(key_id.to_owned());
+        Ok(())
+    }
+
+    fn load_key(&mut self, key_id: &str, _config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        if !self.keys.contains(key_id) {
+            return Err(SecurityModuleError::InitializationError(
+                "Key not found".to_owned(),
+            ));
+        }
+        self.key = Some(key_id.to_owned());
+        Ok(())
+    }
+
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        match self.available {
+            true => Ok(()),
+            false => Err(SecurityModuleError::InitializationError(
+                "No device".to_owned(),
+            )),
+        }
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        String::new()
+    }
+
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        Ok(Capabilities {
+            key_algorithms: self
+                .algorithms
+                .iter()
+                .map(|&algorithm| KeyAlgorithm::new(algorithm, Vec::new(), vec![Operation::Sign]))
+                .collect(),
+            key_usages: vec![KeyUsage::SignEncrypt],
+            operations: vec![Operation::Sign],
+            max_keys: self.max_keys,
+            ..Default::default()
+        })
+    }
+}
+
+impl KeyHandle for MockProvider {
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        self.key.is_some().then_some(self)
+    }
+}
+
+impl Signer for MockProvider {
+    fn sign(&self, _data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        Ok(format!("{}:{}", self.name, self.key.as_ref().unwrap()).into_bytes())
+    }
+}
+
+fn backend(name: &'static str, algorithms: Vec<AsymmetricEncryption>, available: bool) -> Backend {
+    limited_backend(name, algorithms, available, None)
+}
+
+fn limited_backend(
+    name: &'static str,
+    algorithms: Vec<AsymmetricEncryption>,
+    available: bool,
+    max_keys: Option<usize>,
+) -> Backend {
+    let provider = MockProvider {
+        name,
+        algorithms,
+        available,
+        max_keys,
+        keys: HashSet::new(),
+        key: None,
+    };
+    Backend::new(name, Arc::new(Mutex::new(provider)), |config| {
+        Box::new(config.clone())
+    })
+}
+
+fn config(key_algorithm: AsymmetricEncryption) -> Box<CompositeKeyConfig> {
+    Box::new(CompositeKeyConfig {
+        key_algorithm,
+        key_usages: vec![KeyUsage::SignEncrypt],
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_keys_are_created_on_first_supporting_backend() {
+    let backends = vec![
+        backend("tpm", vec![RSA], true),
+        backend("yubikey", vec![ECC, RSA], true),
+    ];
+    let mut provider = CompositeProvider::new(backends.clone());
+    provider.initialize_module().unwrap();
+
+    provider.create_key("ecc", config(ECC)).unwrap();
+    assert_eq!(provider.backend_of("ecc"), Some("yubikey"));
+    provider.create_key("rsa", config(RSA)).unwrap();
+    assert_eq!(provider.backend_of("rsa"), Some("tpm"));
+    assert_eq!(provider.sign_data(b"data").unwrap(), b"tpm:rsa");
+
+    provider.load_key("ecc", config(ECC)).unwrap();
+    assert_eq!(provider.sign_data(b"data").unwrap(), b"yubikey:ecc");
+
+    // A new provider finds the keys by trying the backends in order.
+    let mut provider = CompositeProvider::new(backends);
+    provider.load_key("ecc", config(ECC)).unwrap();
+    assert_eq!(provider.backend_of("ecc"), Some("yubikey"));
+    assert!(provider.load_key("missing", config(ECC)).is_err());
+}
+
+#[test]
+fn test_unavailable_backends_are_skipped() {
+    let mut provider = CompositeProvider::new(vec![
+        backend("tpm", vec![RSA], false),
+        backend("yubikey", vec![RSA], true),
+    ]);
+    provider.initialize_module().unwrap();
+    provider.create_key("rsa", config(RSA)).unwrap();
+    assert_eq!(provider.backend_of("rsa"), Some("yubikey"));
+
+    let mut provider = CompositeProvider::new(vec![backend("tpm", vec![RSA], false)]);
+    assert!(provider.initialize_module().is_err());
+}
+
+#[test]
+fn test_fallback_requires_policy() {
+    let software = backend("software", vec![ECC, RSA], true);
+    let mut provider = CompositeProvider::new(vec![backend("tpm", vec![RSA], true)])
+        .with_fallback(software.clone(), FallbackPolicy::Deny);
+    assert!(matches!(
+        provider.create_key("ecc", config(ECC)),
+        Err(SecurityModuleError::InitializationError(_))
+    ));
+    assert!(!provider.capabilities().unwrap().supports_key_algorithm(ECC));
+
+    let mut provider = CompositeProvider::new(vec![backend("tpm", vec![RSA], true)])
+        .with_fallback(software, FallbackPolicy::Allow);
+    assert!(provider.capabilities().unwrap().supports_key_algorithm(ECC));
+    provider.create_key("rsa", config(RSA)).unwrap();
+    assert_eq!(provider.backend_of("rsa"), Some("tpm"));
+    provider.create_key("ecc", config(ECC)).unwrap();
+    assert_eq!(provider.backend_of("ecc"), Some("software"));
+    assert_eq!(provider.sign_data(b"data").unwrap(), b"software:ecc");
+}
+
+#[test]
+fn test_max_keys_add_up() {
+    let provider = CompositeProvider::new(vec![
+        limited_backend("tpm", vec![RSA], true, Some(7)),
+        limited_backend("yubikey", vec![ECC], true, Some(24)),
+    ]);
+    assert_eq!(provider.capabilities().unwrap().max_keys, Some(31));
+
+    let provider = CompositeProvider::new(vec![
+        limited_backend("tpm", vec![RSA], true, Some(7)),
+        backend("pkcs11", vec![ECC], true),
+    ]);
+    assert_eq!(provider.capabilities().unwrap().max_keys, None);
+}
+
+#[cfg(feature = "tpm")]
+#[test]
+fn test_tpm_config_is_supported_by_tpm() {
+    use crate::{
+        common::{
+            composite::tpm_config,
+            crypto::algorithms::{
+                encryption::{BlockCiphers, SymmetricMode},
+                hashes::{Hash, Sha2Bits},
+            },
+        },
+        tpm::TpmConfig,
+    };
+
+    let tpm = tpm_config(&config(ECC)).downcast::<TpmConfig>().unwrap();
+    assert_eq!(
+        tpm.sym_algorithm,
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128)
+    );
+    assert_eq!(tpm.hash, Hash::Sha2(Sha2Bits::Sha256));
+    assert_eq!(tpm.key_usages, vec![KeyUsage::SignEncrypt]);
+
+    let hash = Hash::Sha2(Sha2Bits::Sha384);
+    let config = CompositeKeyConfig {
+        hash: Some(hash),
+        ..*config(ECC)
+    };
+    assert_eq!(
+        tpm_config(&config).downcast::<TpmConfig>().unwrap().hash,
+        hash
+    );
+}
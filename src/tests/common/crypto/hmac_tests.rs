@@ -0,0 +1,39 @@
+use crate::common::{
+    crypto::{
+        algorithms::hashes::{Hash, Sha2Bits},
+        hmac::SoftwareHmacKey,
+    },
+    traits::key_handle::KeyHandle,
+};
+
+// RFC 4231, test case 2.
+const KEY: &[u8] = b"Jefe";
+const DATA: &[u8] = b"what do ya want for nothing?";
+const MAC_SHA256: &str = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+#[test]
+fn test_mac_data_matches_rfc_4231() {
+    let key =
+        SoftwareHmacKey::new(Hash::Sha2(Sha2Bits::Sha256), KEY).expect("Failed to create key");
+
+    let mac = key.mac_data(DATA).expect("Failed to compute MAC");
+
+    assert_eq!(hex::encode(mac), MAC_SHA256);
+}
+
+#[test]
+fn test_verify_mac() {
+    let key =
+        SoftwareHmacKey::generate(Hash::Sha2(Sha2Bits::Sha512)).expect("Failed to create key");
+
+    let mut mac = key.mac_data(DATA).expect("Failed to compute MAC");
+    assert!(key.verify_mac(DATA, &mac).expect("Failed to verify MAC"));
+
+    mac[0] ^= 1;
+    assert!(!key.verify_mac(DATA, &mac).expect("Failed to verify MAC"));
+}
+
+#[test]
+fn test_unsupported_hash() {
+    assert!(SoftwareHmacKey::new(Hash::Md5, KEY).is_err());
+}
@@ -0,0 +1,5 @@
+mod hmac_tests;
+#[cfg(all(feature = "pkcs11-module", feature = "hsm"))]
+mod pkcs11_module;
+mod public_key_tests;
+mod rng_tests;
@@ -0,0 +1,2 @@
+mod module_tests;
+mod software_provider;
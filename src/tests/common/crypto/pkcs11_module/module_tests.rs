@@ -0,0 +1,615 @@
+/// # Test Cases for the PKCS #11 Module
+///
+/// The tests call the functions returned by `C_GetFunctionList`, with a
+/// [`SoftwareProvider`](super::software_provider::SoftwareProvider) in place of the
+/// security module of the configured token, so they need no devices.
+///
+/// The module has global state, so the tests take turns.
+///
+/// Please use **cargo test --features "pkcs11-module hsm"**.
+use super::software_provider::{factory, KEYS, PIN};
+use crate::common::crypto::pkcs::{
+    cryptoki::*,
+    module::{
+        set_provider_factory,
+        token::{der, ecdsa_signature},
+        ModuleConfig, CONFIG_VARIABLE,
+    },
+};
+use openssl::{
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Padding,
+    sign::{RsaPssSaltlen, Verifier},
+};
+use std::{
+    env, fs,
+    mem::size_of,
+    process, ptr,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use test_case::test_case;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+const CONFIG: &str = r#"{ "slots": [
+    { "label": "Software", "module": "NitroKey", "keys": [
+        { "id": "rsa", "algorithm": { "Rsa": "Bits2048" } },
+        { "id": "rsa-pss", "algorithm": { "Rsa": "Bits2048" }, "hash": { "Sha2": "Sha384" },
+          "rsa_padding": "Pss" },
+        { "id": "ec", "algorithm": { "Ecc": { "EcDsa": "P256" } } },
+        { "id": "missing", "algorithm": { "Rsa": "Bits2048" } }
+    ] },
+    { "label": "Empty", "module": "YubiKey:12345678", "keys": [], "management_key": "0102" }
+] }"#;
+
+/// The initialized module, finalized on drop.
+struct Module {
+    functions: &'static CK_FUNCTION_LIST,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Module {
+    fn initialize() -> Self {
+        let lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = env::temp_dir().join(format!("crypto-layer-pkcs11-{}.json", process::id()));
+        fs::write(&path, CONFIG).unwrap();
+        env::set_var(CONFIG_VARIABLE, &path);
+        set_provider_factory(factory);
+
+        let functions = function_list();
+        let rv = unsafe { functions.C_Initialize.unwrap()(ptr::null_mut()) };
+        assert_eq!(rv, CKR_OK);
+        Self {
+            functions,
+            _lock: lock,
+        }
+    }
+
+    fn open_session(&self) -> CK_SESSION_HANDLE {
+        let mut session = 0;
+        let rv = unsafe {
+            self.functions.C_OpenSession.unwrap()(
+                0,
+                CKF_SERIAL_SESSION,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut session,
+            )
+        };
+        assert_eq!(rv, CKR_OK);
+        session
+    }
+
+    fn login(&self, session: CK_SESSION_HANDLE, pin: &[u8]) -> CK_RV {
+        unsafe {
+            self.functions.C_Login.unwrap()(session, CKU_USER, pin.as_ptr(), pin.len() as CK_ULONG)
+        }
+    }
+
+    fn find(
+        &self,
+        session: CK_SESSION_HANDLE,
+        template: &[(CK_ATTRIBUTE_TYPE, Vec<u8>)],
+    ) -> Vec<CK_OBJECT_HANDLE> {
+        let mut template: Vec<CK_ATTRIBUTE> = template
+            .iter()
+            .map(|(type_, value)| CK_ATTRIBUTE {
+                type_: *type_,
+                pValue: value.as_ptr() as *mut _,
+                ulValueLen: value.len() as CK_ULONG,
+            })
+            .collect();
+        let mut objects = [0; 16];
+        let mut count = 0;
+        unsafe {
+            let rv = self.functions.C_FindObjectsInit.unwrap()(
+                session,
+                template.as_mut_ptr(),
+                template.len() as CK_ULONG,
+            );
+            assert_eq!(rv, CKR_OK);
+            let rv = self.functions.C_FindObjects.unwrap()(
+                session,
+                objects.as_mut_ptr(),
+                objects.len() as CK_ULONG,
+                &mut count,
+            );
+            assert_eq!(rv, CKR_OK);
+            assert_eq!(self.functions.C_FindObjectsFinal.unwrap()(session), CKR_OK);
+        }
+        objects[..count as usize].to_vec()
+    }
+
+    /// The private key with the given ID.
+    fn private_key(&self, session: CK_SESSION_HANDLE, id: &str) -> CK_OBJECT_HANDLE {
+        let template = [
+            (CKA_CLASS, ulong(CKO_PRIVATE_KEY)),
+            (CKA_ID, id.as_bytes().to_vec()),
+        ];
+        let objects = self.find(session, &template);
+        assert_eq!(objects.len(), 1);
+        objects[0]
+    }
+
+    fn attribute(
+        &self,
+        session: CK_SESSION_HANDLE,
+        object: CK_OBJECT_HANDLE,
+        type_: CK_ATTRIBUTE_TYPE,
+    ) -> Result<Vec<u8>, CK_RV> {
+        let mut attribute = CK_ATTRIBUTE {
+            type_,
+            pValue: ptr::null_mut(),
+            ulValueLen: 0,
+        };
+        let get = self.functions.C_GetAttributeValue.unwrap();
+        let rv = unsafe { get(session, object, &mut attribute, 1) };
+        if rv != CKR_OK {
+            return Err(rv);
+        }
+        let mut value = vec![0u8; attribute.ulValueLen as usize];
+        attribute.pValue = value.as_mut_ptr().cast();
+        let rv = unsafe { get(session, object, &mut attribute, 1) };
+        assert_eq!(rv, CKR_OK);
+        Ok(value)
+    }
+
+    fn sign(
+        &self,
+        session: CK_SESSION_HANDLE,
+        key: CK_OBJECT_HANDLE,
+        mut mechanism: CK_MECHANISM,
+        data: &[u8],
+    ) -> Result<Vec<u8>, CK_RV> {
+        unsafe {
+            let rv = self.functions.C_SignInit.unwrap()(session, &mut mechanism, key);
+            if rv != CKR_OK {
+                return Err(rv);
+            }
+            let sign = self.functions.C_Sign.unwrap();
+            let mut len = 0;
+            let rv = sign(
+                session,
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                ptr::null_mut(),
+                &mut len,
+            );
+            assert_eq!(rv, CKR_OK);
+            let mut signature = vec![0; len as usize];
+            let rv = sign(
+                session,
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                signature.as_mut_ptr(),
+                &mut len,
+            );
+            if rv != CKR_OK {
+                return Err(rv);
+            }
+            signature.truncate(len as usize);
+            Ok(signature)
+        }
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe { self.functions.C_Finalize.unwrap()(ptr::null_mut()) };
+    }
+}
+
+fn function_list() -> &'static CK_FUNCTION_LIST {
+    let mut functions = ptr::null();
+    let rv = unsafe { crate::common::crypto::pkcs::module::C_GetFunctionList(&mut functions) };
+    assert_eq!(rv, CKR_OK);
+    unsafe { &*functions }
+}
+
+fn key(id: &str) -> &'static PKey<Private> {
+    &KEYS.iter().find(|(key_id, _)| *key_id == id).unwrap().1
+}
+
+fn ulong(value: CK_ULONG) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+fn plain(mechanism: CK_MECHANISM_TYPE) -> CK_MECHANISM {
+    CK_MECHANISM {
+        mechanism,
+        pParameter: ptr::null_mut(),
+        ulParameterLen: 0,
+    }
+}
+
+#[test]
+fn test_config_from_json() {
+    let config = ModuleConfig::from_json(CONFIG).unwrap();
+    assert_eq!(config.slots.len(), 2);
+    assert_eq!(config.slots[0].keys[0].hash, None);
+    assert_eq!(
+        config.slots[1].management_key().unwrap(),
+        Some(vec![0x01, 0x02])
+    );
+
+    let unsupported = CONFIG.replace("\"NitroKey\"", "\"Floppy\"");
+    assert!(ModuleConfig::from_json(&unsupported).is_err());
+    let invalid_serial = CONFIG.replace("YubiKey:12345678", "YubiKey:serial");
+    assert!(ModuleConfig::from_json(&invalid_serial).is_err());
+    let ecdh = CONFIG.replace("EcDsa", "EcDh");
+    assert!(ModuleConfig::from_json(&ecdh).is_err());
+}
+
+#[test]
+fn test_not_initialized() {
+    let _lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut count = 0;
+    let rv =
+        unsafe { function_list().C_GetSlotList.unwrap()(CK_TRUE, ptr::null_mut(), &mut count) };
+    assert_eq!(rv, CKR_CRYPTOKI_NOT_INITIALIZED);
+}
+
+#[test]
+fn test_initialize_twice() {
+    let module = Module::initialize();
+    let rv = unsafe { module.functions.C_Initialize.unwrap()(ptr::null_mut()) };
+    assert_eq!(rv, CKR_CRYPTOKI_ALREADY_INITIALIZED);
+}
+
+#[test]
+fn test_slots_and_tokens() {
+    let module = Module::initialize();
+    let mut count = 0;
+    unsafe {
+        let get_slots = module.functions.C_GetSlotList.unwrap();
+        assert_eq!(get_slots(CK_TRUE, ptr::null_mut(), &mut count), CKR_OK);
+        assert_eq!(count, 2);
+        let mut slots = [CK_SLOT_ID::MAX; 1];
+        count = slots.len() as CK_ULONG;
+        assert_eq!(
+            get_slots(CK_TRUE, slots.as_mut_ptr(), &mut count),
+            CKR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(count, 2);
+
+        let mut info = std::mem::zeroed::<CK_TOKEN_INFO>();
+        let rv = module.functions.C_GetTokenInfo.unwrap()(1, &mut info);
+        assert_eq!(rv, CKR_OK);
+        assert!(info.label.starts_with(b"Empty "));
+        assert_eq!(
+            module.functions.C_GetTokenInfo.unwrap()(2, &mut info),
+            CKR_SLOT_ID_INVALID
+        );
+    }
+}
+
+#[test]
+fn test_mechanisms() {
+    let module = Module::initialize();
+    let mut mechanisms = [0; 8];
+    let mut count = mechanisms.len() as CK_ULONG;
+    unsafe {
+        let rv =
+            module.functions.C_GetMechanismList.unwrap()(0, mechanisms.as_mut_ptr(), &mut count);
+        assert_eq!(rv, CKR_OK);
+    }
+    let mut expected = vec![
+        CKM_RSA_PKCS,
+        CKM_SHA256_RSA_PKCS,
+        CKM_SHA384_RSA_PKCS_PSS,
+        CKM_ECDSA_SHA256,
+    ];
+    expected.sort_unstable();
+    assert_eq!(mechanisms[..count as usize], expected);
+
+    let mut info = CK_MECHANISM_INFO {
+        ulMinKeySize: 0,
+        ulMaxKeySize: 0,
+        flags: 0,
+    };
+    unsafe {
+        let get_info = module.functions.C_GetMechanismInfo.unwrap();
+        assert_eq!(get_info(0, CKM_RSA_PKCS, &mut info), CKR_OK);
+        assert_eq!(info.ulMaxKeySize, 2048);
+        assert_ne!(info.flags & CKF_DECRYPT, 0);
+        assert_eq!(get_info(0, CKM_ECDSA, &mut info), CKR_MECHANISM_INVALID);
+    }
+}
+
+#[test]
+fn test_login() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert!(module.find(session, &[]).is_empty());
+
+    assert_eq!(module.login(session, b"0000"), CKR_PIN_INCORRECT);
+    assert_eq!(module.login(session, PIN), CKR_OK);
+    assert_eq!(module.login(session, PIN), CKR_USER_ALREADY_LOGGED_IN);
+    // The missing key is skipped.
+    assert_eq!(module.find(session, &[]).len(), 6);
+
+    let rv = unsafe { module.functions.C_Logout.unwrap()(session) };
+    assert_eq!(rv, CKR_OK);
+    assert!(module.find(session, &[]).is_empty());
+}
+
+#[test]
+fn test_close_last_session_logs_out() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+    let other = module.open_session();
+    unsafe {
+        assert_eq!(module.functions.C_CloseSession.unwrap()(session), CKR_OK);
+        assert_eq!(module.find(other, &[]).len(), 6);
+        assert_eq!(module.functions.C_CloseSession.unwrap()(other), CKR_OK);
+    }
+    let session = module.open_session();
+    assert!(module.find(session, &[]).is_empty());
+}
+
+#[test]
+fn test_find_objects() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let private_keys = module.find(session, &[(CKA_CLASS, ulong(CKO_PRIVATE_KEY))]);
+    assert_eq!(private_keys.len(), 3);
+    let ec_keys = module.find(session, &[(CKA_KEY_TYPE, ulong(CKK_EC))]);
+    assert_eq!(ec_keys.len(), 2);
+    let labelled = module.find(session, &[(CKA_LABEL, b"rsa-pss".to_vec())]);
+    assert_eq!(labelled.len(), 2);
+    assert!(module
+        .find(session, &[(CKA_ID, b"missing".to_vec())])
+        .is_empty());
+}
+
+#[test]
+fn test_get_attribute_value() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let rsa = module.private_key(session, "rsa");
+    let modulus = module.attribute(session, rsa, CKA_MODULUS).unwrap();
+    assert_eq!(modulus, key("rsa").rsa().unwrap().n().to_vec());
+    assert_eq!(module.attribute(session, rsa, CKA_SIGN), Ok(vec![CK_TRUE]));
+    assert_eq!(
+        module.attribute(session, rsa, CKA_PRIVATE_EXPONENT),
+        Err(CKR_ATTRIBUTE_SENSITIVE)
+    );
+    assert_eq!(
+        module.attribute(session, rsa, CKA_EC_POINT),
+        Err(CKR_ATTRIBUTE_TYPE_INVALID)
+    );
+    assert_eq!(
+        module.attribute(session, 100, CKA_CLASS),
+        Err(CKR_OBJECT_HANDLE_INVALID)
+    );
+
+    let mut buffer = [0u8; 4];
+    let mut attribute = CK_ATTRIBUTE {
+        type_: CKA_MODULUS,
+        pValue: buffer.as_mut_ptr().cast(),
+        ulValueLen: buffer.len() as CK_ULONG,
+    };
+    let rv =
+        unsafe { module.functions.C_GetAttributeValue.unwrap()(session, rsa, &mut attribute, 1) };
+    assert_eq!(rv, CKR_BUFFER_TOO_SMALL);
+    assert_eq!({ attribute.ulValueLen }, CK_UNAVAILABLE_INFORMATION);
+}
+
+#[test]
+fn test_ec_public_key_attributes() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let template = [(CKA_CLASS, ulong(CKO_PUBLIC_KEY)), (CKA_ID, b"ec".to_vec())];
+    let public = module.find(session, &template)[0];
+    let params = module.attribute(session, public, CKA_EC_PARAMS).unwrap();
+    // prime256v1
+    assert_eq!(
+        params,
+        [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]
+    );
+
+    let ec = key("ec").ec_key().unwrap();
+    let mut context = openssl::bn::BigNumContext::new().unwrap();
+    let point = ec
+        .public_key()
+        .to_bytes(
+            ec.group(),
+            openssl::ec::PointConversionForm::UNCOMPRESSED,
+            &mut context,
+        )
+        .unwrap();
+    let value = module.attribute(session, public, CKA_EC_POINT).unwrap();
+    assert_eq!(value, der(0x04, &point));
+}
+
+#[test_case("rsa", plain(CKM_SHA256_RSA_PKCS) ; "RSA PKCS1")]
+#[test_case("ec", plain(CKM_ECDSA_SHA256) ; "ECDSA")]
+fn test_sign(id: &str, mechanism: CK_MECHANISM) {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let data = b"Hello, PKCS #11";
+    let key_handle = module.private_key(session, id);
+    let signature = module.sign(session, key_handle, mechanism, data).unwrap();
+
+    let signature = match id {
+        "ec" => {
+            assert_eq!(signature.len(), 64);
+            let r = openssl::bn::BigNum::from_slice(&signature[..32]).unwrap();
+            let s = openssl::bn::BigNum::from_slice(&signature[32..]).unwrap();
+            EcdsaSig::from_private_components(r, s)
+                .unwrap()
+                .to_der()
+                .unwrap()
+        }
+        _ => signature,
+    };
+    let mut verifier = Verifier::new(MessageDigest::sha256(), key(id)).unwrap();
+    assert!(verifier.verify_oneshot(&signature, data).unwrap());
+}
+
+#[test]
+fn test_sign_pss() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let mut params = CK_RSA_PKCS_PSS_PARAMS {
+        hashAlg: CKM_SHA384,
+        mgf: CKG_MGF1_SHA384,
+        sLen: 48,
+    };
+    let mechanism = CK_MECHANISM {
+        mechanism: CKM_SHA384_RSA_PKCS_PSS,
+        pParameter: (&mut params as *mut CK_RSA_PKCS_PSS_PARAMS).cast(),
+        ulParameterLen: size_of::<CK_RSA_PKCS_PSS_PARAMS>() as CK_ULONG,
+    };
+    let data = b"Hello, PKCS #11";
+    let key_handle = module.private_key(session, "rsa-pss");
+    let signature = module.sign(session, key_handle, mechanism, data).unwrap();
+
+    let mut verifier = Verifier::new(MessageDigest::sha384(), key("rsa-pss")).unwrap();
+    verifier.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+    verifier
+        .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+        .unwrap();
+    assert!(verifier.verify_oneshot(&signature, data).unwrap());
+
+    let mut short_salt = CK_RSA_PKCS_PSS_PARAMS { sLen: 20, ..params };
+    let mechanism = CK_MECHANISM {
+        pParameter: (&mut short_salt as *mut CK_RSA_PKCS_PSS_PARAMS).cast(),
+        ..mechanism
+    };
+    assert_eq!(
+        module.sign(session, key_handle, mechanism, data),
+        Err(CKR_MECHANISM_PARAM_INVALID)
+    );
+}
+
+#[test]
+fn test_sign_multi_part() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let key_handle = module.private_key(session, "rsa");
+    let mut mechanism = plain(CKM_SHA256_RSA_PKCS);
+    let mut signature = vec![0; 256];
+    let mut len = signature.len() as CK_ULONG;
+    unsafe {
+        let rv = module.functions.C_SignInit.unwrap()(session, &mut mechanism, key_handle);
+        assert_eq!(rv, CKR_OK);
+        for part in [&b"Hello, "[..], b"PKCS #11"] {
+            let rv = module.functions.C_SignUpdate.unwrap()(
+                session,
+                part.as_ptr(),
+                part.len() as CK_ULONG,
+            );
+            assert_eq!(rv, CKR_OK);
+        }
+        let rv = module.functions.C_SignFinal.unwrap()(session, signature.as_mut_ptr(), &mut len);
+        assert_eq!(rv, CKR_OK);
+    }
+    let mut verifier = Verifier::new(MessageDigest::sha256(), key("rsa")).unwrap();
+    assert!(verifier
+        .verify_oneshot(&signature[..len as usize], b"Hello, PKCS #11")
+        .unwrap());
+}
+
+#[test]
+fn test_sign_invalid() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    let data = b"Hello, PKCS #11";
+    assert_eq!(
+        module.sign(session, 2, plain(CKM_SHA256_RSA_PKCS), data),
+        Err(CKR_USER_NOT_LOGGED_IN)
+    );
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let key_handle = module.private_key(session, "rsa");
+    assert_eq!(
+        module.sign(session, key_handle, plain(CKM_SHA384_RSA_PKCS), data),
+        Err(CKR_MECHANISM_INVALID)
+    );
+    assert_eq!(
+        module.sign(session, key_handle, plain(CKM_RSA_PKCS), data),
+        Err(CKR_MECHANISM_INVALID)
+    );
+    assert_eq!(
+        module.sign(session, key_handle - 1, plain(CKM_SHA256_RSA_PKCS), data),
+        Err(CKR_KEY_FUNCTION_NOT_PERMITTED)
+    );
+}
+
+#[test]
+fn test_decrypt() {
+    let module = Module::initialize();
+    let session = module.open_session();
+    assert_eq!(module.login(session, PIN), CKR_OK);
+
+    let rsa = key("rsa").rsa().unwrap();
+    let mut encrypted = vec![0; rsa.size() as usize];
+    let len = rsa
+        .public_encrypt(b"secret", &mut encrypted, Padding::PKCS1)
+        .unwrap();
+    encrypted.truncate(len);
+
+    let key_handle = module.private_key(session, "rsa");
+    let mut mechanism = plain(CKM_RSA_PKCS);
+    let mut decrypted = vec![0; 256];
+    let mut len = decrypted.len() as CK_ULONG;
+    unsafe {
+        let rv = module.functions.C_DecryptInit.unwrap()(session, &mut mechanism, key_handle);
+        assert_eq!(rv, CKR_OK);
+        let rv = module.functions.C_Decrypt.unwrap()(
+            session,
+            encrypted.as_ptr(),
+            encrypted.len() as CK_ULONG,
+            decrypted.as_mut_ptr(),
+            &mut len,
+        );
+        assert_eq!(rv, CKR_OK);
+    }
+    assert_eq!(&decrypted[..len as usize], b"secret");
+
+    let ec = module.private_key(session, "ec");
+    let rv = unsafe { module.functions.C_DecryptInit.unwrap()(session, &mut mechanism, ec) };
+    assert_eq!(rv, CKR_KEY_FUNCTION_NOT_PERMITTED);
+}
+
+#[test]
+fn test_ecdsa_signature() {
+    let r = [0x01; 32];
+    let s = [0x00, 0x02, 0x03];
+    let signature = EcdsaSig::from_private_components(
+        openssl::bn::BigNum::from_slice(&r).unwrap(),
+        openssl::bn::BigNum::from_slice(&s).unwrap(),
+    )
+    .unwrap();
+    let mut expected = r.to_vec();
+    expected.extend([0; 30]);
+    expected.extend([0x02, 0x03]);
+    assert_eq!(
+        ecdsa_signature(&signature.to_der().unwrap(), 32),
+        Some(expected.clone())
+    );
+
+    // A marshalled TPMT_SIGNATURE: TPM_ALG_ECDSA, TPM_ALG_SHA256, then the sized r and s.
+    let mut tpm = vec![0x00, 0x18, 0x00, 0x0b, 0x00, 0x20];
+    tpm.extend(r);
+    tpm.extend([0x00, 0x02, 0x02, 0x03]);
+    assert_eq!(ecdsa_signature(&tpm, 32), Some(expected));
+
+    assert_eq!(ecdsa_signature(&[0x30, 0x00, 0x01], 32), None);
+}
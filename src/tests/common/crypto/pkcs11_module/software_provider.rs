@@ -0,0 +1,166 @@
+use crate::{
+    common::{
+        crypto::algorithms::encryption::AsymmetricEncryption,
+        error::{FactoryError, SecurityModuleError},
+        factory::SecurityModule,
+        traits::{
+            interaction::{CredentialKind, CredentialRequest, InteractionHandler},
+            key_handle::KeyHandle,
+            module_provider::Provider,
+            operations::{Decryptor, Signer},
+        },
+    },
+    hsm::{core::error::HsmError, HsmProviderConfig, RsaPadding},
+};
+use once_cell::sync::Lazy;
+use openssl::{
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::{Padding, Rsa},
+    sign::{self, RsaPssSaltlen},
+};
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+};
+
+/// The PIN `SoftwareProvider` accepts.
+pub const PIN: &[u8] = b"1234";
+
+/// The keys `SoftwareProvider` loads, by key ID.
+pub static KEYS: Lazy<Vec<(&str, PKey<Private>)>> = Lazy::new(|| {
+    let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let ec = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    vec![("rsa", rsa.clone()), ("rsa-pss", rsa), ("ec", ec)]
+});
+
+/// A `ProviderFactory` creating `SoftwareProvider`s.
+pub fn factory(
+    _key_id: String,
+    _module: &SecurityModule,
+) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
+    Ok(Arc::new(Mutex::new(SoftwareProvider::default())))
+}
+
+/// A provider with keys in memory, protected by `PIN`, in place of a security module.
+#[derive(Debug, Default)]
+pub struct SoftwareProvider {
+    handler: Option<Arc<dyn InteractionHandler>>,
+    key: Option<PKey<Private>>,
+    config: Option<HsmProviderConfig>,
+}
+
+impl SoftwareProvider {
+    fn key(&self) -> Result<&PKey<Private>, SecurityModuleError> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| HsmError::DeviceSpecific("No key loaded".to_owned()).into())
+    }
+}
+
+impl Provider for SoftwareProvider {
+    fn create_key(
+        &mut self,
+        _key_id: &str,
+        _config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        Err(HsmError::UnsupportedFeature("create_key".to_owned()).into())
+    }
+
+    fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        let config = config
+            .downcast::<HsmProviderConfig>()
+            .map_err(|_| HsmError::DeviceSpecific("Invalid config".to_owned()))?;
+        let (_, key) = KEYS
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .ok_or_else(|| HsmError::DeviceSpecific("Key not found".to_owned()))?;
+        self.key = Some(key.clone());
+        self.config = Some(*config);
+        Ok(())
+    }
+
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        let request = CredentialRequest {
+            kind: CredentialKind::Pin,
+            module: "Software".to_owned(),
+            key_id: None,
+            prompt: "PIN".to_owned(),
+            retries_left: None,
+        };
+        let handler = self
+            .handler
+            .as_ref()
+            .ok_or_else(|| HsmError::Authentication("No interaction handler".to_owned()))?;
+        if handler.request_credential(&request)? != PIN {
+            return Err(HsmError::WrongCredential {
+                kind: CredentialKind::Pin,
+                retries_left: None,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        self.key
+            .as_ref()
+            .and_then(|key| key.public_key_to_pem().ok())
+            .map(|pem| String::from_utf8(pem).unwrap())
+            .unwrap_or_default()
+    }
+
+    fn set_interaction_handler(&mut self, handler: Arc<dyn InteractionHandler>) {
+        self.handler = Some(handler);
+    }
+}
+
+impl KeyHandle for SoftwareProvider {
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        self.key.is_some().then_some(self)
+    }
+
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        self.key
+            .as_ref()
+            .is_some_and(|key| key.rsa().is_ok())
+            .then_some(self)
+    }
+}
+
+impl Signer for SoftwareProvider {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let config = self.config.as_ref().unwrap();
+        let digest = config
+            .hash
+            .and_then(|hash| hash.message_digest())
+            .unwrap_or_else(MessageDigest::sha256);
+        let key = self.key()?;
+        let mut signer = sign::Signer::new(digest, key).unwrap();
+        if let (AsymmetricEncryption::Rsa(_), RsaPadding::Pss) =
+            (config.key_algorithm, config.rsa_padding)
+        {
+            signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+            signer
+                .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+                .unwrap();
+        }
+        // ECDSA signatures are DER-encoded, like those of the YubiKey provider.
+        Ok(signer.sign_oneshot_to_vec(data).unwrap())
+    }
+}
+
+impl Decryptor for SoftwareProvider {
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let rsa = self.key()?.rsa().unwrap();
+        let mut decrypted = vec![0; rsa.size() as usize];
+        let len = rsa
+            .private_decrypt(encrypted_data, &mut decrypted, Padding::PKCS1)
+            .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?;
+        decrypted.truncate(len);
+        Ok(decrypted)
+    }
+}
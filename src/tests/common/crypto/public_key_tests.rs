@@ -0,0 +1,290 @@
+use crate::{
+    common::{
+        crypto::{
+            algorithms::{
+                encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+                hashes::{Hash, Sha2Bits},
+                KeyBits,
+            },
+            public_key::{ecies_decrypt, PublicKeyHandle, RsaEncryptionPadding},
+        },
+        error::SecurityModuleError,
+        traits::{key_handle::KeyHandle, module_provider::Provider, operations::KeyAgreement},
+    },
+    hsm::RsaPadding,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openssl::{
+    bn::BigNumContext,
+    derive::Deriver,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::{Padding, Rsa},
+    sign::{RsaPssSaltlen, Signer},
+};
+use std::any::Any;
+
+const DATA: &[u8] = b"Hello, verifier!";
+
+fn handle(key: &PKey<Private>) -> PublicKeyHandle {
+    PublicKeyHandle::from_spki_der(&key.public_key_to_der().unwrap()).unwrap()
+}
+
+fn sign(key: &PKey<Private>, pss: bool) -> Vec<u8> {
+    let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+    if pss {
+        signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+        signer
+            .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+            .unwrap();
+    }
+    signer.sign_oneshot_to_vec(DATA).unwrap()
+}
+
+/// A private key agreeing on secrets in software, standing in for a device key.
+#[derive(Debug)]
+struct SoftwareKeyAgreement(PKey<Private>);
+
+impl KeyAgreement for SoftwareKeyAgreement {
+    fn agree(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let peer = PKey::public_key_from_der(peer_public_key).unwrap();
+        let mut deriver = Deriver::new(&self.0).unwrap();
+        deriver.set_peer(&peer).unwrap();
+        Ok(deriver.derive_to_vec().unwrap())
+    }
+}
+
+/// A provider holding a key with the PEM encoded public key `pem`.
+#[derive(Debug)]
+struct PublicKeyProvider(String);
+
+impl Provider for PublicKeyProvider {
+    fn create_key(
+        &mut self,
+        _key_id: &str,
+        _config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn load_key(
+        &mut self,
+        _key_id: &str,
+        _config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        self.0.clone()
+    }
+}
+
+impl KeyHandle for PublicKeyProvider {}
+
+#[test]
+fn test_rsa_verify_and_encrypt() {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let pem = String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+    let public_key = PublicKeyHandle::from_pem(&pem).unwrap();
+    assert_eq!(
+        public_key.algorithm(),
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048)
+    );
+
+    let signature = sign(&key, false);
+    assert!(public_key.verify_signature(DATA, &signature).unwrap());
+    assert!(!public_key.verify_signature(b"Goodbye", &signature).unwrap());
+    assert!(!public_key.verify_signature(DATA, b"garbage").unwrap());
+    let public_key = public_key.with_rsa_padding(RsaPadding::Pss);
+    assert!(public_key
+        .verify_signature(DATA, &sign(&key, true))
+        .unwrap());
+    assert!(!public_key.verify_signature(DATA, &signature).unwrap());
+
+    let rsa = key.rsa().unwrap();
+    let mut decrypted = vec![0; rsa.size() as usize];
+    let encrypted = public_key.encrypt_data(DATA).unwrap();
+    let len = rsa
+        .private_decrypt(&encrypted, &mut decrypted, Padding::PKCS1)
+        .unwrap();
+    assert_eq!(&decrypted[..len], DATA);
+
+    let public_key = public_key.with_rsa_encryption_padding(RsaEncryptionPadding::Oaep);
+    let encrypted = public_key.encrypt_data(DATA).unwrap();
+    let mut decrypter = openssl::encrypt::Decrypter::new(&key).unwrap();
+    decrypter.set_rsa_padding(Padding::PKCS1_OAEP).unwrap();
+    decrypter.set_rsa_oaep_md(MessageDigest::sha256()).unwrap();
+    decrypter.set_rsa_mgf1_md(MessageDigest::sha256()).unwrap();
+    let mut decrypted = vec![0; decrypter.decrypt_len(&encrypted).unwrap()];
+    let len = decrypter.decrypt(&encrypted, &mut decrypted).unwrap();
+    assert_eq!(&decrypted[..len], DATA);
+}
+
+#[test]
+fn test_from_provider() {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let pem = String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+    let public_key = PublicKeyHandle::from_provider(
+        &mut PublicKeyProvider(pem),
+        Hash::Sha2(Sha2Bits::Sha384),
+        RsaPadding::Pss,
+        RsaEncryptionPadding::Oaep,
+    )
+    .unwrap();
+
+    let mut signer = Signer::new(MessageDigest::sha384(), &key).unwrap();
+    signer.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+    signer
+        .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+        .unwrap();
+    let signature = signer.sign_oneshot_to_vec(DATA).unwrap();
+    assert!(public_key.verify_signature(DATA, &signature).unwrap());
+
+    let encrypted = public_key.encrypt_data(DATA).unwrap();
+    let mut decrypter = openssl::encrypt::Decrypter::new(&key).unwrap();
+    decrypter.set_rsa_padding(Padding::PKCS1_OAEP).unwrap();
+    decrypter.set_rsa_oaep_md(MessageDigest::sha384()).unwrap();
+    decrypter.set_rsa_mgf1_md(MessageDigest::sha384()).unwrap();
+    let mut decrypted = vec![0; decrypter.decrypt_len(&encrypted).unwrap()];
+    let len = decrypter.decrypt(&encrypted, &mut decrypted).unwrap();
+    assert_eq!(&decrypted[..len], DATA);
+
+    assert!(PublicKeyHandle::from_provider(
+        &mut PublicKeyProvider(String::new()),
+        Hash::Sha2(Sha2Bits::Sha256),
+        RsaPadding::Pkcs1v15,
+        RsaEncryptionPadding::Pkcs1v15,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_ecdsa_verify() {
+    let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let public_key = handle(&key).with_hash(Hash::Sha2(Sha2Bits::Sha256));
+    assert_eq!(
+        public_key.algorithm(),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384))
+    );
+
+    let signature = sign(&key, false);
+    assert!(public_key.verify_signature(DATA, &signature).unwrap());
+
+    // The same signature as `r || s`, as returned by PKCS #11 tokens.
+    let signature = EcdsaSig::from_der(&signature).unwrap();
+    let mut raw = signature.r().to_vec_padded(48).unwrap();
+    raw.extend(signature.s().to_vec_padded(48).unwrap());
+    assert!(public_key.verify_signature(DATA, &raw).unwrap());
+    assert!(!public_key.verify_signature(b"Goodbye", &raw).unwrap());
+    assert!(!public_key.verify_signature(DATA, &raw[1..]).unwrap());
+}
+
+#[test]
+fn test_eddsa_verify() {
+    let key = PKey::generate_ed25519().unwrap();
+    let public_key = handle(&key);
+    assert_eq!(
+        public_key.algorithm(),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519))
+    );
+    assert!(public_key.as_encryptor().is_none());
+
+    let signature = Signer::new_without_digest(&key)
+        .unwrap()
+        .sign_oneshot_to_vec(DATA)
+        .unwrap();
+    assert!(public_key.verify_signature(DATA, &signature).unwrap());
+    assert!(!public_key.verify_signature(b"Goodbye", &signature).unwrap());
+    assert!(matches!(
+        public_key.encrypt_data(DATA),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+}
+
+#[test]
+fn test_ecies_round_trip() {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let keys = [
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap(),
+        PKey::generate_x25519().unwrap(),
+    ];
+    for key in keys {
+        let public_key = handle(&key);
+        let encrypted = public_key.encrypt_data(DATA).unwrap();
+        let private_key = SoftwareKeyAgreement(key);
+        assert_eq!(ecies_decrypt(&private_key, &encrypted).unwrap(), DATA);
+
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(ecies_decrypt(&private_key, &tampered).is_err());
+        assert!(ecies_decrypt(&private_key, &encrypted[..10]).is_err());
+    }
+    assert!(handle(&PKey::generate_x25519().unwrap())
+        .as_verifier()
+        .is_none());
+}
+
+#[test]
+fn test_from_jwk() {
+    let encode = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+
+    let rsa = Rsa::generate(2048).unwrap();
+    let jwk = format!(
+        r#"{{"kty":"RSA","n":"{}","e":"{}"}}"#,
+        encode(&rsa.n().to_vec()),
+        encode(&rsa.e().to_vec())
+    );
+    let key = PKey::from_rsa(rsa).unwrap();
+    let public_key = PublicKeyHandle::from_jwk(&jwk).unwrap();
+    assert!(public_key
+        .verify_signature(DATA, &sign(&key, false))
+        .unwrap());
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let ec_key = EcKey::generate(&group).unwrap();
+    let (mut x, mut y) = (
+        openssl::bn::BigNum::new().unwrap(),
+        openssl::bn::BigNum::new().unwrap(),
+    );
+    ec_key
+        .public_key()
+        .affine_coordinates(&group, &mut x, &mut y, &mut BigNumContext::new().unwrap())
+        .unwrap();
+    let jwk = format!(
+        r#"{{"kty":"EC","crv":"P-256","x":"{}","y":"{}"}}"#,
+        encode(&x.to_vec_padded(32).unwrap()),
+        encode(&y.to_vec_padded(32).unwrap())
+    );
+    let key = PKey::from_ec_key(ec_key).unwrap();
+    let public_key = PublicKeyHandle::from_jwk(&jwk).unwrap();
+    assert_eq!(
+        public_key.to_spki_der().unwrap(),
+        key.public_key_to_der().unwrap()
+    );
+
+    let key = PKey::generate_ed25519().unwrap();
+    let jwk = format!(
+        r#"{{"kty":"OKP","crv":"Ed25519","x":"{}"}}"#,
+        encode(&key.raw_public_key().unwrap())
+    );
+    let public_key = PublicKeyHandle::from_jwk(&jwk).unwrap();
+    assert_eq!(
+        public_key.to_spki_der().unwrap(),
+        key.public_key_to_der().unwrap()
+    );
+
+    assert!(matches!(
+        PublicKeyHandle::from_jwk(r#"{"kty":"EC","crv":"P-192","x":"","y":""}"#),
+        Err(SecurityModuleError::InitializationError(_))
+    ));
+    assert!(PublicKeyHandle::from_jwk(r#"{"kty":"oct","k":""}"#).is_err());
+}
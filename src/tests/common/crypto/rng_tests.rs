@@ -0,0 +1,81 @@
+use crate::common::{
+    crypto::rng::HardwareRng,
+    error::SecurityModuleError,
+    traits::{key_handle::KeyHandle, module_provider::Provider},
+};
+use rand_core::RngCore;
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+};
+
+/// A provider whose "hardware" RNG returns a constant byte, or fails for `None`.
+#[derive(Debug)]
+struct FixedRandomProvider(Option<u8>);
+
+impl KeyHandle for FixedRandomProvider {}
+
+impl Provider for FixedRandomProvider {
+    fn create_key(
+        &mut self,
+        _key_id: &str,
+        _config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn load_key(
+        &mut self,
+        _key_id: &str,
+        _config: Box<dyn Any>,
+    ) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        Ok(())
+    }
+
+    fn get_pub_key(&mut self) -> String {
+        String::new()
+    }
+
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        self.0.map(|byte| vec![byte; len]).ok_or_else(|| {
+            SecurityModuleError::InitializationError("No random number generator".to_owned())
+        })
+    }
+}
+
+fn rng(byte: Option<u8>) -> HardwareRng {
+    HardwareRng::new(Arc::new(Mutex::new(FixedRandomProvider(byte))))
+}
+
+#[test]
+fn test_output_is_mixed_with_os_randomness() {
+    // A constant hardware source must not make the output predictable.
+    let mut rng = rng(Some(0));
+
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    rng.fill_bytes(&mut first);
+    rng.fill_bytes(&mut second);
+
+    assert_ne!(first, [0u8; 64]);
+    assert_ne!(first, second);
+    assert_ne!(rng.next_u64(), rng.next_u64());
+}
+
+#[test]
+fn test_provider_error_is_reported() {
+    let mut rng = rng(None);
+
+    let mut dest = [0u8; 16];
+    assert!(rng.try_fill_bytes(&mut dest).is_err());
+}
+
+#[test]
+#[should_panic]
+fn test_fill_bytes_panics_on_provider_error() {
+    rng(None).fill_bytes(&mut [0u8; 16]);
+}
@@ -0,0 +1,79 @@
+use crate::common::{
+    device_events::DeviceWatcher,
+    traits::device_events::{DeviceEvent, DeviceEventHandler},
+};
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Records the events it receives.
+#[derive(Debug, Default)]
+struct RecordingHandler {
+    events: Mutex<Vec<DeviceEvent>>,
+}
+
+impl DeviceEventHandler for RecordingHandler {
+    fn device_event(&self, event: &DeviceEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+fn devices(ids: &[&str]) -> Option<Vec<String>> {
+    Some(ids.iter().map(|id| id.to_string()).collect())
+}
+
+#[test]
+fn test_device_watcher_reports_changes() {
+    let handler = Arc::new(RecordingHandler::default());
+    let (done, finished) = mpsc::channel();
+    let mut rounds = VecDeque::from([devices(&["a"]), devices(&["a", "b"]), None, devices(&["b"])]);
+    let list = move || match rounds.pop_front() {
+        Some(round) => round,
+        None => {
+            let _ = done.send(());
+            devices(&["b"])
+        }
+    };
+
+    let watcher = DeviceWatcher::spawn(
+        "Test",
+        handler.clone(),
+        |_| thread::sleep(Duration::from_millis(1)),
+        list,
+    );
+    finished
+        .recv_timeout(Duration::from_secs(10))
+        .expect("The watcher did not list the devices");
+    drop(watcher);
+
+    assert_eq!(
+        *handler.events.lock().unwrap(),
+        vec![
+            DeviceEvent::Inserted {
+                module: "Test".to_owned(),
+                device: "b".to_owned()
+            },
+            DeviceEvent::Removed {
+                module: "Test".to_owned(),
+                device: "a".to_owned()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_device_watcher_stops_on_drop() {
+    let handler = Arc::new(RecordingHandler::default());
+    let watcher = DeviceWatcher::spawn(
+        "Test",
+        handler.clone(),
+        |_| thread::sleep(Duration::from_millis(1)),
+        || devices(&[]),
+    );
+
+    drop(watcher);
+    assert!(handler.events.lock().unwrap().is_empty());
+}
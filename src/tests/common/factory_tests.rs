@@ -0,0 +1,96 @@
+#[allow(unused_imports)]
+use crate::{
+    common::{
+        error::FactoryError,
+        factory::{InstanceKey, SecModules, SecurityModule},
+    },
+    hsm::core::instance::HsmType,
+};
+#[allow(unused_imports)]
+use std::sync::Arc;
+
+#[test]
+fn test_unknown_module() {
+    assert_eq!(
+        "Floppy".parse::<SecurityModule>().unwrap_err(),
+        FactoryError::UnknownModule("Floppy".to_owned())
+    );
+    assert!(matches!(
+        HsmType::try_from("YubiKey:serial"),
+        Err(FactoryError::InvalidName { .. })
+    ));
+}
+
+#[cfg(feature = "hsm")]
+#[test]
+fn test_parse_hsm_module() {
+    assert_eq!("HSM".parse(), Ok(SecurityModule::Hsm(HsmType::default())));
+    assert_eq!(
+        SecurityModule::try_from("YubiKey:12345678"),
+        Ok(SecurityModule::Hsm(HsmType::YubiKey(Some(12345678))))
+    );
+    assert!(matches!(
+        "YubiKey:serial".parse::<SecurityModule>(),
+        Err(FactoryError::InvalidName { .. })
+    ));
+}
+
+#[cfg(not(feature = "tpm"))]
+#[test]
+fn test_disabled_module() {
+    assert!(matches!(
+        "TPM".parse::<SecurityModule>(),
+        Err(FactoryError::FeatureDisabled { feature: "tpm", .. })
+    ));
+}
+
+#[cfg(all(feature = "hsm", not(feature = "pkcs11")))]
+#[test]
+fn test_provider_feature_disabled() {
+    let result = SecModules::get_instance(
+        "test_provider_feature_disabled".to_owned(),
+        SecurityModule::Hsm(HsmType::NitroKey),
+        None,
+    );
+    assert!(matches!(
+        result,
+        Err(FactoryError::FeatureDisabled {
+            feature: "pkcs11",
+            ..
+        })
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_instances_by_name() {
+    let module = SecurityModule::Hsm(HsmType::YubiKey(None));
+    let first =
+        SecModules::get_instance("test_instances_first".to_owned(), module.clone(), None).unwrap();
+    let second =
+        SecModules::get_instance("test_instances_second".to_owned(), module.clone(), None).unwrap();
+    assert!(!Arc::ptr_eq(&first, &second));
+
+    let key = InstanceKey::new(module, "test_instances_first");
+    let cached = SecModules::get_keyed_instance(key.clone(), None).unwrap();
+    assert!(Arc::ptr_eq(&first, &cached));
+
+    let removed = SecModules::remove_instance(&key).unwrap();
+    assert!(Arc::ptr_eq(&first, &removed));
+    let recreated = SecModules::get_keyed_instance(key, None).unwrap();
+    assert!(!Arc::ptr_eq(&first, &recreated));
+}
+
+#[test]
+#[cfg(any(feature = "hsm", feature = "tpm"))]
+fn test_discovered_modules_can_be_created() {
+    for discovered in SecModules::discover() {
+        assert!(!discovered.description.is_empty());
+        let name = format!("test_discover {}", discovered.description);
+        assert!(
+            SecModules::get_instance(name, discovered.module.clone(), None).is_ok(),
+            "{:?}",
+            discovered
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::common::{
+    error::SecurityModuleError,
+    interaction::{CachingInteractionHandler, StaticCredentials},
+    traits::interaction::{CredentialKind, CredentialRequest, InteractionHandler},
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Answers every request with the number of requests seen so far.
+#[derive(Debug, Default)]
+struct CountingHandler {
+    requests: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+impl InteractionHandler for CountingHandler {
+    fn request_credential(
+        &self,
+        _request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let count = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(count.to_string().into_bytes())
+    }
+
+    fn credential_rejected(&self, _request: &CredentialRequest) {
+        self.rejected.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn request(kind: CredentialKind, key_id: Option<&str>) -> CredentialRequest {
+    CredentialRequest {
+        kind,
+        module: "YubiKey".to_owned(),
+        key_id: key_id.map(str::to_owned),
+        prompt: "PIN of YubiKey 1234".to_owned(),
+        retries_left: Some(3),
+    }
+}
+
+#[test]
+fn test_cache_reuses_credential() {
+    let inner = Arc::new(CountingHandler::default());
+    let cache = CachingInteractionHandler::new(inner.clone(), Duration::from_secs(60));
+
+    let pin = request(CredentialKind::Pin, None);
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"1");
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"1");
+
+    // Other kinds and keys are cached separately.
+    let puk = request(CredentialKind::Puk, None);
+    assert_eq!(cache.request_credential(&puk).unwrap(), b"2");
+    let key_pin = request(CredentialKind::Pin, Some("key"));
+    assert_eq!(cache.request_credential(&key_pin).unwrap(), b"3");
+
+    assert_eq!(inner.requests.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_cache_expires() {
+    let inner = Arc::new(CountingHandler::default());
+    let cache = CachingInteractionHandler::new(inner.clone(), Duration::ZERO);
+
+    let pin = request(CredentialKind::Pin, None);
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"1");
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"2");
+}
+
+#[test]
+fn test_rejected_credential_is_forgotten() {
+    let inner = Arc::new(CountingHandler::default());
+    let cache = CachingInteractionHandler::new(inner.clone(), Duration::from_secs(60));
+
+    let pin = request(CredentialKind::Pin, None);
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"1");
+    cache.credential_rejected(&pin);
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"2");
+    assert_eq!(inner.rejected.load(Ordering::SeqCst), 1);
+
+    cache.clear();
+    assert_eq!(cache.request_credential(&pin).unwrap(), b"3");
+}
+
+#[test]
+fn test_static_credentials() {
+    let credentials = StaticCredentials::new()
+        .with_credential(CredentialKind::Pin, "YubiKey", None, b"123456")
+        .with_credential(CredentialKind::Pin, "YubiKey", Some("signing"), b"654321");
+
+    assert_eq!(
+        credentials
+            .request_credential(&request(CredentialKind::Pin, None))
+            .unwrap(),
+        b"123456"
+    );
+    assert_eq!(
+        credentials
+            .request_credential(&request(CredentialKind::Pin, Some("other")))
+            .unwrap(),
+        b"123456"
+    );
+    assert_eq!(
+        credentials
+            .request_credential(&request(CredentialKind::Pin, Some("signing")))
+            .unwrap(),
+        b"654321"
+    );
+    assert!(credentials
+        .request_credential(&request(CredentialKind::Puk, None))
+        .is_err());
+}
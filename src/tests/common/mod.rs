@@ -1 +1,6 @@
+pub mod crypto;
 pub mod traits;
+mod composite_tests;
+mod device_events_tests;
+mod factory_tests;
+mod interaction_tests;
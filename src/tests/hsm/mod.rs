@@ -1 +1,4 @@
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
+#[cfg(feature = "yubi")]
 pub mod yubikey;
@@ -0,0 +1,154 @@
+/// # Test Cases for Cryptographic Operations with PKCS #11
+///
+/// The tests sign, verify, encrypt and decrypt with keys on a SoftHSMv2 token set up by
+/// [`SoftHsm`](super::softhsm::SoftHsm).
+///
+/// `test_ec_params`, `test_ec_point` and `test_ecdsa_signature_to_der` check the encodings
+/// exchanged with the token against OpenSSL and do not need SoftHSMv2.
+///
+/// Please use **cargo test --features pkcs11** with SoftHSMv2 installed.
+use super::softhsm::SoftHsm;
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits, Sha3Bits},
+            KeyBits,
+        },
+        traits::{key_handle::KeyHandle, module_provider::Provider},
+    },
+    hsm::{
+        pkcs11::{
+            key_handle::{curve_nid, ec_params, ecdsa_signature_to_der},
+            provider::ec_point,
+        },
+        HsmProviderConfig, RsaPadding,
+    },
+};
+use openssl::{
+    bn::BigNumContext,
+    ec::{EcGroup, EcKey, PointConversionForm},
+    ecdsa::EcdsaSig,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+};
+use test_case::test_case;
+
+const DATA: &[u8] = b"Hello, PKCS #11!";
+
+#[test_case(AsymmetricEncryption::Rsa(KeyBits::Bits2048), None, RsaPadding::Pkcs1v15 ; "rsa_2048")]
+#[test_case(AsymmetricEncryption::Rsa(KeyBits::Bits3072), Some(Hash::Sha2(Sha2Bits::Sha384)), RsaPadding::Pss ; "rsa_3072_pss_sha384")]
+#[test_case(AsymmetricEncryption::Rsa(KeyBits::Bits2048), Some(Hash::Sha3(Sha3Bits::Sha3_256)), RsaPadding::Pkcs1v15 ; "rsa_2048_sha3")]
+#[test_case(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)), None, RsaPadding::Pkcs1v15 ; "ecc_256")]
+#[test_case(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)), Some(Hash::Sha2(Sha2Bits::Sha384)), RsaPadding::Pkcs1v15 ; "ecc_384")]
+#[test_case(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P521)), Some(Hash::Sha2(Sha2Bits::Sha512)), RsaPadding::Pkcs1v15 ; "ecc_521")]
+fn test_sign_and_verify(
+    key_algorithm: AsymmetricEncryption,
+    hash: Option<Hash>,
+    rsa_padding: RsaPadding,
+) {
+    let key_id = format!(
+        "test_sign_and_verify_{:?}_{:?}_{:?}",
+        key_algorithm, hash, rsa_padding
+    );
+    let mut provider = SoftHsm::get().provider(&key_id);
+    let config = Box::new(HsmProviderConfig {
+        key_algorithm,
+        hash,
+        rsa_padding,
+        ..Default::default()
+    });
+    provider
+        .create_key(&key_id, config)
+        .expect("Failed to create key");
+
+    let signature = provider.sign_data(DATA).expect("Failed to sign data");
+    assert!(provider
+        .verify_signature(DATA, &signature)
+        .expect("Failed to verify signature"));
+    assert!(!provider
+        .verify_signature(b"Other data", &signature)
+        .expect("Failed to verify signature"));
+}
+
+#[test]
+fn test_encrypt_and_decrypt_rsa() {
+    let mut provider = SoftHsm::get().provider("test_encrypt_and_decrypt_rsa");
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048));
+    provider
+        .create_key("test_encrypt_and_decrypt_rsa", config)
+        .expect("Failed to create RSA key");
+
+    let encrypted = provider.encrypt_data(DATA).expect("Failed to encrypt data");
+    assert_ne!(encrypted, DATA);
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt data");
+    assert_eq!(decrypted, DATA);
+}
+
+#[test]
+fn test_encrypt_ecc_unsupported() {
+    let mut provider = SoftHsm::get().provider("test_encrypt_ecc_unsupported");
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P256,
+    )));
+    provider
+        .create_key("test_encrypt_ecc_unsupported", config)
+        .expect("Failed to create ECDSA key");
+
+    assert!(provider.encrypt_data(DATA).is_err());
+}
+
+#[test_case(EccCurves::P256, Nid::X9_62_PRIME256V1)]
+#[test_case(EccCurves::P384, Nid::SECP384R1)]
+#[test_case(EccCurves::Secp256k1, Nid::SECP256K1)]
+#[test_case(EccCurves::BrainpoolP512r1, Nid::BRAINPOOL_P512R1)]
+fn test_ec_params(curve: EccCurves, nid: Nid) {
+    let group = EcGroup::from_curve_name(nid).unwrap();
+    let key = EcKey::generate(&group).unwrap();
+    // The OID of the curve is the last element of the AlgorithmIdentifier of the SPKI.
+    let spki = key.public_key_to_der().unwrap();
+    let params = ec_params(curve).unwrap();
+
+    assert_eq!(curve_nid(curve).unwrap(), nid);
+    assert!(spki.windows(params.len()).any(|window| window == params));
+}
+
+#[test]
+fn test_ec_params_unsupported() {
+    assert!(ec_params(EccCurves::Curve25519).is_err());
+}
+
+#[test]
+fn test_ec_point() {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = EcKey::generate(&group).unwrap();
+    let mut ctx = BigNumContext::new().unwrap();
+    let point = key
+        .public_key()
+        .to_bytes(&group, PointConversionForm::UNCOMPRESSED, &mut ctx)
+        .unwrap();
+    let mut wrapped = vec![0x04, point.len() as u8];
+    wrapped.extend_from_slice(&point);
+
+    for value in [&wrapped, &point] {
+        let decoded = ec_point(&group, value).expect("Failed to decode EC point");
+        assert!(decoded.eq(&group, key.public_key(), &mut ctx).unwrap());
+    }
+}
+
+#[test]
+fn test_ecdsa_signature_to_der() {
+    let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+    let key = EcKey::generate(&group).unwrap();
+    let digest = hash(MessageDigest::sha384(), DATA).unwrap();
+    let signature = EcdsaSig::sign(&digest, &key).unwrap();
+    let mut raw = signature.r().to_vec_padded(48).unwrap();
+    raw.extend_from_slice(&signature.s().to_vec_padded(48).unwrap());
+
+    let der = ecdsa_signature_to_der(&raw).expect("Failed to encode signature");
+    let decoded = EcdsaSig::from_der(&der).unwrap();
+    assert!(decoded.verify(&digest, &key).unwrap());
+    assert!(ecdsa_signature_to_der(&raw[1..]).is_err());
+}
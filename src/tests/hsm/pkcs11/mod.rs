@@ -0,0 +1,3 @@
+mod key_handle_tests;
+mod provider_handle_tests;
+mod softhsm;
@@ -0,0 +1,237 @@
+/// # Test Cases for the PKCS #11 Provider
+///
+/// The tests create, replace and load keys on a SoftHSMv2 token set up by
+/// [`SoftHsm`](super::softhsm::SoftHsm), and check the login with a wrong PIN.
+/// `test_unknown_module` does not need SoftHSMv2.
+///
+/// Please use **cargo test --features pkcs11** with SoftHSMv2 installed.
+use super::softhsm::{SoftHsm, TOKEN_LABEL, UNUSED_TOKEN_LABEL};
+use crate::{
+    common::{
+        capabilities::Operation,
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            KeyBits,
+        },
+        error::SecurityModuleError,
+        interaction::StaticCredentials,
+        traits::{interaction::CredentialKind, module_provider::Provider},
+    },
+    hsm::{
+        core::error::HsmError,
+        pkcs11::{Pkcs11Config, Pkcs11Provider, TokenSelector},
+        HsmProviderConfig,
+    },
+};
+use std::sync::Arc;
+
+#[test]
+fn test_create_rsa_key() {
+    let mut provider = SoftHsm::get().provider("test_create_rsa_key");
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048));
+
+    provider
+        .create_key("test_create_rsa_key", config)
+        .expect("Failed to create RSA key");
+    assert!(provider
+        .get_pub_key()
+        .starts_with("-----BEGIN PUBLIC KEY-----"));
+}
+
+#[test]
+fn test_create_ecdsa_key() {
+    let mut provider = SoftHsm::get().provider("test_create_ecdsa_key");
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P384,
+    )));
+
+    provider
+        .create_key("test_create_ecdsa_key", config)
+        .expect("Failed to create ECDSA key");
+    assert!(provider
+        .get_pub_key()
+        .starts_with("-----BEGIN PUBLIC KEY-----"));
+}
+
+#[test]
+fn test_load_key() {
+    let algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+    let mut provider = SoftHsm::get().provider("test_load_key");
+    provider
+        .create_key("test_load_key", HsmProviderConfig::new(algorithm))
+        .expect("Failed to create RSA key");
+    let public_key = provider.get_pub_key();
+
+    let mut provider = SoftHsm::get().provider("test_load_key");
+    provider
+        .load_key("test_load_key", HsmProviderConfig::new(algorithm))
+        .expect("Failed to load RSA key");
+    assert_eq!(provider.get_pub_key(), public_key);
+}
+
+#[test]
+fn test_replace_key() {
+    let algorithm = AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256));
+    let mut provider = SoftHsm::get().provider("test_replace_key");
+    provider
+        .create_key("test_replace_key", HsmProviderConfig::new(algorithm))
+        .expect("Failed to create ECDSA key");
+    let first = provider.get_pub_key();
+    provider
+        .create_key("test_replace_key", HsmProviderConfig::new(algorithm))
+        .expect("Failed to replace ECDSA key");
+
+    let mut provider = SoftHsm::get().provider("test_replace_key");
+    provider
+        .load_key("test_replace_key", HsmProviderConfig::new(algorithm))
+        .expect("Failed to load ECDSA key");
+    assert_ne!(provider.get_pub_key(), first);
+}
+
+#[test]
+fn test_replace_key_failed_generation() {
+    let algorithm = AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256));
+    let mut provider = SoftHsm::get().provider("test_replace_key_failed_generation");
+    provider
+        .create_key(
+            "test_replace_key_failed_generation",
+            HsmProviderConfig::new(algorithm),
+        )
+        .expect("Failed to create ECDSA key");
+    let public_key = provider.get_pub_key();
+
+    // SoftHSMv2 does not generate RSA keys this small.
+    assert!(provider
+        .create_key(
+            "test_replace_key_failed_generation",
+            HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits128)),
+        )
+        .is_err());
+
+    let mut provider = SoftHsm::get().provider("test_replace_key_failed_generation");
+    provider
+        .load_key(
+            "test_replace_key_failed_generation",
+            HsmProviderConfig::new(algorithm),
+        )
+        .expect("Failed to load the previous ECDSA key");
+    assert_eq!(provider.get_pub_key(), public_key);
+}
+
+#[test]
+fn test_load_missing_key() {
+    let mut provider = SoftHsm::get().provider("test_load_missing_key");
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048));
+
+    assert!(provider.load_key("test_load_missing_key", config).is_err());
+}
+
+#[test]
+fn test_load_key_with_other_algorithm() {
+    let mut provider = SoftHsm::get().provider("test_load_key_with_other_algorithm");
+    provider
+        .create_key(
+            "test_load_key_with_other_algorithm",
+            HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048)),
+        )
+        .expect("Failed to create RSA key");
+
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P256,
+    )));
+    assert!(provider
+        .load_key("test_load_key_with_other_algorithm", config)
+        .is_err());
+}
+
+#[test]
+fn test_random_bytes() {
+    let provider = SoftHsm::get().provider("test_random_bytes");
+
+    let random = provider
+        .random_bytes(100)
+        .expect("Failed to get random bytes");
+    assert_eq!(random.len(), 100);
+    assert_ne!(random, vec![0; 100]);
+}
+
+#[test]
+fn test_capabilities() {
+    let mut provider = SoftHsm::get().provider("test_capabilities");
+
+    let capabilities = provider.capabilities().expect("Failed to get capabilities");
+    let rsa = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+    let p256 = AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256));
+    assert!(capabilities.supports_key_algorithm(rsa));
+    assert!(capabilities.supports_key_algorithm(p256));
+    assert!(capabilities
+        .key_algorithm(rsa)
+        .unwrap()
+        .operations
+        .contains(&Operation::Decrypt));
+    assert!(capabilities.supports_operation(Operation::RandomBytes));
+
+    for algorithm in [rsa, p256] {
+        let config = HsmProviderConfig::new(algorithm);
+        provider
+            .create_key("test_capabilities", Box::new(config))
+            .expect("Failed to create a reported key algorithm");
+    }
+}
+
+#[test]
+fn test_list_tokens() {
+    let softhsm = SoftHsm::get();
+
+    let tokens = Pkcs11Provider::list_tokens(softhsm.module()).expect("Failed to list tokens");
+    let token = tokens
+        .iter()
+        .find(|token| token.label == TOKEN_LABEL)
+        .expect("Test token not listed");
+
+    let mut provider = Pkcs11Provider::new(
+        "test_list_tokens".to_owned(),
+        Pkcs11Config::new(softhsm.module()).with_token(TokenSelector::Serial(token.serial.clone())),
+    );
+    provider.set_interaction_handler(Arc::new(StaticCredentials::new().with_credential(
+        CredentialKind::Pin,
+        "PKCS#11",
+        None,
+        b"1234",
+    )));
+    provider
+        .initialize_module()
+        .expect("Failed to select the token by its serial number");
+}
+
+#[test]
+fn test_wrong_pin() {
+    let config = SoftHsm::get()
+        .config()
+        .with_token(TokenSelector::Label(UNUSED_TOKEN_LABEL.to_owned()));
+    let mut provider = Pkcs11Provider::new("test_wrong_pin".to_owned(), config);
+    provider.set_interaction_handler(Arc::new(StaticCredentials::new().with_credential(
+        CredentialKind::Pin,
+        "PKCS#11",
+        None,
+        b"0000",
+    )));
+
+    assert!(matches!(
+        provider.initialize_module(),
+        Err(SecurityModuleError::Hsm(HsmError::WrongCredential {
+            kind: CredentialKind::Pin,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_unknown_module() {
+    let mut provider = Pkcs11Provider::new(
+        "test_unknown_module".to_owned(),
+        Pkcs11Config::new("/nonexistent/libpkcs11.so"),
+    );
+
+    assert!(provider.initialize_module().is_err());
+}
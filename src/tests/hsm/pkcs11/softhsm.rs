@@ -0,0 +1,111 @@
+/// # SoftHSMv2 harness for the PKCS #11 tests
+///
+/// The tests in `tests::hsm::pkcs11` that need a token use a SoftHSMv2 token initialized
+/// once per test process by [`SoftHsm::get`], so they run without a hardware HSM. The
+/// token is stored in a fresh directory below the system temp directory, selected with a
+/// generated `softhsm2.conf`, and every test uses its own key labels.
+///
+/// Requires `softhsm2-util` on the `PATH` and the SoftHSMv2 module at
+/// `/usr/lib/softhsm/libsofthsm2.so`; set `SOFTHSM2_MODULE` to use a different module.
+use crate::{
+    common::{
+        interaction::StaticCredentials,
+        traits::{interaction::CredentialKind, module_provider::Provider},
+    },
+    hsm::pkcs11::{Pkcs11Config, Pkcs11Provider, TokenSelector},
+};
+use once_cell::sync::Lazy;
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+pub const TOKEN_LABEL: &str = "crypto-layer";
+/// A second token no test logs in to, as a login holds for all sessions with a token.
+pub const UNUSED_TOKEN_LABEL: &str = "crypto-layer-unused";
+pub const PIN: &[u8] = b"1234";
+const SO_PIN: &str = "5678";
+const DEFAULT_MODULE: &str = "/usr/lib/softhsm/libsofthsm2.so";
+
+static SOFTHSM: Lazy<SoftHsm> = Lazy::new(SoftHsm::init);
+
+pub struct SoftHsm {
+    module: PathBuf,
+}
+
+impl SoftHsm {
+    /// Returns the token shared by the tests, initializing it on first use.
+    ///
+    /// Panics if the token cannot be initialized, as the calling test cannot run without it.
+    pub fn get() -> &'static Self {
+        &SOFTHSM
+    }
+
+    fn init() -> Self {
+        let dir = env::temp_dir().join(format!("crypto-layer-softhsm-{}", std::process::id()));
+        let token_dir = dir.join("tokens");
+        fs::create_dir_all(&token_dir).expect("Failed to create SoftHSM token directory");
+        let conf = dir.join("softhsm2.conf");
+        fs::write(
+            &conf,
+            format!(
+                "directories.tokendir = {}\nobjectstore.backend = file\nlog.level = ERROR\n",
+                token_dir.display()
+            ),
+        )
+        .expect("Failed to write softhsm2.conf");
+        // Read by the module when it is initialized, which happens after this.
+        env::set_var("SOFTHSM2_CONF", &conf);
+
+        for label in [TOKEN_LABEL, UNUSED_TOKEN_LABEL] {
+            let status = Command::new("softhsm2-util")
+                .args(["--init-token", "--free", "--label", label])
+                .args([
+                    "--pin",
+                    std::str::from_utf8(PIN).unwrap(),
+                    "--so-pin",
+                    SO_PIN,
+                ])
+                .stdout(Stdio::null())
+                .status()
+                .expect("Failed to start softhsm2-util, is SoftHSMv2 installed?");
+            assert!(
+                status.success(),
+                "softhsm2-util failed to initialize {}",
+                label
+            );
+        }
+
+        let module = env::var_os("SOFTHSM2_MODULE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_MODULE));
+        Self { module }
+    }
+
+    /// The path of the SoftHSMv2 module.
+    pub fn module(&self) -> &PathBuf {
+        &self.module
+    }
+
+    /// The configuration selecting the test token.
+    pub fn config(&self) -> Pkcs11Config {
+        Pkcs11Config::new(&self.module).with_token(TokenSelector::Label(TOKEN_LABEL.to_owned()))
+    }
+
+    /// Creates a `Pkcs11Provider` for the test token, logged in with the user PIN.
+    pub fn provider(&self, key_id: &str) -> Pkcs11Provider {
+        let mut provider = Pkcs11Provider::new(key_id.to_owned(), self.config());
+        provider.set_interaction_handler(Arc::new(StaticCredentials::new().with_credential(
+            CredentialKind::Pin,
+            "PKCS#11",
+            None,
+            PIN,
+        )));
+        provider
+            .initialize_module()
+            .expect("Failed to initialize module");
+        provider
+    }
+}
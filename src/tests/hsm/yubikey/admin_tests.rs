@@ -0,0 +1,184 @@
+/// # Test Cases for YubiKey administration
+///
+/// These tests change the PIN, PUK and management key of the connected YubiKey and restore
+/// the factory defaults afterwards. They assume a YubiKey with the default PIN `123456`, the
+/// default PUK `12345678` and the default management key. `test_reset_piv` deletes all PIV
+/// keys and certificates.
+///
+/// Please use **cargo test --features yubi -- --test-threads=1** for successful testing due to parallelization issues
+#[allow(unused_imports)]
+use crate::{
+    common::{traits::interaction::CredentialKind, traits::module_provider::Provider},
+    hsm::{
+        core::error::HsmError,
+        yubikey::{
+            admin::{ManagementKey, ManagementKeyAlgorithm, ManagementKeyProtection},
+            YubiKeyProvider,
+        },
+    },
+};
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_retry_counters() {
+    let mut provider = YubiKeyProvider::new("test_retry_counters".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let counters = provider
+        .retry_counters()
+        .expect("Failed to read retry counters");
+    assert!(counters.pin > 0);
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_change_pin() {
+    let mut provider = YubiKeyProvider::new("test_change_pin".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider
+        .change_pin(b"123456", b"654321")
+        .expect("Failed to change PIN");
+    provider
+        .change_pin(b"654321", b"123456")
+        .expect("Failed to restore PIN");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_change_pin_wrong_pin() {
+    let mut provider = YubiKeyProvider::new("test_change_pin_wrong_pin".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let result = provider.change_pin(b"000000", b"654321");
+    assert!(matches!(
+        result,
+        Err(HsmError::WrongCredential {
+            kind: CredentialKind::Pin,
+            retries_left: Some(_),
+        })
+    ));
+
+    // Verifying the correct PIN restores the retry counter.
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_set_pin_protected_aes_management_key() {
+    let mut provider = YubiKeyProvider::new("test_set_management_key".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let key = ManagementKey::generate(ManagementKeyAlgorithm::Aes192)
+        .expect("Failed to generate management key");
+    provider
+        .set_management_key(key, ManagementKeyProtection::PinProtected, false)
+        .expect("Failed to set management key");
+
+    // The PIN-protected key is picked up without asking for it.
+    let mut provider = YubiKeyProvider::new("test_set_management_key".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module with the protected management key");
+
+    provider
+        .set_management_key(
+            ManagementKey::default(),
+            ManagementKeyProtection::None,
+            false,
+        )
+        .expect("Failed to restore management key");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_change_puk() {
+    let mut provider = YubiKeyProvider::new("test_change_puk".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider
+        .change_puk(b"12345678", b"87654321")
+        .expect("Failed to change PUK");
+    assert!(matches!(
+        provider.change_puk(b"12345678", b"87654321"),
+        Err(HsmError::WrongCredential {
+            kind: CredentialKind::Puk,
+            ..
+        })
+    ));
+    provider
+        .change_puk(b"87654321", b"12345678")
+        .expect("Failed to restore PUK");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_unblock_pin() {
+    let mut provider = YubiKeyProvider::new("test_unblock_pin".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    while let Err(HsmError::WrongCredential { .. }) = provider.change_pin(b"000000", b"654321") {}
+    assert_eq!(
+        provider
+            .retry_counters()
+            .expect("Failed to read retry counters")
+            .pin,
+        0
+    );
+
+    provider
+        .unblock_pin(b"12345678", b"123456")
+        .expect("Failed to unblock PIN");
+    assert!(
+        provider
+            .retry_counters()
+            .expect("Failed to read retry counters")
+            .pin
+            > 0
+    );
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module with the new PIN");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_reset_piv() {
+    let mut provider = YubiKeyProvider::new("test_reset_piv".to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider.reset_piv().expect("Failed to reset PIV");
+    assert_eq!(
+        provider
+            .retry_counters()
+            .expect("Failed to read retry counters")
+            .pin,
+        3
+    );
+
+    // The provider authenticates with the default management key of the firmware.
+    let key = ManagementKey::generate(ManagementKeyAlgorithm::Aes128)
+        .expect("Failed to generate management key");
+    provider
+        .set_management_key(key, ManagementKeyProtection::None, false)
+        .expect("Failed to authenticate after the reset");
+    provider
+        .reset_piv()
+        .expect("Failed to restore factory defaults");
+}
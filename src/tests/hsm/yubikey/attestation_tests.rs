@@ -0,0 +1,249 @@
+/// # Test Cases for YubiKey attestation
+///
+/// The verifier tests build a root, an intermediate and an attestation certificate with the
+/// YubiKey extensions in software, so they do not need a YubiKey. `test_attest_key` creates
+/// a key on a connected YubiKey and checks its attestation against the device's own
+/// intermediate.
+///
+/// Please use **cargo test --features yubi -- --test-threads=1** for successful testing due to parallelization issues
+#[allow(unused_imports)]
+use crate::{
+    common::{
+        crypto::algorithms::encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+        traits::module_provider::Provider,
+    },
+    hsm::{
+        yubikey::{
+            attestation::{AttestationVerifier, KeyAttestation},
+            YubiKeyProvider,
+        },
+        HsmProviderConfig,
+    },
+};
+#[allow(unused_imports)]
+use openssl::{
+    asn1::{Asn1Object, Asn1OctetString, Asn1Time},
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{X509Extension, X509Name, X509},
+};
+#[allow(unused_imports)]
+use yubikey::{PinPolicy, Serial, TouchPolicy, Version};
+
+#[cfg(feature = "yubi")]
+fn key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+#[cfg(feature = "yubi")]
+fn certificate(
+    subject: &str,
+    issuer: &str,
+    key: &PKey<Private>,
+    issuer_key: &PKey<Private>,
+    extensions: &[(&str, &[u8])],
+) -> X509 {
+    let name = |cn| {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        name.build()
+    };
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name(subject)).unwrap();
+    builder.set_issuer_name(&name(issuer)).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    for (oid, value) in extensions {
+        let extension = X509Extension::new_from_der(
+            &Asn1Object::from_str(oid).unwrap(),
+            false,
+            &Asn1OctetString::new_from_bytes(value).unwrap(),
+        )
+        .unwrap();
+        builder.append_extension(extension).unwrap();
+    }
+    builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+/// Returns the PEM encoded root and an attestation chained to it.
+#[cfg(feature = "yubi")]
+fn attestation() -> (Vec<u8>, KeyAttestation, PKey<Private>) {
+    let root_key = key();
+    let intermediate_key = key();
+    let attested_key = key();
+
+    let root = certificate(
+        "Test PIV Root CA",
+        "Test PIV Root CA",
+        &root_key,
+        &root_key,
+        &[],
+    );
+    let intermediate = certificate(
+        "Test PIV Attestation",
+        "Test PIV Root CA",
+        &intermediate_key,
+        &root_key,
+        &[],
+    );
+    let certificate = certificate(
+        "YubiKey PIV Attestation 9a",
+        "Test PIV Attestation",
+        &attested_key,
+        &intermediate_key,
+        &[
+            ("1.3.6.1.4.1.41482.3.3", &[5, 4, 3]),
+            // DER INTEGER 12345678
+            (
+                "1.3.6.1.4.1.41482.3.7",
+                &[0x02, 0x04, 0x00, 0xbc, 0x61, 0x4e],
+            ),
+            ("1.3.6.1.4.1.41482.3.8", &[2, 3]),
+        ],
+    );
+
+    let attestation = KeyAttestation {
+        certificate: certificate.to_der().unwrap(),
+        intermediate: intermediate.to_der().unwrap(),
+    };
+    (root.to_pem().unwrap(), attestation, attested_key)
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_verify_attestation() {
+    let (root, attestation, attested_key) = attestation();
+    let verifier = AttestationVerifier::new(&root).expect("Failed to load root");
+
+    let info = verifier
+        .verify(&attestation)
+        .expect("Failed to verify attestation");
+
+    assert_eq!(info.serial, Serial(12345678));
+    assert_eq!(
+        info.firmware_version,
+        Version {
+            major: 5,
+            minor: 4,
+            patch: 3
+        }
+    );
+    assert_eq!(info.pin_policy, PinPolicy::Once);
+    assert_eq!(info.touch_policy, TouchPolicy::Cached);
+    assert_eq!(info.public_key, attested_key.public_key_to_der().unwrap());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_verify_attestation_with_intermediates() {
+    let root_key = key();
+    let ca_key = key();
+    let sub_ca_key = key();
+    let device_key = key();
+    let root = certificate("Test Root CA", "Test Root CA", &root_key, &root_key, &[]);
+    let ca = certificate("Test CA", "Test Root CA", &ca_key, &root_key, &[]);
+    let sub_ca = certificate("Test Sub CA", "Test CA", &sub_ca_key, &ca_key, &[]);
+    let device = certificate(
+        "Test PIV Attestation",
+        "Test Sub CA",
+        &device_key,
+        &sub_ca_key,
+        &[],
+    );
+    let certificate = certificate(
+        "YubiKey PIV Attestation 9a",
+        "Test PIV Attestation",
+        &key(),
+        &device_key,
+        &[
+            ("1.3.6.1.4.1.41482.3.3", &[5, 7, 4]),
+            ("1.3.6.1.4.1.41482.3.7", &[0x02, 0x02, 0x30, 0x39]),
+            ("1.3.6.1.4.1.41482.3.8", &[1, 1]),
+        ],
+    );
+    let attestation = KeyAttestation {
+        certificate: certificate.to_der().unwrap(),
+        intermediate: device.to_der().unwrap(),
+    };
+    let root = root.to_pem().unwrap();
+
+    // The device certificate is not issued by the root directly.
+    let verifier = AttestationVerifier::new(&root).expect("Failed to load root");
+    assert!(verifier.verify(&attestation).is_err());
+    let verifier = verifier
+        .with_intermediates(&sub_ca.to_pem().unwrap())
+        .expect("Failed to load intermediates");
+    assert!(verifier.verify(&attestation).is_err());
+
+    let verifier = verifier
+        .with_intermediates(&ca.to_pem().unwrap())
+        .expect("Failed to load intermediates");
+    let info = verifier
+        .verify(&attestation)
+        .expect("Failed to verify attestation");
+    assert_eq!(info.serial, Serial(12345));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_verify_attestation_untrusted_root() {
+    let (other_root, _, _) = attestation();
+    let (_, attestation, _) = attestation();
+    let verifier = AttestationVerifier::new(&other_root).expect("Failed to load root");
+
+    assert!(verifier.verify(&attestation).is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_verify_attestation_swapped_chain() {
+    let (root, attestation, _) = attestation();
+    let verifier = AttestationVerifier::new(&root).expect("Failed to load root");
+    let swapped = KeyAttestation {
+        certificate: attestation.intermediate.clone(),
+        intermediate: attestation.certificate.clone(),
+    };
+
+    assert!(verifier.verify(&swapped).is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_attest_key() {
+    let key_id = "test_attest_key";
+    let mut provider = YubiKeyProvider::new(key_id.to_string());
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P256,
+    )));
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(key_id, config)
+        .expect("Failed to create ECC key");
+
+    let attestation = provider.attest_key().expect("Failed to attest key");
+
+    // The device intermediate is signed by Yubico; trust it directly to check the leaf.
+    let intermediate = X509::from_der(&attestation.intermediate).unwrap();
+    let issuer = X509::from_der(&attestation.certificate)
+        .unwrap()
+        .issuer_name()
+        .try_cmp(intermediate.subject_name())
+        .unwrap();
+    assert!(issuer.is_eq());
+}
@@ -0,0 +1,99 @@
+/// # Test Cases for selecting among several YubiKeys
+///
+/// `test_hsm_type_from_str`, `test_form_factor` and `test_unknown_serial` do not need a
+/// YubiKey. `test_list_devices` opens every connected YubiKey by its serial number and
+/// assumes the default PIN and management key on each. `test_watch_devices` needs a running
+/// PC/SC service.
+///
+/// Please use **cargo test --features yubi -- --test-threads=1** for successful testing due to parallelization issues
+#[allow(unused_imports)]
+use crate::{
+    common::{
+        error::FactoryError,
+        traits::{
+            device_events::{DeviceEvent, DeviceEventHandler},
+            module_provider::Provider,
+        },
+    },
+    hsm::{
+        core::instance::HsmType,
+        yubikey::{device::FormFactor, YubiKeyProvider},
+    },
+};
+#[allow(unused_imports)]
+use std::sync::Arc;
+#[allow(unused_imports)]
+use yubikey::Serial;
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_hsm_type_from_str() {
+    assert_eq!("YubiKey".parse(), Ok(HsmType::YubiKey(None)));
+    assert_eq!(
+        HsmType::try_from("YubiKey:12345678"),
+        Ok(HsmType::YubiKey(Some(12345678)))
+    );
+    assert_ne!(
+        "YubiKey:1".parse::<HsmType>(),
+        "YubiKey:2".parse::<HsmType>()
+    );
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_hsm_type_invalid_serial() {
+    assert!(matches!(
+        "YubiKey:serial".parse::<HsmType>(),
+        Err(FactoryError::InvalidName { .. })
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_form_factor() {
+    assert_eq!(FormFactor::from(0x03), FormFactor::UsbCKeychain);
+    // The FIPS flag does not change the form factor.
+    assert_eq!(FormFactor::from(0x21), FormFactor::UsbAKeychain);
+    assert_eq!(FormFactor::from(0x00), FormFactor::Unknown);
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_unknown_serial() {
+    let mut provider = YubiKeyProvider::with_serial("test_unknown_serial".to_string(), Serial(0));
+
+    assert_eq!(provider.serial(), Some(Serial(0)));
+    assert!(provider.initialize_module().is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_list_devices() {
+    let devices = YubiKeyProvider::list_devices().expect("Failed to list YubiKeys");
+    assert!(!devices.is_empty());
+
+    for device in devices {
+        let mut provider =
+            YubiKeyProvider::with_serial("test_list_devices".to_string(), device.serial);
+        provider
+            .initialize_module()
+            .expect("Failed to open YubiKey by serial");
+    }
+}
+
+#[cfg(feature = "yubi")]
+#[derive(Debug)]
+struct IgnoreEvents;
+
+#[cfg(feature = "yubi")]
+impl DeviceEventHandler for IgnoreEvents {
+    fn device_event(&self, _event: &DeviceEvent) {}
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_watch_devices() {
+    let watcher =
+        YubiKeyProvider::watch_devices(Arc::new(IgnoreEvents)).expect("Failed to watch devices");
+    drop(watcher);
+}
@@ -11,6 +11,10 @@
 /// - `test_sign_and_verify_rsa_2048`: Tests signing and verifying data with a 2048-bit RSA key.
 /// - `test_sign_and_verify_ecc_256`: Tests signing and verifying data with a 256-bit ECC key.
 /// - `test_sign_and_verify_ecc_384`: Tests signing and verifying data with a 384-bit ECC key.
+/// - `test_sign_and_verify_rsa_pss`: Tests PSS signatures with SHA-384 and a 2048-bit RSA key.
+/// - `test_sign_and_verify_ecc_256_sha512`: Tests a digest longer than the curve order.
+/// - `test_pss_encoding`, `test_pkcs1_encoding`: Check the signature encodings against
+///   OpenSSL with software keys and do not need a YubiKey.
 ///
 /// ## Test Procedures
 ///
@@ -60,7 +64,21 @@ use crate::common::{
 };
 
 // Import YubiKeyProvider and HsmProviderConfig for HSM operations
-use crate::hsm::{yubikey::YubiKeyProvider, HsmProviderConfig};
+#[allow(unused_imports)]
+use crate::common::crypto::algorithms::hashes::Sha2Bits;
+#[cfg(feature = "yubi")]
+use crate::hsm::yubikey::key_handle::{
+    apply_pkcs1v15_padding, create_digest_info, emsa_pss_encode, message_digest,
+};
+#[allow(unused_imports)]
+use crate::hsm::{yubikey::YubiKeyProvider, HsmProviderConfig, RsaPadding};
+#[allow(unused_imports)]
+use openssl::{
+    hash::hash,
+    pkey::PKey,
+    rsa::{Padding, Rsa},
+    sign::{RsaPssSaltlen, Verifier},
+};
 // The following tests cover different cryptographic scenarios, ensuring the robustness and
 // compatibility of the system across various configurations and key sizes.
 
@@ -72,9 +90,7 @@ fn test_sign_and_verify_rsa_1024() {
     // omitted for brevity; please refer to the individual test implementations
     let mut provider = YubiKeyProvider::new("test_sv_1024".to_string());
 
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits1024),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits1024));
 
     provider
         .initialize_module()
@@ -95,9 +111,7 @@ fn test_sign_and_verify_rsa_1024() {
 fn test_sign_and_verify_rsa_2048() {
     let mut provider = YubiKeyProvider::new("test_sv_2048".to_string());
 
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits2048),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048));
 
     provider
         .initialize_module()
@@ -118,9 +132,9 @@ fn test_sign_and_verify_rsa_2048() {
 fn test_sign_and_verify_ecc_256() {
     let mut provider = YubiKeyProvider::new("test_ecc_256".to_string());
 
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P256,
+    )));
 
     provider
         .initialize_module()
@@ -135,15 +149,125 @@ fn test_sign_and_verify_ecc_256() {
     assert!(provider.verify_signature(data, &signature).unwrap());
 }
 
+// Test for PSS signatures with a hash other than the default
+#[cfg(feature = "yubi")]
+#[test]
+fn test_sign_and_verify_rsa_pss() {
+    let mut provider = YubiKeyProvider::new("test_pss_2048".to_string());
+
+    let config = Box::new(HsmProviderConfig {
+        key_algorithm: AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+        hash: Some(Hash::Sha2(Sha2Bits::Sha384)),
+        rsa_padding: RsaPadding::Pss,
+        ..Default::default()
+    });
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key("test_rsa_pss_key", config)
+        .expect("Failed to create RSA key");
+
+    let data = b"Hello, World!";
+    let signature = provider.sign_data(data).expect("Failed to sign data");
+
+    assert!(provider.verify_signature(data, &signature).unwrap());
+}
+
+// Test for a digest that is longer than the order of the curve
+#[cfg(feature = "yubi")]
+#[test]
+fn test_sign_and_verify_ecc_256_sha512() {
+    let mut provider = YubiKeyProvider::new("test_ecc_256_sha512".to_string());
+
+    let config = Box::new(HsmProviderConfig {
+        key_algorithm: AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+        hash: Some(Hash::Sha2(Sha2Bits::Sha512)),
+        ..Default::default()
+    });
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key("test_ecc_key_sha512", config)
+        .expect("Failed to create ECC key");
+
+    let data = b"Hello, World!";
+    let signature = provider.sign_data(data).expect("Failed to sign data");
+
+    assert!(provider.verify_signature(data, &signature).unwrap());
+}
+
+/// Signs `encoded` with the raw RSA operation the YubiKey performs.
+#[cfg(feature = "yubi")]
+fn raw_sign(rsa: &Rsa<openssl::pkey::Private>, encoded: &[u8]) -> Vec<u8> {
+    let mut signature = vec![0; rsa.size() as usize];
+    rsa.private_encrypt(encoded, &mut signature, Padding::NONE)
+        .unwrap();
+    signature
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_pss_encoding() {
+    let rsa = Rsa::generate(2048).unwrap();
+    let md = message_digest(Hash::Sha2(Sha2Bits::Sha384)).unwrap();
+    let data = b"Hello, World!";
+
+    let encoded = emsa_pss_encode(&hash(md, data).unwrap(), md, 256).unwrap();
+    let signature = raw_sign(&rsa, &encoded);
+
+    let key = PKey::from_rsa(rsa).unwrap();
+    let mut verifier = Verifier::new(md, &key).unwrap();
+    verifier.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+    verifier
+        .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+        .unwrap();
+    verifier.set_rsa_mgf1_md(md).unwrap();
+    assert!(verifier.verify_oneshot(&signature, data).unwrap());
+
+    // A 1024-bit modulus is too short for two SHA-512 digests.
+    let md = message_digest(Hash::Sha2(Sha2Bits::Sha512)).unwrap();
+    assert!(emsa_pss_encode(&hash(md, data).unwrap(), md, 128).is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_pkcs1_encoding() {
+    let rsa = Rsa::generate(1024).unwrap();
+    let data = b"Hello, World!";
+
+    for hash_algorithm in [
+        Hash::Sha1,
+        Hash::Sha2(Sha2Bits::Sha384),
+        Hash::Sha2(Sha2Bits::Sha512),
+    ] {
+        let md = message_digest(hash_algorithm).unwrap();
+        let digest_info = create_digest_info(&hash(md, data).unwrap(), hash_algorithm).unwrap();
+        let signature = raw_sign(&rsa, &apply_pkcs1v15_padding(&digest_info, 128).unwrap());
+
+        let key = PKey::from_rsa(rsa.clone()).unwrap();
+        let mut verifier = Verifier::new(md, &key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, data).unwrap());
+    }
+
+    assert!(message_digest(Hash::Md5).is_err());
+    // A SHA-512 DigestInfo does not fit into a 64 byte block with enough padding.
+    assert!(apply_pkcs1v15_padding(&[0; 83], 64).is_err());
+    assert!(create_digest_info(&[0; 16], Hash::Md5).is_err());
+}
+
 // Test for signing and verifying ECC data with a 384-bit key
 #[cfg(feature = "yubi")]
 #[test]
 fn test_sign_and_verify_ecc_384() {
     let mut provider = YubiKeyProvider::new("test_ecc_384".to_string());
 
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+        EccCurves::P384,
+    )));
 
     provider
         .initialize_module()
@@ -162,9 +286,7 @@ fn test_sign_and_verify_ecc_384() {
 #[test]
 fn test_encrypt_and_decrypt_rsa_1024() {
     let mut provider = YubiKeyProvider::new("test_enc_dec_1024".to_string());
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits1024),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits1024));
 
     provider
         .initialize_module()
@@ -186,9 +308,7 @@ fn test_encrypt_and_decrypt_rsa_1024() {
 #[test]
 fn test_encrypt_and_decrypt_rsa_2048() {
     let mut provider = YubiKeyProvider::new("test_enc_dec_2048".to_string());
-    let config = HsmProviderConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits2048),
-    );
+    let config = HsmProviderConfig::new(AsymmetricEncryption::Rsa(KeyBits::Bits2048));
 
     provider
         .initialize_module()
@@ -0,0 +1,81 @@
+#[cfg(feature = "yubi")]
+use crate::hsm::yubikey::metadata::KeyMetadata;
+/// # Test Cases for the YubiKey key metadata format
+///
+/// These tests encode and decode key metadata records in software and do not need a YubiKey.
+#[allow(unused_imports)]
+use crate::{
+    common::crypto::{
+        algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha3Bits},
+            KeyBits,
+        },
+        KeyUsage,
+    },
+    hsm::{HsmProviderConfig, PinPolicy, RsaPadding, TouchPolicy},
+};
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_metadata_round_trip() {
+    let config = HsmProviderConfig {
+        key_algorithm: AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::P384)),
+        key_usages: vec![KeyUsage::SignEncrypt, KeyUsage::Decrypt],
+        hash: Some(Hash::Sha3(Sha3Bits::Sha3_384)),
+        rsa_padding: RsaPadding::Pss,
+        pin_policy: PinPolicy::Always,
+        touch_policy: TouchPolicy::Cached,
+        ..Default::default()
+    };
+    let mut metadata = KeyMetadata::new(
+        "key\0with\u{e4}nusual id",
+        0x82,
+        &config,
+        "-----BEGIN PUBLIC KEY-----\n".to_owned() + &"A".repeat(400),
+    );
+    metadata.certificate = Some(0x005f_c10d);
+
+    let encoded = metadata.encode().expect("Failed to encode metadata");
+    let decoded = KeyMetadata::decode(&encoded).expect("Failed to decode metadata");
+
+    assert!(metadata.created.is_some());
+    assert_eq!(decoded, Some(metadata.clone()));
+
+    // Records of later versions are read, skipping the tags added since.
+    let mut newer = encoded;
+    newer[4] = 2;
+    newer.extend(b"\x7f\x03new");
+    let decoded = KeyMetadata::decode(&newer).expect("Failed to decode newer metadata");
+    assert_eq!(decoded, Some(metadata));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_metadata_legacy() {
+    let legacy = b"legacy_key\x006275341\x00-----BEGIN PUBLIC KEY-----\x00Rsa2048";
+
+    assert_eq!(KeyMetadata::decode(legacy).unwrap(), None);
+    let metadata = KeyMetadata::decode_legacy(legacy, 0x83).expect("Failed to parse legacy data");
+
+    assert_eq!(metadata.key_id, "legacy_key");
+    assert_eq!(metadata.slot, 0x83);
+    assert_eq!(
+        metadata.algorithm,
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048)
+    );
+    assert_eq!(metadata.created, None);
+    assert_eq!(metadata.hash, None);
+    assert_eq!(metadata.pin_policy, PinPolicy::Default);
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_metadata_malformed() {
+    assert_eq!(KeyMetadata::decode(&[]).unwrap(), None);
+    assert!(KeyMetadata::decode(b"CLKM").is_err());
+    assert!(KeyMetadata::decode(b"CLKM\x01\x01\x05abc").is_err());
+    assert!(KeyMetadata::decode(b"CLKM\x02").is_err());
+    assert!(KeyMetadata::decode_legacy(b"\xff\xfe\x00slot\x00key\x00Rsa2048", 0x82).is_err());
+    assert!(KeyMetadata::decode_legacy(b"key\x00slot\x00key\x00Unknown", 0x82).is_err());
+}
@@ -1,2 +1,8 @@
+mod admin_tests;
+mod attestation_tests;
+mod device_tests;
 mod key_handle_tests;
-mod provider_handle_tests;
\ No newline at end of file
+mod metadata_tests;
+mod provider_handle_tests;
+mod simulator_tests;
+mod slot_tests;
@@ -0,0 +1,638 @@
+/// # Test Cases for `YubiKeyProvider` on the PIV simulator
+///
+/// These tests run the provider against `PivSimulator` instead of a connected YubiKey, so
+/// slot allocation, key metadata, padding and credential handling are tested without a
+/// device.
+#[allow(unused_imports)]
+use crate::{
+    common::{
+        capabilities::Operation,
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits},
+            KeyBits,
+        },
+        crypto::KeyUsage,
+        error::SecurityModuleError,
+        interaction::StaticCredentials,
+        traits::{
+            interaction::{
+                CredentialKind, CredentialRequest, InteractionEvent, InteractionHandler,
+            },
+            key_handle::KeyHandle,
+            module_provider::Provider,
+        },
+    },
+    hsm::{
+        core::error::HsmError,
+        yubikey::{
+            admin::{ManagementKey, ManagementKeyAlgorithm},
+            metadata::KeyMetadata,
+            simulator::PivSimulator,
+            transport::PivTransport,
+            YubiKeyProvider,
+        },
+        HsmProviderConfig, KeySlot, PinPolicy, RsaPadding, SlotRole, TouchPolicy,
+    },
+};
+#[allow(unused_imports)]
+use openssl::{
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::PKey,
+    rsa::{Padding, Rsa},
+    sign::{RsaPssSaltlen, Verifier},
+};
+#[allow(unused_imports)]
+use std::sync::{Arc, Mutex};
+#[allow(unused_imports)]
+use test_case::test_case;
+#[allow(unused_imports)]
+use yubikey::piv::{AlgorithmId, RetiredSlotId, SlotId};
+
+const SERIAL: u32 = 12345678;
+
+#[cfg(feature = "yubi")]
+fn config(key_algorithm: AsymmetricEncryption, slot: KeySlot) -> HsmProviderConfig {
+    HsmProviderConfig {
+        key_algorithm,
+        slot,
+        ..Default::default()
+    }
+}
+
+#[cfg(feature = "yubi")]
+fn p256() -> AsymmetricEncryption {
+    AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256))
+}
+
+/// Creates a key with `config` on `simulator` through a new provider.
+#[cfg(feature = "yubi")]
+fn create_key(
+    simulator: &PivSimulator,
+    key_id: &str,
+    config: HsmProviderConfig,
+) -> YubiKeyProvider {
+    let mut provider = YubiKeyProvider::with_simulator(key_id.to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(key_id, Box::new(config))
+        .expect("Failed to create key");
+    provider
+}
+
+/// Records the events sent by the provider.
+#[derive(Debug, Default)]
+struct RecordingHandler {
+    events: Mutex<Vec<InteractionEvent>>,
+}
+
+impl InteractionHandler for RecordingHandler {
+    fn request_credential(
+        &self,
+        request: &CredentialRequest,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        match request.kind {
+            CredentialKind::Pin => Ok(b"123456".to_vec()),
+            _ => Ok(ManagementKey::default().as_bytes().to_vec()),
+        }
+    }
+
+    fn notify(&self, event: &InteractionEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_keys_use_free_retired_slots() {
+    let simulator = PivSimulator::new(SERIAL);
+    let imported = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap())
+        .and_then(PKey::from_ec_key)
+        .unwrap();
+    simulator
+        .import_key(
+            SlotId::Retired(RetiredSlotId::R2),
+            imported,
+            yubikey::PinPolicy::Default,
+            yubikey::TouchPolicy::Default,
+        )
+        .unwrap();
+
+    create_key(&simulator, "first", config(p256(), KeySlot::Any));
+    create_key(&simulator, "second", config(p256(), KeySlot::Any));
+
+    // R2 holds a key of another application and is skipped.
+    assert!(simulator.has_key(SlotId::Retired(RetiredSlotId::R1)));
+    assert!(simulator.has_key(SlotId::Retired(RetiredSlotId::R3)));
+    assert!(!simulator.has_key(SlotId::Retired(RetiredSlotId::R4)));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_requested_slot_is_not_overwritten() {
+    let simulator = PivSimulator::new(SERIAL);
+    let mut first = create_key(
+        &simulator,
+        "first",
+        config(p256(), KeySlot::Role(SlotRole::Signature)),
+    );
+
+    let mut provider = YubiKeyProvider::with_simulator("second".to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    let second = config(p256(), KeySlot::Role(SlotRole::Signature));
+    assert!(provider
+        .create_key("second", Box::new(second.clone()))
+        .is_err());
+    let mut loaded = YubiKeyProvider::with_simulator("first".to_owned(), simulator.clone());
+    loaded
+        .initialize_module()
+        .expect("Failed to initialize module");
+    loaded
+        .load_key("first", Box::new(config(p256(), KeySlot::Any)))
+        .expect("Failed to load key");
+    assert_eq!(loaded.get_pub_key(), first.get_pub_key());
+
+    provider
+        .create_key(
+            "second",
+            Box::new(HsmProviderConfig {
+                overwrite: true,
+                ..second
+            }),
+        )
+        .expect("Failed to overwrite key");
+    assert_ne!(provider.get_pub_key(), first.get_pub_key());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_create_key_without_initialization() {
+    let mut provider = YubiKeyProvider::with_simulator("key".to_owned(), PivSimulator::new(SERIAL));
+
+    assert!(matches!(
+        provider.create_key("key", Box::new(config(p256(), KeySlot::Any))),
+        Err(SecurityModuleError::InitializationError(_))
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_create_existing_key_replaces_it() {
+    let simulator = PivSimulator::new(SERIAL);
+    let mut first = create_key(&simulator, "key", config(p256(), KeySlot::Any));
+    let mut second = create_key(&simulator, "key", config(p256(), KeySlot::Any));
+
+    assert!(!simulator.has_key(SlotId::Retired(RetiredSlotId::R2)));
+    assert_ne!(first.get_pub_key(), second.get_pub_key());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_load_key_from_metadata() {
+    let simulator = PivSimulator::new(SERIAL);
+    let created = create_key(
+        &simulator,
+        "signing",
+        HsmProviderConfig {
+            hash: Some(Hash::Sha2(Sha2Bits::Sha384)),
+            ..config(p256(), KeySlot::Role(SlotRole::KeyManagement))
+        },
+    );
+
+    let mut provider = YubiKeyProvider::with_simulator("signing".to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .load_key("signing", Box::new(config(p256(), KeySlot::Any)))
+        .expect("Failed to load key");
+
+    // The hash is taken from the metadata.
+    let signature = provider.sign_data(b"data").expect("Failed to sign data");
+    assert!(created.verify_signature(b"data", &signature).unwrap());
+    let key = PKey::public_key_from_pem(provider.get_pub_key().as_bytes()).unwrap();
+    let mut verifier = Verifier::new(MessageDigest::sha384(), &key).unwrap();
+    assert!(verifier.verify_oneshot(&signature, b"data").unwrap());
+
+    let other = config(AsymmetricEncryption::Rsa(KeyBits::Bits2048), KeySlot::Any);
+    assert!(provider.load_key("signing", Box::new(other)).is_err());
+    assert!(provider
+        .load_key("missing", Box::new(config(p256(), KeySlot::Any)))
+        .is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_load_legacy_key_object() {
+    let simulator = PivSimulator::new(SERIAL);
+    let key = Rsa::generate(1024).and_then(PKey::from_rsa).unwrap();
+    let public_key = String::from_utf8(key.public_key_to_pem().unwrap()).unwrap();
+    simulator
+        .import_key(
+            SlotId::Retired(RetiredSlotId::R1),
+            key,
+            yubikey::PinPolicy::Default,
+            yubikey::TouchPolicy::Default,
+        )
+        .unwrap();
+    // Earlier versions stored the object of R1 in the certificate object of R11.
+    let legacy = format!("legacy\0{}\0{}\0Rsa1024", 0x005f_c10d, public_key);
+    simulator.put_object(0x005f_c117, legacy.as_bytes());
+
+    let mut provider = YubiKeyProvider::with_simulator("legacy".to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    let algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits1024);
+    provider
+        .load_key("legacy", Box::new(config(algorithm, KeySlot::Any)))
+        .expect("Failed to load legacy key");
+
+    assert_eq!(provider.get_pub_key(), public_key);
+    let migrated = simulator
+        .object(0x005f_c282)
+        .expect("Object was not migrated");
+    let metadata = KeyMetadata::decode(&migrated).unwrap().unwrap();
+    assert_eq!(metadata.key_id, "legacy");
+    assert_eq!(simulator.object(0x005f_c117), Some(Vec::new()));
+}
+
+#[cfg(feature = "yubi")]
+#[test_case(
+    AsymmetricEncryption::Rsa(KeyBits::Bits1024),
+    RsaPadding::Pkcs1v15,
+    MessageDigest::sha256() ;
+    "RSA 1024 PKCS1"
+)]
+#[test_case(
+    AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+    RsaPadding::Pss,
+    MessageDigest::sha256() ;
+    "RSA 2048 PSS"
+)]
+#[test_case(p256(), RsaPadding::Pkcs1v15, MessageDigest::sha256() ; "ECDSA P256")]
+#[test_case(
+    AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)),
+    RsaPadding::Pkcs1v15,
+    MessageDigest::sha384() ;
+    "ECDSA P384"
+)]
+fn test_sign_data(
+    key_algorithm: AsymmetricEncryption,
+    rsa_padding: RsaPadding,
+    default_digest: MessageDigest,
+) {
+    let simulator = PivSimulator::new(SERIAL);
+    let mut provider = create_key(
+        &simulator,
+        "signing",
+        HsmProviderConfig {
+            rsa_padding,
+            ..config(key_algorithm, KeySlot::Any)
+        },
+    );
+
+    let signature = provider.sign_data(b"data").expect("Failed to sign data");
+    assert!(provider.verify_signature(b"data", &signature).unwrap());
+
+    let key = PKey::public_key_from_pem(provider.get_pub_key().as_bytes()).unwrap();
+    // Without a configured hash the one matching the key is used.
+    let mut verifier = Verifier::new(default_digest, &key).unwrap();
+    if rsa_padding == RsaPadding::Pss {
+        verifier.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+        verifier
+            .set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+            .unwrap();
+    }
+    assert!(verifier.verify_oneshot(&signature, b"data").unwrap());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_decrypt_data() {
+    let simulator = PivSimulator::new(SERIAL);
+    let algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+    let provider = create_key(&simulator, "decryption", config(algorithm, KeySlot::Any));
+
+    let encrypted = provider
+        .encrypt_data(b"secret")
+        .expect("Failed to encrypt data");
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt data");
+
+    assert_eq!(decrypted, b"secret");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_operations_follow_algorithm_and_usages() {
+    let simulator = PivSimulator::new(SERIAL);
+    let ecc = create_key(&simulator, "ecc", config(p256(), KeySlot::Any));
+
+    assert!(ecc.as_signer().is_some());
+    assert!(ecc.as_verifier().is_some());
+    assert!(ecc.as_encryptor().is_none());
+    assert!(ecc.as_decryptor().is_none());
+    assert!(ecc.as_key_agreement().is_none());
+    assert!(matches!(
+        ecc.decrypt_data(b"data"),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+
+    let algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+    let rsa = create_key(&simulator, "rsa", config(algorithm, KeySlot::Any));
+    assert!(rsa.as_signer().is_some());
+    assert!(rsa.as_decryptor().is_some());
+
+    // The usages are stored with the key and apply after loading it again.
+    let signing_only = HsmProviderConfig {
+        key_usages: vec![KeyUsage::SignEncrypt],
+        ..config(algorithm, KeySlot::Any)
+    };
+    create_key(&simulator, "signing only", signing_only);
+    let mut provider =
+        YubiKeyProvider::with_simulator("signing only".to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .load_key("signing only", Box::new(config(algorithm, KeySlot::Any)))
+        .expect("Failed to load key");
+
+    let signature = provider
+        .as_signer()
+        .expect("Key cannot sign")
+        .sign(b"data")
+        .expect("Failed to sign data");
+    assert!(provider.verify_signature(b"data", &signature).unwrap());
+    assert!(provider.as_encryptor().is_none());
+    assert!(matches!(
+        provider.encrypt_data(b"data"),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_wrong_pin_counts_down() {
+    let simulator = PivSimulator::new(SERIAL);
+    let credentials =
+        StaticCredentials::new().with_credential(CredentialKind::Pin, "YubiKey", None, b"000000");
+    let mut provider = YubiKeyProvider::with_simulator("pin".to_owned(), simulator.clone());
+    provider.set_interaction_handler(Arc::new(credentials));
+
+    for retries_left in [2, 1] {
+        let result = provider.initialize_module();
+        assert!(matches!(
+            result,
+            Err(SecurityModuleError::Hsm(HsmError::WrongCredential {
+                kind: CredentialKind::Pin,
+                retries_left: Some(retries),
+            })) if retries == retries_left
+        ));
+        assert_eq!(simulator.pin_retries(), retries_left);
+    }
+    let result = provider.initialize_module();
+    assert!(matches!(
+        result,
+        Err(SecurityModuleError::Hsm(HsmError::Blocked(
+            CredentialKind::Pin
+        )))
+    ));
+
+    // A blocked PIN stays blocked, even with the correct PIN.
+    let mut provider = YubiKeyProvider::with_simulator("pin".to_owned(), simulator);
+    assert!(matches!(
+        provider.initialize_module(),
+        Err(SecurityModuleError::Hsm(HsmError::Blocked(
+            CredentialKind::Pin
+        )))
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_correct_pin_resets_retries() {
+    let mut simulator = PivSimulator::new(SERIAL).with_pin(b"246810");
+
+    assert!(simulator.verify_pin(b"123456").is_err());
+    assert_eq!(simulator.pin_retries(), 2);
+    simulator
+        .verify_pin(b"246810")
+        .expect("Failed to verify PIN");
+    assert_eq!(simulator.pin_retries(), 3);
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_aes_management_key() {
+    let key = ManagementKey::generate(ManagementKeyAlgorithm::Aes256).unwrap();
+    let simulator = PivSimulator::new(SERIAL).with_management_key(key.clone());
+
+    let credentials = |management_key: &ManagementKey| {
+        Arc::new(
+            StaticCredentials::new()
+                .with_credential(CredentialKind::Pin, "YubiKey", None, b"123456")
+                .with_credential(
+                    CredentialKind::ManagementKey,
+                    "YubiKey",
+                    None,
+                    management_key.as_bytes(),
+                ),
+        )
+    };
+
+    let mut provider = YubiKeyProvider::with_simulator("aes".to_owned(), simulator);
+    let wrong = ManagementKey::generate(ManagementKeyAlgorithm::Aes256).unwrap();
+    provider.set_interaction_handler(credentials(&wrong));
+    assert!(matches!(
+        provider.initialize_module(),
+        Err(SecurityModuleError::Hsm(HsmError::WrongCredential {
+            kind: CredentialKind::ManagementKey,
+            ..
+        }))
+    ));
+
+    provider.set_interaction_handler(credentials(&key));
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key("aes", Box::new(config(p256(), KeySlot::Any)))
+        .expect("Failed to create key");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_signature_slot_requires_pin_per_operation() {
+    let mut simulator = PivSimulator::new(SERIAL);
+    let provider = create_key(
+        &simulator,
+        "signature",
+        config(p256(), KeySlot::Role(SlotRole::Signature)),
+    );
+
+    // The provider verifies the PIN before every signature.
+    provider.sign_data(b"first").expect("Failed to sign data");
+    provider.sign_data(b"second").expect("Failed to sign data");
+
+    let digest = [0u8; 32];
+    simulator.verify_pin(b"123456").unwrap();
+    simulator
+        .sign_data(&digest, AlgorithmId::EccP256, SlotId::Signature)
+        .expect("Failed to sign after PIN verification");
+    assert!(matches!(
+        simulator.sign_data(&digest, AlgorithmId::EccP256, SlotId::Signature),
+        Err(yubikey::Error::AuthenticationError)
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_reconnect_after_reset() {
+    let simulator = PivSimulator::new(SERIAL);
+    let provider = create_key(&simulator, "reset", config(p256(), KeySlot::Any));
+
+    simulator.reset_connection();
+
+    let signature = provider
+        .sign_data(b"data")
+        .expect("Failed to sign after reset");
+    assert!(provider.verify_signature(b"data", &signature).unwrap());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_unlock_after_security_status_reset() {
+    let simulator = PivSimulator::new(SERIAL);
+    let algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+    let mut provider = create_key(&simulator, "decryption", config(algorithm, KeySlot::Any));
+    let encrypted = provider
+        .encrypt_data(b"secret")
+        .expect("Failed to encrypt data");
+
+    simulator.reset_security_status();
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt after the security status was reset");
+    assert_eq!(decrypted, b"secret");
+
+    simulator.reset_security_status();
+    provider
+        .create_key("other", Box::new(config(algorithm, KeySlot::Any)))
+        .expect("Failed to create key after the security status was reset");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_touch_events() {
+    let simulator = PivSimulator::new(SERIAL);
+    let handler = Arc::new(RecordingHandler::default());
+    let mut provider = YubiKeyProvider::with_simulator("touch".to_owned(), simulator);
+    provider.set_interaction_handler(handler.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "touch",
+            Box::new(HsmProviderConfig {
+                touch_policy: TouchPolicy::Always,
+                ..config(p256(), KeySlot::Any)
+            }),
+        )
+        .expect("Failed to create key");
+
+    provider.sign_data(b"data").expect("Failed to sign data");
+
+    let module = "YubiKey".to_owned();
+    assert_eq!(
+        *handler.events.lock().unwrap(),
+        vec![
+            InteractionEvent::TouchRequired {
+                module: module.clone()
+            },
+            InteractionEvent::Completed { module }
+        ]
+    );
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_random_bytes() {
+    let mut provider =
+        YubiKeyProvider::with_simulator("random".to_owned(), PivSimulator::new(SERIAL));
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    assert!(matches!(
+        provider.random_bytes(20),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_capabilities() {
+    let simulator = PivSimulator::new(SERIAL);
+    let mut provider =
+        YubiKeyProvider::with_simulator("capabilities".to_owned(), simulator.clone());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let capabilities = provider.capabilities().expect("Failed to get capabilities");
+
+    assert_eq!(capabilities.key_algorithms.len(), 4);
+    assert!(!capabilities.supports_key_algorithm(AsymmetricEncryption::Rsa(KeyBits::Bits4096)));
+    assert!(
+        !capabilities.supports_key_algorithm(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(
+            EccCurves::P521
+        )))
+    );
+    assert!(capabilities.supports_hash(Hash::Sha2(Sha2Bits::Sha384)));
+    assert!(!capabilities.supports_operation(Operation::RandomBytes));
+    assert!(!capabilities.supports_operation(Operation::Mac));
+    assert_eq!(capabilities.max_keys, Some(24));
+    let p256 = capabilities.key_algorithm(p256()).unwrap();
+    assert!(!p256.operations.contains(&Operation::Decrypt));
+
+    // Every reported algorithm can be used to create a key.
+    for (index, key_algorithm) in capabilities.key_algorithms.iter().enumerate() {
+        let key_id = format!("capabilities {}", index);
+        let provider = create_key(
+            &simulator,
+            &key_id,
+            config(key_algorithm.algorithm, KeySlot::Any),
+        );
+        assert!(!provider.sign_data(b"data").unwrap().is_empty());
+    }
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_device_only_features() {
+    let simulator = PivSimulator::new(SERIAL);
+    let mut provider = create_key(&simulator, "device", config(p256(), KeySlot::Any));
+
+    assert!(matches!(
+        provider.attest_key(),
+        Err(HsmError::UnsupportedFeature(_))
+    ));
+    assert!(matches!(
+        provider.change_pin(b"123456", b"654321"),
+        Err(HsmError::UnsupportedFeature(_))
+    ));
+    let counters = provider
+        .retry_counters()
+        .expect("Failed to read retry counters");
+    assert_eq!(counters.pin, 3);
+}
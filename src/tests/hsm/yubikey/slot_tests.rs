@@ -0,0 +1,124 @@
+/// # Test Cases for YubiKey slot selection and certificates
+///
+/// Except for `test_invalid_retired_slot`, these tests assume a YubiKey with the default PIN
+/// and management key. Keys created in the requested slots replace any key stored there.
+///
+/// Please use **cargo test --features yubi -- --test-threads=1** for successful testing due to parallelization issues
+#[allow(unused_imports)]
+use crate::{
+    common::{
+        crypto::algorithms::encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+        traits::module_provider::Provider,
+    },
+    hsm::{yubikey::YubiKeyProvider, HsmProviderConfig, KeySlot, SlotRole},
+};
+#[allow(unused_imports)]
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::PKey,
+    x509::{X509Name, X509},
+};
+
+#[cfg(feature = "yubi")]
+fn config(slot: KeySlot) -> Box<dyn std::any::Any> {
+    Box::new(HsmProviderConfig {
+        key_algorithm: AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+        slot,
+        overwrite: true,
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_invalid_retired_slot() {
+    let mut provider = YubiKeyProvider::new("test_invalid_retired_slot".to_string());
+
+    assert!(provider
+        .create_key("test_invalid_retired_slot", config(KeySlot::Retired(21)))
+        .is_err());
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_create_key_in_signature_slot() {
+    let key_id = "test_signature_slot";
+    let mut provider = YubiKeyProvider::new(key_id.to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider
+        .create_key(key_id, config(KeySlot::Role(SlotRole::Signature)))
+        .expect("Failed to create key in the signature slot");
+
+    // The key is found without naming its slot.
+    provider
+        .load_key(key_id, config(KeySlot::Any))
+        .expect("Failed to load key");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_create_key_in_last_retired_slot() {
+    let key_id = "test_retired_slot_20";
+    let mut provider = YubiKeyProvider::new(key_id.to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider
+        .create_key(key_id, config(KeySlot::Retired(20)))
+        .expect("Failed to create key in retired slot 20");
+    provider
+        .load_key(key_id, config(KeySlot::Retired(20)))
+        .expect("Failed to load key");
+}
+
+#[cfg(feature = "yubi")]
+#[test]
+fn test_store_and_fetch_certificate() {
+    let key_id = "test_authentication_slot";
+    let mut provider = YubiKeyProvider::new(key_id.to_string());
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(key_id, config(KeySlot::Role(SlotRole::Authentication)))
+        .expect("Failed to create key in the authentication slot");
+
+    // Issue a certificate for the YubiKey key from a software CA.
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let ca_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let key = PKey::public_key_from_pem(provider.get_pub_key().as_bytes()).unwrap();
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, key_id).unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder.sign(&ca_key, MessageDigest::sha256()).unwrap();
+    let certificate = builder.build().to_der().unwrap();
+
+    provider
+        .store_certificate(&certificate)
+        .expect("Failed to store certificate");
+    let fetched = provider.certificate().expect("Failed to fetch certificate");
+
+    assert_eq!(fetched, Some(certificate));
+}
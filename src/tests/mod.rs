@@ -3,5 +3,8 @@ pub mod common;
 #[cfg(feature = "hsm")]
 pub mod hsm;
 
+#[cfg(feature = "plugin")]
+mod plugin;
+
 #[cfg(feature = "tpm")]
 mod tpm;
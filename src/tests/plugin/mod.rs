@@ -0,0 +1,2 @@
+mod plugin_tests;
+mod test_plugin;
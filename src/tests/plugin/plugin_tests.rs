@@ -0,0 +1,135 @@
+/// # Test Cases for Provider Plugins
+///
+/// The tests register the plugin in [`test_plugin`](super::test_plugin), linked into the
+/// test binary, so they need no plugin library.
+///
+/// Please use **cargo test --features plugin**.
+use super::test_plugin::{entry, reserved_entry, unsupported_entry, NAME};
+use crate::{
+    common::{
+        crypto::{
+            algorithms::{encryption::AsymmetricEncryption, KeyBits},
+            KeyUsage,
+        },
+        error::{FactoryError, SecurityModuleError},
+        factory::{SecModules, SecurityModule},
+    },
+    plugin::{abi::PLUGIN_ABI_VERSION, PluginInfo, PluginKeyConfig},
+};
+use once_cell::sync::Lazy;
+use openssl::{pkey::PKey, rsa::Padding};
+
+static REGISTERED: Lazy<PluginInfo> = Lazy::new(|| SecModules::register_plugin(entry).unwrap());
+
+fn key_config(key_usages: Vec<KeyUsage>) -> Box<PluginKeyConfig> {
+    Box::new(PluginKeyConfig {
+        key_algorithm: Some(AsymmetricEncryption::Rsa(KeyBits::Bits2048)),
+        key_usages,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_register_plugin() {
+    assert_eq!(
+        *REGISTERED,
+        PluginInfo {
+            name: NAME.to_owned(),
+            version: "1.0.0".to_owned(),
+            abi_version: PLUGIN_ABI_VERSION,
+        }
+    );
+    assert_eq!(NAME.parse(), Ok(SecurityModule::Plugin(NAME.to_owned())));
+    assert!(SecModules::plugins().contains(&REGISTERED));
+    assert!(SecModules::discover()
+        .iter()
+        .any(|module| module.module == SecurityModule::Plugin(NAME.to_owned())));
+    assert!(matches!(
+        SecModules::register_plugin(entry),
+        Err(FactoryError::InvalidPlugin { .. })
+    ));
+}
+
+#[test]
+fn test_plugin_key_operations() {
+    Lazy::force(&REGISTERED);
+    let module: SecurityModule = NAME.parse().unwrap();
+    let provider = SecModules::get_instance("plugin_key".to_owned(), module.clone(), None).unwrap();
+    let mut provider = provider.lock().unwrap();
+    provider.initialize_module().unwrap();
+    provider
+        .create_key(
+            "plugin_key",
+            key_config(vec![KeyUsage::SignEncrypt, KeyUsage::Decrypt]),
+        )
+        .unwrap();
+
+    let capabilities = provider.capabilities().unwrap();
+    assert!(capabilities.supports_key_algorithm(AsymmetricEncryption::Rsa(KeyBits::Bits2048)));
+    assert_eq!(provider.random_bytes(16).unwrap().len(), 16);
+
+    let data = b"Hello, plugin!";
+    let signature = provider.sign_data(data).unwrap();
+    assert!(provider.verify_signature(data, &signature).unwrap());
+    assert!(!provider.verify_signature(b"Goodbye", &signature).unwrap());
+
+    // The plugin implements no encryption, so it is done with the exported public key.
+    assert!(provider.as_encryptor().is_none());
+    let public_key = PKey::public_key_from_pem(provider.get_pub_key().as_bytes()).unwrap();
+    let rsa = public_key.rsa().unwrap();
+    let mut encrypted = vec![0; rsa.size() as usize];
+    let len = rsa
+        .public_encrypt(data, &mut encrypted, Padding::PKCS1)
+        .unwrap();
+    encrypted.truncate(len);
+    assert_eq!(provider.decrypt_data(&encrypted).unwrap(), data);
+
+    let loaded = SecModules::get_instance("plugin_key_loaded".to_owned(), module, None).unwrap();
+    let mut loaded = loaded.lock().unwrap();
+    loaded
+        .load_key("plugin_key", key_config(Vec::new()))
+        .unwrap();
+    assert!(loaded.verify_signature(data, &signature).unwrap());
+    assert!(matches!(
+        loaded.load_key("missing", key_config(Vec::new())),
+        Err(SecurityModuleError::InitializationError(_))
+    ));
+}
+
+#[test]
+fn test_plugin_key_usages() {
+    Lazy::force(&REGISTERED);
+    let module = SecurityModule::Plugin(NAME.to_owned());
+    let provider = SecModules::get_instance("plugin_decrypt_key".to_owned(), module, None).unwrap();
+    let mut provider = provider.lock().unwrap();
+    provider
+        .create_key("plugin_decrypt_key", key_config(vec![KeyUsage::Decrypt]))
+        .unwrap();
+
+    assert!(provider.as_signer().is_none());
+    assert!(matches!(
+        provider.sign_data(b"data"),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+    assert!(provider.as_decryptor().is_some());
+    assert!(matches!(
+        provider.create_key("plugin_ec_key", Box::new(PluginKeyConfig::default())),
+        Err(SecurityModuleError::InitializationError(message)) if message.contains("RSA 2048")
+    ));
+}
+
+#[test]
+fn test_invalid_plugins() {
+    assert!(matches!(
+        SecModules::register_plugin(unsupported_entry),
+        Err(FactoryError::InvalidPlugin { .. })
+    ));
+    assert!(matches!(
+        SecModules::register_plugin(reserved_entry),
+        Err(FactoryError::InvalidPlugin { plugin, .. }) if plugin == "TPM"
+    ));
+    assert!(matches!(
+        SecModules::load_plugin("/nonexistent/libcrypto_layer_plugin.so"),
+        Err(FactoryError::InvalidPlugin { .. })
+    ));
+}
@@ -0,0 +1,296 @@
+use crate::{
+    common::{
+        capabilities::{Capabilities, KeyAlgorithm, Operation},
+        crypto::{
+            algorithms::{
+                encryption::AsymmetricEncryption,
+                hashes::{Hash, Sha2Bits},
+                KeyBits,
+            },
+            KeyUsage,
+        },
+    },
+    plugin::{
+        abi::{
+            PluginApi, PluginBuffer, PluginInstance, PLUGIN_ABI_VERSION, PLUGIN_ERROR,
+            PLUGIN_INVALID_ARGUMENT, PLUGIN_KEY_NOT_FOUND, PLUGIN_NO_KEY, PLUGIN_OK,
+        },
+        PluginKeyConfig,
+    },
+};
+use once_cell::sync::Lazy;
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    rsa::{Padding, Rsa},
+    sign,
+};
+use std::{collections::HashMap, os::raw::c_char, ptr, slice, sync::Mutex};
+
+/// The name `entry` registers the plugin under.
+pub const NAME: &str = "TestPlugin";
+
+/// The keys created through the plugin, by key ID, shared by all instances.
+static KEYS: Lazy<Mutex<HashMap<String, PKey<Private>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A function table in a static, which raw pointers otherwise prevent.
+struct Api(PluginApi);
+
+// SAFETY: the table is never written, and its strings are static.
+unsafe impl Sync for Api {}
+
+static API: Api = Api(api(c"TestPlugin".as_ptr()));
+static RESERVED_API: Api = Api(api(c"TPM".as_ptr()));
+
+/// The entry function of a plugin with RSA keys in memory, which cannot encrypt.
+pub unsafe extern "C" fn entry(min_version: u32, max_version: u32) -> *const PluginApi {
+    if (min_version..=max_version).contains(&PLUGIN_ABI_VERSION) {
+        &API.0
+    } else {
+        ptr::null()
+    }
+}
+
+/// The entry function of a plugin supporting no ABI version of the host.
+pub unsafe extern "C" fn unsupported_entry(
+    _min_version: u32,
+    _max_version: u32,
+) -> *const PluginApi {
+    ptr::null()
+}
+
+/// The entry function of a plugin named like a built-in module.
+pub unsafe extern "C" fn reserved_entry(_min_version: u32, _max_version: u32) -> *const PluginApi {
+    &RESERVED_API.0
+}
+
+const fn api(name: *const c_char) -> PluginApi {
+    PluginApi {
+        abi_version: PLUGIN_ABI_VERSION,
+        name,
+        version: c"1.0.0".as_ptr(),
+        free_buffer,
+        capabilities,
+        new_instance,
+        free_instance,
+        last_error,
+        initialize,
+        create_key,
+        load_key,
+        public_key,
+        sign: Some(sign),
+        verify: Some(verify),
+        encrypt: None,
+        decrypt: Some(decrypt),
+        agree: None,
+        random_bytes: Some(random_bytes),
+    }
+}
+
+#[derive(Default)]
+struct Instance {
+    key: Option<PKey<Private>>,
+    error: String,
+}
+
+impl Instance {
+    /// Records `error` for `last_error` and returns `status`.
+    fn fail(&mut self, status: i32, error: impl ToString) -> i32 {
+        self.error = error.to_string();
+        status
+    }
+
+    fn key(&mut self) -> Result<&PKey<Private>, i32> {
+        match self.key {
+            Some(ref key) => Ok(key),
+            None => {
+                self.error = "No key loaded".to_owned();
+                Err(PLUGIN_NO_KEY)
+            }
+        }
+    }
+}
+
+unsafe fn instance<'a>(instance: *mut PluginInstance) -> &'a mut Instance {
+    &mut *(instance as *mut Instance)
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    slice::from_raw_parts(data, len)
+}
+
+unsafe fn write(out: *mut PluginBuffer, data: Vec<u8>) -> i32 {
+    let data = Box::into_raw(data.into_boxed_slice());
+    *out = PluginBuffer {
+        data: data as *mut u8,
+        len: data.len(),
+    };
+    PLUGIN_OK
+}
+
+unsafe extern "C" fn free_buffer(buffer: PluginBuffer) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        buffer.data,
+        buffer.len,
+    )));
+}
+
+unsafe extern "C" fn capabilities(out: *mut PluginBuffer) -> i32 {
+    let capabilities = Capabilities {
+        key_algorithms: vec![KeyAlgorithm::new(
+            AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            vec![Hash::Sha2(Sha2Bits::Sha256)],
+            vec![Operation::Sign, Operation::Verify, Operation::Decrypt],
+        )],
+        key_usages: vec![KeyUsage::SignEncrypt, KeyUsage::Decrypt],
+        operations: vec![
+            Operation::Sign,
+            Operation::Verify,
+            Operation::Decrypt,
+            Operation::RandomBytes,
+        ],
+        ..Default::default()
+    };
+    write(out, serde_json::to_vec(&capabilities).unwrap())
+}
+
+unsafe extern "C" fn new_instance(_key_id: *const u8, _key_id_len: usize) -> *mut PluginInstance {
+    Box::into_raw(Box::<Instance>::default()) as *mut PluginInstance
+}
+
+unsafe extern "C" fn free_instance(instance: *mut PluginInstance) {
+    drop(Box::from_raw(instance as *mut Instance));
+}
+
+unsafe extern "C" fn last_error(instance_ptr: *mut PluginInstance, out: *mut PluginBuffer) -> i32 {
+    let error = instance(instance_ptr).error.clone();
+    write(out, error.into_bytes())
+}
+
+unsafe extern "C" fn initialize(_instance: *mut PluginInstance) -> i32 {
+    PLUGIN_OK
+}
+
+unsafe extern "C" fn create_key(
+    instance_ptr: *mut PluginInstance,
+    key_id: *const u8,
+    key_id_len: usize,
+    config: *const u8,
+    config_len: usize,
+) -> i32 {
+    let instance = instance(instance_ptr);
+    let key_id = String::from_utf8_lossy(bytes(key_id, key_id_len)).into_owned();
+    let config: PluginKeyConfig = match serde_json::from_slice(bytes(config, config_len)) {
+        Ok(config) => config,
+        Err(e) => return instance.fail(PLUGIN_INVALID_ARGUMENT, e),
+    };
+    if config.key_algorithm != Some(AsymmetricEncryption::Rsa(KeyBits::Bits2048)) {
+        return instance.fail(PLUGIN_INVALID_ARGUMENT, "Only RSA 2048 is supported");
+    }
+    let key = match Rsa::generate(2048).and_then(PKey::from_rsa) {
+        Ok(key) => key,
+        Err(e) => return instance.fail(PLUGIN_ERROR, e),
+    };
+    KEYS.lock().unwrap().insert(key_id, key.clone());
+    instance.key = Some(key);
+    PLUGIN_OK
+}
+
+unsafe extern "C" fn load_key(
+    instance_ptr: *mut PluginInstance,
+    key_id: *const u8,
+    key_id_len: usize,
+    _config: *const u8,
+    _config_len: usize,
+) -> i32 {
+    let instance = instance(instance_ptr);
+    let key_id = String::from_utf8_lossy(bytes(key_id, key_id_len));
+    match KEYS.lock().unwrap().get(key_id.as_ref()) {
+        Some(key) => {
+            instance.key = Some(key.clone());
+            PLUGIN_OK
+        }
+        None => instance.fail(PLUGIN_KEY_NOT_FOUND, format!("No key {}", key_id)),
+    }
+}
+
+unsafe extern "C" fn public_key(instance_ptr: *mut PluginInstance, out: *mut PluginBuffer) -> i32 {
+    let instance = instance(instance_ptr);
+    match instance.key().map(|key| key.public_key_to_pem()) {
+        Ok(Ok(pem)) => write(out, pem),
+        Ok(Err(e)) => instance.fail(PLUGIN_ERROR, e),
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn sign(
+    instance_ptr: *mut PluginInstance,
+    data: *const u8,
+    data_len: usize,
+    out: *mut PluginBuffer,
+) -> i32 {
+    let instance = instance(instance_ptr);
+    let signature = instance.key().map(|key| {
+        sign::Signer::new(MessageDigest::sha256(), key)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(bytes(data, data_len)))
+    });
+    match signature {
+        Ok(Ok(signature)) => write(out, signature),
+        Ok(Err(e)) => instance.fail(PLUGIN_ERROR, e),
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn verify(
+    instance_ptr: *mut PluginInstance,
+    data: *const u8,
+    data_len: usize,
+    signature: *const u8,
+    signature_len: usize,
+    valid: *mut bool,
+) -> i32 {
+    let instance = instance(instance_ptr);
+    let result = instance.key().map(|key| {
+        sign::Verifier::new(MessageDigest::sha256(), key).and_then(|mut verifier| {
+            verifier.verify_oneshot(bytes(signature, signature_len), bytes(data, data_len))
+        })
+    });
+    match result {
+        Ok(Ok(result)) => {
+            *valid = result;
+            PLUGIN_OK
+        }
+        Ok(Err(e)) => instance.fail(PLUGIN_ERROR, e),
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn decrypt(
+    instance_ptr: *mut PluginInstance,
+    data: *const u8,
+    data_len: usize,
+    out: *mut PluginBuffer,
+) -> i32 {
+    let instance = instance(instance_ptr);
+    let rsa = match instance.key().map(|key| key.rsa()) {
+        Ok(Ok(rsa)) => rsa,
+        Ok(Err(e)) => return instance.fail(PLUGIN_ERROR, e),
+        Err(status) => return status,
+    };
+    let mut decrypted = vec![0; rsa.size() as usize];
+    match rsa.private_decrypt(bytes(data, data_len), &mut decrypted, Padding::PKCS1) {
+        Ok(len) => {
+            decrypted.truncate(len);
+            write(out, decrypted)
+        }
+        Err(e) => instance.fail(PLUGIN_ERROR, e),
+    }
+}
+
+unsafe extern "C" fn random_bytes(_instance: *mut PluginInstance, out: *mut u8, len: usize) -> i32 {
+    match rand_bytes(slice::from_raw_parts_mut(out, len)) {
+        Ok(()) => PLUGIN_OK,
+        Err(_) => PLUGIN_ERROR,
+    }
+}
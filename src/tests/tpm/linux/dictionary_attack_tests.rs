@@ -0,0 +1,108 @@
+use super::swtpm::Swtpm;
+use crate::{
+    common::{
+        crypto::algorithms::hashes::{Hash, Sha2Bits},
+        traits::{key_handle::KeyHandle, module_provider::Provider},
+    },
+    tpm::{linux::DictionaryAttackParameters, TpmHmacConfig},
+};
+
+#[test]
+fn test_read_dictionary_attack_state() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let state = provider
+        .dictionary_attack_state()
+        .expect("Failed to read dictionary attack state");
+
+    assert_eq!(state.failed_tries, 0);
+    assert!(!state.in_lockout);
+}
+
+#[test]
+fn test_set_dictionary_attack_parameters() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    let parameters = DictionaryAttackParameters {
+        max_tries: 5,
+        recovery_time: 600,
+        lockout_recovery: 3600,
+    };
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .set_dictionary_attack_parameters(&[], parameters)
+        .expect("Failed to set dictionary attack parameters");
+
+    let state = provider
+        .dictionary_attack_state()
+        .expect("Failed to read dictionary attack state");
+
+    assert_eq!(state.parameters, parameters);
+}
+
+#[test]
+fn test_dictionary_attack_lock_reset() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .dictionary_attack_lock_reset(&[])
+        .expect("Failed to reset dictionary attack lockout");
+
+    let state = provider
+        .dictionary_attack_state()
+        .expect("Failed to read dictionary attack state");
+
+    assert_eq!(state.failed_tries, 0);
+}
+
+#[test]
+fn test_dictionary_attack_lock_reset_keeps_key() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_hmac_key");
+    let data = b"after lock reset";
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_hmac_key",
+            TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)),
+        )
+        .expect("Failed to create HMAC key");
+    let mac = provider.mac_data(data).expect("Failed to compute MAC");
+
+    provider
+        .dictionary_attack_lock_reset(&[])
+        .expect("Failed to reset dictionary attack lockout");
+
+    assert!(provider
+        .verify_mac(data, &mac)
+        .expect("Failed to verify MAC after lock reset"));
+}
+
+#[test]
+fn test_dictionary_attack_lock_reset_wrong_auth() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    assert!(provider
+        .dictionary_attack_lock_reset(b"wrong lockout password")
+        .is_err());
+}
@@ -0,0 +1,91 @@
+use super::swtpm::Swtpm;
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves, EccSchemeAlgorithm},
+            hashes::{Hash, Sha2Bits},
+            KeyBits,
+        },
+        traits::module_provider::Provider,
+    },
+    tpm::linux::{make_credential, object_name},
+};
+use test_case::test_case;
+
+#[test_case(AsymmetricEncryption::Rsa(KeyBits::Bits2048) ; "RSA EK")]
+#[test_case(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)) ; "ECC EK")]
+fn test_activate_credential(ek_algorithm: AsymmetricEncryption) {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let ek = provider
+        .create_ek(ek_algorithm)
+        .expect("Failed to create EK");
+    let ak = provider
+        .create_attestation_key(
+            &ek,
+            AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            Hash::Sha2(Sha2Bits::Sha256),
+        )
+        .expect("Failed to create AK");
+
+    // The server computes the AK name itself instead of trusting the device.
+    let ak_name = object_name(&ak.public).expect("Failed to compute AK name");
+    assert_eq!(ak_name, ak.name);
+
+    let credential = b"enrollment challenge";
+    let (credential_blob, secret) =
+        make_credential(&ek.public, &ak_name, credential).expect("Failed to make credential");
+
+    let activated = provider
+        .activate_credential(&ek, &ak, &credential_blob, &secret)
+        .expect("Failed to activate credential");
+
+    assert_eq!(activated, credential);
+}
+
+#[test]
+fn test_activate_credential_for_other_key_fails() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    let ek_algorithm = AsymmetricEncryption::Rsa(KeyBits::Bits2048);
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let ek = provider
+        .create_ek(ek_algorithm)
+        .expect("Failed to create EK");
+    let ak = provider
+        .create_attestation_key(&ek, ek_algorithm, Hash::Sha2(Sha2Bits::Sha256))
+        .expect("Failed to create AK");
+    let other = provider
+        .create_attestation_key(&ek, ek_algorithm, Hash::Sha2(Sha2Bits::Sha256))
+        .expect("Failed to create AK");
+
+    let (credential_blob, secret) =
+        make_credential(&ek.public, &other.name, b"challenge").expect("Failed to make credential");
+
+    assert!(provider
+        .activate_credential(&ek, &ak, &credential_blob, &secret)
+        .is_err());
+}
+
+#[test]
+fn test_ek_template_requires_standard_algorithm() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    assert!(provider
+        .create_ek(AsymmetricEncryption::Rsa(KeyBits::Bits4096))
+        .is_err());
+}
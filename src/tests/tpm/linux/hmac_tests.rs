@@ -0,0 +1,132 @@
+use super::swtpm::Swtpm;
+use crate::{
+    common::{
+        crypto::algorithms::hashes::{Hash, Sha2Bits},
+        traits::{key_handle::KeyHandle, module_provider::Provider},
+    },
+    tpm::{
+        linux::hmac::{hmac_key_template, hmac_sequence},
+        TpmHmacConfig,
+    },
+};
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+
+#[test]
+fn test_mac_and_verify() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_hmac_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_hmac_key",
+            TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)),
+        )
+        .expect("Failed to create HMAC key");
+
+    let data = b"GET /api/v1/tenants";
+    let mut mac = provider.mac_data(data).expect("Failed to compute MAC");
+    assert_eq!(mac.len(), 32);
+    assert!(provider
+        .verify_mac(data, &mac)
+        .expect("Failed to verify MAC"));
+
+    mac[0] ^= 1;
+    assert!(!provider
+        .verify_mac(data, &mac)
+        .expect("Failed to verify MAC"));
+}
+
+#[test]
+fn test_load_hmac_key() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("tenant_a");
+    let data = b"tenant secret";
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key("tenant_a", TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)))
+        .expect("Failed to create HMAC key");
+    let created = provider.mac_data(data).expect("Failed to compute MAC");
+
+    provider
+        .load_key("tenant_b", TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)))
+        .expect("Failed to load HMAC key");
+    let other = provider.mac_data(data).expect("Failed to compute MAC");
+
+    provider
+        .load_key("tenant_a", TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)))
+        .expect("Failed to load HMAC key");
+    let loaded = provider.mac_data(data).expect("Failed to compute MAC");
+
+    assert_eq!(created, loaded);
+    assert_ne!(created, other);
+}
+
+#[test]
+fn test_mac_max_buffer_input() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_hmac_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_hmac_key",
+            TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha384)),
+        )
+        .expect("Failed to create HMAC key");
+
+    let data = vec![0x5a; 1024];
+    let mac = provider.mac_data(&data).expect("Failed to compute MAC");
+    assert_eq!(mac.len(), 48);
+
+    // swtpm serves a single connection, so HMAC sequences are not available.
+    assert!(provider.mac_data(&vec![0x5a; 1025]).is_err());
+}
+
+#[test]
+fn test_hmac_sequence() {
+    let swtpm = Swtpm::start();
+    let data = vec![0x5a; 3 * 1024 + 1];
+
+    let mac = {
+        let mut provider = swtpm.provider("test_hmac_key");
+        provider
+            .initialize_module()
+            .expect("Failed to initialize module");
+        provider
+            .create_key(
+                "test_hmac_key",
+                TpmHmacConfig::new(Hash::Sha2(Sha2Bits::Sha256)),
+            )
+            .expect("Failed to create HMAC key");
+        provider
+            .mac_data(&data[..1024])
+            .expect("Failed to compute MAC")
+    };
+
+    // swtpm serves a single connection, so the sequence runs once the provider is dropped.
+    let public = hmac_key_template("test_hmac_key", HashingAlgorithm::Sha256)
+        .expect("Failed to build HMAC key template");
+    let sequence = |data: &[u8]| {
+        hmac_sequence(
+            &swtpm.tcti(),
+            public.clone(),
+            HashingAlgorithm::Sha256,
+            data,
+        )
+        .expect("Failed to run HMAC sequence")
+    };
+
+    assert_eq!(sequence(&data[..1024]), mac);
+    let long_mac = sequence(&data);
+    assert_eq!(long_mac.len(), 32);
+    assert_eq!(sequence(&data), long_mac);
+    assert_ne!(sequence(&data[..3 * 1024]), long_mac);
+}
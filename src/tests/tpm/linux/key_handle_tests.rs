@@ -1,3 +1,4 @@
+use super::swtpm::Swtpm;
 use crate::{
     common::crypto::algorithms::{encryption::SymmetricMode, hashes::Sha2Bits, KeyBits},
     tpm::TpmConfig,
@@ -5,6 +6,7 @@ use crate::{
 #[allow(unused_imports)]
 use crate::{
     common::{
+        capabilities::Operation,
         crypto::{
             algorithms::{
                 encryption::{AsymmetricEncryption, BlockCiphers, EccCurves, EccSchemeAlgorithm},
@@ -12,6 +14,7 @@ use crate::{
             },
             KeyUsage,
         },
+        error::SecurityModuleError,
         traits::{key_handle::KeyHandle, module_provider::Provider},
     },
     tpm::linux::TpmProvider,
@@ -19,17 +22,18 @@ use crate::{
 
 #[test]
 fn test_sign_and_verify_rsa() {
-    let mut provider = TpmProvider::new("test_rsa_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_rsa_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits4096),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
     );
 
     provider
-        .initialize_module() 
+        .initialize_module()
         .expect("Failed to initialize module");
     provider
         .create_key("test_rsa_key", config)
@@ -43,17 +47,18 @@ fn test_sign_and_verify_rsa() {
 
 #[test]
 fn test_sign_and_verify_ecdsa() {
-    let mut provider = TpmProvider::new("test_ecdsa_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_ecdsa_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
     );
 
     provider
-        .initialize_module() 
+        .initialize_module()
         .expect("Failed to initialize module");
     provider
         .create_key("test_ecdsa_key", config)
@@ -67,11 +72,12 @@ fn test_sign_and_verify_ecdsa() {
 
 #[test]
 fn test_encrypt_and_decrypt_rsa() {
-    let mut provider = TpmProvider::new("test_rsa_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_rsa_key");
 
     let config = TpmConfig::new(
         AsymmetricEncryption::Rsa(2048.into()),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![
             KeyUsage::SignEncrypt,
@@ -81,7 +87,7 @@ fn test_encrypt_and_decrypt_rsa() {
     );
 
     provider
-        .initialize_module() 
+        .initialize_module()
         .expect("Failed to initialize module");
     provider
         .create_key("test_rsa_key", config)
@@ -98,11 +104,12 @@ fn test_encrypt_and_decrypt_rsa() {
 
 #[test]
 fn test_encrypt_and_decrypt_ecdh() {
-    let mut provider = TpmProvider::new("test_ecdh_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_ecdh_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::P256)),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![
             KeyUsage::SignEncrypt,
@@ -112,17 +119,62 @@ fn test_encrypt_and_decrypt_ecdh() {
     );
 
     provider
-        .initialize_module() 
+        .initialize_module()
         .expect("Failed to initialize module");
     provider
         .create_key("test_ecdh_key", config)
         .expect("Failed to create ECDH key");
 
-    let data = b"Hello, World!";
-    let encrypted_data = provider.encrypt_data(data).expect("Failed to encrypt data");
-    let decrypted_data = provider
-        .decrypt_data(&encrypted_data)
-        .expect("Failed to decrypt data");
+    // ECC keys only agree on secrets, the TPM has no ECC encryption scheme.
+    assert!(matches!(
+        provider.encrypt_data(b"Hello, World!"),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+    assert!(matches!(
+        provider.decrypt_data(b"Hello, World!"),
+        Err(SecurityModuleError::UnsupportedOperation(_))
+    ));
+}
 
-    assert_eq!(data, decrypted_data.as_slice());
+#[test]
+fn test_sign_with_reported_capabilities() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_capabilities");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    let capabilities = provider
+        .capabilities()
+        .expect("Failed to query capabilities");
+
+    // Larger RSA keys take too long to generate on swtpm.
+    let signing_keys = capabilities.key_algorithms.iter().filter(|key_algorithm| {
+        key_algorithm.operations.contains(&Operation::Sign)
+            && !matches!(
+                key_algorithm.algorithm,
+                AsymmetricEncryption::Rsa(KeyBits::Bits3072 | KeyBits::Bits4096)
+            )
+    });
+    for key_algorithm in signing_keys {
+        for &hash in &key_algorithm.hashes {
+            let config = TpmConfig::new(
+                key_algorithm.algorithm,
+                BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+                hash,
+                vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
+            );
+            provider
+                .create_key("test_capabilities", config)
+                .unwrap_or_else(|e| {
+                    panic!("Failed to create {:?} key: {}", key_algorithm.algorithm, e)
+                });
+
+            let data = b"Hello, World!";
+            let signature = provider
+                .sign_data(data)
+                .unwrap_or_else(|e| panic!("Failed to sign with {:?}: {}", hash, e));
+            assert!(provider.verify_signature(data, &signature).unwrap());
+        }
+    }
 }
@@ -1,2 +1,9 @@
+mod dictionary_attack_tests;
+mod enrollment_tests;
+mod hmac_tests;
 mod key_handle_tests;
+mod pcr_tests;
 mod provider_handle_tests;
+mod swtpm;
+mod symmetric_tests;
+mod tcti_tests;
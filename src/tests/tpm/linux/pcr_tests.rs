@@ -0,0 +1,146 @@
+use super::swtpm::Swtpm;
+use crate::{
+    common::{
+        crypto::algorithms::hashes::{Hash, Sha2Bits},
+        traits::module_provider::Provider,
+    },
+    tpm::linux::EventLog,
+};
+use openssl::hash::{hash, MessageDigest};
+
+#[test]
+fn test_read_pcrs_of_every_bank() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let banks = provider
+        .read_pcrs(&[0, 16, 23])
+        .expect("Failed to read PCRs");
+
+    assert!(banks
+        .iter()
+        .any(|bank| bank.hash == Hash::Sha2(Sha2Bits::Sha256)));
+    for bank in banks {
+        assert_eq!(bank.values.len(), 3);
+        assert!(bank.values[&16].iter().all(|b| *b == 0));
+    }
+}
+
+#[test]
+fn test_extend_pcr() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    let digests = provider
+        .extend_pcr(16, b"configuration")
+        .expect("Failed to extend PCR");
+    let (_, digest) = digests
+        .iter()
+        .find(|(hash, _)| *hash == Hash::Sha2(Sha2Bits::Sha256))
+        .expect("No SHA-256 bank");
+
+    let expected = hash(MessageDigest::sha256(), &[&[0u8; 32][..], digest].concat()).unwrap();
+    let banks = provider.read_pcrs(&[16]).expect("Failed to read PCRs");
+    let bank = banks
+        .iter()
+        .find(|bank| bank.hash == Hash::Sha2(Sha2Bits::Sha256))
+        .unwrap();
+
+    assert_eq!(bank.values[&16], expected.to_vec());
+}
+
+#[test]
+fn test_replay_event_log() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    let mut log = EventLog::default();
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    provider
+        .record_event(&mut log, 23, "config", b"log_level = debug")
+        .expect("Failed to record event");
+    provider
+        .record_event(&mut log, 23, "plugin", b"plugin binary")
+        .expect("Failed to record event");
+    provider
+        .record_event(&mut log, 16, "debug", b"debug build")
+        .expect("Failed to record event");
+
+    for bank in provider
+        .read_pcrs(&[0, 16, 23])
+        .expect("Failed to read PCRs")
+    {
+        assert!(log.verify(&bank).expect("Failed to replay log"));
+    }
+}
+
+#[test]
+fn test_tampered_event_log_fails() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    let mut log = EventLog::default();
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .record_event(&mut log, 23, "plugin", b"plugin binary")
+        .expect("Failed to record event");
+
+    log.events[0].data = b"other plugin".to_vec();
+    assert!(log.replay(Hash::Sha2(Sha2Bits::Sha256)).is_err());
+
+    let mut log = EventLog::default();
+    provider
+        .record_event(&mut log, 23, "plugin", b"plugin binary")
+        .expect("Failed to record event");
+    // The first measurement is missing from this log.
+    let bank = provider
+        .read_pcrs(&[23])
+        .expect("Failed to read PCRs")
+        .into_iter()
+        .find(|bank| bank.hash == Hash::Sha2(Sha2Bits::Sha256))
+        .unwrap();
+    assert!(!log.verify(&bank).expect("Failed to replay log"));
+}
+
+#[test]
+fn test_quote_digest_matches_pcr_values() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    let mut log = EventLog::default();
+    let sha256 = Hash::Sha2(Sha2Bits::Sha256);
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .record_event(&mut log, 23, "config", b"log_level = debug")
+        .expect("Failed to record event");
+
+    let bank = provider
+        .read_pcrs(&[16, 23])
+        .expect("Failed to read PCRs")
+        .into_iter()
+        .find(|bank| bank.hash == sha256)
+        .unwrap();
+    let concatenated: Vec<u8> = bank.values.values().flatten().copied().collect();
+    let expected = hash(MessageDigest::sha256(), &concatenated).unwrap();
+
+    assert_eq!(
+        log.quote_digest(sha256, &[23, 16], sha256)
+            .expect("Failed to compute quote digest"),
+        expected.to_vec()
+    );
+}
@@ -1,5 +1,10 @@
+use super::swtpm::Swtpm;
 use crate::{
-    common::crypto::algorithms::{encryption::SymmetricMode, hashes::Sha2Bits, KeyBits},
+    common::{
+        composite::{tpm_config, Backend, CompositeKeyConfig, CompositeProvider},
+        crypto::algorithms::{encryption::SymmetricMode, hashes::Sha2Bits, KeyBits},
+        traits::key_handle::KeyHandle,
+    },
     tpm::TpmConfig,
 };
 #[allow(unused_imports)]
@@ -12,24 +17,26 @@ use crate::{
             },
             KeyUsage,
         },
+        error::SecurityModuleError,
         traits::module_provider::Provider,
     },
-    tpm::linux::TpmProvider,
+    tpm::{core::error::TpmError, linux::TpmProvider},
 };
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn test_create_rsa_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits4096),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![
             KeyUsage::SignEncrypt,
             KeyUsage::ClientAuth,
             KeyUsage::Decrypt,
-            KeyUsage::CreateX509,
         ],
     );
 
@@ -43,11 +50,12 @@ fn test_create_rsa_key() {
 
 #[test]
 fn test_create_ecdsa_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
     );
@@ -62,11 +70,12 @@ fn test_create_ecdsa_key() {
 
 #[test]
 fn test_create_ecdh_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
     let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::P256)),
+        BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
         Hash::Sha2(Sha2Bits::Sha256),
         vec![
             KeyUsage::SignEncrypt,
@@ -85,68 +94,178 @@ fn test_create_ecdh_key() {
 
 #[test]
 fn test_load_rsa_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
-    let config = TpmConfig::new(
-        AsymmetricEncryption::Rsa(KeyBits::Bits4096),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
-        Hash::Sha2(Sha2Bits::Sha256),
-        vec![
-            KeyUsage::SignEncrypt,
-            KeyUsage::ClientAuth,
-            KeyUsage::Decrypt,
-            KeyUsage::CreateX509,
-        ],
-    );
+    let config = || {
+        TpmConfig::new(
+            AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            Hash::Sha2(Sha2Bits::Sha256),
+            vec![
+                KeyUsage::SignEncrypt,
+                KeyUsage::ClientAuth,
+                KeyUsage::Decrypt,
+            ],
+        )
+    };
 
     provider
         .initialize_module()
         .expect("Failed to initialize module");
-
     provider
-        .load_key("test_rsa_key", config)
+        .create_key("test_rsa_key", config())
+        .expect("Failed to create key");
+    let public_key = provider.get_pub_key();
+    provider
+        .load_key("test_rsa_key", config())
         .expect("Failed to load RSA key");
+    assert_eq!(provider.get_pub_key(), public_key);
 }
 
 #[test]
 fn test_load_ecdsa_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
-    let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
-        Hash::Sha2(Sha2Bits::Sha256),
-        vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
-    );
+    let config = || {
+        TpmConfig::new(
+            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P256)),
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            Hash::Sha2(Sha2Bits::Sha256),
+            vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
+        )
+    };
 
     provider
         .initialize_module()
         .expect("Failed to initialize module");
-
     provider
-        .load_key("test_ecdsa_key", config)
+        .create_key("test_ecdsa_key", config())
+        .expect("Failed to create key");
+    let public_key = provider.get_pub_key();
+    provider
+        .load_key("test_ecdsa_key", config())
         .expect("Failed to load ECDSA key");
+    assert_eq!(provider.get_pub_key(), public_key);
 }
 
 #[test]
 fn test_load_ecdh_key() {
-    let mut provider = TpmProvider::new("test_key".to_string());
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
 
-    let config = TpmConfig::new(
-        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::Curve25519)),
-        BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
-        Hash::Sha2(Sha2Bits::Sha256),
-        vec![
-            KeyUsage::SignEncrypt,
-            KeyUsage::ClientAuth,
-            KeyUsage::Decrypt,
-        ],
-    );
+    let config = || {
+        TpmConfig::new(
+            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(EccCurves::P256)),
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            Hash::Sha2(Sha2Bits::Sha256),
+            vec![
+                KeyUsage::SignEncrypt,
+                KeyUsage::ClientAuth,
+                KeyUsage::Decrypt,
+            ],
+        )
+    };
 
     provider
         .initialize_module()
         .expect("Failed to initialize module");
     provider
-        .load_key("test_ecdh_key", config)
+        .create_key("test_ecdh_key", config())
+        .expect("Failed to create key");
+    let public_key = provider.get_pub_key();
+    provider
+        .load_key("test_ecdh_key", config())
         .expect("Failed to load ECDH key");
+    assert_eq!(provider.get_pub_key(), public_key);
+}
+
+#[test]
+fn test_create_key_with_unsupported_parameters() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    for config in [
+        TpmConfig::new(
+            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::Curve25519)),
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            Hash::Sha2(Sha2Bits::Sha256),
+            vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
+        ),
+        TpmConfig::new(
+            AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits512),
+            Hash::Sha2(Sha2Bits::Sha256),
+            vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
+        ),
+        TpmConfig::new(
+            AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+            BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits128),
+            Hash::Sha2(Sha2Bits::Sha224),
+            vec![KeyUsage::SignEncrypt, KeyUsage::ClientAuth],
+        ),
+    ] {
+        assert!(matches!(
+            provider.create_key("test_unsupported_key", config),
+            Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(_)))
+        ));
+    }
+}
+
+#[test]
+fn test_random_bytes() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+
+    // Longer than a single TPM2_GetRandom response.
+    let first = provider
+        .random_bytes(200)
+        .expect("Failed to get random bytes");
+    let second = provider
+        .random_bytes(200)
+        .expect("Failed to get random bytes");
+
+    assert_eq!(first.len(), 200);
+    assert_ne!(first, second);
+    assert!(provider
+        .random_bytes(0)
+        .expect("Failed to get random bytes")
+        .is_empty());
+}
+
+#[test]
+fn test_create_key_through_composite_provider() {
+    let swtpm = Swtpm::start();
+    let tpm = Arc::new(Mutex::new(swtpm.provider("test_key")));
+    let mut provider = CompositeProvider::new(vec![Backend::new("tpm", tpm, tpm_config)]);
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    for key_algorithm in [
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048),
+        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(EccCurves::P384)),
+    ] {
+        let config = Box::new(CompositeKeyConfig {
+            key_algorithm,
+            key_usages: vec![KeyUsage::SignEncrypt],
+            ..Default::default()
+        });
+        provider
+            .create_key("test_composite_key", config)
+            .expect("Failed to create key through the composite provider");
+        assert_eq!(provider.backend_of("test_composite_key"), Some("tpm"));
+
+        let data = b"Hello, World!";
+        let signature = provider.sign_data(data).expect("Failed to sign data");
+        assert!(provider.verify_signature(data, &signature).unwrap());
+    }
 }
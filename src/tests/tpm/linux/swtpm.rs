@@ -0,0 +1,115 @@
+/// # Software TPM harness for the Linux TPM tests
+///
+/// Every test in `tests::tpm::linux` starts its own `swtpm` process through
+/// [`Swtpm::start`], so the suites run on CI machines without a physical TPM and
+/// without the tests seeing each other's persistent handles.
+///
+/// The instance listens on two free local TCP ports (command and control channel),
+/// keeps its state in a fresh directory below the system temp directory and is
+/// killed again, together with its state directory, when the `Swtpm` value is dropped.
+///
+/// Requires the `swtpm` binary on the `PATH`; set `SWTPM` to use a different binary.
+use crate::tpm::linux::{TctiConfig, TpmProvider};
+use std::{
+    env, fs,
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+static INSTANCE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Swtpm {
+    process: Child,
+    state_dir: PathBuf,
+    port: u16,
+}
+
+impl Swtpm {
+    /// Starts a fresh `swtpm` instance and waits until it accepts connections.
+    ///
+    /// Panics if `swtpm` cannot be started, as the calling test cannot run without it.
+    pub fn start() -> Self {
+        let state_dir = env::temp_dir().join(format!(
+            "crypto-layer-swtpm-{}-{}",
+            std::process::id(),
+            INSTANCE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&state_dir).expect("Failed to create swtpm state directory");
+
+        let port = free_port_pair();
+        let binary = env::var("SWTPM").unwrap_or_else(|_| "swtpm".to_string());
+        let process = Command::new(binary)
+            .arg("socket")
+            .arg("--tpm2")
+            .arg("--tpmstate")
+            .arg(format!("dir={}", state_dir.display()))
+            .arg("--server")
+            .arg(format!("type=tcp,port={}", port))
+            .arg("--ctrl")
+            .arg(format!("type=tcp,port={}", port + 1))
+            .arg("--flags")
+            .arg("not-need-init,startup-clear")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start swtpm, is it installed?");
+
+        let swtpm = Self {
+            process,
+            state_dir,
+            port,
+        };
+        swtpm.wait_until_ready();
+        swtpm
+    }
+
+    /// Returns the TCTI configuration pointing at this instance.
+    pub fn tcti(&self) -> TctiConfig {
+        TctiConfig::Swtpm {
+            host: "localhost".to_string(),
+            port: self.port,
+        }
+    }
+
+    /// Creates a `TpmProvider` connected to this instance.
+    ///
+    /// The provider still has to be initialized with `initialize_module`.
+    pub fn provider(&self, key_id: &str) -> TpmProvider {
+        TpmProvider::new(key_id.to_string()).with_tcti(self.tcti())
+    }
+
+    fn wait_until_ready(&self) {
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", self.port)).is_err() {
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!("swtpm did not start listening on port {}", self.port);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Swtpm {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.state_dir);
+    }
+}
+
+/// Finds a port `p` such that both `p` and `p + 1` are currently free.
+fn free_port_pair() -> u16 {
+    loop {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("Failed to bind local port");
+        let port = listener.local_addr().unwrap().port();
+        if port < u16::MAX && TcpListener::bind(("127.0.0.1", port + 1)).is_ok() {
+            return port;
+        }
+    }
+}
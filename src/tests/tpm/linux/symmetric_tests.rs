@@ -0,0 +1,136 @@
+use super::swtpm::Swtpm;
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{BlockCiphers, SymmetricMode},
+            KeyBits,
+        },
+        traits::{key_handle::KeyHandle, module_provider::Provider},
+    },
+    tpm::TpmSymmetricConfig,
+};
+use test_case::test_case;
+
+#[test_case(SymmetricMode::Cfb, KeyBits::Bits128 ; "cfb")]
+#[test_case(SymmetricMode::Cbc, KeyBits::Bits256 ; "cbc")]
+#[test_case(SymmetricMode::Ofb, KeyBits::Bits128 ; "ofb")]
+#[test_case(SymmetricMode::Ctr, KeyBits::Bits256 ; "ctr")]
+#[test_case(SymmetricMode::Ecb, KeyBits::Bits128 ; "ecb")]
+fn test_encrypt_and_decrypt(mode: SymmetricMode, key_bits: KeyBits) {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_aes_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_aes_key",
+            TpmSymmetricConfig::new(BlockCiphers::Aes(mode, key_bits)),
+        )
+        .expect("Failed to create AES key");
+
+    let data = b"Hello, World! Not a multiple of the block size.";
+    let encrypted = provider.encrypt_data(data).expect("Failed to encrypt data");
+    assert_ne!(&encrypted[..], &data[..]);
+
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt data");
+    assert_eq!(decrypted, data);
+}
+
+#[test]
+fn test_fresh_iv_per_encryption() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_aes_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_aes_key",
+            TpmSymmetricConfig::new(BlockCiphers::Aes(SymmetricMode::Cfb, KeyBits::Bits256)),
+        )
+        .expect("Failed to create AES key");
+
+    let data = b"same plaintext";
+    let first = provider.encrypt_data(data).expect("Failed to encrypt data");
+    let second = provider.encrypt_data(data).expect("Failed to encrypt data");
+
+    assert_eq!(first.len(), 16 + data.len());
+    assert_ne!(first[..16], second[..16]);
+    assert_ne!(first[16..], second[16..]);
+}
+
+#[test_case(SymmetricMode::Cbc ; "cbc")]
+#[test_case(SymmetricMode::Ctr ; "ctr")]
+fn test_encrypt_large_input(mode: SymmetricMode) {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_aes_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key(
+            "test_aes_key",
+            TpmSymmetricConfig::new(BlockCiphers::Aes(mode, KeyBits::Bits128)),
+        )
+        .expect("Failed to create AES key");
+
+    let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+    let encrypted = provider
+        .encrypt_data(&data)
+        .expect("Failed to encrypt data");
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt data");
+    assert_eq!(decrypted, data);
+}
+
+#[test]
+fn test_load_symmetric_key() {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("tenant_a");
+    let sym_algorithm = BlockCiphers::Aes(SymmetricMode::Cbc, KeyBits::Bits256);
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    provider
+        .create_key("tenant_a", TpmSymmetricConfig::new(sym_algorithm))
+        .expect("Failed to create AES key");
+    let encrypted = provider
+        .encrypt_data(b"tenant secret")
+        .expect("Failed to encrypt data");
+
+    provider
+        .load_key("tenant_b", TpmSymmetricConfig::new(sym_algorithm))
+        .expect("Failed to load AES key");
+    assert!(provider.decrypt_data(&encrypted).is_err());
+
+    provider
+        .load_key("tenant_a", TpmSymmetricConfig::new(sym_algorithm))
+        .expect("Failed to load AES key");
+    let decrypted = provider
+        .decrypt_data(&encrypted)
+        .expect("Failed to decrypt data");
+    assert_eq!(decrypted, b"tenant secret");
+}
+
+#[test_case(BlockCiphers::Aes(SymmetricMode::Gcm, KeyBits::Bits256) ; "gcm")]
+#[test_case(BlockCiphers::Aes(SymmetricMode::Cbc, KeyBits::Bits512) ; "512 bit")]
+#[test_case(BlockCiphers::Des ; "des")]
+fn test_unsupported_cipher(sym_algorithm: BlockCiphers) {
+    let swtpm = Swtpm::start();
+    let mut provider = swtpm.provider("test_aes_key");
+
+    provider
+        .initialize_module()
+        .expect("Failed to initialize module");
+    assert!(provider
+        .create_key("test_aes_key", TpmSymmetricConfig::new(sym_algorithm))
+        .is_err());
+}
@@ -0,0 +1,51 @@
+use crate::tpm::linux::TctiConfig;
+use std::str::FromStr;
+use tss_esapi::TctiNameConf;
+
+#[test]
+fn test_parse_swtpm_tcti() {
+    let tcti = TctiConfig::from_str("swtpm:host=localhost,port=2331").unwrap();
+
+    assert_eq!(
+        tcti,
+        TctiConfig::Swtpm {
+            host: "localhost".to_string(),
+            port: 2331
+        }
+    );
+    assert_eq!(tcti.to_tcti_string(), "swtpm:host=localhost,port=2331");
+}
+
+#[test]
+fn test_parse_device_tcti() {
+    let tcti = TctiConfig::from_str("device:/dev/tpmrm0").unwrap();
+
+    assert_eq!(tcti, TctiConfig::Device("/dev/tpmrm0".to_string()));
+}
+
+#[test]
+fn test_parse_invalid_tcti() {
+    assert!(TctiConfig::from_str("carrier-pigeon:coop=1").is_err());
+}
+
+#[test]
+fn test_tcti_to_name_conf() {
+    let tcti = TctiConfig::Mssim {
+        host: "127.0.0.1".to_string(),
+        port: 2321,
+    };
+
+    assert!(TctiNameConf::try_from(&tcti).is_ok());
+}
+
+#[test]
+fn test_resource_managed_tcti() {
+    assert!(TctiConfig::Device("/dev/tpmrm0".to_string()).is_resource_managed());
+    assert!(!TctiConfig::Device("/dev/tpm0".to_string()).is_resource_managed());
+    assert!(!TctiConfig::from_str("swtpm:host=localhost,port=2321")
+        .unwrap()
+        .is_resource_managed());
+    assert!(TctiConfig::from_str("tabrmd:")
+        .unwrap()
+        .is_resource_managed());
+}
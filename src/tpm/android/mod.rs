@@ -14,14 +14,17 @@ use utils::{
     get_signature_padding, get_sym_block_mode,
 };
 
+use crate::common::capabilities::Operation;
 use crate::common::crypto::KeyUsage;
 use crate::common::error::SecurityModuleError;
 use crate::common::traits::key_handle::KeyHandle;
+use crate::common::traits::operations::{Decryptor, Encryptor, Signer, Verifier};
 use crate::common::{
     crypto::algorithms::encryption::{AsymmetricEncryption, BlockCiphers},
     traits::module_provider::Provider,
 };
-use crate::tpm::android::config::AndroidConfig;
+use crate::tpm::android::config::{AndroidConfig, EncryptionMode};
+use crate::tpm::android::wrapper::key_generation::secure_random::jni::SecureRandom;
 use crate::tpm::android::wrapper::key_store::key_store::jni::KeyStore;
 use crate::tpm::android::wrapper::key_store::signature::jni::Signature;
 use crate::tpm::core::error::ToTpmError;
@@ -29,6 +32,19 @@ use crate::tpm::core::error::TpmError;
 
 const ANDROID_KEYSTORE: &str = "AndroidKeyStore";
 
+/// The keystore daemons of Android 12 and later, and of earlier versions.
+const KEYSTORE_DAEMONS: [&str; 2] = ["/system/bin/keystore2", "/system/bin/keystore"];
+
+/// Whether the device runs an Android keystore daemon.
+///
+/// This does not need a Java VM, so it works before `AndroidConfig` is available. Whether
+/// keys end up in StrongBox or the TEE is only known when they are generated.
+pub(crate) fn keystore_available() -> bool {
+    KEYSTORE_DAEMONS
+        .iter()
+        .any(|daemon| std::path::Path::new(daemon).exists())
+}
+
 /// A TPM-based cryptographic provider for managing cryptographic keys and performing
 /// cryptographic operations in an Android environment.
 ///
@@ -255,12 +271,93 @@ impl Provider for AndroidProvider {
     fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
         Ok(())
     }
+
+    /// Returns `len` random bytes from the platform's `SecureRandom`.
+    ///
+    /// # Java Example
+    ///
+    /// ```java
+    /// byte[] random = new byte[len];
+    /// new SecureRandom().nextBytes(random);
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the random bytes as a `Vec<u8>` if successful, or a `SecurityModuleError` if an error occurs.
+    #[instrument]
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        let config = self
+            .config
+            .as_ref()
+            .ok_or(SecurityModuleError::InitializationError(
+                "Module is not initialized".to_owned(),
+            ))?;
+
+        let env = config
+            .vm
+            .as_ref()
+            .ok_or_else(|| TpmError::InitializationError("Module is not initialized".to_owned()))?
+            .get_env()
+            .map_err(|_| {
+                TpmError::InitializationError(
+                    "Could not get java environment, this should never happen".to_owned(),
+                )
+            })?;
+
+        let secure_random = SecureRandom::new(&env).err_internal()?;
+        Ok(secure_random.nextBytes(&env, len).err_internal()?)
+    }
 }
 
 /// Implementation of the `KeyHandle` trait for the `AndroidProvider` struct.
-/// All of the functions in this KeyHandle are basically re-implementations
-/// of the equivalent Java functions in the Android KeyStore API.
+/// The operations are implemented by the `Signer`, `Verifier`, `Encryptor` and `Decryptor`
+/// traits below, which are basically re-implementations of the equivalent Java functions
+/// in the Android KeyStore API.
 impl KeyHandle for AndroidProvider {
+    /// Returns the key as a `Signer` if it is an asymmetric key created with
+    /// `KeyUsage::SignEncrypt`.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        let config = self.config.as_ref()?;
+        (matches!(config.mode, EncryptionMode::ASym { .. })
+            && Operation::Sign.permitted_by(&config.key_usages))
+        .then_some(self)
+    }
+
+    /// Returns the key as a `Verifier` if it is an asymmetric key.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        let config = self.config.as_ref()?;
+        matches!(config.mode, EncryptionMode::ASym { .. }).then_some(self)
+    }
+
+    /// Returns the key as an `Encryptor` if it is a symmetric or RSA key.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        self.can_encrypt().then_some(self)
+    }
+
+    /// Returns the key as a `Decryptor` if it is a symmetric or RSA key.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        self.can_encrypt().then_some(self)
+    }
+}
+
+impl AndroidProvider {
+    /// Whether the key is a symmetric or RSA key, the keys the Android KeyStore can encrypt
+    /// and decrypt with.
+    fn can_encrypt(&self) -> bool {
+        self.config.as_ref().is_some_and(|config| {
+            matches!(
+                config.mode,
+                EncryptionMode::Sym(_)
+                    | EncryptionMode::ASym {
+                        algo: AsymmetricEncryption::Rsa(_),
+                        ..
+                    }
+            )
+        })
+    }
+}
+
+impl Signer for AndroidProvider {
     /// Signs the given data using the Android KeyStore.
     ///
     /// # Arguments
@@ -287,7 +384,7 @@ impl KeyHandle for AndroidProvider {
     ///
     /// Returns a `Result` containing the signed data as a `Vec<u8>` if successful, or a `SecurityModuleError` if an error occurs.
     #[instrument]
-    fn sign_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         // check that signing is allowed
         let config = self
             .config
@@ -337,7 +434,9 @@ impl KeyHandle for AndroidProvider {
 
         Ok(output)
     }
+}
 
+impl Decryptor for AndroidProvider {
     /// Decrypts the given encrypted data using the Android KeyStore.
     ///
     /// # Arguments
@@ -360,7 +459,7 @@ impl KeyHandle for AndroidProvider {
     ///
     /// Returns a `Result` containing the decrypted data as a `Vec<u8>` if successful, or a `SecurityModuleError` if an error occurs.
     #[instrument]
-    fn decrypt_data(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         info!("decrypting data");
 
         let config = self
@@ -401,7 +500,9 @@ impl KeyHandle for AndroidProvider {
         debug!("decrypted data: {:?}", decrypted);
         Ok(decrypted)
     }
+}
 
+impl Encryptor for AndroidProvider {
     /// Encrypts the given data using the Android KeyStore.
     ///
     /// # Arguments
@@ -425,7 +526,7 @@ impl KeyHandle for AndroidProvider {
     ///
     /// Returns a `Result` containing the encrypted data as a `Vec<u8>` if successful, or a `SecurityModuleError` if an error occurs.
     #[instrument]
-    fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         info!("encrypting");
 
         let config = self
@@ -471,7 +572,9 @@ impl KeyHandle for AndroidProvider {
         debug!("encrypted: {:?}", encrypted);
         Ok(encrypted)
     }
+}
 
+impl Verifier for AndroidProvider {
     /// Verifies the signature of the given data using the Android KeyStore.
     ///
     /// # Arguments
@@ -499,7 +602,7 @@ impl KeyHandle for AndroidProvider {
     ///
     /// Returns a `Result` containing `true` if the signature is valid, `false` otherwise, or a `SecurityModuleError` if an error occurs.
     #[instrument]
-    fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
         info!("verifiying");
 
         let config = self
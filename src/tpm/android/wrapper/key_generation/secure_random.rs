@@ -7,7 +7,8 @@ pub mod jni {
     use robusta_jni::{
         convert::{IntoJavaValue, Signature, TryFromJavaValue, TryIntoJavaValue},
         jni::errors::Result as JniResult,
-        jni::objects::AutoLocal,
+        jni::objects::{AutoLocal, JValue},
+        jni::sys::jbyteArray,
         jni::JNIEnv,
     };
 
@@ -26,5 +27,22 @@ pub mod jni {
 
         /// Returns the algorithm name of the `SecureRandom` instance.
         pub extern "java" fn getAlgorithm(&self, env: &JNIEnv<'env>) -> JniResult<String> {}
+
+        /// Generates `len` random bytes.
+        ///
+        /// Java fills a caller supplied array in place, so the array is allocated here and
+        /// converted back after the call.
+        pub fn nextBytes(&self, env: &JNIEnv, len: usize) -> JniResult<Vec<u8>> {
+            let output_array: jbyteArray = env.new_byte_array(len as i32)?;
+
+            env.call_method(
+                self.raw.as_obj(),
+                "nextBytes",
+                "([B)V",
+                &[JValue::from(output_array)],
+            )?;
+
+            env.convert_byte_array(output_array)
+        }
     }
 }
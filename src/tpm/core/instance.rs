@@ -1,9 +1,18 @@
-use crate::common::traits::module_provider::Provider;
+#[cfg(any(feature = "win", feature = "linux", feature = "android"))]
+use crate::common::factory::SecurityModule;
+use crate::common::{
+    error::FactoryError, factory::DiscoveredModule, traits::module_provider::Provider,
+};
 #[cfg(feature = "linux")]
-use crate::tpm::linux::TpmProvider;
+use crate::tpm::linux::{TctiConfig, TpmProvider};
 #[cfg(feature = "win")]
 use crate::tpm::win::TpmProvider as WinTpmProvider;
-use std::sync::{Arc, Mutex};
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+#[cfg(any(feature = "linux", feature = "win"))]
+use tracing::warn;
 
 /// Represents the different environments where a Trusted Platform Module (TPM) can operate.
 ///
@@ -69,23 +78,52 @@ impl Default for TpmType {
 /// Enables conversion from a string slice to a `TpmType`.
 ///
 /// This implementation allows for dynamic TPM type determination based on string values,
-/// useful for configuration or runtime environment specification.
-impl From<&str> for TpmType {
-    fn from(s: &str) -> Self {
+/// useful for configuration or runtime environment specification. The names are `"Windows"`,
+/// `"MacOs"`, `"Linux"` and `"Android"`, which selects Knox; `"Android:Keystore"` and
+/// `"Android:Knox"` select the Android provider explicitly.
+///
+/// Names of TPM types whose feature is not enabled give `FactoryError::FeatureDisabled`,
+/// other names `FactoryError::UnknownModule`.
+impl FromStr for TpmType {
+    type Err = FactoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             #[cfg(feature = "win")]
-            "Windows" => TpmType::Windows,
+            "Windows" => Ok(TpmType::Windows),
             #[cfg(feature = "macos")]
-            "MacOs" => TpmType::MacOs,
+            "MacOs" => Ok(TpmType::MacOs),
             #[cfg(feature = "linux")]
-            "Linux" => TpmType::Linux,
+            "Linux" => Ok(TpmType::Linux),
+            #[cfg(feature = "android")]
+            "Android" | "Android:Knox" => Ok(TpmType::Android(AndroidTpmType::Knox)),
             #[cfg(feature = "android")]
-            "Android" => TpmType::Android(AndroidTpmType::Knox),
-            _ => panic!("Unsupported TpmType"),
+            "Android:Keystore" => Ok(TpmType::Android(AndroidTpmType::Keystore)),
+            _ => {
+                let feature = match s {
+                    "Windows" => "win",
+                    "MacOs" => "macos",
+                    "Linux" => "linux",
+                    "Android" | "Android:Knox" | "Android:Keystore" => "android",
+                    _ => return Err(FactoryError::UnknownModule(s.to_owned())),
+                };
+                Err(FactoryError::FeatureDisabled {
+                    module: s.to_owned(),
+                    feature,
+                })
+            }
         }
     }
 }
 
+impl TryFrom<&str> for TpmType {
+    type Error = FactoryError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 /// Manages instances of TPM providers based on the specified `TpmType`.
 ///
 /// This structure is responsible for creating and encapsulating a TPM provider instance,
@@ -109,29 +147,81 @@ impl TpmInstance {
     /// * `tpm_type` - A reference to the `TpmType` indicating the environment of the TPM.
     ///
     /// # Returns
-    /// An `Arc<dyn Provider>` encapsulating the created TPM provider instance.
-    pub fn create_instance(key_id: String, tpm_type: &TpmType) -> Arc<Mutex<dyn Provider>> {
+    /// An `Arc<dyn Provider>` encapsulating the created TPM provider instance, or
+    /// `FactoryError::NotImplemented` for TPM types without a provider yet.
+    #[cfg_attr(
+        not(any(feature = "win", feature = "linux", feature = "android")),
+        allow(unused_variables)
+    )]
+    pub fn create_instance(
+        key_id: String,
+        tpm_type: &TpmType,
+    ) -> Result<Arc<Mutex<dyn Provider>>, FactoryError> {
         match tpm_type {
             #[cfg(feature = "win")]
             TpmType::Windows => {
                 let instance = WinTpmProvider::new(key_id);
-                Arc::new(Mutex::new(instance))
+                Ok(Arc::new(Mutex::new(instance)))
             }
             #[cfg(feature = "macos")]
-            TpmType::MacOs => todo!(),
+            TpmType::MacOs => Err(FactoryError::NotImplemented("the macOS TPM".to_owned())),
             #[cfg(feature = "linux")]
             TpmType::Linux => {
                 let instance = TpmProvider::new(key_id);
-                Arc::new(Mutex::new(instance))
+                Ok(Arc::new(Mutex::new(instance)))
             }
             #[cfg(feature = "android")]
             TpmType::Android(tpm_type) => match tpm_type {
-                AndroidTpmType::Keystore => Arc::new(Mutex::new(
+                AndroidTpmType::Keystore => Ok(Arc::new(Mutex::new(
                     crate::tpm::android::AndroidProvider::new(key_id),
-                )),
-                AndroidTpmType::Knox => todo!(),
+                ))),
+                AndroidTpmType::Knox => {
+                    Err(FactoryError::NotImplemented("Samsung Knox".to_owned()))
+                }
             },
-            TpmType::None => todo!(),
+            TpmType::None => Err(FactoryError::NotImplemented(
+                "a platform without a supported TPM".to_owned(),
+            )),
         }
     }
+
+    /// Probes the TPM of the platform, as used by `SecModules::discover`.
+    ///
+    /// On Linux the TPM is reached through the TCTI from the environment, falling back to
+    /// `/dev/tpmrm0`.
+    pub fn discover() -> Vec<DiscoveredModule> {
+        #[allow(unused_mut)]
+        let mut modules = Vec::new();
+
+        #[cfg(feature = "win")]
+        match WinTpmProvider::probe() {
+            Ok(()) => modules.push(DiscoveredModule {
+                module: SecurityModule::Tpm(TpmType::Windows),
+                description: "TPM 2.0 via the Microsoft Platform Crypto Provider".to_owned(),
+            }),
+            Err(e) => warn!("No usable TPM: {}", e),
+        }
+
+        #[cfg(feature = "linux")]
+        match TctiConfig::Environment.probe() {
+            Ok(info) => modules.push(DiscoveredModule {
+                module: SecurityModule::Tpm(TpmType::Linux),
+                description: format!(
+                    "TPM 2.0 by {}, firmware {}.{}",
+                    info.manufacturer, info.firmware_version.0, info.firmware_version.1
+                ),
+            }),
+            Err(e) => warn!("No usable TPM: {}", e),
+        }
+
+        #[cfg(feature = "android")]
+        if crate::tpm::android::keystore_available() {
+            modules.push(DiscoveredModule {
+                module: SecurityModule::Tpm(TpmType::Android(AndroidTpmType::Keystore)),
+                description: "Android Keystore".to_owned(),
+            });
+        }
+
+        modules
+    }
 }
@@ -0,0 +1,287 @@
+use super::{
+    provider::{ecc_parameters, rsa_parameters},
+    tss_error, TpmProvider,
+};
+use crate::{
+    common::{
+        capabilities::{Capabilities, KeyAlgorithm, Operation},
+        crypto::{
+            algorithms::{
+                encryption::{
+                    AsymmetricEncryption, BlockCiphers, EccCurves, EccSchemeAlgorithm,
+                    SymmetricMode,
+                },
+                hashes::{Hash, Sha2Bits, Sha3Bits},
+                KeyBits,
+            },
+            KeyUsage,
+        },
+        error::SecurityModuleError,
+    },
+    tpm::core::error::TpmError,
+};
+use tss_esapi::{
+    constants::{AlgorithmIdentifier, CapabilityType},
+    interface_types::algorithm::HashingAlgorithm,
+    structures::{
+        AlgorithmPropertyList, CapabilityData, EccScheme, HashScheme, PublicEccParameters,
+        PublicParameters, PublicRsaParameters, RsaScheme, SymmetricCipherParameters,
+        SymmetricDefinitionObject,
+    },
+    tss2_esys::TPM2_ALG_ID,
+    Context,
+};
+
+/// The hashes of this crate, of which those `HashingAlgorithm` converts can be used.
+const HASHES: [Hash; 9] = [
+    Hash::Sha1,
+    Hash::Sha2(Sha2Bits::Sha224),
+    Hash::Sha2(Sha2Bits::Sha256),
+    Hash::Sha2(Sha2Bits::Sha384),
+    Hash::Sha2(Sha2Bits::Sha512),
+    Hash::Sha3(Sha3Bits::Sha3_224),
+    Hash::Sha3(Sha3Bits::Sha3_256),
+    Hash::Sha3(Sha3Bits::Sha3_384),
+    Hash::Sha3(Sha3Bits::Sha3_512),
+];
+
+/// The RSA key sizes of this crate, of which those `rsa_parameters` accepts can be used.
+const RSA_KEY_BITS: [KeyBits; 4] = [
+    KeyBits::Bits1024,
+    KeyBits::Bits2048,
+    KeyBits::Bits3072,
+    KeyBits::Bits4096,
+];
+
+/// The curves of this crate, of which those `ecc_parameters` accepts can be used.
+const ECC_CURVES: [EccCurves; 7] = [
+    EccCurves::P256,
+    EccCurves::P384,
+    EccCurves::P521,
+    EccCurves::Secp256k1,
+    EccCurves::BrainpoolP256r1,
+    EccCurves::BrainpoolP384r1,
+    EccCurves::BrainpoolP512r1,
+];
+
+/// The modes of symmetric keys, see `aes_parameters`, with their TPM identifiers.
+const SYMMETRIC_MODES: [(SymmetricMode, AlgorithmIdentifier); 5] = [
+    (SymmetricMode::Cfb, AlgorithmIdentifier::Cfb),
+    (SymmetricMode::Cbc, AlgorithmIdentifier::Cbc),
+    (SymmetricMode::Ofb, AlgorithmIdentifier::Ofb),
+    (SymmetricMode::Ctr, AlgorithmIdentifier::Ctr),
+    (SymmetricMode::Ecb, AlgorithmIdentifier::Ecb),
+];
+
+/// The key sizes of symmetric keys.
+const AES_KEY_BITS: [KeyBits; 3] = [KeyBits::Bits128, KeyBits::Bits192, KeyBits::Bits256];
+
+impl TpmProvider {
+    /// Describes what the TPM supports.
+    ///
+    /// The algorithms are read with `TPM2_GetCapability`. Keys are checked with
+    /// `TPM2_TestParms` and the parameters `create_key` creates them with, so only keys this
+    /// provider can create are reported. The hashes of a signing key are those the TPM
+    /// accepts in its signature scheme, which every signature is made with. `max_keys` is
+    /// not limited, as all keys are derived again on every load and take no space in the TPM.
+    pub(super) fn query_capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        let mut context = self.context()?;
+        let algorithms = algorithms(&mut context)?;
+        let implements = |algorithm| algorithms.contains(&algorithm);
+
+        let hash_algorithms: Vec<(Hash, HashingAlgorithm)> = HASHES
+            .into_iter()
+            .filter_map(|hash| Some((hash, HashingAlgorithm::try_from(hash).ok()?)))
+            .filter(|&(_, algorithm)| implements(algorithm.into()))
+            .collect();
+        let hashes: Vec<Hash> = hash_algorithms.iter().map(|&(hash, _)| hash).collect();
+
+        let mut key_algorithms = Vec::new();
+        if implements(AlgorithmIdentifier::Rsa) {
+            for bits in RSA_KEY_BITS {
+                let Ok(key) = rsa_parameters(bits) else {
+                    continue;
+                };
+                if !test_parms(&mut context, PublicParameters::Rsa(key)) {
+                    continue;
+                }
+                let signature_hashes = if implements(AlgorithmIdentifier::RsaSsa) {
+                    signature_hashes(&mut context, &hash_algorithms, |hash_scheme| {
+                        PublicParameters::Rsa(PublicRsaParameters::new(
+                            key.symmetric_definition_object(),
+                            RsaScheme::RsaSsa(hash_scheme),
+                            key.key_bits(),
+                            key.exponent(),
+                        ))
+                    })
+                } else {
+                    Vec::new()
+                };
+
+                let mut operations = vec![Operation::Verify];
+                if !signature_hashes.is_empty() {
+                    operations.insert(0, Operation::Sign);
+                }
+                if implements(AlgorithmIdentifier::Oaep) {
+                    operations.extend([Operation::Encrypt, Operation::Decrypt]);
+                }
+                key_algorithms.push(KeyAlgorithm::new(
+                    AsymmetricEncryption::Rsa(bits),
+                    signature_hashes,
+                    operations,
+                ));
+            }
+        }
+        if implements(AlgorithmIdentifier::Ecc) {
+            let mut agreement_keys = Vec::new();
+            for curve in ECC_CURVES {
+                let Ok(key) = ecc_parameters(curve) else {
+                    continue;
+                };
+                if !test_parms(&mut context, PublicParameters::Ecc(key)) {
+                    continue;
+                }
+                if implements(AlgorithmIdentifier::EcDsa) {
+                    let signature_hashes =
+                        signature_hashes(&mut context, &hash_algorithms, |hash_scheme| {
+                            PublicParameters::Ecc(PublicEccParameters::new(
+                                key.symmetric_definition_object(),
+                                EccScheme::EcDsa(hash_scheme),
+                                key.ecc_curve(),
+                                key.key_derivation_function_scheme(),
+                            ))
+                        });
+                    if !signature_hashes.is_empty() {
+                        key_algorithms.push(KeyAlgorithm::new(
+                            AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(curve)),
+                            signature_hashes,
+                            vec![Operation::Sign, Operation::Verify],
+                        ));
+                    }
+                }
+                if implements(AlgorithmIdentifier::EcDh) {
+                    agreement_keys.push(KeyAlgorithm::new(
+                        AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(curve)),
+                        Vec::new(),
+                        vec![Operation::KeyAgreement],
+                    ));
+                }
+            }
+            key_algorithms.extend(agreement_keys);
+        }
+
+        let mut block_ciphers = Vec::new();
+        if implements(AlgorithmIdentifier::Aes) {
+            for (mode, identifier) in SYMMETRIC_MODES {
+                if !implements(identifier) {
+                    continue;
+                }
+                for bits in AES_KEY_BITS {
+                    let cipher = BlockCiphers::Aes(mode, bits);
+                    let Ok(definition) = SymmetricDefinitionObject::try_from(cipher) else {
+                        continue;
+                    };
+                    let parameters =
+                        PublicParameters::SymCipher(SymmetricCipherParameters::new(definition));
+                    if test_parms(&mut context, parameters) {
+                        block_ciphers.push(cipher);
+                    }
+                }
+            }
+        }
+
+        let mut operations: Vec<Operation> = Vec::new();
+        for key_algorithm in &key_algorithms {
+            for &operation in &key_algorithm.operations {
+                if !operations.contains(&operation) {
+                    operations.push(operation);
+                }
+            }
+        }
+        if !block_ciphers.is_empty() {
+            for operation in [Operation::Encrypt, Operation::Decrypt] {
+                if !operations.contains(&operation) {
+                    operations.push(operation);
+                }
+            }
+        }
+        if implements(AlgorithmIdentifier::Hmac) && !hashes.is_empty() {
+            operations.push(Operation::Mac);
+        }
+        operations.push(Operation::RandomBytes);
+
+        Ok(Capabilities {
+            key_algorithms,
+            block_ciphers,
+            hashes,
+            key_usages: vec![
+                KeyUsage::ClientAuth,
+                KeyUsage::Decrypt,
+                KeyUsage::SignEncrypt,
+                KeyUsage::CreateX509,
+            ],
+            max_keys: None,
+            operations,
+        })
+    }
+}
+
+/// Reads the algorithms the TPM implements.
+fn algorithms(context: &mut Context) -> Result<Vec<AlgorithmIdentifier>, SecurityModuleError> {
+    let mut algorithms = Vec::new();
+    let mut first: u32 = 0;
+    loop {
+        let (capabilities, more) = context
+            .execute_without_session(|ctx| {
+                ctx.get_capability(
+                    CapabilityType::Algorithms,
+                    first,
+                    AlgorithmPropertyList::MAX_SIZE as u32,
+                )
+            })
+            .map_err(tss_error)?;
+        let CapabilityData::Algorithms(properties) = capabilities else {
+            return Err(unexpected_capability_data());
+        };
+        let Some(last) = properties.last() else {
+            break;
+        };
+        first = u32::from(TPM2_ALG_ID::from(last.algorithm_identifier())) + 1;
+        algorithms.extend(
+            properties
+                .iter()
+                .map(|property| property.algorithm_identifier()),
+        );
+        if !more {
+            break;
+        }
+    }
+    Ok(algorithms)
+}
+
+/// Returns whether the TPM accepts `parameters` for an object, with `TPM2_TestParms`.
+fn test_parms(context: &mut Context, parameters: PublicParameters) -> bool {
+    context
+        .execute_without_session(|ctx| ctx.test_parms(parameters))
+        .is_ok()
+}
+
+/// Returns the hashes of `hashes` the TPM accepts in the signature scheme of a key, with
+/// the parameters `parameters` returns for a scheme.
+fn signature_hashes(
+    context: &mut Context,
+    hashes: &[(Hash, HashingAlgorithm)],
+    parameters: impl Fn(HashScheme) -> PublicParameters,
+) -> Vec<Hash> {
+    hashes
+        .iter()
+        .filter(|&&(_, algorithm)| test_parms(context, parameters(HashScheme::new(algorithm))))
+        .map(|&(hash, _)| hash)
+        .collect()
+}
+
+fn unexpected_capability_data() -> SecurityModuleError {
+    SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+        "Unexpected capability data".to_owned(),
+    ))
+}
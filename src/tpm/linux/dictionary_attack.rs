@@ -0,0 +1,278 @@
+use super::{
+    esys::{check_rc, RawEsysContext},
+    TpmProvider,
+};
+use crate::{
+    common::{error::SecurityModuleError, traits::module_provider::Provider},
+    tpm::core::error::TpmError,
+};
+use std::sync::{Arc, Mutex};
+use tracing::instrument;
+use tss_esapi::{
+    constants::{
+        tss::{TPMA_PERMANENT_INLOCKOUT, TPMA_PERMANENT_LOCKOUTAUTHSET},
+        CapabilityType, PropertyTag,
+    },
+    handles::KeyHandle as TssKeyHandle,
+    structures::{Auth, CapabilityData},
+    tss2_esys::{
+        Esys_DictionaryAttackLockReset, Esys_DictionaryAttackParameters, ESYS_TR_NONE,
+        ESYS_TR_PASSWORD, ESYS_TR_RH_LOCKOUT,
+    },
+    utils::TpmsContext,
+    Context,
+};
+
+/// The dictionary-attack settings of a TPM.
+///
+/// All times are in seconds. A `recovery_time` of zero disables dictionary-attack
+/// protection, a `lockout_recovery` of zero means that a failed lockout authorization
+/// can only be recovered from by a TPM reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryAttackParameters {
+    /// Number of authorization failures before the TPM enters lockout (`TPM_PT_MAX_AUTH_FAIL`).
+    pub max_tries: u32,
+    /// Seconds after which one failure is forgotten (`TPM_PT_LOCKOUT_INTERVAL`).
+    pub recovery_time: u32,
+    /// Seconds to wait after a failed lockout authorization (`TPM_PT_LOCKOUT_RECOVERY`).
+    pub lockout_recovery: u32,
+}
+
+/// The current dictionary-attack state of a TPM, as reported by `TPM2_GetCapability`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryAttackState {
+    /// Number of authorization failures not yet forgiven (`TPM_PT_LOCKOUT_COUNTER`).
+    pub failed_tries: u32,
+    /// The configured dictionary-attack parameters.
+    pub parameters: DictionaryAttackParameters,
+    /// Whether the TPM currently refuses authorizations with a user password.
+    pub in_lockout: bool,
+    /// Whether a lockout authorization value other than the empty one has been set.
+    pub lockout_auth_set: bool,
+}
+
+impl TpmProvider {
+    /// Reads the dictionary-attack state of the TPM.
+    ///
+    /// The values are queried on every call, the property cache of the ESAPI
+    /// context is bypassed because the counters change between calls.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DictionaryAttackState` on success, or a `SecurityModuleError`
+    /// if the module is not initialized or the TPM does not report the properties.
+    #[instrument]
+    pub fn dictionary_attack_state(&self) -> Result<DictionaryAttackState, SecurityModuleError> {
+        let handle = self.handle.as_ref().ok_or_else(|| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(
+                "Module is not initialized".to_owned(),
+            ))
+        })?;
+        let mut context = handle.lock().unwrap();
+
+        let mut property = |tag: PropertyTag| -> Result<u32, SecurityModuleError> {
+            let (capabilities, _) = context
+                .execute_without_session(|ctx| {
+                    ctx.get_capability(CapabilityType::TpmProperties, tag.into(), 1)
+                })
+                .map_err(|e| SecurityModuleError::Tpm(TpmError::InternalError(Box::new(e))))?;
+
+            match capabilities {
+                CapabilityData::TpmProperties(properties) => properties
+                    .find(tag)
+                    .map(|property| property.value())
+                    .ok_or_else(|| {
+                        SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+                            "TPM does not report {:?}",
+                            tag
+                        )))
+                    }),
+                _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                    "Unexpected capability data".to_owned(),
+                ))),
+            }
+        };
+
+        let permanent = property(PropertyTag::Permanent)?;
+
+        Ok(DictionaryAttackState {
+            failed_tries: property(PropertyTag::LockoutCounter)?,
+            parameters: DictionaryAttackParameters {
+                max_tries: property(PropertyTag::MaxAuthFail)?,
+                recovery_time: property(PropertyTag::LockoutInterval)?,
+                lockout_recovery: property(PropertyTag::LockoutRecovery)?,
+            },
+            in_lockout: permanent & TPMA_PERMANENT_INLOCKOUT != 0,
+            lockout_auth_set: permanent & TPMA_PERMANENT_LOCKOUTAUTHSET != 0,
+        })
+    }
+
+    /// Resets the failure counter and leaves lockout (`TPM2_DictionaryAttackLockReset`).
+    ///
+    /// Requires lockout-hierarchy authorization. A wrong `lockout_auth` puts the lockout
+    /// hierarchy itself into lockout for `lockout_recovery` seconds.
+    ///
+    /// The TPM connection is reopened for the command; the loaded key stays loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `lockout_auth` - The authorization value of the lockout hierarchy, empty by default.
+    #[instrument(skip(lockout_auth))]
+    pub fn dictionary_attack_lock_reset(
+        &mut self,
+        lockout_auth: &[u8],
+    ) -> Result<(), SecurityModuleError> {
+        self.with_lockout_session(lockout_auth, |raw| {
+            // SAFETY: `raw.esys` is a valid context for the lifetime of `raw`.
+            check_rc(
+                unsafe {
+                    Esys_DictionaryAttackLockReset(
+                        raw.esys,
+                        ESYS_TR_RH_LOCKOUT,
+                        ESYS_TR_PASSWORD,
+                        ESYS_TR_NONE,
+                        ESYS_TR_NONE,
+                    )
+                },
+                "TPM2_DictionaryAttackLockReset",
+            )
+        })
+    }
+
+    /// Changes the dictionary-attack parameters (`TPM2_DictionaryAttackParameters`).
+    ///
+    /// Requires lockout-hierarchy authorization.
+    ///
+    /// The TPM connection is reopened for the command; the loaded key stays loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `lockout_auth` - The authorization value of the lockout hierarchy, empty by default.
+    /// * `parameters` - The new `DictionaryAttackParameters`.
+    #[instrument(skip(lockout_auth))]
+    pub fn set_dictionary_attack_parameters(
+        &mut self,
+        lockout_auth: &[u8],
+        parameters: DictionaryAttackParameters,
+    ) -> Result<(), SecurityModuleError> {
+        self.with_lockout_session(lockout_auth, |raw| {
+            // SAFETY: `raw.esys` is a valid context for the lifetime of `raw`.
+            check_rc(
+                unsafe {
+                    Esys_DictionaryAttackParameters(
+                        raw.esys,
+                        ESYS_TR_RH_LOCKOUT,
+                        ESYS_TR_PASSWORD,
+                        ESYS_TR_NONE,
+                        ESYS_TR_NONE,
+                        parameters.max_tries,
+                        parameters.recovery_time,
+                        parameters.lockout_recovery,
+                    )
+                },
+                "TPM2_DictionaryAttackParameters",
+            )
+        })
+    }
+
+    /// Runs `command` on a dedicated ESAPI connection authorized for the lockout hierarchy.
+    ///
+    /// `tss-esapi` does not wrap the dictionary-attack commands, so they are issued through
+    /// the raw ESAPI. Simulators like `swtpm` serve one connection at a time, so the provider's
+    /// own connection is closed while the command runs and reopened afterwards. The loaded key
+    /// is saved with `TPM2_ContextSave` before and loaded back into the new connection after.
+    fn with_lockout_session<F>(
+        &mut self,
+        lockout_auth: &[u8],
+        command: F,
+    ) -> Result<(), SecurityModuleError>
+    where
+        F: FnOnce(&RawEsysContext) -> Result<(), SecurityModuleError>,
+    {
+        let auth = Auth::try_from(lockout_auth.to_vec())
+            .map_err(|e| SecurityModuleError::Tpm(TpmError::InternalError(Box::new(e))))?;
+
+        let (was_initialized, saved_key) = match self.handle.take() {
+            Some(handle) => {
+                let saved_key = self.release_context(handle)?;
+                (true, saved_key)
+            }
+            None => (false, None),
+        };
+
+        let result = RawEsysContext::open(&self.tcti).and_then(|raw| {
+            raw.set_auth(ESYS_TR_RH_LOCKOUT, auth)?;
+            command(&raw)
+        });
+
+        if was_initialized {
+            self.initialize_module()?;
+            if let Some(saved_key) = saved_key {
+                self.restore_key(saved_key)?;
+            }
+        }
+
+        result
+    }
+
+    /// Closes the ESAPI context if this provider holds the only reference to it, saving the
+    /// loaded key first.
+    ///
+    /// The saved key is flushed from the TPM, which would otherwise keep it loaded without a
+    /// resource manager.
+    fn release_context(
+        &mut self,
+        handle: Arc<Mutex<Context>>,
+    ) -> Result<Option<TpmsContext>, SecurityModuleError> {
+        let context = Arc::try_unwrap(handle).map_err(|handle| {
+            self.handle = Some(handle);
+            SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                "TPM context is shared with another provider".to_owned(),
+            ))
+        })?;
+        let mut context = context.into_inner().unwrap();
+
+        let Some(key_handle) = self.key_handle.as_ref().map(|key| *key.lock().unwrap()) else {
+            return Ok(None);
+        };
+        let saved_key = context
+            .context_save(key_handle.into())
+            .and_then(|saved_key| {
+                context.flush_context(key_handle.into())?;
+                Ok(saved_key)
+            })
+            .map_err(|e| {
+                SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+                    "The loaded key cannot be kept across the lockout command: {}",
+                    e
+                )))
+            });
+        match saved_key {
+            Ok(saved_key) => Ok(Some(saved_key)),
+            Err(e) => {
+                self.handle = Some(Arc::new(Mutex::new(context)));
+                Err(e)
+            }
+        }
+    }
+
+    /// Loads the key saved by `release_context` into the current ESAPI context.
+    fn restore_key(&mut self, saved_key: TpmsContext) -> Result<(), SecurityModuleError> {
+        let loaded = self.context()?.context_load(saved_key);
+        let key_handle = self.key_handle.take().unwrap();
+        match loaded {
+            Ok(object_handle) => {
+                *key_handle.lock().unwrap() = TssKeyHandle::from(object_handle);
+                self.key_handle = Some(key_handle);
+                Ok(())
+            }
+            Err(e) => Err(SecurityModuleError::Tpm(TpmError::InitializationError(
+                format!(
+                    "The loaded key could not be restored after the lockout command and \
+                     has to be loaded again: {}",
+                    e
+                ),
+            ))),
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use super::{message_digest, openssl_error, tss_error, TpmProvider};
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{AsymmetricEncryption, EccCurves},
+            hashes::Hash,
+            KeyBits,
+        },
+        error::SecurityModuleError,
+    },
+    tpm::core::error::TpmError,
+};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    derive::Deriver,
+    ec::{EcGroup, EcKey},
+    encrypt::Encrypter,
+    hash::{Hasher, MessageDigest},
+    nid::Nid,
+    pkey::PKey,
+    rand::rand_bytes,
+    rsa::{Padding, Rsa},
+    sign::Signer,
+    symm::{encrypt, Cipher},
+};
+use tracing::instrument;
+use tss_esapi::{
+    abstraction::{ak, ek, KeyCustomization},
+    constants::SessionType,
+    handles::{AuthHandle, KeyHandle as TssKeyHandle, SessionHandle},
+    interface_types::{
+        algorithm::{AsymmetricAlgorithm, HashingAlgorithm, SignatureSchemeAlgorithm},
+        ecc::EccCurve,
+        key_bits::AesKeyBits,
+        session_handles::{AuthSession, PolicySession},
+    },
+    structures::{
+        EccScheme, EncryptedSecret, HashScheme, IdObject, KeyDerivationFunctionScheme, Private,
+        Public, PublicBuilder, PublicEccParameters, PublicEccParametersBuilder,
+        PublicRsaParameters, PublicRsaParametersBuilder, RsaExponent, RsaScheme,
+        SymmetricDefinition, SymmetricDefinitionObject,
+    },
+    traits::Marshall,
+};
+
+/// An Endorsement Key created from the standard TCG EK templates.
+///
+/// The EK is a primary key in the endorsement hierarchy and is derived from the
+/// endorsement primary seed, so the same template yields the same key every time.
+#[derive(Debug, Clone)]
+pub struct EndorsementKey {
+    /// The transient handle of the loaded key.
+    pub handle: TssKeyHandle,
+    /// The public area, to be sent to the server together with the EK certificate.
+    pub public: Public,
+}
+
+/// An Attestation Key created as a child of the Endorsement Key.
+///
+/// `public` and `private` can be stored and passed to
+/// [`TpmProvider::load_attestation_key`] to load the key again later.
+#[derive(Debug, Clone)]
+pub struct AttestationKey {
+    /// The transient handle of the loaded key.
+    pub handle: TssKeyHandle,
+    /// The public area of the key.
+    pub public: Public,
+    /// The private area of the key, encrypted by the TPM for the EK.
+    pub private: Private,
+    /// The TPM name of the key, which the server binds the credential to.
+    pub name: Vec<u8>,
+}
+
+impl TpmProvider {
+    /// Reads the Endorsement Key certificate provisioned by the TPM manufacturer.
+    ///
+    /// The certificate is read from the NV index reserved for the low-range RSA 2048
+    /// or ECC NIST P-256 EK certificate.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_algorithm` - Either `Rsa(Bits2048)` or an ECC scheme on curve `P256`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the DER encoded certificate on success, or a `SecurityModuleError`
+    /// if the algorithm has no standard NV index or the certificate cannot be read.
+    #[instrument]
+    pub fn ek_certificate(
+        &self,
+        key_algorithm: AsymmetricEncryption,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let algorithm = ek_algorithm(key_algorithm)?;
+        ek::retrieve_ek_pubcert(&mut *self.context()?, algorithm).map_err(tss_error)
+    }
+
+    /// Creates the Endorsement Key from the default TCG template.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_algorithm` - Either `Rsa(Bits2048)` or an ECC scheme on curve `P256`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded `EndorsementKey` on success, or a `SecurityModuleError`
+    /// if the algorithm has no standard template or the TPM refuses to create the key.
+    #[instrument]
+    pub fn create_ek(
+        &self,
+        key_algorithm: AsymmetricEncryption,
+    ) -> Result<EndorsementKey, SecurityModuleError> {
+        let algorithm = ek_algorithm(key_algorithm)?;
+        let mut context = self.context()?;
+
+        let handle = ek::create_ek_object(&mut context, algorithm, None).map_err(tss_error)?;
+        let (public, _, _) = context.read_public(handle).map_err(tss_error)?;
+
+        Ok(EndorsementKey { handle, public })
+    }
+
+    /// Creates and loads a restricted signing key under the Endorsement Key.
+    ///
+    /// RSA keys sign with RSASSA, ECC keys with ECDSA.
+    ///
+    /// # Arguments
+    ///
+    /// * `ek` - The parent `EndorsementKey`.
+    /// * `key_algorithm` - The algorithm and key size or curve of the new key.
+    /// * `hash` - The name and signing hash algorithm of the new key.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded `AttestationKey` on success, or a `SecurityModuleError`
+    /// on failure.
+    #[instrument]
+    pub fn create_attestation_key(
+        &self,
+        ek: &EndorsementKey,
+        key_algorithm: AsymmetricEncryption,
+        hash: Hash,
+    ) -> Result<AttestationKey, SecurityModuleError> {
+        let hash: HashingAlgorithm = hash.try_into()?;
+        let template = AttestationKeyTemplate::new(key_algorithm, hash)?;
+        let sign_algorithm = match key_algorithm {
+            AsymmetricEncryption::Rsa(_) => SignatureSchemeAlgorithm::RsaSsa,
+            AsymmetricEncryption::Ecc(_) => SignatureSchemeAlgorithm::EcDsa,
+        };
+
+        let created = ak::create_ak(
+            &mut *self.context()?,
+            ek.handle,
+            hash,
+            sign_algorithm,
+            None,
+            template,
+        )
+        .map_err(tss_error)?;
+
+        self.load_attestation_key(ek, created.out_public, created.out_private)
+    }
+
+    /// Loads an Attestation Key previously created with [`TpmProvider::create_attestation_key`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ek` - The `EndorsementKey` the key was created under.
+    /// * `public` - The stored public area of the key.
+    /// * `private` - The stored private area of the key.
+    #[instrument(skip(private))]
+    pub fn load_attestation_key(
+        &self,
+        ek: &EndorsementKey,
+        public: Public,
+        private: Private,
+    ) -> Result<AttestationKey, SecurityModuleError> {
+        let mut context = self.context()?;
+
+        let handle = ak::load_ak(
+            &mut context,
+            ek.handle,
+            None,
+            private.clone(),
+            public.clone(),
+        )
+        .map_err(tss_error)?;
+        let (_, name, _) = context.read_public(handle).map_err(tss_error)?;
+
+        Ok(AttestationKey {
+            handle,
+            public,
+            private,
+            name: name.value().to_vec(),
+        })
+    }
+
+    /// Recovers a credential produced by [`make_credential`] (`TPM2_ActivateCredential`).
+    ///
+    /// The TPM only releases the credential if the EK can decrypt `secret` and the
+    /// credential was bound to the name of `ak`, which proves to the server that the
+    /// AK resides in the same TPM as the EK.
+    ///
+    /// # Arguments
+    ///
+    /// * `ek` - The `EndorsementKey` the credential was encrypted for.
+    /// * `ak` - The `AttestationKey` the credential was bound to.
+    /// * `credential_blob` - The encrypted credential (`TPM2B_ID_OBJECT`).
+    /// * `secret` - The encrypted seed (`TPM2B_ENCRYPTED_SECRET`).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the decrypted credential on success, or a `SecurityModuleError`
+    /// if the TPM rejects the credential.
+    #[instrument(skip(credential_blob, secret))]
+    pub fn activate_credential(
+        &self,
+        ek: &EndorsementKey,
+        ak: &AttestationKey,
+        credential_blob: &[u8],
+        secret: &[u8],
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let credential_blob = IdObject::try_from(credential_blob.to_vec()).map_err(tss_error)?;
+        let secret = EncryptedSecret::try_from(secret.to_vec()).map_err(tss_error)?;
+        let mut context = self.context()?;
+
+        // The EK only accepts a policy session satisfying PolicySecret(TPM_RH_ENDORSEMENT).
+        let ek_session = context
+            .start_auth_session(
+                None,
+                None,
+                None,
+                SessionType::Policy,
+                SymmetricDefinition::AES_128_CFB,
+                HashingAlgorithm::Sha256,
+            )
+            .map_err(tss_error)?
+            .ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::InitializationError(
+                    "Failed to start policy session".to_owned(),
+                ))
+            })?;
+
+        let result = PolicySession::try_from(ek_session)
+            .and_then(|policy_session| {
+                context.execute_with_nullauth_session(|ctx| {
+                    ctx.policy_secret(
+                        policy_session,
+                        AuthHandle::Endorsement,
+                        Default::default(),
+                        Default::default(),
+                        Default::default(),
+                        None,
+                    )
+                })
+            })
+            .and_then(|_| {
+                context.execute_with_sessions(
+                    (Some(AuthSession::Password), Some(ek_session), None),
+                    |ctx| ctx.activate_credential(ak.handle, ek.handle, credential_blob, secret),
+                )
+            });
+
+        context
+            .flush_context(SessionHandle::from(ek_session).into())
+            .map_err(tss_error)?;
+
+        Ok(result.map_err(tss_error)?.value().to_vec())
+    }
+
+    /// Flushes a transient EK or AK from the TPM.
+    ///
+    /// TPMs only hold a few transient objects at a time, so enrollment keys should be
+    /// flushed once the enrollment is finished.
+    #[instrument]
+    pub fn flush_enrollment_key(&self, handle: TssKeyHandle) -> Result<(), SecurityModuleError> {
+        self.context()?
+            .flush_context(handle.into())
+            .map_err(tss_error)
+    }
+}
+
+/// Computes the TPM name of an object from its public area.
+///
+/// The name is the name algorithm identifier followed by the digest of the
+/// marshalled public area. The server uses it to bind a credential to an AK it
+/// received from the device without having to trust the name the device reports.
+pub fn object_name(public: &Public) -> Result<Vec<u8>, SecurityModuleError> {
+    let name_algorithm = public.name_hashing_algorithm();
+    let digest = message_digest(name_algorithm)?;
+    let marshalled = public.marshall().map_err(tss_error)?;
+    let hash = openssl::hash::hash(digest, &marshalled).map_err(openssl_error)?;
+
+    let mut name = algorithm_id(name_algorithm).to_be_bytes().to_vec();
+    name.extend_from_slice(&hash);
+    Ok(name)
+}
+
+/// Encrypts `credential` for the TPM holding `ek_public`, bound to the key named `ak_name`
+/// (`TPM2_MakeCredential` in software).
+///
+/// This is the server side of the enrollment. Only the TPM holding the EK can recover the
+/// credential, and only if the key named `ak_name` is loaded in it, so returning the
+/// credential (or a value derived from it) proves that the AK is genuine.
+///
+/// # Arguments
+///
+/// * `ek_public` - The public area of the device's EK, checked against its EK certificate.
+/// * `ak_name` - The TPM name of the AK, see [`object_name`].
+/// * `credential` - The secret to protect, at most as long as a digest of the EK name algorithm.
+///
+/// # Returns
+///
+/// A `Result` containing the `(credential_blob, secret)` pair to be passed to
+/// [`TpmProvider::activate_credential`], or a `SecurityModuleError` if the EK type is unsupported.
+pub fn make_credential(
+    ek_public: &Public,
+    ak_name: &[u8],
+    credential: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), SecurityModuleError> {
+    let name_algorithm = ek_public.name_hashing_algorithm();
+    let digest = message_digest(name_algorithm)?;
+    if credential.is_empty() || credential.len() > digest.size() {
+        return Err(SecurityModuleError::EncryptionError(format!(
+            "Credential must be between 1 and {} bytes long",
+            digest.size()
+        )));
+    }
+
+    let (seed, secret, symmetric) = match ek_public {
+        Public::Rsa {
+            parameters, unique, ..
+        } => {
+            let mut seed = vec![0; digest.size()];
+            rand_bytes(&mut seed).map_err(openssl_error)?;
+            let secret =
+                rsa_encrypt_seed(parameters.exponent().value(), unique.value(), digest, &seed)?;
+            (seed, secret, parameters.symmetric_definition_object())
+        }
+        Public::Ecc {
+            parameters, unique, ..
+        } => {
+            let (seed, secret) = ecc_derive_seed(
+                parameters.ecc_curve(),
+                unique.x().value(),
+                unique.y().value(),
+                digest,
+            )?;
+            (seed, secret, parameters.symmetric_definition_object())
+        }
+        _ => {
+            return Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                "Only RSA and ECC endorsement keys are supported".to_owned(),
+            )))
+        }
+    };
+
+    let cipher = match symmetric {
+        SymmetricDefinitionObject::Aes { key_bits, .. } => match key_bits {
+            AesKeyBits::Aes128 => Cipher::aes_128_cfb128(),
+            AesKeyBits::Aes192 => Cipher::aes_192_cfb128(),
+            AesKeyBits::Aes256 => Cipher::aes_256_cfb128(),
+        },
+        _ => {
+            return Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                "The endorsement key must use AES as its storage cipher".to_owned(),
+            )))
+        }
+    };
+
+    // encIdentity = CFB(symKey, TPM2B_DIGEST(credential)), with an all-zero IV.
+    let sym_key = kdf_a(digest, &seed, "STORAGE", ak_name, &[], cipher.key_len() * 8)?;
+    let mut plaintext = (credential.len() as u16).to_be_bytes().to_vec();
+    plaintext.extend_from_slice(credential);
+    let iv = vec![0; cipher.iv_len().unwrap_or_default()];
+    let enc_identity = encrypt(cipher, &sym_key, Some(&iv), &plaintext).map_err(openssl_error)?;
+
+    // outerHMAC = HMAC(hmacKey, encIdentity || name)
+    let hmac_key = kdf_a(digest, &seed, "INTEGRITY", &[], &[], digest.size() * 8)?;
+    let hmac_key = PKey::hmac(&hmac_key).map_err(openssl_error)?;
+    let mut signer = Signer::new(digest, &hmac_key).map_err(openssl_error)?;
+    signer.update(&enc_identity).map_err(openssl_error)?;
+    signer.update(ak_name).map_err(openssl_error)?;
+    let outer_hmac = signer.sign_to_vec().map_err(openssl_error)?;
+
+    let mut credential_blob = (outer_hmac.len() as u16).to_be_bytes().to_vec();
+    credential_blob.extend_from_slice(&outer_hmac);
+    credential_blob.extend_from_slice(&enc_identity);
+
+    Ok((credential_blob, secret))
+}
+
+/// Maps the supported EK types to the algorithm of the matching default template.
+fn ek_algorithm(
+    key_algorithm: AsymmetricEncryption,
+) -> Result<AsymmetricAlgorithm, SecurityModuleError> {
+    match key_algorithm {
+        AsymmetricEncryption::Rsa(KeyBits::Bits2048) => Ok(AsymmetricAlgorithm::Rsa),
+        AsymmetricEncryption::Ecc(_) if key_algorithm.ecc_curve() == Some(EccCurves::P256) => {
+            Ok(AsymmetricAlgorithm::Ecc)
+        }
+        _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            format!("No standard EK template for {:?}", key_algorithm),
+        ))),
+    }
+}
+
+/// Replaces the fixed key size and curve of the `tss-esapi` AK template with the requested ones.
+#[derive(Debug, Clone)]
+enum AttestationKeyTemplate {
+    Rsa(PublicRsaParameters),
+    Ecc(PublicEccParameters),
+}
+
+impl AttestationKeyTemplate {
+    fn new(
+        key_algorithm: AsymmetricEncryption,
+        hash: HashingAlgorithm,
+    ) -> Result<Self, SecurityModuleError> {
+        Ok(match key_algorithm {
+            AsymmetricEncryption::Rsa(key_bits) => Self::Rsa(
+                PublicRsaParametersBuilder::new()
+                    .with_scheme(RsaScheme::RsaSsa(HashScheme::new(hash)))
+                    .with_key_bits(key_bits.try_into()?)
+                    .with_exponent(RsaExponent::default())
+                    .with_is_signing_key(true)
+                    .with_is_decryption_key(false)
+                    .with_restricted(true)
+                    .build()
+                    .map_err(tss_error)?,
+            ),
+            AsymmetricEncryption::Ecc(_) => {
+                let curve = key_algorithm.ecc_curve().ok_or_else(|| {
+                    SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                        "ECC attestation keys need a curve".to_owned(),
+                    ))
+                })?;
+                Self::Ecc(
+                    PublicEccParametersBuilder::new()
+                        .with_symmetric(SymmetricDefinitionObject::Null)
+                        .with_ecc_scheme(EccScheme::EcDsa(HashScheme::new(hash)))
+                        .with_curve(curve.try_into()?)
+                        .with_key_derivation_function_scheme(KeyDerivationFunctionScheme::Null)
+                        .with_is_signing_key(true)
+                        .with_is_decryption_key(false)
+                        .with_restricted(true)
+                        .build()
+                        .map_err(tss_error)?,
+                )
+            }
+        })
+    }
+}
+
+impl KeyCustomization for AttestationKeyTemplate {
+    fn template(&self, template_builder: PublicBuilder) -> PublicBuilder {
+        match self {
+            Self::Rsa(parameters) => template_builder.with_rsa_parameters(*parameters),
+            Self::Ecc(parameters) => template_builder.with_ecc_parameters(*parameters),
+        }
+    }
+}
+
+/// Encrypts the seed with RSA-OAEP using the label `IDENTITY`.
+fn rsa_encrypt_seed(
+    exponent: u32,
+    modulus: &[u8],
+    digest: MessageDigest,
+    seed: &[u8],
+) -> Result<Vec<u8>, SecurityModuleError> {
+    // The TPM encodes the default exponent 65537 as zero.
+    let exponent = if exponent == 0 { 65537 } else { exponent };
+    let rsa = Rsa::from_public_components(
+        BigNum::from_slice(modulus).map_err(openssl_error)?,
+        BigNum::from_u32(exponent).map_err(openssl_error)?,
+    )
+    .map_err(openssl_error)?;
+    let key = PKey::from_rsa(rsa).map_err(openssl_error)?;
+
+    let mut encrypter = Encrypter::new(&key).map_err(openssl_error)?;
+    encrypter
+        .set_rsa_padding(Padding::PKCS1_OAEP)
+        .and_then(|_| encrypter.set_rsa_oaep_md(digest))
+        .and_then(|_| encrypter.set_rsa_mgf1_md(digest))
+        .and_then(|_| encrypter.set_rsa_oaep_label(b"IDENTITY\0"))
+        .map_err(openssl_error)?;
+
+    let mut secret = vec![0; encrypter.encrypt_len(seed).map_err(openssl_error)?];
+    let len = encrypter
+        .encrypt(seed, &mut secret)
+        .map_err(openssl_error)?;
+    secret.truncate(len);
+    Ok(secret)
+}
+
+/// Derives the seed through an ephemeral ECDH exchange with the EK.
+///
+/// Returns the seed and the marshalled ephemeral public point (`TPMS_ECC_POINT`).
+fn ecc_derive_seed(
+    curve: EccCurve,
+    ek_x: &[u8],
+    ek_y: &[u8],
+    digest: MessageDigest,
+) -> Result<(Vec<u8>, Vec<u8>), SecurityModuleError> {
+    let nid = match curve {
+        EccCurve::NistP256 => Nid::X9_62_PRIME256V1,
+        EccCurve::NistP384 => Nid::SECP384R1,
+        EccCurve::NistP521 => Nid::SECP521R1,
+        _ => {
+            return Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                format!("Unsupported endorsement key curve {:?}", curve),
+            )))
+        }
+    };
+    let group = EcGroup::from_curve_name(nid).map_err(openssl_error)?;
+    let mut ctx = BigNumContext::new().map_err(openssl_error)?;
+    let size = (group.degree() as usize).div_ceil(8);
+
+    let x = BigNum::from_slice(ek_x).map_err(openssl_error)?;
+    let y = BigNum::from_slice(ek_y).map_err(openssl_error)?;
+    let ek = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+        .and_then(PKey::from_ec_key)
+        .map_err(openssl_error)?;
+
+    let ephemeral = EcKey::generate(&group).map_err(openssl_error)?;
+    let mut eph_x = BigNum::new().map_err(openssl_error)?;
+    let mut eph_y = BigNum::new().map_err(openssl_error)?;
+    ephemeral
+        .public_key()
+        .affine_coordinates(&group, &mut eph_x, &mut eph_y, &mut ctx)
+        .map_err(openssl_error)?;
+    let eph_x = eph_x.to_vec_padded(size as i32).map_err(openssl_error)?;
+    let eph_y = eph_y.to_vec_padded(size as i32).map_err(openssl_error)?;
+    let ephemeral = PKey::from_ec_key(ephemeral).map_err(openssl_error)?;
+
+    let mut deriver = Deriver::new(&ephemeral).map_err(openssl_error)?;
+    deriver.set_peer(&ek).map_err(openssl_error)?;
+    let z = deriver.derive_to_vec().map_err(openssl_error)?;
+
+    let ek_x = x.to_vec_padded(size as i32).map_err(openssl_error)?;
+    let seed = kdf_e(digest, &z, "IDENTITY", &eph_x, &ek_x, digest.size() * 8)?;
+
+    let mut secret = Vec::with_capacity(4 + 2 * size);
+    for coordinate in [&eph_x, &eph_y] {
+        secret.extend_from_slice(&(coordinate.len() as u16).to_be_bytes());
+        secret.extend_from_slice(coordinate);
+    }
+
+    Ok((seed, secret))
+}
+
+/// The counter mode KDF from SP 800-108 used by TPM 2.0 (`KDFa`).
+fn kdf_a(
+    digest: MessageDigest,
+    key: &[u8],
+    label: &str,
+    context_u: &[u8],
+    context_v: &[u8],
+    bits: usize,
+) -> Result<Vec<u8>, SecurityModuleError> {
+    let key = PKey::hmac(key).map_err(openssl_error)?;
+    let mut out = Vec::with_capacity(bits / 8 + digest.size());
+    let mut counter = 1u32;
+
+    while out.len() < bits / 8 {
+        let mut signer = Signer::new(digest, &key).map_err(openssl_error)?;
+        for part in [
+            &counter.to_be_bytes()[..],
+            label.as_bytes(),
+            &[0],
+            context_u,
+            context_v,
+            &(bits as u32).to_be_bytes(),
+        ] {
+            signer.update(part).map_err(openssl_error)?;
+        }
+        out.extend(signer.sign_to_vec().map_err(openssl_error)?);
+        counter += 1;
+    }
+
+    out.truncate(bits / 8);
+    Ok(out)
+}
+
+/// The hash based KDF from SP 800-56A used by TPM 2.0 for ECDH (`KDFe`).
+fn kdf_e(
+    digest: MessageDigest,
+    z: &[u8],
+    label: &str,
+    party_u: &[u8],
+    party_v: &[u8],
+    bits: usize,
+) -> Result<Vec<u8>, SecurityModuleError> {
+    let mut out = Vec::with_capacity(bits / 8 + digest.size());
+    let mut counter = 1u32;
+
+    while out.len() < bits / 8 {
+        let mut hasher = Hasher::new(digest).map_err(openssl_error)?;
+        for part in [
+            &counter.to_be_bytes()[..],
+            z,
+            label.as_bytes(),
+            &[0],
+            party_u,
+            party_v,
+        ] {
+            hasher.update(part).map_err(openssl_error)?;
+        }
+        out.extend_from_slice(&hasher.finish().map_err(openssl_error)?);
+        counter += 1;
+    }
+
+    out.truncate(bits / 8);
+    Ok(out)
+}
+
+fn algorithm_id(algorithm: HashingAlgorithm) -> u16 {
+    tss_esapi::constants::AlgorithmIdentifier::from(algorithm).into()
+}
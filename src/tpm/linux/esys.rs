@@ -0,0 +1,80 @@
+use super::TctiConfig;
+use crate::{common::error::SecurityModuleError, tpm::core::error::TpmError};
+use std::{ffi::CString, ptr::null_mut};
+use tracing::error;
+use tss_esapi::{
+    constants::Tss2ResponseCode,
+    structures::Auth,
+    tss2_esys::{
+        Esys_Finalize, Esys_Initialize, Esys_TR_SetAuth, Tss2_TctiLdr_Finalize,
+        Tss2_TctiLdr_Initialize, ESYS_CONTEXT, ESYS_TR, TPM2B_AUTH, TSS2_RC, TSS2_TCTI_CONTEXT,
+    },
+    TctiNameConf,
+};
+
+/// Maps a raw TSS2 return code to a `SecurityModuleError`.
+pub(super) fn check_rc(rc: TSS2_RC, command: &str) -> Result<(), SecurityModuleError> {
+    let response_code = Tss2ResponseCode::from(rc);
+    if response_code.is_success() {
+        Ok(())
+    } else {
+        error!("{} failed: {}", command, response_code);
+        Err(SecurityModuleError::Tpm(TpmError::InternalError(Box::new(
+            response_code,
+        ))))
+    }
+}
+
+/// A raw ESAPI connection for commands not wrapped by `tss-esapi`.
+pub(super) struct RawEsysContext {
+    pub(super) esys: *mut ESYS_CONTEXT,
+    tcti: *mut TSS2_TCTI_CONTEXT,
+}
+
+impl RawEsysContext {
+    pub(super) fn open(tcti: &TctiConfig) -> Result<Self, SecurityModuleError> {
+        let name_conf = CString::try_from(TctiNameConf::try_from(tcti)?)
+            .map_err(|e| SecurityModuleError::Tpm(TpmError::InternalError(Box::new(e))))?;
+
+        let mut raw = Self {
+            esys: null_mut(),
+            tcti: null_mut(),
+        };
+        // SAFETY: all pointers are valid, ownership of the created contexts moves into `raw`,
+        // which releases them on drop.
+        check_rc(
+            unsafe { Tss2_TctiLdr_Initialize(name_conf.as_ptr(), &mut raw.tcti) },
+            "Tss2_TctiLdr_Initialize",
+        )?;
+        check_rc(
+            unsafe { Esys_Initialize(&mut raw.esys, raw.tcti, null_mut()) },
+            "Esys_Initialize",
+        )?;
+
+        Ok(raw)
+    }
+
+    /// Sets the authorization value used for `handle` in password sessions.
+    pub(super) fn set_auth(&self, handle: ESYS_TR, auth: Auth) -> Result<(), SecurityModuleError> {
+        let auth = TPM2B_AUTH::from(auth);
+        // SAFETY: `self.esys` is valid and `auth` outlives the call.
+        check_rc(
+            unsafe { Esys_TR_SetAuth(self.esys, handle, &auth) },
+            "Esys_TR_SetAuth",
+        )
+    }
+}
+
+impl Drop for RawEsysContext {
+    fn drop(&mut self) {
+        // SAFETY: both contexts were created in `open` and are released exactly once.
+        unsafe {
+            if !self.esys.is_null() {
+                Esys_Finalize(&mut self.esys);
+            }
+            if !self.tcti.is_null() {
+                Tss2_TctiLdr_Finalize(&mut self.tcti);
+            }
+        }
+    }
+}
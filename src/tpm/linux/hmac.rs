@@ -0,0 +1,282 @@
+use super::{
+    esys::{check_rc, RawEsysContext},
+    message_digest, openssl_error, tss_error, TctiConfig, TpmProvider,
+};
+use crate::{
+    common::{crypto::algorithms::hashes::Hash, error::SecurityModuleError},
+    tpm::core::error::TpmError,
+};
+use openssl::hash::hash;
+use std::{mem::MaybeUninit, ptr::null_mut};
+use tss_esapi::{
+    attributes::ObjectAttributesBuilder,
+    constants::AlgorithmIdentifier,
+    interface_types::{
+        algorithm::{HashingAlgorithm, PublicAlgorithm},
+        resource_handles::Hierarchy,
+    },
+    structures::{
+        Digest, HmacScheme, KeyedHashScheme, MaxBuffer, Public, PublicBuilder,
+        PublicKeyedHashParameters,
+    },
+    tss2_esys::{
+        Esys_CreatePrimary, Esys_FlushContext, Esys_Free, Esys_HMAC_Start, Esys_SequenceComplete,
+        Esys_SequenceUpdate, ESYS_TR, ESYS_TR_NONE, ESYS_TR_PASSWORD, ESYS_TR_RH_NULL,
+        ESYS_TR_RH_OWNER, TPM2B_AUTH, TPM2B_DATA, TPM2B_DIGEST, TPM2B_MAX_BUFFER, TPM2B_PUBLIC,
+        TPM2B_SENSITIVE_CREATE, TPML_PCR_SELECTION,
+    },
+};
+
+impl TpmProvider {
+    /// Creates the HMAC key `key_id` in the owner hierarchy.
+    ///
+    /// The key is a primary key whose unique field is derived from `key_id`, so the TPM
+    /// derives the same key from its owner seed every time it is created. Creating and
+    /// loading an HMAC key are therefore the same operation, and the key material never
+    /// has to be stored outside the TPM.
+    pub(super) fn create_hmac_key(
+        &mut self,
+        key_id: &str,
+        hash: Hash,
+    ) -> Result<(), SecurityModuleError> {
+        let public = hmac_key_template(key_id, hash.try_into()?)?;
+
+        let key_handle = self
+            .context()?
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create_primary(Hierarchy::Owner, public, None, None, None, None)
+            })
+            .map_err(tss_error)?
+            .key_handle;
+
+        self.replace_key_handle(key_handle)?;
+        self.key_algorithm = None;
+        self.sym_algorithm = None;
+        self.hash = Some(hash);
+        self.key_usages = None;
+
+        Ok(())
+    }
+
+    /// Computes the HMAC of `data` with the loaded HMAC key.
+    ///
+    /// Inputs up to the size of a `TPM2B_MAX_BUFFER` are authenticated with a single
+    /// `TPM2_HMAC`. Larger inputs are streamed through an HMAC sequence, which
+    /// `tss-esapi` does not wrap; the sequence runs on a second connection and therefore
+    /// needs a resource manager such as `/dev/tpmrm0` or `tpm2-abrmd`. The check is made
+    /// on the resolved TCTI, so a simulator or raw device named in the environment is
+    /// refused as well.
+    pub(super) fn hmac(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key_handle = *self
+            .key_handle
+            .as_ref()
+            .ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::InitializationError("No key loaded".to_owned()))
+            })?
+            .lock()
+            .unwrap();
+        let algorithm: HashingAlgorithm = self
+            .hash
+            .ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::InitializationError("No key loaded".to_owned()))
+            })?
+            .try_into()?;
+        let mut context = self.context()?;
+
+        if data.len() <= MaxBuffer::MAX_SIZE {
+            let buffer = MaxBuffer::try_from(data).map_err(tss_error)?;
+            return context
+                .execute_with_nullauth_session(|ctx| ctx.hmac(key_handle.into(), buffer, algorithm))
+                .map(|digest| digest.value().to_vec())
+                .map_err(tss_error);
+        }
+
+        let tcti = self.tcti.resolve()?;
+        if !tcti.is_resource_managed() {
+            return Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                format!(
+                    "HMAC sequences need a second TPM connection, which '{}' does not serve; \
+                     inputs are limited to {} bytes",
+                    tcti.to_tcti_string(),
+                    MaxBuffer::MAX_SIZE
+                ),
+            )));
+        }
+
+        let (public, _, _) = context.read_public(key_handle).map_err(tss_error)?;
+        drop(context);
+
+        hmac_sequence(&tcti, public, algorithm, data)
+    }
+}
+
+/// Builds the public template of an HMAC key.
+pub(crate) fn hmac_key_template(
+    key_id: &str,
+    algorithm: HashingAlgorithm,
+) -> Result<Public, SecurityModuleError> {
+    let unique = hash(message_digest(algorithm)?, key_id.as_bytes()).map_err(openssl_error)?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::KeyedHash)
+        .with_name_hashing_algorithm(algorithm)
+        .with_object_attributes(
+            ObjectAttributesBuilder::new()
+                .with_fixed_tpm(true)
+                .with_fixed_parent(true)
+                .with_sensitive_data_origin(true)
+                .with_user_with_auth(true)
+                .with_sign_encrypt(true)
+                .build()
+                .map_err(tss_error)?,
+        )
+        .with_keyed_hash_parameters(PublicKeyedHashParameters::new(KeyedHashScheme::Hmac {
+            hmac_scheme: HmacScheme::new(algorithm),
+        }))
+        .with_keyed_hash_unique_identifier(Digest::try_from(unique.to_vec()).map_err(tss_error)?)
+        .build()
+        .map_err(tss_error)
+}
+
+/// Runs `TPM2_HMAC_Start`, `TPM2_SequenceUpdate` and `TPM2_SequenceComplete` over `data`.
+///
+/// The key is recreated from its template on a dedicated connection, which yields the
+/// same key as the one loaded by the provider.
+pub(crate) fn hmac_sequence(
+    tcti: &TctiConfig,
+    public: Public,
+    algorithm: HashingAlgorithm,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityModuleError> {
+    let raw = RawEsysContext::open(tcti)?;
+    let in_public = TPM2B_PUBLIC::try_from(public).map_err(tss_error)?;
+    // SAFETY: all-zero is the valid empty value of these plain C structures.
+    let (in_sensitive, outside_info, creation_pcr): (
+        TPM2B_SENSITIVE_CREATE,
+        TPM2B_DATA,
+        TPML_PCR_SELECTION,
+    ) = unsafe {
+        (
+            MaybeUninit::zeroed().assume_init(),
+            MaybeUninit::zeroed().assume_init(),
+            MaybeUninit::zeroed().assume_init(),
+        )
+    };
+
+    let mut key: ESYS_TR = ESYS_TR_NONE;
+    // SAFETY: `raw.esys` is valid, all inputs outlive the call and the optional outputs are
+    // not requested.
+    check_rc(
+        unsafe {
+            Esys_CreatePrimary(
+                raw.esys,
+                ESYS_TR_RH_OWNER,
+                ESYS_TR_PASSWORD,
+                ESYS_TR_NONE,
+                ESYS_TR_NONE,
+                &in_sensitive,
+                &in_public,
+                &outside_info,
+                &creation_pcr,
+                &mut key,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                null_mut(),
+            )
+        },
+        "TPM2_CreatePrimary",
+    )?;
+
+    let result = run_sequence(&raw, key, algorithm, data);
+
+    // SAFETY: `key` was created above and is flushed exactly once.
+    check_rc(
+        unsafe { Esys_FlushContext(raw.esys, key) },
+        "TPM2_FlushContext",
+    )?;
+
+    result
+}
+
+fn run_sequence(
+    raw: &RawEsysContext,
+    key: ESYS_TR,
+    algorithm: HashingAlgorithm,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityModuleError> {
+    // SAFETY: an all-zero `TPM2B_AUTH` is the empty authorization value.
+    let auth: TPM2B_AUTH = unsafe { MaybeUninit::zeroed().assume_init() };
+    let mut sequence: ESYS_TR = ESYS_TR_NONE;
+    // SAFETY: `raw.esys` and `key` are valid and all inputs outlive the call.
+    check_rc(
+        unsafe {
+            Esys_HMAC_Start(
+                raw.esys,
+                key,
+                ESYS_TR_PASSWORD,
+                ESYS_TR_NONE,
+                ESYS_TR_NONE,
+                &auth,
+                AlgorithmIdentifier::from(algorithm).into(),
+                &mut sequence,
+            )
+        },
+        "TPM2_HMAC_Start",
+    )?;
+
+    // The last chunk is passed to `TPM2_SequenceComplete`, which also ends the sequence.
+    let mut chunks = data.chunks(MaxBuffer::MAX_SIZE).peekable();
+    let mut last = TPM2B_MAX_BUFFER::from(MaxBuffer::default());
+    while let Some(chunk) = chunks.next() {
+        let buffer = TPM2B_MAX_BUFFER::from(MaxBuffer::try_from(chunk).map_err(tss_error)?);
+        if chunks.peek().is_none() {
+            last = buffer;
+            break;
+        }
+        // SAFETY: `raw.esys` and `sequence` are valid and `buffer` outlives the call.
+        check_rc(
+            unsafe {
+                Esys_SequenceUpdate(
+                    raw.esys,
+                    sequence,
+                    ESYS_TR_PASSWORD,
+                    ESYS_TR_NONE,
+                    ESYS_TR_NONE,
+                    &buffer,
+                )
+            },
+            "TPM2_SequenceUpdate",
+        )?;
+    }
+
+    let mut result: *mut TPM2B_DIGEST = null_mut();
+    // SAFETY: `raw.esys` and `sequence` are valid, `last` outlives the call and the returned
+    // digest is copied and freed below.
+    check_rc(
+        unsafe {
+            Esys_SequenceComplete(
+                raw.esys,
+                sequence,
+                ESYS_TR_PASSWORD,
+                ESYS_TR_NONE,
+                ESYS_TR_NONE,
+                &last,
+                ESYS_TR_RH_NULL,
+                &mut result,
+                null_mut(),
+            )
+        },
+        "TPM2_SequenceComplete",
+    )?;
+
+    // SAFETY: on success ESAPI returns a valid, heap allocated digest owned by the caller.
+    let digest = unsafe {
+        let digest = &*result;
+        let mac = digest.buffer[..digest.size as usize].to_vec();
+        Esys_Free(result.cast());
+        mac
+    };
+
+    Ok(digest)
+}
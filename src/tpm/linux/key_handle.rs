@@ -1,21 +1,200 @@
-use super::TpmProvider;
-use crate::common::{
-    crypto::algorithms::encryption::AsymmetricEncryption, error::SecurityModuleError,
-    traits::key_handle::KeyHandle,
+use super::{openssl_error, tss_error, TpmProvider};
+use crate::{
+    common::{
+        capabilities::Operation,
+        crypto::algorithms::encryption::{AsymmetricEncryption, EccSchemeAlgorithm},
+        error::SecurityModuleError,
+        traits::{
+            key_handle::KeyHandle,
+            operations::{Decryptor, Encryptor, KeyAgreement, Signer, Verifier},
+        },
+    },
+    tpm::core::error::TpmError,
+};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    memcmp,
+    pkey::PKey,
 };
 use tracing::instrument;
 use tss_esapi::{
-    interface_types::{algorithm::SymmetricMode, resource_handles::Hierarchy},
+    handles::KeyHandle as TssKeyHandle,
+    interface_types::{algorithm::HashingAlgorithm, ecc::EccCurve, resource_handles::Hierarchy},
     structures::{
-        Data, EccParameter, EccSignature, HashScheme, InitialValue, MaxBuffer, PublicKeyRsa,
+        Data, EccParameter, EccPoint, EccSignature, HashScheme, MaxBuffer, PublicKeyRsa,
         RsaDecryptionScheme, RsaSignature, Signature, SignatureScheme,
     },
-    traits::Marshall,
 };
 
 impl KeyHandle for TpmProvider {
+    /// Returns the key as a `Signer` if it is an RSA or ECC signing key created with
+    /// `KeyUsage::SignEncrypt`.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        (self.is_signing_key() && self.permits(Operation::Sign)).then_some(self)
+    }
+
+    /// Returns the key as a `Verifier` if it is an RSA or ECC signing key.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        self.is_signing_key().then_some(self)
+    }
+
+    /// Returns the key as an `Encryptor` if it is a symmetric key, or an RSA key created
+    /// with `KeyUsage::Decrypt`.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        self.is_encryption_key(Operation::Encrypt).then_some(self)
+    }
+
+    /// Returns the key as a `Decryptor` if it is a symmetric key, or an RSA key created
+    /// with `KeyUsage::Decrypt`.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        self.is_encryption_key(Operation::Decrypt).then_some(self)
+    }
+
+    /// Returns the key as a `KeyAgreement` if it is an ECDH key created with
+    /// `KeyUsage::Decrypt`.
+    fn as_key_agreement(&self) -> Option<&dyn KeyAgreement> {
+        (matches!(
+            self.key_algorithm,
+            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDh(_)))
+        ) && self.permits(Operation::KeyAgreement))
+        .then_some(self)
+    }
+
+    /// Computes the HMAC of the given data using the HMAC key managed by the TPM provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - A byte slice representing the data to be authenticated.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the MAC as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
+    #[instrument(skip(data))]
+    fn mac_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        self.hmac(data)
+            .map_err(|e| SecurityModuleError::SigningError(e.to_string()))
+    }
+
+    /// Verifies the HMAC of the given data using the HMAC key managed by the TPM provider.
+    ///
+    /// The MAC is recomputed by the TPM and compared in constant time.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - A byte slice representing the authenticated data.
+    /// * `mac` - A byte slice representing the MAC to be verified against the data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a boolean indicating whether the MAC is valid (`true`) or not (`false`),
+    /// or a `SecurityModuleError` on failure.
+    #[instrument(skip(data, mac))]
+    fn verify_mac(&self, data: &[u8], mac: &[u8]) -> Result<bool, SecurityModuleError> {
+        let expected = self
+            .hmac(data)
+            .map_err(|e| SecurityModuleError::SignatureVerificationError(e.to_string()))?;
+
+        Ok(expected.len() == mac.len() && memcmp::eq(&expected, mac))
+    }
+}
+
+impl TpmProvider {
+    /// Returns whether the loaded key is an asymmetric key with a signature scheme.
+    fn is_signing_key(&self) -> bool {
+        match self.key_algorithm {
+            Some(AsymmetricEncryption::Rsa(_)) => true,
+            Some(AsymmetricEncryption::Ecc(scheme)) => !matches!(
+                scheme,
+                EccSchemeAlgorithm::EcDh(_)
+                    | EccSchemeAlgorithm::EcMqv(_)
+                    | EccSchemeAlgorithm::Null
+            ),
+            None => false,
+        }
+    }
+
+    /// Returns whether the loaded key can perform `operation`, which is `Encrypt` or
+    /// `Decrypt`.
+    fn is_encryption_key(&self, operation: Operation) -> bool {
+        match (self.key_algorithm, self.sym_algorithm) {
+            (None, Some(_)) => true,
+            (Some(AsymmetricEncryption::Rsa(_)), _) => self.permits(operation),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the usages the key was created with permit `operation`.
+    ///
+    /// HMAC and symmetric keys are created without usages and are not restricted.
+    fn permits(&self, operation: Operation) -> bool {
+        self.key_usages
+            .as_ref()
+            .is_none_or(|usages| operation.permitted_by(usages))
+    }
+
+    /// Returns the handle of the loaded key.
+    fn loaded_key(&self) -> Result<TssKeyHandle, SecurityModuleError> {
+        let key_handle = self.key_handle.as_ref().ok_or_else(|| {
+            SecurityModuleError::Tpm(TpmError::InitializationError("No key loaded".to_owned()))
+        })?;
+        Ok(*key_handle.lock().unwrap())
+    }
+
+    /// Returns the hash the loaded key was created with, which its signature and OAEP
+    /// schemes use.
+    fn signature_hash(&self) -> Result<HashingAlgorithm, SecurityModuleError> {
+        self.hash
+            .ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::InitializationError("No key loaded".to_owned()))
+            })?
+            .try_into()
+    }
+
+    /// Returns the signature scheme of the loaded key.
+    ///
+    /// The keys are created without a scheme, see `TpmProvider::create_key`, so the scheme
+    /// is passed with every signature.
+    fn signature_scheme(&self) -> Result<SignatureScheme, SecurityModuleError> {
+        let hash_scheme = HashScheme::new(self.signature_hash()?);
+        match self.key_algorithm {
+            Some(AsymmetricEncryption::Rsa(_)) => Ok(SignatureScheme::RsaSsa { hash_scheme }),
+            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcDsa(_))) => {
+                Ok(SignatureScheme::EcDsa { hash_scheme })
+            }
+            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::Sm2(_))) => {
+                Ok(SignatureScheme::Sm2 { hash_scheme })
+            }
+            Some(AsymmetricEncryption::Ecc(EccSchemeAlgorithm::EcSchnorr(_))) => {
+                Ok(SignatureScheme::EcSchnorr { hash_scheme })
+            }
+            algorithm => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                format!("{:?} keys cannot sign", algorithm),
+            ))),
+        }
+    }
+
+    /// Returns the size in bytes of the coordinates of the loaded ECC key's curve.
+    fn ecc_coordinate_size(&self) -> Result<usize, SecurityModuleError> {
+        let curve = self
+            .key_algorithm
+            .and_then(|algorithm| algorithm.ecc_curve());
+        match curve.map(EccCurve::try_from).transpose()? {
+            Some(EccCurve::NistP256) => Ok(32),
+            Some(EccCurve::NistP384) => Ok(48),
+            Some(EccCurve::NistP521) => Ok(66),
+            _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                format!("No coordinate size for {:?}", curve),
+            ))),
+        }
+    }
+}
+
+impl Signer for TpmProvider {
     /// Signs the given data using the cryptographic key managed by the TPM provider.
     ///
+    /// RSA signatures are returned as they are, ECC signatures as `r || s` with both values
+    /// padded to the size of the curve.
+    ///
     /// # Arguments
     ///
     /// * `data` - A byte slice representing the data to be signed.
@@ -24,56 +203,50 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the signature as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn sign_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        let key_handle = *self.key_handle.as_ref().unwrap().lock().unwrap();
-        let ticket = self
-            .handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key_handle = self.loaded_key()?;
+        let scheme = self.signature_scheme()?;
+        let mut context = self.context()?;
+        let (digest, ticket) = context
             .hash(
-                MaxBuffer::try_from(data).unwrap(),
-                self.hash.unwrap().into(),
+                MaxBuffer::try_from(data).map_err(tss_error)?,
+                self.signature_hash()?,
                 Hierarchy::Null,
             )
             .map_err(|e| SecurityModuleError::SigningError(e.to_string()))?;
 
-        let signature = match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(_) => self
-                .handle
-                .as_ref()
-                .unwrap()
-                .lock()
-                .unwrap()
-                .sign(
-                    key_handle,
-                    ticket.0,
-                    SignatureScheme::RsaSsa {
-                        hash_scheme: HashScheme::new(self.hash.unwrap().into()),
-                    },
-                    ticket.1,
-                )
-                .map_err(|e| SecurityModuleError::SigningError(e.to_string()))?,
-            AsymmetricEncryption::Ecc(ecc_scheme) => {
-                let signature_scheme: SignatureScheme = (*ecc_scheme).into();
-                self.handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .sign(key_handle, ticket.0, signature_scheme, ticket.1)
-                    .map_err(|e| SecurityModuleError::SigningError(e.to_string()))?
-            }
-        };
+        let signature = context
+            .execute_with_nullauth_session(|ctx| ctx.sign(key_handle, digest, scheme, ticket))
+            .map_err(|e| SecurityModuleError::SigningError(e.to_string()))?;
 
-        signature
-            .marshall()
-            .map_err(|e| SecurityModuleError::SigningError(e.to_string()))
+        match signature {
+            Signature::RsaSsa(signature) => Ok(signature.signature().value().to_vec()),
+            Signature::EcDsa(signature)
+            | Signature::Sm2(signature)
+            | Signature::EcSchnorr(signature) => {
+                let size = self.ecc_coordinate_size()?;
+                let mut raw = vec![0; 2 * size];
+                for (value, target) in [signature.signature_r(), signature.signature_s()]
+                    .into_iter()
+                    .zip(raw.chunks_mut(size))
+                {
+                    let value = value.value();
+                    target[size - value.len()..].copy_from_slice(value);
+                }
+                Ok(raw)
+            }
+            _ => Err(SecurityModuleError::SigningError(
+                "Unexpected signature type".to_owned(),
+            )),
+        }
     }
+}
 
+impl Decryptor for TpmProvider {
     /// Decrypts the given encrypted data using the cryptographic key managed by the TPM provider.
     ///
+    /// RSA keys decrypt with OAEP and the hash the key was created with, without a label.
+    ///
     /// # Arguments
     ///
     /// * `encrypted_data` - A byte slice representing the data to be decrypted.
@@ -82,56 +255,39 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn decrypt_data(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        let key_handle = *self.key_handle.as_ref().unwrap().lock().unwrap();
-
-        match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(_) => {
-                let scheme =
-                    RsaDecryptionScheme::Oaep(HashScheme::new(self.hash.unwrap().into()));
-                let pub_key = PublicKeyRsa::try_from(encrypted_data)
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        if let (None, Some(sym_algorithm)) = (self.key_algorithm, self.sym_algorithm) {
+            return self.symmetric_decrypt(sym_algorithm, encrypted_data);
+        }
+
+        let key_handle = self.loaded_key()?;
+
+        match self.key_algorithm {
+            Some(AsymmetricEncryption::Rsa(_)) => {
+                let scheme = RsaDecryptionScheme::Oaep(HashScheme::new(self.signature_hash()?));
+                let cipher_text = PublicKeyRsa::try_from(encrypted_data)
                     .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?;
                 let decryption_result = self
-                    .handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .rsa_decrypt(
-                        key_handle,
-                        pub_key,
-                        scheme,
-                        Data::try_from(encrypted_data)
-                            .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?,
-                    )
+                    .context()?
+                    .execute_with_nullauth_session(|ctx| {
+                        ctx.rsa_decrypt(key_handle, cipher_text, scheme, Data::default())
+                    })
                     .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?;
-                Ok(decryption_result.to_vec())
-            }
-            AsymmetricEncryption::Ecc(_) => {
-                let initial_value = InitialValue::try_from(vec![0u8; 16])
-                    .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?;
-                let (decrypted_data, _) = self
-                    .handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .encrypt_decrypt_2(
-                        key_handle,
-                        true,
-                        SymmetricMode::Cfb,
-                        MaxBuffer::try_from(encrypted_data)
-                            .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?,
-                        initial_value,
-                    )
-                    .map_err(|e| SecurityModuleError::DecryptionError(e.to_string()))?;
-                Ok(decrypted_data.to_vec())
+                Ok(decryption_result.value().to_vec())
             }
+            _ => Err(SecurityModuleError::UnsupportedOperation(
+                "ECC keys cannot decrypt data, use a key created with a TpmSymmetricConfig"
+                    .to_owned(),
+            )),
         }
     }
+}
 
+impl Encryptor for TpmProvider {
     /// Encrypts the given data using the cryptographic key managed by the TPM provider.
     ///
+    /// RSA keys encrypt with OAEP and the hash the key was created with, without a label.
+    ///
     /// # Arguments
     ///
     /// * `data` - A byte slice representing the data to be encrypted.
@@ -140,56 +296,37 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
-        let key_handle = *self.key_handle.as_ref().unwrap().lock().unwrap();
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        if let (None, Some(sym_algorithm)) = (self.key_algorithm, self.sym_algorithm) {
+            return self.symmetric_encrypt(sym_algorithm, data);
+        }
+
+        let key_handle = self.loaded_key()?;
 
-        match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(_) => {
-                let scheme =
-                    RsaDecryptionScheme::Oaep(HashScheme::new(self.hash.unwrap().into()));
+        match self.key_algorithm {
+            Some(AsymmetricEncryption::Rsa(_)) => {
+                let scheme = RsaDecryptionScheme::Oaep(HashScheme::new(self.signature_hash()?));
                 let message = PublicKeyRsa::try_from(data)
                     .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?;
                 let encryption_result = self
-                    .handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .rsa_encrypt(
-                        key_handle,
-                        message,
-                        scheme,
-                        Data::try_from(data)
-                            .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?,
-                    )
+                    .context()?
+                    .rsa_encrypt(key_handle, message, scheme, Data::default())
                     .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?;
                 Ok(encryption_result.value().to_vec())
             }
-            AsymmetricEncryption::Ecc(_) => {
-                let initial_value = InitialValue::try_from(vec![0u8; 16])
-                    .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?;
-                let (encrypted_data, _) = self
-                    .handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .encrypt_decrypt_2(
-                        key_handle,
-                        false,
-                        SymmetricMode::Cfb,
-                        MaxBuffer::try_from(data)
-                            .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?,
-                        initial_value,
-                    )
-                    .map_err(|e| SecurityModuleError::EncryptionError(e.to_string()))?;
-                Ok(encrypted_data.to_vec())
-            }
+            _ => Err(SecurityModuleError::UnsupportedOperation(
+                "ECC keys cannot encrypt data, use a key created with a TpmSymmetricConfig"
+                    .to_owned(),
+            )),
         }
     }
+}
 
+impl Verifier for TpmProvider {
     /// Verifies the signature of the given data using the cryptographic key managed by the TPM provider.
     ///
+    /// The signature has the format returned by `sign`.
+    ///
     /// # Arguments
     ///
     /// * `data` - A byte slice representing the data whose signature is to be verified.
@@ -200,78 +337,93 @@ impl KeyHandle for TpmProvider {
     /// A `Result` containing a boolean indicating whether the signature is valid (`true`) or not (`false`),
     /// or a `SecurityModuleError` on failure.
     #[instrument]
-    fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
-        let key_handle = *self.key_handle.as_ref().unwrap().lock().unwrap();
-        let digest = self
-            .handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+        let key_handle = self.loaded_key()?;
+        let hash = self.signature_hash()?;
+        let verification_error =
+            |e: tss_esapi::Error| SecurityModuleError::SignatureVerificationError(e.to_string());
+
+        let signature = match self.signature_scheme()? {
+            SignatureScheme::RsaSsa { .. } => Signature::RsaSsa(
+                RsaSignature::create(
+                    hash,
+                    PublicKeyRsa::try_from(signature).map_err(verification_error)?,
+                )
+                .map_err(verification_error)?,
+            ),
+            scheme => {
+                if signature.is_empty() || !signature.len().is_multiple_of(2) {
+                    return Ok(false);
+                }
+                let (r, s) = signature.split_at(signature.len() / 2);
+                let signature = EccSignature::create(
+                    hash,
+                    EccParameter::try_from(r).map_err(verification_error)?,
+                    EccParameter::try_from(s).map_err(verification_error)?,
+                )
+                .map_err(verification_error)?;
+                match scheme {
+                    SignatureScheme::Sm2 { .. } => Signature::Sm2(signature),
+                    SignatureScheme::EcSchnorr { .. } => Signature::EcSchnorr(signature),
+                    _ => Signature::EcDsa(signature),
+                }
+            }
+        };
+
+        let mut context = self.context()?;
+        let (digest, _) = context
             .hash(
-                MaxBuffer::try_from(data).unwrap(),
-                self.hash.unwrap().into(),
+                MaxBuffer::try_from(data).map_err(verification_error)?,
+                hash,
                 Hierarchy::Null,
             )
-            .map_err(|e| SecurityModuleError::SignatureVerificationError(e.to_string()))?
-            .0;
-
-        let verification_result = match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(_) => {
-                let signature = PublicKeyRsa::try_from(signature)
-                    .map_err(|e| SecurityModuleError::SignatureVerificationError(e.to_string()))?;
-                let rsa_signature =
-                    RsaSignature::create(self.hash.unwrap().into(), signature).map_err(
-                        |e| SecurityModuleError::SignatureVerificationError(e.to_string()),
-                    )?;
-                self.handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .verify_signature(key_handle, digest, Signature::RsaSsa(rsa_signature))
-                    .is_ok()
-            }
-            AsymmetricEncryption::Ecc(ecc_scheme) => {
-                let signature_scheme: SignatureScheme = (*ecc_scheme).into();
-                let (signature_r, signature_s) = match signature.split_at(signature.len() / 2) {
-                    (&[], &[]) => {
-                        return Err(SecurityModuleError::SignatureVerificationError(
-                            "Invalid signature length".to_string(),
-                        ))
-                    }
-                    (r, s) => (
-                        EccParameter::try_from(r).map_err(|e| {
-                            SecurityModuleError::SignatureVerificationError(e.to_string())
-                        })?,
-                        EccParameter::try_from(s).map_err(|e| {
-                            SecurityModuleError::SignatureVerificationError(e.to_string())
-                        })?,
-                    ),
-                };
-                let ecc_signature = EccSignature::create(
-                    self.hash.unwrap().into(),
-                    signature_r,
-                    signature_s,
-                )
-                .map_err(|e| SecurityModuleError::SignatureVerificationError(e.to_string()))?;
-                let signature = match signature_scheme {
-                    SignatureScheme::EcDsa { .. } => Signature::EcDsa(ecc_signature),
-                    SignatureScheme::EcDaa { .. } => Signature::EcDaa(ecc_signature),
-                    SignatureScheme::Sm2 { .. } => Signature::Sm2(ecc_signature),
-                    SignatureScheme::EcSchnorr { .. } => Signature::EcSchnorr(ecc_signature),
-                    _ => unreachable!(),
-                };
-                self.handle
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .verify_signature(key_handle, digest, signature)
-                    .is_ok()
-            }
+            .map_err(verification_error)?;
+
+        Ok(context
+            .verify_signature(key_handle, digest, signature)
+            .is_ok())
+    }
+}
+
+impl KeyAgreement for TpmProvider {
+    /// Derives the ECDH shared secret with `peer_public_key` using `TPM2_ECDH_ZGen`.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_public_key` - The DER encoded SubjectPublicKeyInfo of the peer's key.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the x coordinate of the shared point on success, or a
+    /// `SecurityModuleError` on failure.
+    #[instrument(skip(peer_public_key))]
+    fn agree(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+        let key_handle = self.loaded_key()?;
+
+        let peer = PKey::public_key_from_der(peer_public_key)
+            .and_then(|key| key.ec_key())
+            .map_err(openssl_error)?;
+        let group = peer.group();
+        let size = (group.degree() as usize).div_ceil(8);
+        let mut x = BigNum::new().map_err(openssl_error)?;
+        let mut y = BigNum::new().map_err(openssl_error)?;
+        let mut bn_ctx = BigNumContext::new().map_err(openssl_error)?;
+        peer.public_key()
+            .affine_coordinates(group, &mut x, &mut y, &mut bn_ctx)
+            .map_err(openssl_error)?;
+        let coordinate = |value: &BigNum| {
+            value
+                .to_vec_padded(size as i32)
+                .map_err(openssl_error)
+                .and_then(|bytes| EccParameter::try_from(bytes).map_err(tss_error))
         };
+        let point = EccPoint::new(coordinate(&x)?, coordinate(&y)?);
+
+        let shared = self
+            .context()?
+            .execute_with_nullauth_session(|ctx| ctx.ecdh_z_gen(key_handle, point))
+            .map_err(tss_error)?;
 
-        Ok(verification_result)
+        Ok(shared.x().value().to_vec())
     }
 }
@@ -1,14 +1,23 @@
-use crate::common::crypto::{
-    algorithms::{
-        encryption::{
-            AsymmetricEncryption, BlockCiphers, EccCurves, EccSchemeAlgorithm, SymmetricMode,
+use crate::{
+    common::{
+        crypto::{
+            algorithms::{
+                encryption::{AsymmetricEncryption, BlockCiphers, EccCurves, SymmetricMode},
+                hashes::{Hash, Sha2Bits, Sha3Bits},
+                KeyBits,
+            },
+            KeyUsage,
         },
-        hashes::{Hash, Sha2Bits, Sha3Bits},
-        KeyBits,
+        error::SecurityModuleError,
+        traits::interaction::InteractionHandler,
     },
-    KeyUsage,
+    tpm::core::error::TpmError,
+};
+use openssl::hash::MessageDigest;
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
 };
-use std::sync::{Arc, Mutex};
 use tss_esapi::{
     handles::KeyHandle as TssKeyHandle,
     interface_types::{
@@ -16,12 +25,25 @@ use tss_esapi::{
         ecc::EccCurve,
         key_bits::{AesKeyBits, CamelliaKeyBits, RsaKeyBits},
     },
-    structures::{EcDaaScheme, EccScheme, HashScheme, SignatureScheme, SymmetricDefinitionObject},
+    structures::SymmetricDefinitionObject,
     Context,
 };
 
+mod capabilities;
+pub mod dictionary_attack;
+pub mod enrollment;
+mod esys;
+pub(crate) mod hmac;
 pub mod key_handle;
+pub mod pcr;
 pub mod provider;
+mod symmetric;
+pub mod tcti;
+
+pub use dictionary_attack::{DictionaryAttackParameters, DictionaryAttackState};
+pub use enrollment::{make_credential, object_name, AttestationKey, EndorsementKey};
+pub use pcr::{EventLog, PcrBank, PcrEvent};
+pub use tcti::{TctiConfig, TpmInfo};
 
 /// A TPM-based cryptographic provider for managing cryptographic keys and performing
 /// cryptographic operations.
@@ -40,6 +62,8 @@ pub struct TpmProvider {
     pub(super) sym_algorithm: Option<BlockCiphers>,
     pub(super) hash: Option<Hash>,
     pub(super) key_usages: Option<Vec<KeyUsage>>,
+    pub(super) tcti: TctiConfig,
+    pub(super) interaction: Option<Arc<dyn InteractionHandler>>,
 }
 
 impl TpmProvider {
@@ -57,107 +81,91 @@ impl TpmProvider {
             sym_algorithm: None,
             hash: None,
             key_usages: None,
+            tcti: TctiConfig::default(),
+            interaction: None,
         }
     }
-}
 
-impl From<Hash> for HashingAlgorithm {
-    fn from(val: Hash) -> Self {
-        match val {
-            Hash::Sha1 => HashingAlgorithm::Sha1,
-            Hash::Sha2(bits) => match bits {
-                Sha2Bits::Sha256 => HashingAlgorithm::Sha256,
-                Sha2Bits::Sha384 => HashingAlgorithm::Sha384,
-                Sha2Bits::Sha512 => HashingAlgorithm::Sha512,
-                _ => {
-                    unimplemented!()
-                }
-            },
-            Hash::Sha3(bits) => match bits {
-                Sha3Bits::Sha3_256 => HashingAlgorithm::Sha3_256,
-                Sha3Bits::Sha3_384 => HashingAlgorithm::Sha3_384,
-                Sha3Bits::Sha3_512 => HashingAlgorithm::Sha3_512,
-                _ => {
-                    unimplemented!()
-                }
-            },
-            _ => {
-                unimplemented!()
-            }
-        }
+    /// Selects the TCTI used by `initialize_module` to connect to the TPM.
+    ///
+    /// Without this call the TCTI is read from the environment, falling back to
+    /// the in-kernel resource manager at `/dev/tpmrm0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcti` - The `TctiConfig` describing the device, daemon or simulator to use.
+    pub fn with_tcti(mut self, tcti: TctiConfig) -> Self {
+        self.tcti = tcti;
+        self
     }
-}
 
-impl From<EccSchemeAlgorithm> for SignatureScheme {
-    fn from(value: EccSchemeAlgorithm) -> Self {
-        match value {
-            EccSchemeAlgorithm::EcDsa(_) => SignatureScheme::EcDsa {
-                hash_scheme: HashScheme::new(HashingAlgorithm::Sha512),
-            },
-            EccSchemeAlgorithm::EcDaa(_) => Self::EcDaa {
-                ecdaa_scheme: EcDaaScheme::new(HashingAlgorithm::Sha512, 0),
-            },
-            EccSchemeAlgorithm::Sm2(_) => Self::Sm2 {
-                hash_scheme: HashScheme::new(HashingAlgorithm::Sha512),
-            },
-            EccSchemeAlgorithm::EcSchnorr(_) => SignatureScheme::EcSchnorr {
-                hash_scheme: HashScheme::new(HashingAlgorithm::Sha512),
-            },
-            _ => unimplemented!(),
+    /// Locks the ESAPI context, failing if the module has not been initialized.
+    fn context(&self) -> Result<MutexGuard<'_, Context>, SecurityModuleError> {
+        let handle = self.handle.as_ref().ok_or_else(|| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(
+                "Module is not initialized".to_owned(),
+            ))
+        })?;
+        Ok(handle.lock().unwrap())
+    }
+
+    /// Makes `key_handle` the loaded key, flushing the previously loaded transient key.
+    ///
+    /// Without a resource manager transient objects stay loaded until they are flushed,
+    /// and the TPM runs out of object memory after a few keys.
+    fn replace_key_handle(&mut self, key_handle: TssKeyHandle) -> Result<(), SecurityModuleError> {
+        match self.key_handle.replace(Arc::new(Mutex::new(key_handle))) {
+            Some(previous) => {
+                let previous = *previous.lock().unwrap();
+                self.context()?
+                    .flush_context(previous.into())
+                    .map_err(tss_error)
+            }
+            None => Ok(()),
         }
     }
 }
 
-impl From<EccSchemeAlgorithm> for EccScheme {
-    fn from(value: EccSchemeAlgorithm) -> Self {
-        match value {
-            EccSchemeAlgorithm::EcDsa(_) => {
-                EccScheme::EcDsa(HashScheme::new(HashingAlgorithm::Sha512))
-            }
-            EccSchemeAlgorithm::EcDh(_) => {
-                EccScheme::EcDh(HashScheme::new(HashingAlgorithm::Sha512))
-            }
-            EccSchemeAlgorithm::EcDaa(_) => {
-                EccScheme::EcDaa(EcDaaScheme::new(HashingAlgorithm::Sha512, 0))
-            }
-            EccSchemeAlgorithm::Sm2(_) => EccScheme::Sm2(HashScheme::new(HashingAlgorithm::Sha512)),
-            EccSchemeAlgorithm::EcSchnorr(_) => {
-                EccScheme::EcSchnorr(HashScheme::new(HashingAlgorithm::Sha512))
-            }
-            EccSchemeAlgorithm::EcMqv(_) => {
-                EccScheme::EcMqv(HashScheme::new(HashingAlgorithm::Sha512))
-            }
-            EccSchemeAlgorithm::Null => unimplemented!(),
+impl TryFrom<Hash> for HashingAlgorithm {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: Hash) -> Result<Self, Self::Error> {
+        match val {
+            Hash::Sha1 => Ok(HashingAlgorithm::Sha1),
+            Hash::Sha2(Sha2Bits::Sha256) => Ok(HashingAlgorithm::Sha256),
+            Hash::Sha2(Sha2Bits::Sha384) => Ok(HashingAlgorithm::Sha384),
+            Hash::Sha2(Sha2Bits::Sha512) => Ok(HashingAlgorithm::Sha512),
+            Hash::Sha3(Sha3Bits::Sha3_256) => Ok(HashingAlgorithm::Sha3_256),
+            Hash::Sha3(Sha3Bits::Sha3_384) => Ok(HashingAlgorithm::Sha3_384),
+            Hash::Sha3(Sha3Bits::Sha3_512) => Ok(HashingAlgorithm::Sha3_512),
+            _ => Err(unsupported(val)),
         }
     }
 }
 
-impl From<EccCurves> for EccCurve {
-    fn from(val: EccCurves) -> Self {
+impl TryFrom<EccCurves> for EccCurve {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: EccCurves) -> Result<Self, Self::Error> {
         match val {
-            EccCurves::P256 => EccCurve::NistP256,
-            EccCurves::P384 => EccCurve::NistP384,
-            EccCurves::P521 => EccCurve::NistP521,
-            EccCurves::Secp256k1 => EccCurve::Sm2P256,
-            EccCurves::BrainpoolP256r1 => EccCurve::BnP256,
-            EccCurves::BrainpoolP638 => EccCurve::BnP638,
-            _ => {
-                unimplemented!()
-            }
+            EccCurves::P256 => Ok(EccCurve::NistP256),
+            EccCurves::P384 => Ok(EccCurve::NistP384),
+            EccCurves::P521 => Ok(EccCurve::NistP521),
+            _ => Err(unsupported(val)),
         }
     }
 }
 
-impl From<KeyBits> for RsaKeyBits {
-    fn from(val: KeyBits) -> Self {
+impl TryFrom<KeyBits> for RsaKeyBits {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: KeyBits) -> Result<Self, Self::Error> {
         match val {
-            KeyBits::Bits1024 => RsaKeyBits::Rsa1024,
-            KeyBits::Bits2048 => RsaKeyBits::Rsa2048,
-            KeyBits::Bits3072 => RsaKeyBits::Rsa3072,
-            KeyBits::Bits4096 => RsaKeyBits::Rsa4096,
-            _ => {
-                unimplemented!()
-            }
+            KeyBits::Bits1024 => Ok(RsaKeyBits::Rsa1024),
+            KeyBits::Bits2048 => Ok(RsaKeyBits::Rsa2048),
+            KeyBits::Bits3072 => Ok(RsaKeyBits::Rsa3072),
+            KeyBits::Bits4096 => Ok(RsaKeyBits::Rsa4096),
+            _ => Err(unsupported(val)),
         }
     }
 }
@@ -165,59 +173,97 @@ impl From<KeyBits> for RsaKeyBits {
 impl From<AsymmetricEncryption> for PublicAlgorithm {
     fn from(val: AsymmetricEncryption) -> Self {
         match val {
-            AsymmetricEncryption::Rsa(_) => todo!(),
-            AsymmetricEncryption::Ecc(_) => todo!(),
+            AsymmetricEncryption::Rsa(_) => PublicAlgorithm::Rsa,
+            AsymmetricEncryption::Ecc(_) => PublicAlgorithm::Ecc,
         }
     }
 }
 
-impl From<KeyBits> for AesKeyBits {
-    fn from(val: KeyBits) -> Self {
+impl TryFrom<KeyBits> for AesKeyBits {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: KeyBits) -> Result<Self, Self::Error> {
         match val {
-            KeyBits::Bits128 => AesKeyBits::Aes128,
-            KeyBits::Bits192 => AesKeyBits::Aes192,
-            KeyBits::Bits256 => AesKeyBits::Aes256,
-            _ => unimplemented!(),
+            KeyBits::Bits128 => Ok(AesKeyBits::Aes128),
+            KeyBits::Bits192 => Ok(AesKeyBits::Aes192),
+            KeyBits::Bits256 => Ok(AesKeyBits::Aes256),
+            _ => Err(unsupported(val)),
         }
     }
 }
 
-impl From<KeyBits> for CamelliaKeyBits {
-    fn from(val: KeyBits) -> Self {
+impl TryFrom<KeyBits> for CamelliaKeyBits {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: KeyBits) -> Result<Self, Self::Error> {
         match val {
-            KeyBits::Bits128 => CamelliaKeyBits::Camellia128,
-            KeyBits::Bits192 => CamelliaKeyBits::Camellia192,
-            KeyBits::Bits256 => CamelliaKeyBits::Camellia256,
-            _ => unimplemented!(),
+            KeyBits::Bits128 => Ok(CamelliaKeyBits::Camellia128),
+            KeyBits::Bits192 => Ok(CamelliaKeyBits::Camellia192),
+            KeyBits::Bits256 => Ok(CamelliaKeyBits::Camellia256),
+            _ => Err(unsupported(val)),
         }
     }
 }
 
-impl From<SymmetricMode> for TssSymmetricMode {
-    fn from(val: SymmetricMode) -> Self {
+impl TryFrom<SymmetricMode> for TssSymmetricMode {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: SymmetricMode) -> Result<Self, Self::Error> {
         match val {
-            SymmetricMode::Ecb => TssSymmetricMode::Ecb,
-            SymmetricMode::Cbc => TssSymmetricMode::Cbc,
-            SymmetricMode::Cfb => TssSymmetricMode::Cfb,
-            SymmetricMode::Ofb => TssSymmetricMode::Ofb,
-            SymmetricMode::Ctr => TssSymmetricMode::Ctr,
-            _ => unimplemented!(),
+            SymmetricMode::Ecb => Ok(TssSymmetricMode::Ecb),
+            SymmetricMode::Cbc => Ok(TssSymmetricMode::Cbc),
+            SymmetricMode::Cfb => Ok(TssSymmetricMode::Cfb),
+            SymmetricMode::Ofb => Ok(TssSymmetricMode::Ofb),
+            SymmetricMode::Ctr => Ok(TssSymmetricMode::Ctr),
+            _ => Err(unsupported(val)),
         }
     }
 }
 
-impl From<BlockCiphers> for SymmetricDefinitionObject {
-    fn from(val: BlockCiphers) -> Self {
+impl TryFrom<BlockCiphers> for SymmetricDefinitionObject {
+    type Error = SecurityModuleError;
+
+    fn try_from(val: BlockCiphers) -> Result<Self, Self::Error> {
         match val {
-            BlockCiphers::Aes(sym_mode, key_bits) => SymmetricDefinitionObject::Aes {
-                key_bits: key_bits.into(),
-                mode: sym_mode.into(),
-            },
-            BlockCiphers::Camellia(sym_mode, key_bits) => SymmetricDefinitionObject::Camellia {
-                key_bits: key_bits.into(),
-                mode: sym_mode.into(),
-            },
-            _ => unimplemented!(),
+            BlockCiphers::Aes(sym_mode, key_bits) => Ok(SymmetricDefinitionObject::Aes {
+                key_bits: key_bits.try_into()?,
+                mode: sym_mode.try_into()?,
+            }),
+            BlockCiphers::Camellia(sym_mode, key_bits) => Ok(SymmetricDefinitionObject::Camellia {
+                key_bits: key_bits.try_into()?,
+                mode: sym_mode.try_into()?,
+            }),
+            _ => Err(unsupported(val)),
         }
     }
 }
+
+/// Returns the error for a parameter that has no TPM counterpart.
+fn unsupported(parameter: impl fmt::Debug) -> SecurityModuleError {
+    SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+        "{parameter:?} is not supported by the TPM"
+    )))
+}
+
+fn message_digest(algorithm: HashingAlgorithm) -> Result<MessageDigest, SecurityModuleError> {
+    match algorithm {
+        HashingAlgorithm::Sha1 => Ok(MessageDigest::sha1()),
+        HashingAlgorithm::Sha256 => Ok(MessageDigest::sha256()),
+        HashingAlgorithm::Sha384 => Ok(MessageDigest::sha384()),
+        HashingAlgorithm::Sha512 => Ok(MessageDigest::sha512()),
+        HashingAlgorithm::Sha3_256 => Ok(MessageDigest::sha3_256()),
+        HashingAlgorithm::Sha3_384 => Ok(MessageDigest::sha3_384()),
+        HashingAlgorithm::Sha3_512 => Ok(MessageDigest::sha3_512()),
+        _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            format!("Unsupported name algorithm {:?}", algorithm),
+        ))),
+    }
+}
+
+fn tss_error(error: tss_esapi::Error) -> SecurityModuleError {
+    SecurityModuleError::Tpm(TpmError::InternalError(Box::new(error)))
+}
+
+fn openssl_error(error: openssl::error::ErrorStack) -> SecurityModuleError {
+    SecurityModuleError::Tpm(TpmError::InternalError(Box::new(error)))
+}
@@ -0,0 +1,348 @@
+use super::{message_digest, openssl_error, tss_error, TpmProvider};
+use crate::{
+    common::{
+        crypto::algorithms::hashes::{Hash, Sha2Bits, Sha3Bits},
+        error::SecurityModuleError,
+    },
+    tpm::core::error::TpmError,
+};
+use openssl::hash::hash;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::instrument;
+use tss_esapi::{
+    abstraction::pcr,
+    constants::CapabilityType,
+    handles::PcrHandle,
+    interface_types::algorithm::HashingAlgorithm,
+    structures::{CapabilityData, Digest, DigestValues, PcrSelectionList, PcrSlot},
+    Context,
+};
+
+/// The values of a set of PCRs in one hash bank.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcrBank {
+    /// The hash algorithm of the bank.
+    pub hash: Hash,
+    /// The PCR values, keyed by PCR index.
+    pub values: BTreeMap<u8, Vec<u8>>,
+}
+
+/// A measurement recorded with [`TpmProvider::record_event`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcrEvent {
+    /// The PCR the measurement was extended into.
+    pub pcr_index: u8,
+    /// A human readable description, e.g. the name of the measured plugin.
+    pub description: String,
+    /// The measured data.
+    pub data: Vec<u8>,
+    /// The digest of `data` for every bank that was extended.
+    pub digests: Vec<(Hash, Vec<u8>)>,
+}
+
+/// An application event log.
+///
+/// The log records every measurement the application extends into a PCR, so a verifier
+/// can recompute the PCR values from the measured data and compare them against the
+/// values read from the TPM or signed in a quote.
+///
+/// The replay assumes the PCRs started out as all zeros, which holds for PCRs 0 to 16
+/// and 23 after a reset, e.g. the debug PCR 16 and the application PCR 23.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventLog {
+    /// The recorded events in the order they were extended.
+    pub events: Vec<PcrEvent>,
+}
+
+impl EventLog {
+    /// Recomputes the PCR values of one bank from the recorded events.
+    ///
+    /// Every event digest is checked against the hash of the event data first, so
+    /// a log whose data was altered after the fact fails to replay.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the replayed values of every PCR touched by the log, or a
+    /// `SecurityModuleError` if an event lacks a digest for `hash` or its digest does not
+    /// match its data.
+    pub fn replay(&self, hash: Hash) -> Result<BTreeMap<u8, Vec<u8>>, SecurityModuleError> {
+        let digest = message_digest(bank_algorithm(hash)?)?;
+        let mut values = BTreeMap::new();
+
+        for event in &self.events {
+            let event_digest = event
+                .digests
+                .iter()
+                .find(|(event_hash, _)| *event_hash == hash)
+                .map(|(_, event_digest)| event_digest)
+                .ok_or_else(|| {
+                    SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+                        "Event '{}' has no {:?} digest",
+                        event.description, hash
+                    )))
+                })?;
+            if hash_data(digest, &event.data)? != *event_digest {
+                return Err(SecurityModuleError::SignatureVerificationError(format!(
+                    "Digest of event '{}' does not match its data",
+                    event.description
+                )));
+            }
+
+            let value = values
+                .entry(event.pcr_index)
+                .or_insert_with(|| vec![0; digest.size()]);
+            *value = hash_data(digest, &[&value[..], event_digest].concat())?;
+        }
+
+        Ok(values)
+    }
+
+    /// Checks the PCR values of a bank against the log.
+    ///
+    /// Every PCR in `bank` has to match its replayed value; PCRs without events have to
+    /// still be all zeros.
+    pub fn verify(&self, bank: &PcrBank) -> Result<bool, SecurityModuleError> {
+        let replayed = self.replay(bank.hash)?;
+        let size = message_digest(bank_algorithm(bank.hash)?)?.size();
+
+        Ok(bank
+            .values
+            .iter()
+            .all(|(index, value)| match replayed.get(index) {
+                Some(replayed) => replayed == value,
+                None => value.len() == size && value.iter().all(|b| *b == 0),
+            }))
+    }
+
+    /// Computes the PCR digest a quote over `selection` in the `hash` bank has to contain.
+    ///
+    /// This is the `pcrDigest` of `TPMS_QUOTE_INFO`: the digest, using the hash algorithm
+    /// of the signing scheme, of the concatenated replayed PCR values in ascending order.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The PCR bank the quote was made over.
+    /// * `selection` - The quoted PCR indices.
+    /// * `quote_hash` - The hash algorithm of the quote's signing scheme.
+    pub fn quote_digest(
+        &self,
+        hash: Hash,
+        selection: &[u8],
+        quote_hash: Hash,
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let replayed = self.replay(hash)?;
+        let size = message_digest(bank_algorithm(hash)?)?.size();
+
+        let mut indices = selection.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let concatenated: Vec<u8> = indices
+            .iter()
+            .flat_map(|index| {
+                replayed
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_else(|| vec![0; size])
+            })
+            .collect();
+
+        hash_data(message_digest(bank_algorithm(quote_hash)?)?, &concatenated)
+    }
+}
+
+impl TpmProvider {
+    /// Reads the selected PCRs from every active hash bank (`TPM2_PCR_Read`).
+    ///
+    /// Banks using a hash algorithm this crate cannot represent are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `selection` - The PCR indices to read.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing one `PcrBank` per active bank, or a `SecurityModuleError`
+    /// if an index is out of range or the TPM cannot be read.
+    #[instrument]
+    pub fn read_pcrs(&self, selection: &[u8]) -> Result<Vec<PcrBank>, SecurityModuleError> {
+        let slots = selection
+            .iter()
+            .map(|index| pcr_slot(*index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut context = self.context()?;
+
+        let banks: Vec<(HashingAlgorithm, Hash)> = active_banks(&mut context)?
+            .into_iter()
+            .filter_map(|algorithm| bank_hash(algorithm).map(|hash| (algorithm, hash)))
+            .collect();
+        if banks.is_empty() || slots.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let selection_list = banks
+            .iter()
+            .fold(PcrSelectionList::builder(), |builder, (algorithm, _)| {
+                builder.with_selection(*algorithm, &slots)
+            })
+            .build()
+            .map_err(tss_error)?;
+        let pcr_data = pcr::read_all(&mut context, selection_list).map_err(tss_error)?;
+
+        banks
+            .into_iter()
+            .map(|(algorithm, hash)| {
+                let bank = pcr_data.pcr_bank(algorithm).ok_or_else(|| {
+                    SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+                        "TPM did not return the {:?} bank",
+                        algorithm
+                    )))
+                })?;
+                let values = selection
+                    .iter()
+                    .zip(&slots)
+                    .filter_map(|(index, slot)| {
+                        bank.get_digest(*slot)
+                            .map(|digest| (*index, digest.value().to_vec()))
+                    })
+                    .collect();
+                Ok(PcrBank { hash, values })
+            })
+            .collect()
+    }
+
+    /// Measures `data` into a PCR in every active bank (`TPM2_PCR_Extend`).
+    ///
+    /// The data is hashed in software with the algorithm of each bank, so the call fails
+    /// if a bank uses a hash algorithm that is not available here.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The PCR to extend, usually 16 (debug) or 23 (application).
+    /// * `data` - The measured data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the digest extended into each bank, or a `SecurityModuleError`
+    /// on failure.
+    #[instrument(skip(data))]
+    pub fn extend_pcr(
+        &self,
+        index: u8,
+        data: &[u8],
+    ) -> Result<Vec<(Hash, Vec<u8>)>, SecurityModuleError> {
+        let pcr_handle = PcrHandle::try_from(u32::from(index)).map_err(tss_error)?;
+        let mut context = self.context()?;
+
+        let mut digests = Vec::new();
+        let mut digest_values = DigestValues::new();
+        for algorithm in active_banks(&mut context)? {
+            let hash = bank_hash(algorithm).ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::UnsupportedOperation(format!(
+                    "Cannot measure into the {:?} bank",
+                    algorithm
+                )))
+            })?;
+            let digest = hash_data(message_digest(algorithm)?, data)?;
+            digest_values.set(
+                algorithm,
+                Digest::try_from(digest.clone()).map_err(tss_error)?,
+            );
+            digests.push((hash, digest));
+        }
+
+        context
+            .execute_with_nullauth_session(|ctx| ctx.pcr_extend(pcr_handle, digest_values))
+            .map_err(tss_error)?;
+
+        Ok(digests)
+    }
+
+    /// Measures `data` into a PCR and appends the measurement to `log`.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The application `EventLog`.
+    /// * `index` - The PCR to extend, usually 16 (debug) or 23 (application).
+    /// * `description` - A description of the measured data.
+    /// * `data` - The measured data.
+    #[instrument(skip(log, data))]
+    pub fn record_event(
+        &self,
+        log: &mut EventLog,
+        index: u8,
+        description: &str,
+        data: &[u8],
+    ) -> Result<(), SecurityModuleError> {
+        let digests = self.extend_pcr(index, data)?;
+        log.events.push(PcrEvent {
+            pcr_index: index,
+            description: description.to_owned(),
+            data: data.to_vec(),
+            digests,
+        });
+        Ok(())
+    }
+}
+
+/// Returns the hash algorithms of the PCR banks the TPM has allocated.
+fn active_banks(context: &mut Context) -> Result<Vec<HashingAlgorithm>, SecurityModuleError> {
+    let (capabilities, _) = context
+        .get_capability(CapabilityType::AssignedPcr, 0, 1)
+        .map_err(tss_error)?;
+
+    match capabilities {
+        CapabilityData::AssignedPcr(selections) => Ok(selections
+            .get_selections()
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| selection.hashing_algorithm())
+            .collect()),
+        _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            "Unexpected capability data".to_owned(),
+        ))),
+    }
+}
+
+fn pcr_slot(index: u8) -> Result<PcrSlot, SecurityModuleError> {
+    if index >= 24 {
+        return Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            format!("PCR index {} is out of range", index),
+        )));
+    }
+    PcrSlot::try_from(1u32 << index).map_err(tss_error)
+}
+
+fn bank_hash(algorithm: HashingAlgorithm) -> Option<Hash> {
+    match algorithm {
+        HashingAlgorithm::Sha1 => Some(Hash::Sha1),
+        HashingAlgorithm::Sha256 => Some(Hash::Sha2(Sha2Bits::Sha256)),
+        HashingAlgorithm::Sha384 => Some(Hash::Sha2(Sha2Bits::Sha384)),
+        HashingAlgorithm::Sha512 => Some(Hash::Sha2(Sha2Bits::Sha512)),
+        HashingAlgorithm::Sha3_256 => Some(Hash::Sha3(Sha3Bits::Sha3_256)),
+        HashingAlgorithm::Sha3_384 => Some(Hash::Sha3(Sha3Bits::Sha3_384)),
+        HashingAlgorithm::Sha3_512 => Some(Hash::Sha3(Sha3Bits::Sha3_512)),
+        _ => None,
+    }
+}
+
+fn bank_algorithm(hash: Hash) -> Result<HashingAlgorithm, SecurityModuleError> {
+    match hash {
+        Hash::Sha1
+        | Hash::Sha2(Sha2Bits::Sha256 | Sha2Bits::Sha384 | Sha2Bits::Sha512)
+        | Hash::Sha3(Sha3Bits::Sha3_256 | Sha3Bits::Sha3_384 | Sha3Bits::Sha3_512) => {
+            hash.try_into()
+        }
+        _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            format!("{:?} is not a PCR bank algorithm", hash),
+        ))),
+    }
+}
+
+fn hash_data(
+    digest: openssl::hash::MessageDigest,
+    data: &[u8],
+) -> Result<Vec<u8>, SecurityModuleError> {
+    Ok(hash(digest, data).map_err(openssl_error)?.to_vec())
+}
@@ -1,38 +1,58 @@
-use super::TpmProvider;
+use super::{openssl_error, tss_error, TpmProvider};
 use crate::{
     common::{
-        crypto::{algorithms::encryption::AsymmetricEncryption, KeyUsage},
+        capabilities::Capabilities,
+        crypto::{
+            algorithms::{
+                encryption::{AsymmetricEncryption, EccCurves},
+                KeyBits,
+            },
+            KeyUsage,
+        },
         error::SecurityModuleError,
-        traits::{module_provider::Provider, module_provider_config::ProviderConfig},
+        traits::{
+            interaction::{CredentialKind, CredentialRequest, InteractionHandler},
+            module_provider::Provider,
+        },
     },
-    tpm::TpmConfig,
+    tpm::{core::error::TpmError, TpmConfig, TpmHmacConfig, TpmSymmetricConfig},
+};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey, EcPoint as OpensslEcPoint},
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    rsa::Rsa,
+};
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
 };
-use std::sync::{Arc, Mutex};
 use tracing::instrument;
 use tss_esapi::{
-    attributes::{ObjectAttributesBuilder, SessionAttributesBuilder},
-    constants::SessionType,
-    handles::{KeyHandle as TssKeyHandle, PersistentTpmHandle},
-    interface_types::{
-        algorithm::{HashingAlgorithm, SymmetricMode},
-        key_bits::AesKeyBits,
-        resource_handles::{Hierarchy, Provision},
-    },
+    attributes::ObjectAttributesBuilder,
+    constants::{tss::TPMA_PERMANENT_OWNERAUTHSET, PropertyTag},
+    interface_types::{ecc::EccCurve, resource_handles::Hierarchy},
     structures::{
-        Digest, EccPoint, HashScheme, KeyDerivationFunctionScheme, Private, PublicBuilder,
-        PublicKeyRsa, PublicRsaParameters, RsaExponent, RsaScheme,
+        Auth, Digest, EccParameter, EccPoint, EccScheme, KeyDerivationFunctionScheme, Public,
+        PublicBuilder, PublicEccParameters, PublicKeyRsa, PublicRsaParameters, RsaExponent,
+        RsaScheme, SymmetricDefinitionObject,
     },
     Context, TctiNameConf,
 };
-use tss_esapi::{interface_types::dynamic_handles::Persistent, structures::PublicEccParameters};
 
 /// Implements the `Provider` trait, providing cryptographic operations utilizing a TPM.
 impl Provider for TpmProvider {
     /// Creates a new cryptographic key identified by `key_id`.
     ///
     /// This method generates a new cryptographic key within the TPM, using the specified
-    /// algorithm, symmetric algorithm, hash algorithm, and key usages. The key is made persistent
-    /// and associated with the provided `key_id`.
+    /// algorithm, symmetric algorithm, hash algorithm, and key usages. Like HMAC and
+    /// symmetric keys, the key is a primary key in the owner hierarchy derived from `key_id`,
+    /// so the TPM creates the same key again for the same `key_id` and configuration.
+    ///
+    /// Passing a `TpmHmacConfig` instead of a `TpmConfig` creates a keyed-hash key for
+    /// `mac_data` and `verify_mac`, a `TpmSymmetricConfig` creates an AES key for
+    /// `encrypt_data` and `decrypt_data`.
     ///
     /// # Arguments
     ///
@@ -50,125 +70,24 @@ impl Provider for TpmProvider {
     fn create_key(
         &mut self,
         key_id: &str,
-        config: Box<dyn ProviderConfig>,
+        config: Box<dyn Any>,
     ) -> Result<(), SecurityModuleError> {
-        let config = config.as_any().downcast_ref::<TpmConfig>().unwrap();
-
-        self.key_algorithm = Some(config.key_algorithm);
-        self.sym_algorithm = Some(config.sym_algorithm);
-        self.hash = Some(config.hash);
-        self.key_usages = Some(config.key_usages.clone());
-
-        let primary_pub = match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(key_bits) => PublicBuilder::new()
-                .with_public_algorithm((*self.key_algorithm.as_ref().unwrap()).into())
-                .with_name_hashing_algorithm((*self.hash.as_ref().unwrap()).into())
-                .with_rsa_parameters(PublicRsaParameters::new(
-                    self.sym_algorithm.unwrap().into(),
-                    RsaScheme::Null,
-                    (*key_bits).into(),
-                    RsaExponent::default(),
-                ))
-                .with_rsa_unique_identifier(PublicKeyRsa::default()),
-            AsymmetricEncryption::Ecc(ecc_scheme) => PublicBuilder::new()
-                .with_public_algorithm((*self.key_algorithm.as_ref().unwrap()).into())
-                .with_name_hashing_algorithm((*self.hash.as_ref().unwrap()).into())
-                .with_ecc_parameters(PublicEccParameters::new(
-                    self.sym_algorithm.unwrap().into(),
-                    (*ecc_scheme).into(),
-                    self.key_algorithm
-                        .as_ref()
-                        .unwrap()
-                        .ecc_curve()
-                        .unwrap()
-                        .into(),
-                    KeyDerivationFunctionScheme::Kdf2(HashScheme::new(
-                        (*self.hash.as_ref().unwrap()).into(),
-                    )),
-                ))
-                .with_ecc_unique_identifier(EccPoint::default()),
-        };
-
-        let primary_pub = primary_pub
-            .with_object_attributes(
-                // Private Key attributes
-                ObjectAttributesBuilder::new()
-                    // Indicate the key can only exist within this tpm and can not be exported.
-                    .with_fixed_tpm(true)
-                    // The primary key and it's descendent keys can't be moved to other primary
-                    // keys.
-                    .with_fixed_parent(true)
-                    // The primary key will persist over suspend and resume of the system.
-                    .with_st_clear(true)
-                    // The primary key was generated entirely inside the TPM - only this TPM
-                    // knows it's content.
-                    .with_sensitive_data_origin(true)
-                    // This key requires "authentication" to the TPM to access - this can be
-                    // an HMAC or password session. HMAC sessions are used by default with
-                    // the "execute_with_nullauth_session" function.
-                    .with_user_with_auth(
-                        self.key_usages
-                            .as_ref()
-                            .unwrap()
-                            .contains(&KeyUsage::ClientAuth),
-                    )
-                    // This key has the ability to decrypt
-                    .with_decrypt(
-                        self.key_usages
-                            .as_ref()
-                            .unwrap()
-                            .contains(&KeyUsage::Decrypt),
-                    )
-                    // This key has the ability to sign
-                    .with_sign_encrypt(
-                        self.key_usages
-                            .as_ref()
-                            .unwrap()
-                            .contains(&KeyUsage::SignEncrypt),
-                    )
-                    // Create self-signed certificates
-                    .with_x509_sign(
-                        self.key_usages
-                            .as_ref()
-                            .unwrap()
-                            .contains(&KeyUsage::CreateX509),
-                    )
-                    // This key may only be used to encrypt or sign objects that are within
-                    // the TPM - it can not encrypt or sign external data.
-                    .with_restricted(false)
-                    .build()
-                    .unwrap(),
-            )
-            .build()
-            .unwrap();
-
-        let key_handle = self
-            .handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .create_primary(Hierarchy::Owner, primary_pub, None, None, None, None)
-            .unwrap();
-
-        let persistent_handle =
-            Persistent::Persistent(PersistentTpmHandle::new(key_handle.key_handle.into()).unwrap());
+        if let Some(config) = config.downcast_ref::<TpmHmacConfig>() {
+            self.create_hmac_key(key_id, config.hash)?;
+            self.key_id = key_id.to_string();
+            return Ok(());
+        }
+        if let Some(config) = config.downcast_ref::<TpmSymmetricConfig>() {
+            self.create_symmetric_key(key_id, config.sym_algorithm)?;
+            self.key_id = key_id.to_string();
+            return Ok(());
+        }
 
-        self.key_handle = Some(Arc::new(Mutex::new(key_handle.key_handle)));
-
-        self.key_id = key_handle.key_handle.value().to_string();
-        self.handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .evict_control(
-                Provision::Owner, // TPM owner authorization
-                (*self.key_handle.as_ref().unwrap().lock().unwrap()).into(),
-                persistent_handle, // Chosen persistent handle
-            )
-            .expect("Failed to make key persistent");
+        let config = config
+            .downcast_ref::<TpmConfig>()
+            .ok_or_else(|| SecurityModuleError::InitializationError("Wrong Config".to_owned()))?;
 
+        self.create_asymmetric_key(key_id, config)?;
         self.key_id = key_id.to_string();
 
         Ok(())
@@ -178,7 +97,12 @@ impl Provider for TpmProvider {
     ///
     /// This method loads an existing cryptographic key from the TPM, using the specified
     /// algorithm, symmetric algorithm, hash algorithm, and key usages. The loaded key is
-    /// associated with the provided `key_id`.
+    /// associated with the provided `key_id`. As the key is derived from `key_id`, loading
+    /// it is the same operation as creating it, and the configuration has to match the one
+    /// the key was created with.
+    ///
+    /// Passing a `TpmHmacConfig` or a `TpmSymmetricConfig` loads the HMAC or AES key
+    /// created for `key_id`.
     ///
     /// # Arguments
     ///
@@ -193,132 +117,23 @@ impl Provider for TpmProvider {
     /// A `Result` that, on success, contains `Ok(())`, indicating that the key was loaded successfully.
     /// On failure, it returns a `SecurityModuleError`.
     #[instrument]
-    fn load_key(
-        &mut self,
-        key_id: &str,
-        config: Box<dyn ProviderConfig>,
-    ) -> Result<(), SecurityModuleError> {
-        let config = config.as_any().downcast_ref::<TpmConfig>().unwrap();
-
-        self.key_algorithm = Some(config.key_algorithm);
-        self.sym_algorithm = Some(config.sym_algorithm);
-        self.hash = Some(config.hash);
-        self.key_usages = Some(config.key_usages.clone());
-
-        // Start an authorization session
-        let session = self
-            .handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .start_auth_session(
-                None,
-                None,
-                None,
-                SessionType::Hmac,
-                tss_esapi::structures::SymmetricDefinition::Aes {
-                    key_bits: AesKeyBits::Aes256,
-                    mode: SymmetricMode::Cbc,
-                },
-                HashingAlgorithm::Sha512,
-            )
-            .unwrap();
-        let session_attr = SessionAttributesBuilder::new()
-            .with_decrypt(true)
-            .with_encrypt(true)
-            .with_continue_session(true)
-            .build();
-
-        self.handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .tr_sess_set_attributes(session.unwrap(), session_attr.0, session_attr.1)
-            .unwrap();
-
-        let primary_pub = match self.key_algorithm.as_ref().unwrap() {
-            AsymmetricEncryption::Rsa(_) => todo!(),
-            AsymmetricEncryption::Ecc(ecc_scheme) => PublicEccParameters::new(
-                self.sym_algorithm.unwrap().into(),
-                (*ecc_scheme).into(),
-                self.key_algorithm
-                    .as_ref()
-                    .unwrap()
-                    .ecc_curve()
-                    .unwrap()
-                    .into(),
-                KeyDerivationFunctionScheme::Kdf2(HashScheme::new(self.hash.unwrap().into())),
-            ),
-        };
-
-        let obj_attributes = ObjectAttributesBuilder::new()
-            // Indicate the key can only exist within this tpm and can not be exported.
-            .with_fixed_tpm(true)
-            // The primary key and it's descendent keys can't be moved to other primary
-            // keys.
-            .with_fixed_parent(true)
-            // The primary key will persist over suspend and resume of the system.
-            .with_st_clear(true)
-            // The primary key was generated entirely inside the TPM - only this TPM
-            // knows it's content.
-            .with_sensitive_data_origin(true)
-            // This key requires "authentication" to the TPM to access - this can be
-            // an HMAC or password session. HMAC sessions are used by default with
-            // the "execute_with_nullauth_session" function.
-            .with_user_with_auth(
-                self.key_usages
-                    .as_ref()
-                    .unwrap()
-                    .contains(&KeyUsage::ClientAuth),
-            )
-            // This key has the ability to decrypt
-            .with_decrypt(
-                self.key_usages
-                    .as_ref()
-                    .unwrap()
-                    .contains(&KeyUsage::Decrypt),
-            )
-            // This key has the ability to sign
-            .with_sign_encrypt(
-                self.key_usages
-                    .as_ref()
-                    .unwrap()
-                    .contains(&KeyUsage::SignEncrypt),
-            )
-            // Create self-signed certificates
-            .with_x509_sign(
-                self.key_usages
-                    .as_ref()
-                    .unwrap()
-                    .contains(&KeyUsage::CreateX509),
-            )
-            // This key may only be used to encrypt or sign objects that are within
-            // the TPM - it can not encrypt or sign external data.
-            .with_restricted(false)
-            .build()
-            .unwrap();
+    fn load_key(&mut self, key_id: &str, config: Box<dyn Any>) -> Result<(), SecurityModuleError> {
+        if let Some(config) = config.downcast_ref::<TpmHmacConfig>() {
+            self.create_hmac_key(key_id, config.hash)?;
+            self.key_id = key_id.to_string();
+            return Ok(());
+        }
+        if let Some(config) = config.downcast_ref::<TpmSymmetricConfig>() {
+            self.create_symmetric_key(key_id, config.sym_algorithm)?;
+            self.key_id = key_id.to_string();
+            return Ok(());
+        }
 
-        let private = Private::default();
-        let public = tss_esapi::structures::Public::Ecc {
-            object_attributes: obj_attributes,
-            name_hashing_algorithm: self.hash.unwrap().into(),
-            auth_policy: Digest::default(),
-            parameters: primary_pub,
-            unique: EccPoint::default(),
-        };
+        let config = config
+            .downcast_ref::<TpmConfig>()
+            .ok_or_else(|| SecurityModuleError::InitializationError("Wrong Config".to_owned()))?;
 
-        let key_handle = self
-            .handle
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .load(TssKeyHandle::Null, private, public)
-            .unwrap();
-
-        self.key_handle = Some(Arc::new(Mutex::new(key_handle)));
+        self.create_asymmetric_key(key_id, config)?;
         self.key_id = key_id.to_string();
 
         Ok(())
@@ -327,32 +142,277 @@ impl Provider for TpmProvider {
     /// Initializes the TPM module and returns a handle for further operations.
     ///
     /// This method initializes the TPM context and prepares it for use. It should be called
-    /// before performing any other operations with the TPM.
+    /// before performing any other operations with the TPM. The connection is opened through
+    /// the TCTI selected with `TpmProvider::with_tcti`, or through the TCTI named in the
+    /// environment if none was selected.
+    ///
+    /// If the TPM owner has set a hierarchy password and an interaction handler is set, the
+    /// password is requested from the handler and used for all operations in the owner
+    /// hierarchy, such as creating keys.
     ///
     /// # Returns
     ///
     /// A `Result` that, on success, contains `Ok(())`, indicating that the module was initialized successfully.
     /// On failure, it returns a `SecurityModuleError`.
     #[instrument]
-    fn initialize_module(
-        &mut self,
-        key_algorithm: AsymmetricEncryption,
-        sym_algorithm: Option<BlockCiphers>,
-        hash: Option<Hash>,
-        key_usages: Vec<KeyUsage>,
-    ) -> Result<(), SecurityModuleError> {
-        self.key_algorithm = Some(key_algorithm);
-        self.sym_algorithm = sym_algorithm;
-        self.hash = hash;
-        self.key_usages = Some(key_usages);
+    fn initialize_module(&mut self) -> Result<(), SecurityModuleError> {
+        let tcti = TctiNameConf::try_from(&self.tcti)?;
 
-        let tcti = TctiNameConf::from_environment_variable().unwrap();
+        let mut context = Context::new(tcti).map_err(|e| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(format!(
+                "Failed to connect to TPM via '{}': {}",
+                self.tcti.to_tcti_string(),
+                e
+            )))
+        })?;
 
-        let context = Context::new(tcti)
-            .map_err(|e| SecurityModuleError::InitializationError(e.to_string()))?;
+        if let Some(interaction) = &self.interaction {
+            if owner_auth_set(&mut context)? {
+                let request = CredentialRequest {
+                    kind: CredentialKind::Password,
+                    module: "TPM".to_owned(),
+                    key_id: None,
+                    prompt: "Owner hierarchy password of the TPM".to_owned(),
+                    retries_left: None,
+                };
+                let auth =
+                    Auth::try_from(interaction.request_credential(&request)?).map_err(tss_error)?;
+                context
+                    .tr_set_auth(Hierarchy::Owner.into(), auth)
+                    .map_err(tss_error)?;
+            }
+        }
 
         self.handle = Some(Arc::new(Mutex::new(context)));
 
         Ok(())
     }
+
+    /// Sets the handler asked for the owner hierarchy password in `initialize_module`.
+    fn set_interaction_handler(&mut self, handler: Arc<dyn InteractionHandler>) {
+        self.interaction = Some(handler);
+    }
+
+    /// Returns the public part of the currently loaded key as a PEM encoded string.
+    ///
+    /// An empty string is returned if no key is loaded or the key type cannot be exported.
+    #[instrument]
+    fn get_pub_key(&mut self) -> String {
+        let (Some(handle), Some(key_handle)) = (self.handle.as_ref(), self.key_handle.as_ref())
+        else {
+            return String::new();
+        };
+        let key_handle = *key_handle.lock().unwrap();
+
+        handle
+            .lock()
+            .unwrap()
+            .read_public(key_handle)
+            .ok()
+            .and_then(|(public, _, _)| public_key_pem(&public))
+            .unwrap_or_default()
+    }
+
+    /// Returns `len` random bytes from the TPM's random number generator.
+    ///
+    /// `TPM2_GetRandom` returns at most one digest worth of bytes per call and may return
+    /// fewer than requested, so the TPM is queried until `len` bytes have been collected.
+    #[instrument]
+    fn random_bytes(&self, len: usize) -> Result<Vec<u8>, SecurityModuleError> {
+        let mut context = self.context()?;
+        let mut random = Vec::with_capacity(len);
+        while random.len() < len {
+            let requested = (len - random.len()).min(Digest::MAX_SIZE);
+            let digest = context.get_random(requested).map_err(tss_error)?;
+            if digest.value().is_empty() {
+                return Err(SecurityModuleError::Tpm(TpmError::InternalError(
+                    "TPM2_GetRandom returned no data".into(),
+                )));
+            }
+            random.extend_from_slice(digest.value());
+        }
+        random.truncate(len);
+        Ok(random)
+    }
+
+    /// Describes what the TPM supports, as reported by `TPM2_GetCapability`.
+    #[instrument]
+    fn capabilities(&self) -> Result<Capabilities, SecurityModuleError> {
+        self.query_capabilities()
+    }
+}
+
+impl TpmProvider {
+    /// Creates the RSA or ECC key `key_id` described by `config` in the owner hierarchy.
+    fn create_asymmetric_key(
+        &mut self,
+        key_id: &str,
+        config: &TpmConfig,
+    ) -> Result<(), SecurityModuleError> {
+        let public = asymmetric_key_template(key_id, config)?;
+
+        let key_handle = self
+            .context()?
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create_primary(Hierarchy::Owner, public, None, None, None, None)
+            })
+            .map_err(tss_error)?
+            .key_handle;
+
+        self.replace_key_handle(key_handle)?;
+        self.key_algorithm = Some(config.key_algorithm);
+        self.sym_algorithm = Some(config.sym_algorithm);
+        self.hash = Some(config.hash);
+        self.key_usages = Some(config.key_usages.clone());
+
+        Ok(())
+    }
+}
+
+/// Builds the public template of an RSA or ECC key.
+///
+/// The keys are not restricted, so the TPM requires their symmetric algorithm to be null;
+/// the one of `config` is only checked. The scheme is null as well, which lets a key both
+/// sign and decrypt, and is chosen for every signature or decryption instead.
+fn asymmetric_key_template(
+    key_id: &str,
+    config: &TpmConfig,
+) -> Result<Public, SecurityModuleError> {
+    SymmetricDefinitionObject::try_from(config.sym_algorithm)?;
+    let unique = hash(MessageDigest::sha256(), key_id.as_bytes()).map_err(openssl_error)?;
+    let usages = &config.key_usages;
+
+    let builder = PublicBuilder::new()
+        .with_public_algorithm(config.key_algorithm.into())
+        .with_name_hashing_algorithm(config.hash.try_into()?)
+        .with_object_attributes(
+            ObjectAttributesBuilder::new()
+                // Indicate the key can only exist within this tpm and can not be exported.
+                .with_fixed_tpm(true)
+                // The primary key and it's descendent keys can't be moved to other primary
+                // keys.
+                .with_fixed_parent(true)
+                // The primary key was generated entirely inside the TPM - only this TPM
+                // knows it's content.
+                .with_sensitive_data_origin(true)
+                // This key requires "authentication" to the TPM to access - this can be
+                // an HMAC or password session. HMAC sessions are used by default with
+                // the "execute_with_nullauth_session" function.
+                .with_user_with_auth(usages.contains(&KeyUsage::ClientAuth))
+                // This key has the ability to decrypt
+                .with_decrypt(usages.contains(&KeyUsage::Decrypt))
+                // This key has the ability to sign
+                .with_sign_encrypt(usages.contains(&KeyUsage::SignEncrypt))
+                // Create self-signed certificates
+                .with_x509_sign(usages.contains(&KeyUsage::CreateX509))
+                // This key may only be used to encrypt or sign objects that are within
+                // the TPM - it can not encrypt or sign external data.
+                .with_restricted(false)
+                .build()
+                .map_err(tss_error)?,
+        );
+
+    let builder = match config.key_algorithm {
+        AsymmetricEncryption::Rsa(key_bits) => builder
+            .with_rsa_parameters(rsa_parameters(key_bits)?)
+            .with_rsa_unique_identifier(
+                PublicKeyRsa::try_from(unique.to_vec()).map_err(tss_error)?,
+            ),
+        AsymmetricEncryption::Ecc(_) => {
+            let curve = config.key_algorithm.ecc_curve().ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+                    "ECC keys need a curve".to_owned(),
+                ))
+            })?;
+            builder
+                .with_ecc_parameters(ecc_parameters(curve)?)
+                .with_ecc_unique_identifier(EccPoint::new(
+                    EccParameter::try_from(unique.to_vec()).map_err(tss_error)?,
+                    EccParameter::default(),
+                ))
+        }
+    };
+
+    builder.build().map_err(tss_error)
+}
+
+/// The parameters of RSA keys with `key_bits`, see `asymmetric_key_template`.
+pub(super) fn rsa_parameters(
+    key_bits: KeyBits,
+) -> Result<PublicRsaParameters, SecurityModuleError> {
+    Ok(PublicRsaParameters::new(
+        SymmetricDefinitionObject::Null,
+        RsaScheme::Null,
+        key_bits.try_into()?,
+        RsaExponent::default(),
+    ))
+}
+
+/// The parameters of ECC keys on `curve`, see `asymmetric_key_template`.
+pub(super) fn ecc_parameters(curve: EccCurves) -> Result<PublicEccParameters, SecurityModuleError> {
+    Ok(PublicEccParameters::new(
+        SymmetricDefinitionObject::Null,
+        EccScheme::Null,
+        curve.try_into()?,
+        KeyDerivationFunctionScheme::Null,
+    ))
+}
+
+/// Returns whether an owner hierarchy password is set, i.e. `ownerAuthSet` of
+/// `TPMA_PERMANENT`.
+fn owner_auth_set(context: &mut Context) -> Result<bool, SecurityModuleError> {
+    let permanent = context
+        .get_tpm_property(PropertyTag::Permanent)
+        .map_err(tss_error)?
+        .unwrap_or_default();
+    Ok(permanent & TPMA_PERMANENT_OWNERAUTHSET != 0)
+}
+
+/// Converts the public area of a TPM key into a PEM encoded `SubjectPublicKeyInfo`.
+///
+/// Only RSA keys and ECC keys on the NIST curves can be represented; `None` is
+/// returned for every other key type.
+pub(super) fn public_key_pem(public: &Public) -> Option<String> {
+    let pem = match public {
+        Public::Rsa {
+            parameters, unique, ..
+        } => {
+            // The TPM encodes the default exponent 65537 as zero.
+            let exponent = match parameters.exponent().value() {
+                0 => 65537,
+                e => e,
+            };
+            let rsa = Rsa::from_public_components(
+                BigNum::from_slice(unique.value()).ok()?,
+                BigNum::from_u32(exponent).ok()?,
+            )
+            .ok()?;
+            rsa.public_key_to_pem().ok()?
+        }
+        Public::Ecc {
+            parameters, unique, ..
+        } => {
+            let nid = match parameters.ecc_curve() {
+                EccCurve::NistP256 => Nid::X9_62_PRIME256V1,
+                EccCurve::NistP384 => Nid::SECP384R1,
+                EccCurve::NistP521 => Nid::SECP521R1,
+                _ => return None,
+            };
+            let group = EcGroup::from_curve_name(nid).ok()?;
+            let mut ctx = BigNumContext::new().ok()?;
+            let x = BigNum::from_slice(unique.x().value()).ok()?;
+            let y = BigNum::from_slice(unique.y().value()).ok()?;
+            let mut point = OpensslEcPoint::new(&group).ok()?;
+            point
+                .set_affine_coordinates_gfp(&group, &x, &y, &mut ctx)
+                .ok()?;
+            EcKey::from_public_key(&group, &point)
+                .ok()?
+                .public_key_to_pem()
+                .ok()?
+        }
+        _ => return None,
+    };
+
+    String::from_utf8(pem).ok()
 }
@@ -0,0 +1,249 @@
+use super::{openssl_error, tss_error, TpmProvider};
+use crate::{
+    common::{
+        crypto::algorithms::{
+            encryption::{BlockCiphers, SymmetricMode},
+            KeyBits,
+        },
+        error::SecurityModuleError,
+    },
+    tpm::core::error::TpmError,
+};
+use openssl::hash::{hash, MessageDigest};
+use tss_esapi::{
+    attributes::ObjectAttributesBuilder,
+    handles::KeyHandle as TssKeyHandle,
+    interface_types::{
+        algorithm::{HashingAlgorithm, PublicAlgorithm, SymmetricMode as TssSymmetricMode},
+        resource_handles::Hierarchy,
+    },
+    structures::{
+        Digest, InitialValue, MaxBuffer, Public, PublicBuilder, SymmetricCipherParameters,
+        SymmetricDefinitionObject,
+    },
+};
+
+/// The AES block size, which is also the length of the IV.
+const BLOCK_SIZE: usize = 16;
+
+impl TpmProvider {
+    /// Creates the symmetric key `key_id` in the owner hierarchy.
+    ///
+    /// Like HMAC keys, the key is a primary key whose unique field is derived from `key_id`,
+    /// so creating and loading it are the same operation.
+    ///
+    /// Only AES in the modes the TPM implements (CFB, CBC, OFB, CTR and ECB) is supported.
+    pub(super) fn create_symmetric_key(
+        &mut self,
+        key_id: &str,
+        sym_algorithm: BlockCiphers,
+    ) -> Result<(), SecurityModuleError> {
+        let (mode, key_bits) = aes_parameters(sym_algorithm)?;
+        let public = symmetric_key_template(key_id, mode, key_bits)?;
+
+        let key_handle = self
+            .context()?
+            .execute_with_nullauth_session(|ctx| {
+                ctx.create_primary(Hierarchy::Owner, public, None, None, None, None)
+            })
+            .map_err(tss_error)?
+            .key_handle;
+
+        self.replace_key_handle(key_handle)?;
+        self.key_algorithm = None;
+        self.sym_algorithm = Some(sym_algorithm);
+        self.hash = None;
+        self.key_usages = None;
+
+        Ok(())
+    }
+
+    /// Encrypts `data` with the loaded symmetric key.
+    ///
+    /// A fresh IV is drawn from the TPM for every call. The result is laid out as
+    /// `IV (16 bytes) || ciphertext`; in ECB mode, which takes no IV, it is the bare
+    /// ciphertext. CBC and ECB pad the plaintext with PKCS#7, the stream modes CFB,
+    /// OFB and CTR do not pad.
+    pub(super) fn symmetric_encrypt(
+        &self,
+        sym_algorithm: BlockCiphers,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let (mode, _) = aes_parameters(sym_algorithm)?;
+
+        let iv = if matches!(mode, SymmetricMode::Ecb) {
+            Vec::new()
+        } else {
+            self.context()?
+                .get_random(BLOCK_SIZE)
+                .map_err(tss_error)?
+                .value()
+                .to_vec()
+        };
+
+        let plaintext = if is_padded(mode) {
+            pkcs7_pad(data)
+        } else {
+            data.to_vec()
+        };
+
+        let mut encrypted = iv.clone();
+        encrypted.extend(self.encrypt_decrypt_chunked(mode, false, iv, &plaintext)?);
+        Ok(encrypted)
+    }
+
+    /// Decrypts data produced by [`TpmProvider::symmetric_encrypt`].
+    pub(super) fn symmetric_decrypt(
+        &self,
+        sym_algorithm: BlockCiphers,
+        encrypted_data: &[u8],
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let (mode, _) = aes_parameters(sym_algorithm)?;
+
+        let (iv, ciphertext) = if matches!(mode, SymmetricMode::Ecb) {
+            (&[][..], encrypted_data)
+        } else if encrypted_data.len() >= BLOCK_SIZE {
+            encrypted_data.split_at(BLOCK_SIZE)
+        } else {
+            return Err(SecurityModuleError::DecryptionError(
+                "Ciphertext is shorter than the IV".to_owned(),
+            ));
+        };
+        if is_padded(mode) && (ciphertext.is_empty() || ciphertext.len() % BLOCK_SIZE != 0) {
+            return Err(SecurityModuleError::DecryptionError(
+                "Ciphertext is not a multiple of the block size".to_owned(),
+            ));
+        }
+
+        let plaintext = self.encrypt_decrypt_chunked(mode, true, iv.to_vec(), ciphertext)?;
+
+        if is_padded(mode) {
+            pkcs7_unpad(plaintext)
+        } else {
+            Ok(plaintext)
+        }
+    }
+
+    /// Runs `TPM2_EncryptDecrypt2` over `data` in chunks of at most `MaxBuffer::MAX_SIZE`
+    /// bytes, feeding the chaining value returned by the TPM into the next chunk.
+    fn encrypt_decrypt_chunked(
+        &self,
+        mode: SymmetricMode,
+        decrypt: bool,
+        iv: Vec<u8>,
+        data: &[u8],
+    ) -> Result<Vec<u8>, SecurityModuleError> {
+        let key_handle: TssKeyHandle = *self
+            .key_handle
+            .as_ref()
+            .ok_or_else(|| {
+                SecurityModuleError::Tpm(TpmError::InitializationError("No key loaded".to_owned()))
+            })?
+            .lock()
+            .unwrap();
+        let mode: TssSymmetricMode = mode.try_into()?;
+        let mut context = self.context()?;
+
+        let mut iv = InitialValue::try_from(iv).map_err(tss_error)?;
+        let mut output = Vec::with_capacity(data.len());
+        // `MaxBuffer::MAX_SIZE` is a multiple of the block size, so every chunk but the
+        // last one is block aligned and the chaining value stays valid.
+        for chunk in data.chunks(MaxBuffer::MAX_SIZE) {
+            let chunk = MaxBuffer::try_from(chunk).map_err(tss_error)?;
+            let (out, next_iv) = context
+                .execute_with_nullauth_session(|ctx| {
+                    ctx.encrypt_decrypt_2(key_handle, decrypt, mode, chunk, iv.clone())
+                })
+                .map_err(tss_error)?;
+            output.extend_from_slice(out.value());
+            iv = next_iv;
+        }
+
+        Ok(output)
+    }
+}
+
+/// Validates `sym_algorithm` and returns its mode and key size.
+pub(super) fn aes_parameters(
+    sym_algorithm: BlockCiphers,
+) -> Result<(SymmetricMode, KeyBits), SecurityModuleError> {
+    match sym_algorithm {
+        BlockCiphers::Aes(
+            mode @ (SymmetricMode::Cfb
+            | SymmetricMode::Cbc
+            | SymmetricMode::Ofb
+            | SymmetricMode::Ctr
+            | SymmetricMode::Ecb),
+            key_bits @ (KeyBits::Bits128 | KeyBits::Bits192 | KeyBits::Bits256),
+        ) => Ok((mode, key_bits)),
+        _ => Err(SecurityModuleError::Tpm(TpmError::UnsupportedOperation(
+            format!(
+                "{:?} is not supported for TPM symmetric keys",
+                sym_algorithm
+            ),
+        ))),
+    }
+}
+
+fn symmetric_key_template(
+    key_id: &str,
+    mode: SymmetricMode,
+    key_bits: KeyBits,
+) -> Result<Public, SecurityModuleError> {
+    let unique = hash(MessageDigest::sha256(), key_id.as_bytes()).map_err(openssl_error)?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::SymCipher)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(
+            ObjectAttributesBuilder::new()
+                .with_fixed_tpm(true)
+                .with_fixed_parent(true)
+                .with_sensitive_data_origin(true)
+                .with_user_with_auth(true)
+                .with_decrypt(true)
+                .with_sign_encrypt(true)
+                .build()
+                .map_err(tss_error)?,
+        )
+        .with_symmetric_cipher_parameters(SymmetricCipherParameters::new(
+            SymmetricDefinitionObject::Aes {
+                key_bits: key_bits.try_into()?,
+                mode: mode.try_into()?,
+            },
+        ))
+        .with_symmetric_cipher_unique_identifier(
+            Digest::try_from(unique.to_vec()).map_err(tss_error)?,
+        )
+        .build()
+        .map_err(tss_error)
+}
+
+fn is_padded(mode: SymmetricMode) -> bool {
+    matches!(mode, SymmetricMode::Cbc | SymmetricMode::Ecb)
+}
+
+fn pkcs7_pad(data: &[u8]) -> Vec<u8> {
+    let padding = BLOCK_SIZE - data.len() % BLOCK_SIZE;
+    let mut padded = Vec::with_capacity(data.len() + padding);
+    padded.extend_from_slice(data);
+    padded.resize(data.len() + padding, padding as u8);
+    padded
+}
+
+fn pkcs7_unpad(mut data: Vec<u8>) -> Result<Vec<u8>, SecurityModuleError> {
+    let padding = data.last().copied().unwrap_or(0) as usize;
+    if padding == 0
+        || padding > BLOCK_SIZE
+        || padding > data.len()
+        || data[data.len() - padding..]
+            .iter()
+            .any(|b| *b as usize != padding)
+    {
+        return Err(SecurityModuleError::DecryptionError(
+            "Invalid padding".to_owned(),
+        ));
+    }
+    data.truncate(data.len() - padding);
+    Ok(data)
+}
@@ -0,0 +1,192 @@
+use super::tss_error;
+use crate::{common::error::SecurityModuleError, tpm::core::error::TpmError};
+use std::{env, path::Path, str::FromStr};
+use tss_esapi::{constants::PropertyTag, Context, TctiNameConf};
+
+/// Selects the TPM Command Transmission Interface (TCTI) used to reach the TPM.
+///
+/// The TCTI decides whether commands go to a local device node, through the
+/// access broker daemon, or to a network simulator such as `swtpm` or the
+/// Microsoft reference simulator. `Environment` keeps the previous behaviour of
+/// reading `TPM2TOOLS_TCTI`, `TCTI` or `TEST_TCTI`, but falls back to the
+/// in-kernel resource manager instead of panicking when none of them is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TctiConfig {
+    /// Reads the TCTI from the environment, falling back to `/dev/tpmrm0`.
+    #[default]
+    Environment,
+    /// Talks to a TPM device node, e.g. `/dev/tpm0` or `/dev/tpmrm0`.
+    Device(String),
+    /// Talks to the `tpm2-abrmd` access broker over D-Bus.
+    ///
+    /// `bus_name` defaults to `com.intel.tss2.Tabrmd` and `bus_type` to `system`
+    /// when left empty.
+    Tabrmd { bus_name: String, bus_type: String },
+    /// Talks to a simulator speaking the Microsoft simulator (MSSIM) protocol.
+    Mssim { host: String, port: u16 },
+    /// Talks to an `swtpm` instance started with `--server type=tcp`.
+    Swtpm { host: String, port: u16 },
+}
+
+/// The TPM found by `TctiConfig::probe`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TpmInfo {
+    /// The vendor ID of the manufacturer, e.g. `IFX` or `STM`.
+    pub manufacturer: String,
+    /// The major and minor firmware version, as defined by the manufacturer.
+    pub firmware_version: (u16, u16),
+}
+
+/// The device node used when no TCTI is configured through the environment.
+const DEFAULT_DEVICE: &str = "/dev/tpmrm0";
+
+impl TctiConfig {
+    /// Returns the TCTI configuration string as understood by `tpm2-tools`,
+    /// e.g. `swtpm:host=localhost,port=2321`.
+    pub fn to_tcti_string(&self) -> String {
+        match self {
+            TctiConfig::Environment => format!("device:{}", DEFAULT_DEVICE),
+            TctiConfig::Device(path) => format!("device:{}", path),
+            TctiConfig::Tabrmd { bus_name, bus_type } => {
+                let mut params = Vec::new();
+                if !bus_name.is_empty() {
+                    params.push(format!("bus_name={}", bus_name));
+                }
+                if !bus_type.is_empty() {
+                    params.push(format!("bus_type={}", bus_type));
+                }
+                format!("tabrmd:{}", params.join(","))
+            }
+            TctiConfig::Mssim { host, port } => format!("mssim:host={},port={}", host, port),
+            TctiConfig::Swtpm { host, port } => format!("swtpm:host={},port={}", host, port),
+        }
+    }
+
+    /// Resolves `Environment` to the TCTI named by `TPM2TOOLS_TCTI`, `TCTI` or
+    /// `TEST_TCTI`, or to the default device when none of them is set. Every other
+    /// variant is returned unchanged.
+    pub(crate) fn resolve(&self) -> Result<TctiConfig, SecurityModuleError> {
+        match self {
+            TctiConfig::Environment => ["TPM2TOOLS_TCTI", "TCTI", "TEST_TCTI"]
+                .iter()
+                .find_map(|name| env::var(name).ok())
+                .map_or_else(
+                    || Ok(TctiConfig::Device(DEFAULT_DEVICE.to_owned())),
+                    |tcti| tcti.parse(),
+                ),
+            config => Ok(config.clone()),
+        }
+    }
+
+    /// Returns whether the TPM behind this TCTI serves several connections at once.
+    ///
+    /// Only resource managers do, i.e. the in-kernel one at `/dev/tpmrm*` and
+    /// `tpm2-abrmd`. Raw device nodes and simulators accept a single connection.
+    /// `Environment` must be resolved first and is reported as not shareable.
+    pub(crate) fn is_resource_managed(&self) -> bool {
+        match self {
+            TctiConfig::Device(path) => Path::new(path)
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("tpmrm")),
+            TctiConfig::Tabrmd { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Connects to the TPM through this TCTI and reads its manufacturer and firmware
+    /// version.
+    ///
+    /// # Returns
+    ///
+    /// The `TpmInfo` on success, or a `SecurityModuleError` if the TPM cannot be reached.
+    pub fn probe(&self) -> Result<TpmInfo, SecurityModuleError> {
+        let mut context = Context::new(TctiNameConf::try_from(self)?).map_err(|e| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(format!(
+                "Failed to connect to TPM via '{}': {}",
+                self.to_tcti_string(),
+                e
+            )))
+        })?;
+        let mut property = |tag| {
+            context
+                .get_tpm_property(tag)
+                .map(Option::unwrap_or_default)
+                .map_err(tss_error)
+        };
+
+        // The manufacturer is up to four ASCII characters, padded with spaces or zeros.
+        let manufacturer = property(PropertyTag::Manufacturer)?.to_be_bytes();
+        let firmware = property(PropertyTag::FirmwareVersion1)?;
+        Ok(TpmInfo {
+            manufacturer: String::from_utf8_lossy(&manufacturer)
+                .trim_end_matches(['\0', ' '])
+                .to_owned(),
+            firmware_version: ((firmware >> 16) as u16, firmware as u16),
+        })
+    }
+}
+
+impl FromStr for TctiConfig {
+    type Err = SecurityModuleError;
+
+    /// Parses a `tpm2-tools` style TCTI string such as `device:/dev/tpm0`,
+    /// `tabrmd:bus_type=session` or `swtpm:host=localhost,port=2321`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name_conf = TctiNameConf::from_str(s).map_err(|e| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(format!(
+                "Invalid TCTI '{}': {}",
+                s, e
+            )))
+        })?;
+
+        let params = s.split_once(':').map_or("", |(_, params)| params);
+        let param = |key: &str| {
+            params
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        };
+        // `TctiNameConf` has already validated the port, so parsing cannot fail here.
+        let port = || param("port").map_or(2321, |p| p.parse().unwrap_or(2321));
+
+        Ok(match name_conf {
+            TctiNameConf::Device(_) if params.is_empty() => {
+                TctiConfig::Device("/dev/tpm0".to_string())
+            }
+            TctiNameConf::Device(_) => TctiConfig::Device(params.to_string()),
+            TctiNameConf::Tabrmd(_) => TctiConfig::Tabrmd {
+                bus_name: param("bus_name").unwrap_or_default(),
+                bus_type: param("bus_type").unwrap_or_default(),
+            },
+            TctiNameConf::Mssim(_) => TctiConfig::Mssim {
+                host: param("host").unwrap_or_else(|| "localhost".to_string()),
+                port: port(),
+            },
+            TctiNameConf::Swtpm(_) => TctiConfig::Swtpm {
+                host: param("host").unwrap_or_else(|| "localhost".to_string()),
+                port: port(),
+            },
+        })
+    }
+}
+
+impl TryFrom<&TctiConfig> for TctiNameConf {
+    type Error = SecurityModuleError;
+
+    fn try_from(config: &TctiConfig) -> Result<Self, Self::Error> {
+        let tcti = match config {
+            TctiConfig::Environment => TctiNameConf::from_environment_variable()
+                .or_else(|_| TctiNameConf::from_str(&config.to_tcti_string())),
+            _ => TctiNameConf::from_str(&config.to_tcti_string()),
+        };
+
+        tcti.map_err(|e| {
+            SecurityModuleError::Tpm(TpmError::InitializationError(format!(
+                "Invalid TCTI '{}': {}",
+                config.to_tcti_string(),
+                e
+            )))
+        })
+    }
+}
@@ -41,7 +41,7 @@ impl TpmConfig {
         sym_algorithm: BlockCiphers,
         hash: Hash,
         key_usages: Vec<KeyUsage>,
-    ) -> Box<dyn ProviderConfig> {
+    ) -> Box<dyn Any> {
         Box::new(Self {
             key_algorithm,
             sym_algorithm,
@@ -50,3 +50,47 @@ impl TpmConfig {
         })
     }
 }
+
+/// Configuration for a keyed-hash (HMAC) key.
+///
+/// Passed to `create_key` or `load_key` instead of a `TpmConfig` to get a key that
+/// computes HMACs inside the TPM with the given hash algorithm.
+#[derive(Debug, Clone, Default)]
+pub struct TpmHmacConfig {
+    pub hash: Hash,
+}
+
+impl ProviderConfig for TpmHmacConfig {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl TpmHmacConfig {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(hash: Hash) -> Box<dyn Any> {
+        Box::new(Self { hash })
+    }
+}
+
+/// Configuration for a symmetric key.
+///
+/// Passed to `create_key` or `load_key` instead of a `TpmConfig` to get a key that
+/// encrypts inside the TPM with the given block cipher and mode.
+#[derive(Debug, Clone, Default)]
+pub struct TpmSymmetricConfig {
+    pub sym_algorithm: BlockCiphers,
+}
+
+impl ProviderConfig for TpmSymmetricConfig {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl TpmSymmetricConfig {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(sym_algorithm: BlockCiphers) -> Box<dyn Any> {
+        Box::new(Self { sym_algorithm })
+    }
+}
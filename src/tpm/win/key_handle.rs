@@ -1,6 +1,14 @@
 use super::TpmProvider;
 use crate::{
-    common::{error::SecurityModuleError, traits::key_handle::KeyHandle},
+    common::{
+        capabilities::Operation,
+        crypto::algorithms::encryption::AsymmetricEncryption,
+        error::SecurityModuleError,
+        traits::{
+            key_handle::KeyHandle,
+            operations::{Decryptor, Encryptor, Signer, Verifier},
+        },
+    },
     tpm::core::error::TpmError,
 };
 use tracing::instrument;
@@ -18,6 +26,42 @@ use windows::{
 /// Provides cryptographic operations for asymmetric keys on Windows,
 /// such as signing, encryption, decryption, and signature verification.
 impl KeyHandle for TpmProvider {
+    /// Returns the key as a `Signer` if its usages permit signing.
+    fn as_signer(&self) -> Option<&dyn Signer> {
+        (self.key_algo.is_some() && self.permits(Operation::Sign)).then_some(self)
+    }
+
+    /// Returns the key as a `Verifier` once a key is created or loaded.
+    fn as_verifier(&self) -> Option<&dyn Verifier> {
+        self.key_algo.is_some().then_some(self)
+    }
+
+    /// Returns the key as an `Encryptor` if it is an RSA key whose usages permit encryption.
+    fn as_encryptor(&self) -> Option<&dyn Encryptor> {
+        (self.is_rsa_key() && self.permits(Operation::Encrypt)).then_some(self)
+    }
+
+    /// Returns the key as a `Decryptor` if it is an RSA key whose usages permit decryption.
+    fn as_decryptor(&self) -> Option<&dyn Decryptor> {
+        (self.is_rsa_key() && self.permits(Operation::Decrypt)).then_some(self)
+    }
+}
+
+impl TpmProvider {
+    /// Returns whether the key is an RSA key, the only keys CNG encrypts with here.
+    fn is_rsa_key(&self) -> bool {
+        matches!(self.key_algo, Some(AsymmetricEncryption::Rsa(_)))
+    }
+
+    /// Returns whether the usages the key was created with permit `operation`.
+    fn permits(&self, operation: Operation) -> bool {
+        self.key_usages
+            .as_ref()
+            .is_none_or(|usages| operation.permitted_by(usages))
+    }
+}
+
+impl Signer for TpmProvider {
     /// Signs data using the cryptographic key.
     ///
     /// This method hashes the input data using SHA-256 and then signs the hash.
@@ -31,7 +75,7 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the signature as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn sign_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         // Open an algorithm provider for SHA-512
         let mut alg_handle: BCRYPT_ALG_HANDLE = self.hash.unwrap().into();
         let hash_algo: PCWSTR = self.hash.unwrap().into();
@@ -142,7 +186,9 @@ impl KeyHandle for TpmProvider {
 
         Ok(signature)
     }
+}
 
+impl Decryptor for TpmProvider {
     /// Decrypts data encrypted with the corresponding public key.
     ///
     /// Utilizes the NCryptDecrypt function from the Windows CNG API.
@@ -155,7 +201,7 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the decrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn decrypt_data(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         let mut decrypted_data_len: u32 = 0;
 
         // First, determine the size of the decrypted data without actually decrypting
@@ -198,7 +244,9 @@ impl KeyHandle for TpmProvider {
 
         Ok(decrypted_data)
     }
+}
 
+impl Encryptor for TpmProvider {
     /// Encrypts data with the cryptographic key.
     ///
     /// Uses the NCryptEncrypt function from the Windows CNG API.
@@ -211,7 +259,7 @@ impl KeyHandle for TpmProvider {
     ///
     /// A `Result` containing the encrypted data as a `Vec<u8>` on success, or a `SecurityModuleError` on failure.
     #[instrument]
-    fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, SecurityModuleError> {
         // First call to determine the size of the encrypted data
         let mut encrypted_data_len: u32 = 0;
         if unsafe {
@@ -253,7 +301,9 @@ impl KeyHandle for TpmProvider {
 
         Ok(encrypted_data)
     }
+}
 
+impl Verifier for TpmProvider {
     /// Verifies a signature against the provided data.
     ///
     /// This method hashes the input data using SHA-256 and then verifies the signature.
@@ -269,7 +319,7 @@ impl KeyHandle for TpmProvider {
     /// A `Result` indicating whether the signature is valid (`true`) or not (`false`),
     /// or a `SecurityModuleError` on failure.
     #[instrument]
-    fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool, SecurityModuleError> {
         // Open an algorithm provider for SHA-256, just like in sign_data
         let mut alg_handle = BCRYPT_ALG_HANDLE::default();
         let alg_id: PCWSTR = self.hash.unwrap().into();
@@ -1,19 +1,26 @@
-use crate::common::crypto::{
-    algorithms::{
-        encryption::{AsymmetricEncryption, BlockCiphers, EccSchemeAlgorithm},
-        hashes::{Hash, Sha2Bits},
+use crate::{
+    common::{
+        crypto::{
+            algorithms::{
+                encryption::{AsymmetricEncryption, BlockCiphers, EccSchemeAlgorithm},
+                hashes::{Hash, Sha2Bits},
+            },
+            KeyUsage,
+        },
+        error::SecurityModuleError,
     },
-    KeyUsage,
+    tpm::core::error::TpmError,
 };
 use tracing::instrument;
 use windows::{
     core::PCWSTR,
     Win32::Security::Cryptography::{
-        BCRYPT_ALG_HANDLE, BCRYPT_ECDH_ALGORITHM, BCRYPT_ECDSA_ALGORITHM, BCRYPT_MD2_ALGORITHM,
-        BCRYPT_MD2_ALG_HANDLE, BCRYPT_MD4_ALGORITHM, BCRYPT_MD4_ALG_HANDLE, BCRYPT_MD5_ALGORITHM,
-        BCRYPT_MD5_ALG_HANDLE, BCRYPT_RSA_ALGORITHM, BCRYPT_SHA256_ALGORITHM,
-        BCRYPT_SHA256_ALG_HANDLE, BCRYPT_SHA384_ALGORITHM, BCRYPT_SHA384_ALG_HANDLE,
-        BCRYPT_SHA512_ALGORITHM, BCRYPT_SHA512_ALG_HANDLE, NCRYPT_KEY_HANDLE, NCRYPT_PROV_HANDLE,
+        NCryptFreeObject, NCryptOpenStorageProvider, BCRYPT_ALG_HANDLE, BCRYPT_ECDH_ALGORITHM,
+        BCRYPT_ECDSA_ALGORITHM, BCRYPT_MD2_ALGORITHM, BCRYPT_MD2_ALG_HANDLE, BCRYPT_MD4_ALGORITHM,
+        BCRYPT_MD4_ALG_HANDLE, BCRYPT_MD5_ALGORITHM, BCRYPT_MD5_ALG_HANDLE, BCRYPT_RSA_ALGORITHM,
+        BCRYPT_SHA256_ALGORITHM, BCRYPT_SHA256_ALG_HANDLE, BCRYPT_SHA384_ALGORITHM,
+        BCRYPT_SHA384_ALG_HANDLE, BCRYPT_SHA512_ALGORITHM, BCRYPT_SHA512_ALG_HANDLE,
+        MS_PLATFORM_CRYPTO_PROVIDER, NCRYPT_HANDLE, NCRYPT_KEY_HANDLE, NCRYPT_PROV_HANDLE,
     },
 };
 
@@ -62,6 +69,20 @@ impl TpmProvider {
             key_usages: None,
         }
     }
+
+    /// Checks that the TPM is usable by opening the Microsoft Platform Crypto Provider.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the provider opens, or a `SecurityModuleError` if there is no usable TPM.
+    pub fn probe() -> Result<(), SecurityModuleError> {
+        let mut handle = NCRYPT_PROV_HANDLE::default();
+        unsafe { NCryptOpenStorageProvider(&mut handle, MS_PLATFORM_CRYPTO_PROVIDER, 0) }
+            .map_err(TpmError::Win)?;
+        // Closing the handle cannot fail in a way that matters for the probe.
+        let _ = unsafe { NCryptFreeObject(NCRYPT_HANDLE(handle.0)) };
+        Ok(())
+    }
 }
 
 /// Converts a `Hash` value to the corresponding Windows API constant for algorithm handles.
@@ -6,10 +6,11 @@ use crate::{
             KeyUsage,
         },
         error::SecurityModuleError,
-        traits::{module_provider::Provider, module_provider_config::ProviderConfig},
+        traits::module_provider::Provider,
     },
     tpm::{core::error::TpmError, TpmConfig},
 };
+use std::any::Any;
 use tracing::instrument;
 use windows::{
     core::PCWSTR,
@@ -51,9 +52,9 @@ impl Provider for TpmProvider {
     fn create_key(
         &mut self,
         key_id: &str,
-        config: Box<dyn ProviderConfig>,
+        config: Box<dyn Any>,
     ) -> Result<(), SecurityModuleError> {
-        let config = config.as_any().downcast_ref::<TpmConfig>().unwrap();
+        let config = config.downcast_ref::<TpmConfig>().unwrap();
 
         self.key_algo = Some(config.key_algorithm);
         self.sym_algo = Some(config.sym_algorithm);
@@ -201,9 +202,9 @@ impl Provider for TpmProvider {
     fn load_key(
         &mut self,
         key_id: &str,
-        config: Box<dyn ProviderConfig>,
+        config: Box<dyn Any>,
     ) -> Result<(), SecurityModuleError> {
-        let config = config.as_any().downcast_ref::<TpmConfig>().unwrap();
+        let config = config.downcast_ref::<TpmConfig>().unwrap();
 
         self.key_algo = Some(config.key_algorithm);
         self.sym_algo = Some(config.sym_algorithm);